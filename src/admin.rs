@@ -0,0 +1,145 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+
+use crate::server_state::ServerState;
+
+pub async fn verify(State(state): State<ServerState>) -> impl IntoResponse {
+    let requests = state.recorded_requests();
+
+    let unmet: Vec<Value> = state
+        .scenario()
+        .expectations
+        .iter()
+        .filter_map(|expectation| {
+            let actual = requests.iter().filter(|r| expectation.matches(r)).count();
+            (!expectation.is_satisfied_by(actual)).then(|| {
+                json!({
+                    "expectation": expectation,
+                    "actual": actual,
+                })
+            })
+        })
+        .collect();
+
+    let status = if unmet.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::EXPECTATION_FAILED
+    };
+
+    (
+        status,
+        Json(json!({
+            "satisfied": unmet.is_empty(),
+            "unmet": unmet,
+        })),
+    )
+}
+
+pub async fn api_key(State(state): State<ServerState>) -> impl IntoResponse {
+    let Some(issued) = state.current_api_key() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No API key configured"})),
+        );
+    };
+
+    let issued_at = issued
+        .issued_at
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration");
+    let expires_at = state
+        .args()
+        .api_key_ttl
+        .map(|ttl| (issued_at + ttl).as_secs());
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "api_key": issued.key,
+            "issued_at": issued_at.as_secs(),
+            "expires_at": expires_at,
+        })),
+    )
+}
+
+/// The text to count tokens for, either raw or as the `messages` of a chat completion request.
+#[derive(Deserialize)]
+pub struct CountTokensRequest {
+    pub text: Option<String>,
+    pub messages: Option<Vec<Value>>,
+}
+
+pub async fn count_tokens(
+    State(state): State<ServerState>,
+    Json(payload): Json<CountTokensRequest>,
+) -> impl IntoResponse {
+    // Messages are counted the same way the chat completions endpoint counts the prompt
+    let tokens = match (payload.text, payload.messages) {
+        (Some(text), _) => state.count_tokens(&text),
+        (None, Some(messages)) => state.count_message_tokens(&messages),
+        (None, None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Either 'text' or 'messages' is required"})),
+            );
+        }
+    };
+
+    match tokens {
+        Ok(tokens) => (StatusCode::OK, Json(json!({"tokens": tokens}))),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": err.to_string()})),
+        ),
+    }
+}
+
+/// Returns the current state of the stub scenarios.
+pub async fn scenarios(State(state): State<ServerState>) -> impl IntoResponse {
+    Json(json!({ "states": state.scenario_states() }))
+}
+
+/// Moves every stub scenario back to the initial state.
+pub async fn reset_scenarios(State(state): State<ServerState>) -> impl IntoResponse {
+    state.reset_scenario_states();
+    StatusCode::NO_CONTENT
+}
+
+/// Describes the served models and their prices, in USD per million tokens.
+pub async fn pricing(State(state): State<ServerState>) -> impl IntoResponse {
+    let mut models: BTreeMap<&str, Value> = state
+        .args()
+        .models
+        .iter()
+        .map(|model| (model.as_str(), Value::Null))
+        .collect();
+    for (model, price) in state.prices() {
+        models.insert(model, json!(price));
+    }
+
+    Json(json!({
+        "currency": "USD",
+        "unit": "1M tokens",
+        "models": models,
+    }))
+}
+
+/// Describes the limits currently enforced, `null` meaning unlimited.
+pub async fn limits(State(state): State<ServerState>) -> impl IntoResponse {
+    let args = state.args();
+    Json(json!({
+        "rpm": state.rpm_limit(),
+        "tpm": args.tpm,
+        "quota_exceeded_after": args.quota_exceeded_after,
+        "max_streams_per_key": args.max_streams_per_key,
+        "max_requests_per_connection": args.max_requests_per_connection,
+        "api_key_ttl": args.api_key_ttl.map(|ttl| ttl.as_secs()),
+    }))
+}
@@ -0,0 +1,652 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{sse::Event, IntoResponse, Response, Sse},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chat_completions::paced;
+use crate::errors::ApiError;
+use crate::resume;
+use crate::server_state::ServerState;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Assistant {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub model: String,
+    pub instructions: Option<String>,
+    pub tools: Vec<Value>,
+    pub metadata: Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Thread {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub metadata: Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ThreadMessage {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub thread_id: String,
+    pub status: String,
+    pub role: String,
+    pub content: Vec<Value>,
+    pub assistant_id: Option<String>,
+    pub run_id: Option<String>,
+    pub attachments: Vec<Value>,
+    pub metadata: Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Run {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub thread_id: String,
+    pub assistant_id: String,
+    pub status: String,
+    pub model: String,
+    pub instructions: Option<String>,
+    pub tools: Vec<Value>,
+    pub started_at: Option<u64>,
+    pub completed_at: Option<u64>,
+    pub cancelled_at: Option<u64>,
+    pub failed_at: Option<u64>,
+    pub last_error: Option<Value>,
+    pub usage: Option<Value>,
+    pub metadata: Value,
+}
+
+impl Run {
+    fn is_active(&self) -> bool {
+        matches!(self.status.as_str(), "queued" | "in_progress")
+    }
+}
+
+/// Assistants, threads, messages and runs, kept in memory for the lifetime of the server.
+#[derive(Debug, Default)]
+pub struct AssistantsStore {
+    pub assistants: Vec<Assistant>,
+    pub threads: Vec<Thread>,
+    pub messages: Vec<ThreadMessage>,
+    pub runs: Vec<Run>,
+}
+
+impl AssistantsStore {
+    fn run_mut(&mut self, thread_id: &str, run_id: &str) -> Option<&mut Run> {
+        self.runs
+            .iter_mut()
+            .find(|run| run.id == run_id && run.thread_id == thread_id)
+    }
+
+    /// Ends a run with the generated answer, adding it to the thread, or with an error.
+    fn finish_run(
+        &mut self,
+        thread_id: &str,
+        run_id: &str,
+        outcome: &Result<(String, Value), ApiError>,
+    ) -> Option<(Run, Option<ThreadMessage>)> {
+        let finished_at = now();
+        let run = self.run_mut(thread_id, run_id)?;
+        if !run.is_active() {
+            return None;
+        }
+        run.started_at.get_or_insert(finished_at);
+        let message = match outcome {
+            Ok((content, usage)) => {
+                run.status = "completed".to_string();
+                run.completed_at = Some(finished_at);
+                run.usage = Some(usage.clone());
+                Some(ThreadMessage {
+                    assistant_id: Some(run.assistant_id.clone()),
+                    run_id: Some(run.id.clone()),
+                    ..new_message(thread_id, "assistant", text_content(content))
+                })
+            }
+            Err(error) => {
+                run.status = "failed".to_string();
+                run.failed_at = Some(finished_at);
+                run.last_error = Some(json!({
+                    "code": "server_error",
+                    "message": error.message,
+                }));
+                None
+            }
+        };
+        let run = run.clone();
+        self.messages.extend(message.clone());
+        Some((run, message))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateAssistantRequest {
+    pub model: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateMessageRequest {
+    pub role: String,
+    pub content: Value,
+    #[serde(default)]
+    pub attachments: Vec<Value>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct CreateThreadRequest {
+    #[serde(default)]
+    pub messages: Vec<CreateMessageRequest>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRunRequest {
+    pub assistant_id: String,
+    pub model: Option<String>,
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub additional_messages: Vec<CreateMessageRequest>,
+    #[serde(default)]
+    pub stream: bool,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub order: Option<String>,
+    pub after: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs()
+}
+
+fn generate_id(prefix: &str) -> String {
+    format!("{}_{:x}", prefix, rand::thread_rng().gen::<u128>())
+}
+
+fn metadata(metadata: Option<Value>) -> Value {
+    metadata.unwrap_or_else(|| json!({}))
+}
+
+fn text_content(text: &str) -> Vec<Value> {
+    vec![json!({"type": "text", "text": {"value": text, "annotations": []}})]
+}
+
+/// Message content can be a string or an array of parts, plain text parts get the shape
+/// messages are returned with.
+fn message_content(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) => text_content(text),
+        Value::Array(parts) => parts
+            .iter()
+            .map(|part| match part["text"].as_str() {
+                Some(text) => text_content(text).remove(0),
+                None => part.clone(),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn message_text(message: &ThreadMessage) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|part| part["text"]["value"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn new_message(thread_id: &str, role: &str, content: Vec<Value>) -> ThreadMessage {
+    ThreadMessage {
+        id: generate_id("msg"),
+        object: "thread.message".to_string(),
+        created_at: now(),
+        thread_id: thread_id.to_string(),
+        status: "completed".to_string(),
+        role: role.to_string(),
+        content,
+        assistant_id: None,
+        run_id: None,
+        attachments: Vec::new(),
+        metadata: json!({}),
+    }
+}
+
+fn from_request(thread_id: &str, request: CreateMessageRequest) -> ThreadMessage {
+    ThreadMessage {
+        attachments: request.attachments,
+        metadata: metadata(request.metadata),
+        ..new_message(thread_id, &request.role, message_content(&request.content))
+    }
+}
+
+fn not_found(kind: &str, id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("No {} found with id '{}'.", kind, id),
+    )
+}
+
+fn deleted(id: &str, object: &str) -> Response {
+    Json(json!({"id": id, "object": object, "deleted": true})).into_response()
+}
+
+/// Lists items sorted by creation, newest first unless `order` is `asc`.
+fn list_page<T: Serialize>(
+    mut items: Vec<&T>,
+    id: impl Fn(&T) -> &str,
+    query: &ListQuery,
+) -> Response {
+    if query.order.as_deref() != Some("asc") {
+        items.reverse();
+    }
+    if let Some(after) = &query.after {
+        let start = items
+            .iter()
+            .position(|item| id(item) == after)
+            .map_or(0, |position| position + 1);
+        items.drain(..start);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+
+    Json(json!({
+        "object": "list",
+        "data": items,
+        "first_id": items.first().map(|item| id(item)),
+        "last_id": items.last().map(|item| id(item)),
+        "has_more": has_more,
+    }))
+    .into_response()
+}
+
+pub async fn create_assistant(
+    state: State<ServerState>,
+    Json(payload): Json<CreateAssistantRequest>,
+) -> Response {
+    let assistant = Assistant {
+        id: generate_id("asst"),
+        object: "assistant".to_string(),
+        created_at: now(),
+        name: payload.name,
+        description: payload.description,
+        model: payload.model,
+        instructions: payload.instructions,
+        tools: payload.tools,
+        metadata: metadata(payload.metadata),
+    };
+    let mut store = state.assistants_store().lock().unwrap();
+    store.assistants.push(assistant.clone());
+    Json(assistant).into_response()
+}
+
+pub async fn list_assistants(
+    State(state): State<ServerState>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    let store = state.assistants_store().lock().unwrap();
+    list_page(
+        store.assistants.iter().collect(),
+        |assistant| assistant.id.as_str(),
+        &query,
+    )
+}
+
+pub async fn retrieve_assistant(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let store = state.assistants_store().lock().unwrap();
+    match store.assistants.iter().find(|assistant| assistant.id == id) {
+        Some(assistant) => Json(assistant).into_response(),
+        None => state.error_response(not_found("assistant", &id)),
+    }
+}
+
+pub async fn delete_assistant(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let mut store = state.assistants_store().lock().unwrap();
+    let before = store.assistants.len();
+    store.assistants.retain(|assistant| assistant.id != id);
+    if store.assistants.len() == before {
+        return state.error_response(not_found("assistant", &id));
+    }
+    deleted(&id, "assistant.deleted")
+}
+
+pub async fn create_thread(
+    state: State<ServerState>,
+    payload: Option<Json<CreateThreadRequest>>,
+) -> Response {
+    let Json(payload) = payload.unwrap_or_default();
+    let thread = Thread {
+        id: generate_id("thread"),
+        object: "thread".to_string(),
+        created_at: now(),
+        metadata: metadata(payload.metadata),
+    };
+    let mut store = state.assistants_store().lock().unwrap();
+    for message in payload.messages {
+        store.messages.push(from_request(&thread.id, message));
+    }
+    store.threads.push(thread.clone());
+    Json(thread).into_response()
+}
+
+pub async fn retrieve_thread(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let store = state.assistants_store().lock().unwrap();
+    match store.threads.iter().find(|thread| thread.id == id) {
+        Some(thread) => Json(thread).into_response(),
+        None => state.error_response(not_found("thread", &id)),
+    }
+}
+
+pub async fn delete_thread(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let mut store = state.assistants_store().lock().unwrap();
+    let before = store.threads.len();
+    store.threads.retain(|thread| thread.id != id);
+    if store.threads.len() == before {
+        return state.error_response(not_found("thread", &id));
+    }
+    store.messages.retain(|message| message.thread_id != id);
+    store.runs.retain(|run| run.thread_id != id);
+    deleted(&id, "thread.deleted")
+}
+
+pub async fn create_message(
+    state: State<ServerState>,
+    Path(thread_id): Path<String>,
+    Json(payload): Json<CreateMessageRequest>,
+) -> Response {
+    let mut store = state.assistants_store().lock().unwrap();
+    if !store.threads.iter().any(|thread| thread.id == thread_id) {
+        return state.error_response(not_found("thread", &thread_id));
+    }
+    let message = from_request(&thread_id, payload);
+    store.messages.push(message.clone());
+    Json(message).into_response()
+}
+
+pub async fn list_messages(
+    state: State<ServerState>,
+    Path(thread_id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    let store = state.assistants_store().lock().unwrap();
+    if !store.threads.iter().any(|thread| thread.id == thread_id) {
+        return state.error_response(not_found("thread", &thread_id));
+    }
+    let messages = store
+        .messages
+        .iter()
+        .filter(|message| message.thread_id == thread_id)
+        .collect();
+    list_page(messages, |message| message.id.as_str(), &query)
+}
+
+/// Moves a run through `queued → in_progress` and ends it after `--run-duration`, unless it
+/// was cancelled in the meantime.
+async fn execute(
+    state: ServerState,
+    thread_id: String,
+    run_id: String,
+    outcome: Result<(String, Value), ApiError>,
+) {
+    let duration = state.args().run_duration;
+    tokio::time::sleep(duration / 4).await;
+    {
+        let mut store = state.assistants_store().lock().unwrap();
+        match store.run_mut(&thread_id, &run_id) {
+            Some(run) if run.status == "queued" => {
+                run.status = "in_progress".to_string();
+                run.started_at = Some(now());
+            }
+            _ => return,
+        }
+    }
+    tokio::time::sleep(duration - duration / 4).await;
+    let mut store = state.assistants_store().lock().unwrap();
+    store.finish_run(&thread_id, &run_id, &outcome);
+}
+
+fn event(name: &str, data: &impl Serialize) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(name)
+        .data(serde_json::to_string(data).unwrap()))
+}
+
+/// The events streamed while a run goes from `queued` to its final status.
+fn run_events(
+    run: &Run,
+    finished: &Run,
+    message: Option<&ThreadMessage>,
+) -> Vec<Result<Event, Infallible>> {
+    let mut events = vec![
+        event("thread.run.created", run),
+        event("thread.run.queued", run),
+    ];
+    let in_progress = Run {
+        status: "in_progress".to_string(),
+        started_at: finished.started_at,
+        ..run.clone()
+    };
+    events.push(event("thread.run.in_progress", &in_progress));
+
+    if let Some(message) = message {
+        let created = ThreadMessage {
+            status: "in_progress".to_string(),
+            content: Vec::new(),
+            ..message.clone()
+        };
+        events.push(event("thread.message.created", &created));
+        events.push(event("thread.message.in_progress", &created));
+        for word in message_text(message).split_whitespace() {
+            let delta = json!({
+                "id": message.id,
+                "object": "thread.message.delta",
+                "delta": {
+                    "content": [{"index": 0, "type": "text", "text": {"value": format!("{} ", word)}}],
+                },
+            });
+            events.push(event("thread.message.delta", &delta));
+        }
+        events.push(event("thread.message.completed", message));
+    }
+
+    events.push(event(&format!("thread.run.{}", finished.status), finished));
+    events.push(Ok(Event::default().event("done").data("[DONE]")));
+    events
+}
+
+pub async fn create_run(
+    state: State<ServerState>,
+    Path(thread_id): Path<String>,
+    Json(payload): Json<CreateRunRequest>,
+) -> Response {
+    let (assistant, prompt) = {
+        let mut store = state.assistants_store().lock().unwrap();
+        if !store.threads.iter().any(|thread| thread.id == thread_id) {
+            return state.error_response(not_found("thread", &thread_id));
+        }
+        let Some(assistant) = store
+            .assistants
+            .iter()
+            .find(|assistant| assistant.id == payload.assistant_id)
+            .cloned()
+        else {
+            return state.error_response(
+                not_found("assistant", &payload.assistant_id).with_param("assistant_id"),
+            );
+        };
+        if let Some(run) = store
+            .runs
+            .iter()
+            .find(|run| run.thread_id == thread_id && run.is_active())
+        {
+            return state.error_response(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Thread {} already has an active run {}.", thread_id, run.id),
+            ));
+        }
+        for message in payload.additional_messages {
+            store.messages.push(from_request(&thread_id, message));
+        }
+        let prompt = store
+            .messages
+            .iter()
+            .filter(|message| message.thread_id == thread_id)
+            .map(message_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        (assistant, prompt)
+    };
+
+    if state.quota_exceeded() {
+        return state.error_response(ApiError::insufficient_quota());
+    }
+    if let Some(response) = state.long_reset_rate_limit() {
+        return response;
+    }
+    if state.check_request_limit_exceeded() {
+        return state.error_response(state.request_limit_error());
+    }
+    state.increment_request_count();
+
+    // Errors don't fail the request, they fail the run
+    let outcome = match state.should_return_error() {
+        Some(code) => Err(ApiError::simulated(code)),
+        None => {
+            let content = state.generate_lorem_content(
+                state.get_response_length(payload.model.as_deref().unwrap_or(&assistant.model)),
+                &prompt,
+            );
+            let prompt_tokens = state.count_tokens(&prompt).unwrap_or(0);
+            let completion_tokens = state.count_tokens(&content).unwrap_or(0);
+            state.add_token_usage(prompt_tokens + completion_tokens);
+            let usage = json!({
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+            });
+            Ok((content, usage))
+        }
+    };
+
+    let run = Run {
+        id: generate_id("run"),
+        object: "thread.run".to_string(),
+        created_at: now(),
+        thread_id: thread_id.clone(),
+        assistant_id: assistant.id,
+        status: "queued".to_string(),
+        model: payload.model.unwrap_or(assistant.model),
+        instructions: payload.instructions.or(assistant.instructions),
+        tools: assistant.tools,
+        started_at: None,
+        completed_at: None,
+        cancelled_at: None,
+        failed_at: None,
+        last_error: None,
+        usage: None,
+        metadata: metadata(payload.metadata),
+    };
+    state
+        .assistants_store()
+        .lock()
+        .unwrap()
+        .runs
+        .push(run.clone());
+
+    if payload.stream {
+        let finished = state
+            .assistants_store()
+            .lock()
+            .unwrap()
+            .finish_run(&thread_id, &run.id, &outcome);
+        let Some((finished, message)) = finished else {
+            return state.error_response(not_found("run", &run.id));
+        };
+        let events = run_events(&run, &finished, message.as_ref());
+        return Sse::new(resume::store(&state, paced(state.0.clone(), events))).into_response();
+    }
+
+    tokio::spawn(execute(state.0.clone(), thread_id, run.id.clone(), outcome));
+    Json(run).into_response()
+}
+
+pub async fn list_runs(
+    state: State<ServerState>,
+    Path(thread_id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    let store = state.assistants_store().lock().unwrap();
+    if !store.threads.iter().any(|thread| thread.id == thread_id) {
+        return state.error_response(not_found("thread", &thread_id));
+    }
+    let runs = store
+        .runs
+        .iter()
+        .filter(|run| run.thread_id == thread_id)
+        .collect();
+    list_page(runs, |run| run.id.as_str(), &query)
+}
+
+pub async fn retrieve_run(
+    state: State<ServerState>,
+    Path((thread_id, run_id)): Path<(String, String)>,
+) -> Response {
+    let mut store = state.assistants_store().lock().unwrap();
+    match store.run_mut(&thread_id, &run_id) {
+        Some(run) => Json(run.clone()).into_response(),
+        None => state.error_response(not_found("run", &run_id)),
+    }
+}
+
+pub async fn cancel_run(
+    state: State<ServerState>,
+    Path((thread_id, run_id)): Path<(String, String)>,
+) -> Response {
+    let mut store = state.assistants_store().lock().unwrap();
+    let Some(run) = store.run_mut(&thread_id, &run_id) else {
+        return state.error_response(not_found("run", &run_id));
+    };
+    if !run.is_active() {
+        return state.error_response(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!("Cannot cancel run with status '{}'.", run.status),
+        ));
+    }
+    run.status = "cancelled".to_string();
+    run.cancelled_at = Some(now());
+    Json(run.clone()).into_response()
+}
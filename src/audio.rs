@@ -0,0 +1,142 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::stream;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+const PCM_SAMPLE_RATE: u32 = 24_000;
+const CHUNK_SIZE: usize = 4096;
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, no CRC, no padding, joint stereo.
+const MP3_FRAME_HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x64];
+const MP3_FRAME_LENGTH: usize = 417;
+const MP3_FRAME_SECONDS: f64 = 1152.0 / 44_100.0;
+/// Characters spoken per second, used to estimate the duration of the audio.
+const CHARACTERS_PER_SECOND: f64 = 15.0;
+
+#[derive(Deserialize)]
+pub struct SpeechRequest {
+    pub input: String,
+    pub model: Option<String>,
+    pub voice: Option<String>,
+    pub response_format: Option<String>,
+    pub speed: Option<f64>,
+}
+
+/// Raw 16-bit little-endian mono samples of a quiet 440 Hz tone.
+fn pcm_samples(duration: Duration) -> Vec<u8> {
+    let samples = (duration.as_secs_f64() * PCM_SAMPLE_RATE as f64) as usize;
+    (0..samples)
+        .flat_map(|i| {
+            let t = i as f32 / PCM_SAMPLE_RATE as f32;
+            let sample = ((2.0 * PI * 440.0 * t).sin() * i16::MAX as f32 * 0.1) as i16;
+            sample.to_le_bytes()
+        })
+        .collect()
+}
+
+fn wav(duration: Duration) -> Vec<u8> {
+    let data = pcm_samples(duration);
+    let mut wav = Vec::with_capacity(44 + data.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&PCM_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(PCM_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    wav
+}
+
+/// A sequence of silent MP3 frames, zeroed side information makes every frame decode to silence.
+fn mp3(duration: Duration) -> Vec<u8> {
+    let frames = (duration.as_secs_f64() / MP3_FRAME_SECONDS).ceil().max(1.0) as usize;
+    let mut frame = vec![0u8; MP3_FRAME_LENGTH];
+    frame[..4].copy_from_slice(&MP3_FRAME_HEADER);
+    frame.repeat(frames)
+}
+
+/// Estimates how long it takes to read `text` aloud, at least a second.
+pub(crate) fn spoken_duration(text: &str, speed: f64) -> Duration {
+    Duration::from_secs_f64((text.chars().count() as f64 / CHARACTERS_PER_SECOND / speed).max(1.0))
+}
+
+/// Encodes `duration` of audio in `format`, raw PCM samples unless it's "mp3" or "wav".
+pub(crate) fn encode(format: &str, duration: Duration) -> Vec<u8> {
+    match format {
+        "mp3" => mp3(duration),
+        "wav" => wav(duration),
+        _ => pcm_samples(duration),
+    }
+}
+
+pub async fn speech(state: State<ServerState>, Json(payload): Json<SpeechRequest>) -> Response {
+    let format = payload.response_format.as_deref().unwrap_or("mp3");
+    let content_type = match format {
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "pcm" => "audio/pcm",
+        _ => {
+            return state.error_response(
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    format!(
+                        "Unsupported response_format '{}', Roy simulates 'mp3', 'wav' and 'pcm'",
+                        format
+                    ),
+                )
+                .with_param("response_format"),
+            );
+        }
+    };
+
+    if let Err(response) = state.admit(&payload.input).await {
+        return response;
+    }
+
+    let input_tokens = state.count_tokens(&payload.input).unwrap_or(0);
+    if state.check_token_limit_exceeded(input_tokens) {
+        return state.error_response(state.token_limit_error(input_tokens));
+    }
+    state.add_token_usage(input_tokens);
+
+    let speed = payload.speed.filter(|speed| *speed > 0.0).unwrap_or(1.0);
+    let duration = state
+        .args()
+        .audio_duration
+        .unwrap_or_else(|| spoken_duration(&payload.input, speed));
+    let audio = encode(format, duration);
+
+    // Send the audio a chunk at a time, like a download clients can start playing early
+    let chunks = audio
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| Ok::<_, Infallible>(Bytes::copy_from_slice(chunk)))
+        .collect::<Vec<_>>();
+
+    let headers = state.get_rate_limit_headers();
+    (
+        headers,
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(stream::iter(chunks)),
+    )
+        .into_response()
+}
@@ -0,0 +1,102 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::{distributions::Alphanumeric, Rng};
+use std::time::SystemTime;
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+/// An API key along with the moment it started being valid.
+#[derive(Debug, Clone)]
+pub struct IssuedKey {
+    pub key: String,
+    pub issued_at: SystemTime,
+}
+
+impl IssuedKey {
+    pub fn new(key: String) -> Self {
+        Self {
+            key,
+            issued_at: SystemTime::now(),
+        }
+    }
+
+    pub fn generate() -> Self {
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        Self::new(format!("sk-roy-{}", suffix))
+    }
+}
+
+fn redact(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    format!(
+        "{}{}{}",
+        chars[..3].iter().collect::<String>(),
+        "*".repeat(chars.len() - 7),
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+/// Returns the API key sent as a Bearer token or, as Gemini clients do, in the
+/// `x-goog-api-key` header, if any.
+pub fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get("x-goog-api-key")
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+pub async fn require_api_key(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(issued) = state.current_api_key() else {
+        return next.run(req).await;
+    };
+
+    match api_key(req.headers()) {
+        Some(key) if key == issued.key => next.run(req).await,
+        Some(key) => state
+            .noisy(
+                ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_request_error",
+                    format!(
+                        "Incorrect API key provided: {}. You can find your API key at https://platform.openai.com/account/api-keys.",
+                        redact(key)
+                    ),
+                )
+                .with_code("invalid_api_key"),
+            )
+            .into_response(),
+        None => state
+            .noisy(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_request_error",
+                "You didn't provide an API key. You need to provide your API key in an Authorization header using Bearer auth (i.e. Authorization: Bearer YOUR_KEY).",
+            ))
+            .into_response(),
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::embeddings::{dimensions, generate_vector};
+use crate::errors::ApiError;
+use crate::files::StoredFile;
+use crate::server_state::ServerState;
+
+const ENDPOINTS: [&str; 3] = ["/v1/chat/completions", "/v1/completions", "/v1/embeddings"];
+
+#[derive(Deserialize)]
+pub struct CreateBatchRequest {
+    pub input_file_id: String,
+    pub endpoint: String,
+    pub completion_window: String,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ListBatchesQuery {
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RequestCounts {
+    pub total: u32,
+    pub completed: u32,
+    pub failed: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Batch {
+    pub id: String,
+    pub object: String,
+    pub endpoint: String,
+    pub errors: Option<Value>,
+    pub input_file_id: String,
+    pub completion_window: String,
+    pub status: String,
+    pub output_file_id: Option<String>,
+    pub error_file_id: Option<String>,
+    pub created_at: u64,
+    pub in_progress_at: Option<u64>,
+    pub expires_at: u64,
+    pub finalizing_at: Option<u64>,
+    pub completed_at: Option<u64>,
+    pub failed_at: Option<u64>,
+    pub expired_at: Option<u64>,
+    pub cancelling_at: Option<u64>,
+    pub cancelled_at: Option<u64>,
+    pub request_counts: RequestCounts,
+    pub metadata: Option<Value>,
+}
+
+/// A line of the batch input file.
+#[derive(Deserialize)]
+struct BatchInput {
+    custom_id: String,
+    #[serde(default)]
+    body: Value,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs()
+}
+
+fn invalid_request(message: impl Into<String>, param: &str) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param)
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("No such Batch object: {}", id),
+    )
+    .with_param("batch_id")
+}
+
+/// Simulates the response body the endpoint would return for `body`, or the error rejecting it.
+fn simulated_body(state: &ServerState, endpoint: &str, body: &Value) -> Result<Value, ApiError> {
+    let model = body["model"].as_str().unwrap_or_default();
+    if endpoint == "/v1/embeddings" {
+        let input = body["input"].as_str().unwrap_or_default();
+        let dimensions = dimensions(model, body["dimensions"].as_u64())?;
+        let prompt_tokens = state.count_tokens(input).unwrap_or(0);
+        return Ok(json!({
+            "object": "list",
+            "data": [{
+                "object": "embedding",
+                "index": 0,
+                "embedding": generate_vector(input, dimensions),
+            }],
+            "model": model,
+            "usage": {"prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens},
+        }));
+    }
+
+    let prompt_tokens = match body["messages"].as_array() {
+        Some(messages) => state.count_message_tokens(messages).unwrap_or(0),
+        None => state
+            .count_tokens(body["prompt"].as_str().unwrap_or_default())
+            .unwrap_or(0),
+    };
+    let content = state.generate_lorem_content(state.get_response_length(model), &body.to_string());
+    let completion_tokens = state.count_tokens(&content).unwrap_or(0);
+    let usage = json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    });
+    let rand_id = rand::thread_rng().gen::<u32>();
+
+    if endpoint == "/v1/completions" {
+        return Ok(json!({
+            "id": format!("cmpl-{}", rand_id),
+            "object": "text_completion",
+            "created": now(),
+            "model": model,
+            "choices": [{"text": content, "index": 0, "logprobs": null, "finish_reason": "stop"}],
+            "usage": usage,
+        }));
+    }
+    Ok(json!({
+        "id": format!("chatcmpl-{}", rand_id),
+        "object": "chat.completion",
+        "created": now(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        }],
+        "usage": usage,
+    }))
+}
+
+/// Runs every request of the batch, each one failing according to `--error-rate`, and returns
+/// the JSONL output with the number of failed requests.
+fn run_batch(state: &ServerState, endpoint: &str, inputs: &[BatchInput]) -> (Vec<u8>, u32) {
+    let mut output = Vec::new();
+    let mut failed = 0;
+    for input in inputs {
+        let (status_code, body) = match state.should_return_error() {
+            Some(code) => {
+                failed += 1;
+                (code, state.noisy(ApiError::simulated(code)).body())
+            }
+            None => match simulated_body(state, endpoint, &input.body) {
+                Ok(body) => (200, body),
+                Err(error) => {
+                    failed += 1;
+                    (error.status.as_u16(), error.body())
+                }
+            },
+        };
+        let line = json!({
+            "id": format!("batch_req_{:x}", rand::thread_rng().gen::<u64>()),
+            "custom_id": input.custom_id,
+            "response": {
+                "status_code": status_code,
+                "request_id": format!("req_{:032x}", rand::thread_rng().gen::<u128>()),
+                "body": body,
+            },
+            "error": null,
+        });
+        output.extend_from_slice(line.to_string().as_bytes());
+        output.push(b'\n');
+    }
+    (output, failed)
+}
+
+/// Moves the batch through `validating → in_progress → completed`: validation takes a tenth of
+/// `--batch-duration` and processing the rest. Cancelled batches stop where they are.
+async fn process(state: ServerState, id: String, inputs: Vec<BatchInput>) {
+    let duration = state.args().batch_duration;
+    tokio::time::sleep(duration / 10).await;
+    let endpoint = match state.update_batch(&id, |batch| {
+        (batch.status == "validating").then(|| {
+            batch.status = "in_progress".to_string();
+            batch.in_progress_at = Some(now());
+            batch.endpoint.clone()
+        })
+    }) {
+        Some(Some(endpoint)) => endpoint,
+        _ => return,
+    };
+
+    tokio::time::sleep(duration - duration / 10).await;
+    let (output, failed) = run_batch(&state, &endpoint, &inputs);
+    let output_file = StoredFile::new(
+        format!("{}_output.jsonl", id),
+        "batch_output".to_string(),
+        output,
+    );
+    let output_file_id = output_file.id.clone();
+
+    let completed = state.update_batch(&id, |batch| {
+        if batch.status != "in_progress" {
+            return false;
+        }
+        let finished_at = now();
+        batch.status = "completed".to_string();
+        batch.finalizing_at = Some(finished_at);
+        batch.completed_at = Some(finished_at);
+        batch.output_file_id = Some(output_file_id);
+        batch.request_counts.completed = batch.request_counts.total - failed;
+        batch.request_counts.failed = failed;
+        true
+    });
+    if completed == Some(true) {
+        state.files().lock().unwrap().push(output_file);
+    }
+}
+
+pub async fn create(
+    state: State<ServerState>,
+    Json(payload): Json<CreateBatchRequest>,
+) -> Response {
+    if !ENDPOINTS.contains(&payload.endpoint.as_str()) {
+        return state.error_response(invalid_request(
+            format!(
+                "Unsupported endpoint '{}', use one of {}",
+                payload.endpoint,
+                ENDPOINTS.join(", ")
+            ),
+            "endpoint",
+        ));
+    }
+    if payload.completion_window != "24h" {
+        return state.error_response(invalid_request(
+            "Only the '24h' completion window is supported",
+            "completion_window",
+        ));
+    }
+    let Some(file) = state.file(&payload.input_file_id) else {
+        return state.error_response(invalid_request(
+            format!("No such File object: {}", payload.input_file_id),
+            "input_file_id",
+        ));
+    };
+
+    let content = String::from_utf8_lossy(&file.content);
+    let inputs = match content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str::<BatchInput>)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            return state.error_response(invalid_request(
+                format!("Invalid batch input file: {}", err),
+                "input_file_id",
+            ));
+        }
+    };
+
+    let created_at = now();
+    let batch = Batch {
+        id: format!("batch_{:x}", rand::thread_rng().gen::<u128>()),
+        object: "batch".to_string(),
+        endpoint: payload.endpoint,
+        input_file_id: payload.input_file_id,
+        completion_window: payload.completion_window,
+        status: "validating".to_string(),
+        created_at,
+        expires_at: created_at + 24 * 60 * 60,
+        request_counts: RequestCounts {
+            total: inputs.len() as u32,
+            ..Default::default()
+        },
+        metadata: payload.metadata,
+        ..Default::default()
+    };
+    state.batches().lock().unwrap().push(batch.clone());
+    tokio::spawn(process(state.0.clone(), batch.id.clone(), inputs));
+
+    Json(batch).into_response()
+}
+
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let batch = state
+        .batches()
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|batch| batch.id == id)
+        .cloned();
+    match batch {
+        Some(batch) => Json(batch).into_response(),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+pub async fn cancel(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let batch = state.update_batch(&id, |batch| {
+        if matches!(batch.status.as_str(), "validating" | "in_progress") {
+            let cancelled_at = now();
+            batch.status = "cancelled".to_string();
+            batch.cancelling_at = Some(cancelled_at);
+            batch.cancelled_at = Some(cancelled_at);
+        }
+        batch.clone()
+    });
+    match batch {
+        Some(batch) if batch.status == "cancelled" => Json(batch).into_response(),
+        Some(batch) => state.error_response(
+            ApiError::new(
+                StatusCode::CONFLICT,
+                "invalid_request_error",
+                format!("Cannot cancel a batch with status '{}'", batch.status),
+            )
+            .with_param("batch_id"),
+        ),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+/// Lists the batches, most recent first.
+pub async fn list(
+    State(state): State<ServerState>,
+    Query(query): Query<ListBatchesQuery>,
+) -> Response {
+    let batches = state.batches().lock().unwrap();
+    let mut data = batches.iter().rev().collect::<Vec<_>>();
+    if let Some(after) = &query.after {
+        let start = data
+            .iter()
+            .position(|batch| batch.id == *after)
+            .map_or(0, |position| position + 1);
+        data.drain(..start);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let has_more = data.len() > limit;
+    data.truncate(limit);
+
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "first_id": data.first().map(|batch| &batch.id),
+        "last_id": data.last().map(|batch| &batch.id),
+        "has_more": has_more,
+    }))
+    .into_response()
+}
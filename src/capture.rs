@@ -0,0 +1,192 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, State},
+    http::{
+        header::{CONTENT_ENCODING, CONTENT_TYPE},
+        HeaderMap, HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::write::GzDecoder;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::journal::RequestId;
+use crate::server_state::ServerState;
+use crate::sse::SseParser;
+
+/// A line of a capture file. Each file holds a `request`, a `response` and then either a single
+/// `body` or the `event`s of a streaming response, with their offset from the request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CaptureLine {
+    Request {
+        id: String,
+        timestamp: u64,
+        method: String,
+        path: String,
+        headers: BTreeMap<String, String>,
+        body: Value,
+    },
+    Response {
+        status: u16,
+        headers: BTreeMap<String, String>,
+    },
+    Body {
+        body: Value,
+    },
+    Event {
+        offset_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        event: Option<String>,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+}
+
+fn headers_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+fn body_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+/// Decodes the bytes of `buffer`, keeping a trailing incomplete character for the next chunk.
+fn decode_utf8(buffer: &mut Vec<u8>) -> String {
+    let valid_up_to = match std::str::from_utf8(buffer) {
+        Ok(_) => buffer.len(),
+        // Invalid bytes in the middle are replaced, a truncated character at the end is kept
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => buffer.len(),
+    };
+    let text = String::from_utf8_lossy(&buffer[..valid_up_to]).into_owned();
+    buffer.drain(..valid_up_to);
+    text
+}
+
+pub async fn capture(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let Some(capture_dir) = state.args().capture_dir.clone() else {
+        return next.run(req).await;
+    };
+
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+
+    let id = parts
+        .extensions
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone())
+        .unwrap_or_else(RequestId::generate_id);
+    let path = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(parts.uri.path(), |uri| uri.path())
+        .to_string();
+    let mut lines = vec![CaptureLine::Request {
+        id: id.clone(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_millis() as u64,
+        method: parts.method.to_string(),
+        path,
+        headers: headers_map(&parts.headers),
+        body: body_value(&bytes),
+    }];
+
+    let started = Instant::now();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    let (parts, body) = response.into_parts();
+    lines.push(CaptureLine::Response {
+        status: parts.status.as_u16(),
+        headers: headers_map(&parts.headers),
+    });
+    let is_sse = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    // `--gzip-streams` compresses the body before it gets here, the capture keeps it readable
+    let mut decoder = (parts
+        .headers
+        .get(CONTENT_ENCODING)
+        .map(HeaderValue::as_bytes)
+        == Some(b"gzip"))
+    .then(|| GzDecoder::new(Vec::new()));
+
+    let mut data_stream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        let mut parser = SseParser::default();
+        let mut body_bytes = vec![];
+        // Writes can end in the middle of a character, e.g. with `--sse-frame-size`
+        let mut undecoded = vec![];
+
+        while let Some(chunk) = data_stream.next().await {
+            if let Ok(chunk) = &chunk {
+                let chunk = match decoder.as_mut() {
+                    Some(decoder) => {
+                        if let Err(err) = decoder.write_all(chunk).and_then(|_| decoder.flush()) {
+                            log::warn!("Failed to decompress the captured response: {}", err);
+                        }
+                        std::mem::take(decoder.get_mut())
+                    }
+                    None => chunk.to_vec(),
+                };
+                if is_sse {
+                    let offset_ms = started.elapsed().as_millis() as u64;
+                    undecoded.extend_from_slice(&chunk);
+                    for event in parser.push(&decode_utf8(&mut undecoded)) {
+                        lines.push(CaptureLine::Event {
+                            offset_ms,
+                            event: event.event,
+                            data: event.data,
+                            id: event.id,
+                        });
+                    }
+                } else {
+                    body_bytes.extend_from_slice(&chunk);
+                }
+            }
+            yield chunk;
+        }
+
+        if !is_sse {
+            lines.push(CaptureLine::Body { body: body_value(&body_bytes) });
+        }
+
+        let content = lines
+            .iter()
+            .filter_map(|line| serde_json::to_string(line).ok())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let file_path = capture_dir.join(format!("{}.jsonl", id));
+        if let Err(err) = tokio::fs::write(&file_path, content + "\n").await {
+            log::warn!("Failed to write capture file {}: {}", file_path.display(), err);
+        }
+    };
+
+    Response::from_parts(parts, Body::from_stream(stream))
+}
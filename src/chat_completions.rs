@@ -2,33 +2,76 @@
 // SPDX-License-Identifier: MIT
 
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
     response::{sse::Event, IntoResponse, Sse},
     Json,
 };
-use futures_util::stream;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::Stream;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
 use std::convert::Infallible;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::audio;
+use crate::errors::ApiError;
+use crate::language;
+use crate::resume;
 use crate::server_state::ServerState;
+use crate::snapshot::{seeded_rng, Snapshot};
+use crate::tools;
+use crate::ChoiceOrder;
+
+/// Maximum number of choices a single request can ask for.
+const MAX_CHOICES: u32 = 128;
+
+/// Maximum number of alternatives `top_logprobs` can ask for.
+const MAX_TOP_LOGPROBS: u64 = 20;
+
+/// Bounds of `frequency_penalty` and `presence_penalty`.
+const MAX_PENALTY: f64 = 2.0;
+
+/// Bounds of the `logit_bias` values.
+const MAX_LOGIT_BIAS: f64 = 100.0;
+
+/// Limits of the `metadata` attached to chat completions and responses.
+const MAX_METADATA_PAIRS: usize = 16;
+const MAX_METADATA_KEY_LENGTH: usize = 64;
+const MAX_METADATA_VALUE_LENGTH: usize = 512;
+
+/// Audio output formats Roy can encode.
+const AUDIO_FORMATS: [&str; 3] = ["wav", "mp3", "pcm16"];
+/// Tokens of every second of audio output.
+const AUDIO_TOKENS_PER_SECOND: f64 = 10.0;
+/// Audio outputs can be referred to in later turns for an hour.
+const AUDIO_EXPIRATION_SECS: u64 = 3600;
 
 #[derive(Serialize, Debug)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<Value>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ChatCompletionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub messages: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<bool>,
     #[serde(flatten)]
     pub _other: Value,
 }
@@ -39,6 +82,7 @@ pub struct ChatCompletionResponse {
     pub object: String,
     pub created: u64,
     pub model: String,
+    pub system_fingerprint: String,
     pub choices: Vec<Choice>,
     pub usage: Usage,
 }
@@ -49,6 +93,7 @@ pub struct ChatCompletionChunk {
     pub object: String,
     pub created: u64,
     pub model: String,
+    pub system_fingerprint: String,
     pub choices: Vec<ChunkChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<Usage>,
@@ -59,6 +104,8 @@ pub struct ChunkChoice {
     pub index: u32,
     pub delta: ChoiceDelta,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub finish_reason: Option<String>,
 }
 
@@ -68,198 +115,1116 @@ pub struct ChoiceDelta {
     pub role: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    pub function: FunctionCallDelta,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FunctionCallDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub arguments: String,
 }
 
 #[derive(Serialize)]
 pub struct Choice {
     pub index: u32,
     pub message: Message,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Value>,
     pub finish_reason: String,
 }
 
 #[derive(Serialize)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    pub content: Option<String>,
+    pub refusal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<Value>,
 }
 
-pub async fn chat_completions(
-    state: State<ServerState>,
-    Json(payload): Json<ChatCompletionRequest>,
-) -> impl IntoResponse {
-    if state.check_request_limit_exceeded() {
-        let headers = state.get_rate_limit_headers();
-        let error_body = json!({
-            "error": {
-                "message": "Too many requests",
-                "type": "rate_limit_error",
-                "code": "rate_limit_exceeded"
+#[derive(Serialize, Clone)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub _type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+/// Sends the events of a stream, waiting between them as long as `--stream-decay` asks to.
+pub(crate) fn paced(
+    state: ServerState,
+    events: Vec<Result<Event, Infallible>>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    async_stream::stream! {
+        for (index, event) in events.into_iter().enumerate() {
+            let delay = state.chunk_delay(Duration::ZERO, index);
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
             }
-        });
-        return (StatusCode::TOO_MANY_REQUESTS, headers, Json(error_body)).into_response();
+            yield event;
+        }
     }
-    state.increment_request_count();
+}
 
-    if let Some(error_code) = state.should_return_error() {
-        let headers = state.get_rate_limit_headers();
-        let status_code =
-            StatusCode::from_u16(error_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-
-        let error_body = json!({
-            "error": {
-                "message": format!("Simulated error with code {}", error_code),
-                "type": "api_error",
-                "code": error_code.to_string()
+/// Reads the token cap `param` of a request, rejecting the values below `minimum`.
+pub(crate) fn token_cap(
+    request: &Value,
+    param: &str,
+    minimum: i64,
+) -> Result<Option<u32>, ApiError> {
+    let Some(value) = request.get(param).filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+    let message = match value.as_i64() {
+        Some(cap) if cap >= minimum => return Ok(Some(cap.min(u32::MAX as i64) as u32)),
+        Some(cap) => format!(
+            "Invalid '{}': integer below minimum value. Expected a value >= {}, but got {} instead.",
+            param, minimum, cap
+        ),
+        None => format!(
+            "Invalid type for '{}': expected an integer, but got {} instead.",
+            param, value
+        ),
+    };
+    Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param))
+}
+
+/// Reads `logprobs` and `top_logprobs`, returning how many alternatives to list for each token
+/// when log probabilities are requested.
+fn requested_logprobs(request: &Value) -> Result<Option<usize>, ApiError> {
+    let top_logprobs = match request.get("top_logprobs").filter(|value| !value.is_null()) {
+        None => None,
+        Some(value) => match value.as_u64().filter(|top| *top <= MAX_TOP_LOGPROBS) {
+            Some(top) => Some(top as usize),
+            None => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    format!(
+                        "Invalid 'top_logprobs': expected an integer between 0 and {}, but got {} instead.",
+                        MAX_TOP_LOGPROBS, value
+                    ),
+                )
+                .with_param("top_logprobs"))
+            }
+        },
+    };
+    if request["logprobs"] != true {
+        if top_logprobs.is_some() {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "'top_logprobs' can only be used when 'logprobs' is true.",
+            )
+            .with_param("top_logprobs"));
+        }
+        return Ok(None);
+    }
+    Ok(Some(top_logprobs.unwrap_or(0)))
+}
+
+/// Reads the `audio` parameter when `modalities` asks for audio output, returning its format.
+fn requested_audio(request: &Value) -> Result<Option<String>, ApiError> {
+    let modalities = request["modalities"].as_array();
+    if !modalities.is_some_and(|modalities| modalities.iter().any(|m| m == "audio")) {
+        return Ok(None);
+    }
+    let audio = &request["audio"];
+    let (message, param) = if !audio.is_object() {
+        (
+            "Audio output requires the 'audio' parameter, with a 'voice' and a 'format'."
+                .to_string(),
+            "audio",
+        )
+    } else if !audio["voice"].is_string() {
+        (
+            "Missing required parameter: 'audio.voice'.".to_string(),
+            "audio.voice",
+        )
+    } else {
+        match audio["format"].as_str() {
+            Some(format) if AUDIO_FORMATS.contains(&format) => return Ok(Some(format.to_string())),
+            Some(format) => (
+                format!(
+                    "Invalid value: '{}'. Supported values are: {}.",
+                    format,
+                    AUDIO_FORMATS
+                        .map(|format| format!("'{}'", format))
+                        .join(", ")
+                ),
+                "audio.format",
+            ),
+            None => (
+                "Missing required parameter: 'audio.format'.".to_string(),
+                "audio.format",
+            ),
+        }
+    };
+    Err(ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param))
+}
+
+/// Builds the `logprobs` of a choice: a plausible log probability for each token of the content,
+/// or of the refusal, along with `top_logprobs` alternatives no more likely than the token.
+fn logprobs<'a>(tokens: impl IntoIterator<Item = &'a str>, refusal: bool, top: usize) -> Value {
+    let mut rng = rand::thread_rng();
+    let entry = |token: &str, logprob: f64| {
+        json!({
+            "token": token,
+            "logprob": logprob,
+            "bytes": token.as_bytes(),
+        })
+    };
+    let entries = tokens
+        .into_iter()
+        .map(|token| {
+            // Sampled tokens are most often the likely ones
+            let logprob = -rng.gen::<f64>().powi(3) * 4.0;
+            let mut top_logprobs = vec![entry(token, logprob)];
+            let mut alternative_logprob = logprob;
+            // Extra words make up for the ones repeating the token
+            for word in lipsum::lipsum_words(top * 2 + 2).split_whitespace() {
+                if top_logprobs.len() >= top {
+                    break;
+                }
+                let alternative = format!(
+                    "{} ",
+                    word.trim_matches(|c: char| !c.is_alphanumeric())
+                        .to_lowercase()
+                );
+                if alternative.trim() == token.trim() {
+                    continue;
+                }
+                alternative_logprob -= rng.gen_range(0.1..3.0);
+                top_logprobs.push(entry(&alternative, alternative_logprob));
+            }
+            top_logprobs.truncate(top);
+            let mut sampled = entry(token, logprob);
+            sampled["top_logprobs"] = json!(top_logprobs);
+            sampled
+        })
+        .collect::<Vec<_>>();
+    if refusal {
+        json!({"content": null, "refusal": entries})
+    } else {
+        json!({"content": entries, "refusal": null})
+    }
+}
+
+/// Checks the `metadata` of a request: at most 16 pairs of strings, with keys up to 64
+/// characters and values up to 512.
+pub(crate) fn check_metadata(metadata: &Value) -> Result<(), ApiError> {
+    let invalid = |param: &str, message: String| {
+        Err(
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+                .with_param(param),
+        )
+    };
+    let pairs = match metadata {
+        Value::Null => return Ok(()),
+        Value::Object(pairs) => pairs,
+        other => {
+            return invalid(
+                "metadata",
+                format!(
+                    "Invalid type for 'metadata': expected an object, but got {} instead.",
+                    other
+                ),
+            )
+        }
+    };
+    if pairs.len() > MAX_METADATA_PAIRS {
+        return invalid(
+            "metadata",
+            format!(
+                "Invalid 'metadata': too many properties. Expected an object with at most {} properties, but got an object with {} properties instead.",
+                MAX_METADATA_PAIRS,
+                pairs.len()
+            ),
+        );
+    }
+    for (key, value) in pairs {
+        let param = format!("metadata.{}", key);
+        if key.chars().count() > MAX_METADATA_KEY_LENGTH {
+            return invalid(
+                &param,
+                format!(
+                    "Invalid 'metadata': key too long. Expected keys with maximum length {}, but got a key with length {} instead.",
+                    MAX_METADATA_KEY_LENGTH,
+                    key.chars().count()
+                ),
+            );
+        }
+        let Some(value) = value.as_str() else {
+            return invalid(
+                &param,
+                format!(
+                    "Invalid type for '{}': expected a string, but got {} instead.",
+                    param, value
+                ),
+            );
+        };
+        if value.chars().count() > MAX_METADATA_VALUE_LENGTH {
+            return invalid(
+                &param,
+                format!(
+                    "Invalid '{}': string too long. Expected a string with maximum length {}, but got a string with length {} instead.",
+                    param,
+                    MAX_METADATA_VALUE_LENGTH,
+                    value.chars().count()
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the text of a message, its content or the text of its parts.
+fn message_text(message: &Value) -> String {
+    match &message["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    }
+}
+
+/// Whether a streaming request asks for a last chunk with the usage, with
+/// `stream_options: {"include_usage": true}`.
+pub(crate) fn include_usage(request: &Value) -> bool {
+    request["stream_options"]["include_usage"] == true
+}
+
+/// Reads the `seed` of a request, which must be an integer.
+pub(crate) fn seed(request: &Value) -> Result<Option<i64>, ApiError> {
+    let Some(value) = request.get("seed").filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+    match value.as_i64() {
+        Some(seed) => Ok(Some(seed)),
+        None => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!(
+                "Invalid type for 'seed': expected an integer, but got {} instead.",
+                value
+            ),
+        )
+        .with_param("seed")),
+    }
+}
+
+/// Validates `logit_bias`, `frequency_penalty` and `presence_penalty`, returning the overall
+/// penalty to the repetition of words.
+fn repetition_penalty(request: &Value) -> Result<f64, ApiError> {
+    let invalid = |param: &str, message: String| {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param)
+    };
+
+    let mut penalty = 0.0;
+    for param in ["frequency_penalty", "presence_penalty"] {
+        let Some(value) = request.get(param).filter(|value| !value.is_null()) else {
+            continue;
+        };
+        let Some(number) = value.as_f64() else {
+            return Err(invalid(
+                param,
+                format!(
+                    "Invalid type for '{}': expected a decimal, but got {} instead.",
+                    param, value
+                ),
+            ));
+        };
+        if number > MAX_PENALTY {
+            return Err(invalid(
+                param,
+                format!(
+                    "Invalid '{}': decimal above maximum value. Expected a value <= {}, but got {} instead.",
+                    param, MAX_PENALTY, number
+                ),
+            ));
+        }
+        if number < -MAX_PENALTY {
+            return Err(invalid(
+                param,
+                format!(
+                    "Invalid '{}': decimal below minimum value. Expected a value >= {}, but got {} instead.",
+                    param, -MAX_PENALTY, number
+                ),
+            ));
+        }
+        penalty += number;
+    }
+
+    match request.get("logit_bias") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(biases)) => {
+            for (token, bias) in biases {
+                if token.parse::<u32>().is_err() {
+                    return Err(invalid(
+                        "logit_bias",
+                        format!(
+                            "Invalid key in 'logit_bias': {}. Only integer token ids are allowed.",
+                            token
+                        ),
+                    ));
+                }
+                if !bias
+                    .as_f64()
+                    .is_some_and(|bias| bias.abs() <= MAX_LOGIT_BIAS)
+                {
+                    return Err(invalid(
+                        "logit_bias",
+                        format!(
+                            "Invalid value for 'logit_bias': the bias of token {} must be a number between {} and {}, but got {} instead.",
+                            token, -MAX_LOGIT_BIAS, MAX_LOGIT_BIAS, bias
+                        ),
+                    ));
+                }
+            }
+        }
+        Some(value) => {
+            return Err(invalid(
+                "logit_bias",
+                format!(
+                    "Invalid type for 'logit_bias': expected an object, but got {} instead.",
+                    value
+                ),
+            ))
+        }
+    }
+    Ok(penalty)
+}
+
+/// Makes the content slightly less repetitive with a positive `penalty`, dropping some of the
+/// words already used, and slightly more with a negative one, using some of them again.
+fn penalize(content: &str, penalty: f64, rng: &mut impl Rng) -> String {
+    if penalty == 0.0 {
+        return content.to_string();
+    }
+    let chance = (penalty.abs() / (4.0 * MAX_PENALTY)).min(0.5);
+    let mut seen = HashSet::new();
+    let mut words = vec![];
+    for word in content.split_whitespace() {
+        let repeated = !seen.insert(word.to_lowercase());
+        if penalty > 0.0 && repeated && rng.gen_bool(chance) {
+            continue;
+        }
+        if penalty < 0.0 && !words.is_empty() && rng.gen_bool(chance) {
+            let used = words[rng.gen_range(0..words.len())];
+            words.push(used);
+        }
+        words.push(word);
+    }
+    words.join(" ")
+}
+
+/// Builds the stream deltas of a single choice: the role, the content pieces, or the refusal
+/// ones, the tool call pieces and, unless the stream is cut short, the finish reason.
+fn choice_deltas(
+    index: u32,
+    pieces: &[String],
+    refusal: bool,
+    top_logprobs: Option<usize>,
+    tool_call: Option<&ToolCall>,
+    truncated_tool_arguments: bool,
+    finish_reason: Option<&str>,
+) -> Vec<ChunkChoice> {
+    let chunk = |delta: ChoiceDelta| ChunkChoice {
+        index,
+        delta,
+        logprobs: None,
+        finish_reason: None,
+    };
+    let mut deltas = vec![chunk(ChoiceDelta {
+        role: Some("assistant".to_string()),
+        ..Default::default()
+    })];
+    for text in pieces {
+        let mut delta = chunk(if refusal {
+            ChoiceDelta {
+                refusal: Some(text.clone()),
+                ..Default::default()
+            }
+        } else {
+            ChoiceDelta {
+                content: Some(text.clone()),
+                ..Default::default()
             }
         });
+        // Each content chunk carries the log probabilities of its own tokens
+        delta.logprobs = top_logprobs.map(|top| logprobs([text.as_str()], refusal, top));
+        deltas.push(delta);
+    }
 
-        return (status_code, headers, Json(error_body)).into_response();
+    // Tool call chunks, the first one carries the function name
+    if let Some(call) = tool_call {
+        let mut arguments = call.function.arguments.chars().collect::<Vec<_>>();
+        // Stop sending the arguments halfway, leaving the JSON incomplete
+        if truncated_tool_arguments {
+            arguments.truncate(arguments.len() / 2);
+        }
+        let mut tool_deltas = vec![ToolCallDelta {
+            index: 0,
+            id: Some(call.id.clone()),
+            _type: Some("function".to_string()),
+            function: FunctionCallDelta {
+                name: Some(call.function.name.clone()),
+                arguments: String::new(),
+            },
+        }];
+        for piece in arguments.chunks(8) {
+            tool_deltas.push(ToolCallDelta {
+                index: 0,
+                id: None,
+                _type: None,
+                function: FunctionCallDelta {
+                    name: None,
+                    arguments: piece.iter().collect(),
+                },
+            });
+        }
+        for delta in tool_deltas {
+            deltas.push(chunk(ChoiceDelta {
+                tool_calls: Some(vec![delta]),
+                ..Default::default()
+            }));
+        }
     }
 
-    let response_length = state.get_response_length();
+    if let Some(finish_reason) = finish_reason {
+        deltas.push(ChunkChoice {
+            index,
+            delta: Default::default(),
+            logprobs: None,
+            finish_reason: Some(finish_reason.to_string()),
+        });
+    }
+    deltas
+}
 
-    if response_length == 0 {
-        let headers = state.get_rate_limit_headers();
-        return (StatusCode::NO_CONTENT, headers, Json(json!({}))).into_response();
+/// Turns the content deltas of a choice into deltas of the transcript of `audio`, the first one
+/// carrying the id of the audio, followed by a delta with its data before the choice finishes.
+fn speak(deltas: &mut Vec<ChunkChoice>, audio: &Value) {
+    for chunk in deltas.iter_mut() {
+        if let Some(transcript) = chunk.delta.content.take() {
+            chunk.delta.audio = Some(json!({"transcript": transcript}));
+        }
+    }
+    if let Some(first) = deltas
+        .iter_mut()
+        .find_map(|chunk| chunk.delta.audio.as_mut())
+    {
+        first["id"] = audio["id"].clone();
     }
+    if let Some(finish) = deltas
+        .iter()
+        .position(|chunk| chunk.finish_reason.is_some())
+    {
+        let chunk = ChunkChoice {
+            index: deltas[finish].index,
+            delta: ChoiceDelta {
+                audio: Some(json!({
+                    "id": audio["id"],
+                    "data": audio["data"],
+                    "expires_at": audio["expires_at"],
+                })),
+                ..Default::default()
+            },
+            logprobs: None,
+            finish_reason: None,
+        };
+        deltas.insert(finish, chunk);
+    }
+}
 
-    let content = state.generate_lorem_content(response_length);
+/// Merges the chunk sequences of the choices into a single stream following `order`. The
+/// chunks of each choice always keep their relative order.
+pub fn interleave<T>(sequences: Vec<Vec<T>>, order: ChoiceOrder) -> Vec<T> {
+    let total = sequences.iter().map(Vec::len).sum();
+    let mut merged = Vec::with_capacity(total);
+    match order {
+        ChoiceOrder::Grouped => merged.extend(sequences.into_iter().flatten()),
+        ChoiceOrder::RoundRobin => {
+            let mut iters = sequences
+                .into_iter()
+                .map(Vec::into_iter)
+                .collect::<Vec<_>>();
+            while merged.len() < total {
+                merged.extend(iters.iter_mut().filter_map(Iterator::next));
+            }
+        }
+        ChoiceOrder::Random => {
+            let mut queues = sequences
+                .into_iter()
+                .map(VecDeque::from)
+                .collect::<Vec<_>>();
+            let mut rng = rand::thread_rng();
+            while merged.len() < total {
+                let pending = (0..queues.len())
+                    .filter(|choice| !queues[*choice].is_empty())
+                    .collect::<Vec<_>>();
+                let choice = pending[rng.gen_range(0..pending.len())];
+                merged.extend(queues[choice].pop_front());
+            }
+        }
+    }
+    merged
+}
 
+pub async fn chat_completions(
+    state: State<ServerState>,
+    Json(payload): Json<ChatCompletionRequest>,
+) -> impl IntoResponse {
     let prompt_text = payload
         .messages
         .as_ref()
         .map(|msgs| serde_json::to_string(msgs).unwrap_or_default())
         .unwrap_or_default();
+    // Magic tokens only count in the new message, not in the ones replayed from the history
+    let last_user_message = payload
+        .messages
+        .iter()
+        .flatten()
+        .rev()
+        .find(|message| message["role"] == "user")
+        .map(message_text)
+        .unwrap_or_default();
+    let directives = match state.admit(&last_user_message).await {
+        Ok(directives) => directives,
+        Err(response) => return response,
+    };
+    if let Err(response) = state.moderate(payload._other["safety_identifier"].as_str()) {
+        return response;
+    }
 
-    let prompt_tokens = state.count_tokens(&prompt_text).unwrap_or(0);
-    let completion_tokens = state.count_tokens(&content).unwrap_or(0);
-    let total_tokens = prompt_tokens + completion_tokens;
+    let n = payload.n.unwrap_or(1);
+    if !(1..=MAX_CHOICES).contains(&n) {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!(
+                    "{} is not a valid value for 'n', it must be between 1 and {}",
+                    n, MAX_CHOICES
+                ),
+            )
+            .with_param("n"),
+        );
+    }
 
-    if state.check_token_limit_exceeded(total_tokens) {
-        let headers = state.get_rate_limit_headers();
-        let error_body = json!({
-            "error": {
-                "message": "You have exceeded your token quota.",
-                "type": "rate_limit_error",
-                "code": "rate_limit_exceeded"
-            }
-        });
-        return (StatusCode::TOO_MANY_REQUESTS, headers, Json(error_body)).into_response();
+    // `max_completion_tokens`, or the older `max_tokens`, caps the content of each choice
+    let max_tokens = match (
+        token_cap(&payload._other, "max_completion_tokens", 1),
+        token_cap(&payload._other, "max_tokens", 1),
+    ) {
+        (Err(error), _) | (_, Err(error)) => return state.error_response(error),
+        (Ok(cap), Ok(legacy_cap)) => cap.or(legacy_cap),
+    };
+    let top_logprobs = match requested_logprobs(&payload._other) {
+        Ok(top_logprobs) => top_logprobs,
+        Err(error) => return state.error_response(error),
+    };
+    let seed = match seed(&payload._other) {
+        Ok(seed) => seed,
+        Err(error) => return state.error_response(error),
+    };
+    let penalty = match repetition_penalty(&payload._other) {
+        Ok(penalty) => penalty,
+        Err(error) => return state.error_response(error),
+    };
+    let audio_format = match requested_audio(&payload._other) {
+        Ok(format) => format,
+        Err(error) => return state.error_response(error),
+    };
+
+    // Metadata is only kept along with stored completions
+    let metadata = &payload._other["metadata"];
+    if let Err(error) = check_metadata(metadata) {
+        return state.error_response(error);
+    }
+    if !metadata.is_null() && payload.store != Some(true) {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "The 'metadata' parameter is only allowed when 'store' is enabled.",
+            )
+            .with_param("metadata"),
+        );
     }
-    state.add_token_usage(total_tokens);
 
-    let stream_response = payload.stream.unwrap_or(false);
-    if stream_response {
-        let id = format!("chatcmpl-{}", rand::thread_rng().gen::<u32>());
-        let created = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("should be able to get duration")
-            .as_secs();
-        let model = payload
-            .model
-            .clone()
-            .unwrap_or_else(|| "gpt-3.5-turbo".to_string());
-        let words = content
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect::<Vec<_>>();
+    // JSON mode answers with a JSON object instead of prose, as long as the messages ask for it,
+    // and structured outputs with a value of the given schema
+    let response_format = &payload._other["response_format"];
+    let json_mode = response_format["type"] == "json_object";
+    if response_format["type"] == "json_schema" && !response_format["json_schema"].is_object() {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "Missing required parameter: 'response_format.json_schema'.",
+            )
+            .with_param("response_format.json_schema"),
+        );
+    }
+    let schema = response_format["json_schema"]
+        .get("schema")
+        .filter(|_| response_format["type"] == "json_schema");
+    if json_mode && !prompt_text.to_lowercase().contains("json") {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "'messages' must contain the word 'json' in some form, to use 'response_format' of type 'json_object'.",
+            )
+            .with_param("messages"),
+        );
+    }
 
-        let mut events = vec![];
+    let prompt_tokens = state
+        .count_message_tokens(payload.messages.as_deref().unwrap_or_default())
+        .unwrap_or(0);
+    if let Some(error_code) = state.apply_size_rules(prompt_tokens).await {
+        return state.error_response(ApiError::simulated(error_code));
+    }
 
-        // 1. First chunk with role
-        let first_chunk = ChatCompletionChunk {
-            id: id.clone(),
-            object: "chat.completion.chunk".to_string(),
-            created,
-            model: model.clone(),
-            choices: vec![ChunkChoice {
-                index: 0,
-                delta: ChoiceDelta {
-                    role: Some("assistant".to_string()),
-                    content: None,
-                },
-                finish_reason: None,
-            }],
-            usage: None,
+    // Only the tools `tool_choice` allows are called
+    let allowed_tools =
+        match tools::allowed_tools(payload.tools.as_ref(), payload._other.get("tool_choice")) {
+            Ok(allowed_tools) => allowed_tools,
+            Err(error) => return state.error_response(error),
         };
-        events.push(Ok::<_, Infallible>(
-            Event::default().data(serde_json::to_string(&first_chunk).unwrap()),
-        ));
+    let make_tool_call = || {
+        let (name, parameters) = tools::pick_function(Some(&allowed_tools));
+        ToolCall {
+            id: format!("call_{:x}", rand::thread_rng().gen::<u64>()),
+            _type: "function".to_string(),
+            function: FunctionCall {
+                name,
+                arguments: tools::generate_arguments(parameters.as_ref()),
+            },
+        }
+    };
 
-        // 2. Content chunks
-        for word in words {
-            let chunk = ChatCompletionChunk {
-                id: id.clone(),
-                object: "chat.completion.chunk".to_string(),
-                created,
-                model: model.clone(),
-                choices: vec![ChunkChoice {
-                    index: 0,
-                    delta: ChoiceDelta {
-                        role: None,
-                        content: Some(format!("{} ", word)),
+    // Seeded requests get the same content for the same model and prompt, or for the same body
+    // with `--content-mode hashed`, always from the current snapshot so that the
+    // `system_fingerprint` is stable too
+    let mut seeded = seed
+        .map(|seed| {
+            seeded_rng(
+                seed,
+                payload.model.as_deref().unwrap_or_default(),
+                &prompt_text,
+            )
+        })
+        .or_else(|| state.hashed_rng(&payload));
+    let snapshot = match seeded {
+        Some(_) => Snapshot::Current,
+        None => state.pick_snapshot(),
+    };
+    tokio::time::sleep(snapshot.extra_latency()).await;
+
+    let model = payload
+        .model
+        .clone()
+        .unwrap_or_else(|| "gpt-3.5-turbo".to_string());
+    let response_length = match &mut seeded {
+        Some(rng) => state.response_length_with(&model, rng),
+        None => state.get_response_length(&model),
+    };
+
+    // A matching fixture answers with its content or tool call, the rest is generated
+    let messages = payload.messages.as_deref().unwrap_or_default();
+    let fixture = state.fixture(
+        &messages
+            .iter()
+            .map(message_text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    let fixture_call = fixture
+        .as_ref()
+        .and_then(|fixture| fixture.tool_call.clone());
+    let fixture_content = fixture.as_ref().and_then(|fixture| fixture.content.clone());
+    let tool_call_answer = directives.tool_call || fixture_call.is_some();
+
+    if response_length == 0 && !tool_call_answer && fixture_content.is_none() {
+        let headers = state.get_rate_limit_headers();
+        return (StatusCode::NO_CONTENT, headers, Json(json!({}))).into_response();
+    }
+
+    let conversation = payload
+        ._other
+        .get("conversation_id")
+        .or_else(|| payload._other.get("user"))
+        .and_then(Value::as_str);
+    let echo = state.echo(&last_user_message);
+    let id = format!("chatcmpl-{}", rand::thread_rng().gen::<u32>());
+    // The conversation moves forward once per request, however many choices it asks for
+    let preamble = if tool_call_answer
+        || fixture_content.is_some()
+        || json_mode
+        || schema.is_some()
+        || echo.is_some()
+    {
+        String::new()
+    } else {
+        state.conversation_preamble(conversation)
+    };
+    // The content or the tool call of each choice, unless the model refuses to answer
+    let refusal = (!tool_call_answer && fixture.is_none())
+        .then(|| state.refusal())
+        .flatten();
+    let mut generated = (0..n)
+        .map(|_| {
+            if let Some(call) = &fixture_call {
+                let call = ToolCall {
+                    id: format!("call_{:x}", rand::thread_rng().gen::<u64>()),
+                    _type: "function".to_string(),
+                    function: FunctionCall {
+                        name: call.name.clone(),
+                        arguments: call.arguments(),
                     },
-                    finish_reason: None,
-                }],
-                usage: None,
-            };
-            events.push(Ok(
-                Event::default().data(serde_json::to_string(&chunk).unwrap())
-            ));
+                };
+                (fixture_content.clone().unwrap_or_default(), Some(call))
+            } else if let Some(content) = &fixture_content {
+                (content.clone(), None)
+            } else if directives.tool_call {
+                (String::new(), Some(make_tool_call()))
+            } else if let Some(refusal) = refusal {
+                (refusal.to_string(), None)
+            } else if let Some(schema) = schema {
+                (tools::generate_value(schema, schema).to_string(), None)
+            } else if json_mode {
+                (tools::generate_json_object(response_length), None)
+            } else if let Some(echo) = &echo {
+                (echo.clone(), None)
+            } else {
+                let content = match &mut seeded {
+                    Some(rng) => {
+                        let content =
+                            state.generate_seeded_content(response_length, &prompt_text, rng);
+                        penalize(&content, penalty, rng)
+                    }
+                    None => {
+                        let content = state.generate_lorem_content(response_length, &prompt_text);
+                        penalize(&content, penalty, &mut rand::thread_rng())
+                    }
+                };
+                let content = format!("{}{}", preamble, snapshot.restyle(content));
+                let content =
+                    state.render_template(content, &model, &last_user_message, &id, prompt_tokens);
+                (content, None)
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut truncated = vec![false; generated.len()];
+    if let Some(max_tokens) = max_tokens {
+        for ((content, _), truncated) in generated
+            .iter_mut()
+            .zip(truncated.iter_mut())
+            .filter(|((_, tool_call), _)| tool_call.is_none())
+        {
+            if let Some(cut) = state.truncate_to_tokens(content, max_tokens) {
+                *content = cut;
+                *truncated = true;
+            }
         }
+    }
 
-        // 3. Final chunk with finish_reason
-        let final_chunk = ChatCompletionChunk {
-            id: id.clone(),
-            object: "chat.completion.chunk".to_string(),
-            created,
-            model: model.clone(),
-            choices: vec![ChunkChoice {
-                index: 0,
-                delta: Default::default(),
-                finish_reason: Some("stop".to_string()),
-            }],
-            usage: Some(Usage {
-                prompt_tokens,
-                completion_tokens,
-                total_tokens,
-            }),
-        };
-        events.push(Ok(
-            Event::default().data(serde_json::to_string(&final_chunk).unwrap())
-        ));
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs();
+
+    // Audio output reads the content aloud, which becomes its transcript
+    let audios = generated
+        .iter()
+        .map(|(content, tool_call)| {
+            let format = audio_format
+                .as_deref()
+                .filter(|_| tool_call.is_none() && refusal.is_none())?;
+            let duration = state
+                .args()
+                .audio_duration
+                .unwrap_or_else(|| audio::spoken_duration(content, 1.0));
+            let audio = json!({
+                "id": format!("audio_{:x}", rand::thread_rng().gen::<u64>()),
+                "expires_at": created + AUDIO_EXPIRATION_SECS,
+                "data": STANDARD.encode(audio::encode(format, duration)),
+                "transcript": content,
+            });
+            Some((
+                audio,
+                (duration.as_secs_f64() * AUDIO_TOKENS_PER_SECOND).ceil() as u32,
+            ))
+        })
+        .collect::<Vec<_>>();
+    let audio_tokens = audios
+        .iter()
+        .flatten()
+        .map(|(_, tokens)| tokens)
+        .sum::<u32>();
+    let completion_tokens_details = audio_format.is_some().then(|| {
+        json!({
+            "audio_tokens": audio_tokens,
+            "reasoning_tokens": 0,
+            "accepted_prediction_tokens": 0,
+            "rejected_prediction_tokens": 0,
+        })
+    });
+
+    let completion_tokens = audio_tokens
+        + generated
+            .iter()
+            .map(|(content, tool_call)| match tool_call {
+                Some(call) => state
+                    .count_tokens(&format!(
+                        "{}{}",
+                        call.function.name, call.function.arguments
+                    ))
+                    .unwrap_or(0),
+                None => state.count_tokens(content).unwrap_or(0),
+            })
+            .sum::<u32>();
+    let total_tokens = prompt_tokens + completion_tokens;
 
-        // 4. Done message
-        events.push(Ok(Event::default().data("[DONE]")));
+    let stream_response = payload.stream.unwrap_or(false);
 
-        let stream = stream::iter(events);
+    // When simulating TPM exhaustion mid-stream, the stream starts as long as the prompt fits
+    // and the completion is cut as soon as the remaining budget runs out.
+    let token_budget = (stream_response && state.args().midstream_tpm_exhaustion)
+        .then(|| state.remaining_token_budget().saturating_sub(prompt_tokens));
+    let cutoff = token_budget.filter(|budget| *budget < completion_tokens);
 
-        return Sse::new(stream).into_response();
+    let requested_tokens = match token_budget {
+        Some(_) => prompt_tokens,
+        None => total_tokens,
+    };
+    if state.check_token_limit_exceeded(requested_tokens) {
+        return state.error_response(state.token_limit_error(requested_tokens));
     }
+    state.add_token_usage(prompt_tokens + cutoff.unwrap_or(completion_tokens));
+    let cost_headers =
+        state.record_cost(&model, prompt_tokens, cutoff.unwrap_or(completion_tokens));
 
-    let response = ChatCompletionResponse {
-        id: format!("chatcmpl-{}", rand::thread_rng().gen::<u32>()),
+    let finish_reason = |index: usize| {
+        if truncated[index] {
+            "length"
+        } else if let Some(reason) = fixture.as_ref().and_then(|f| f.finish_reason.as_deref()) {
+            reason
+        } else if tool_call_answer {
+            "tool_calls"
+        } else {
+            "stop"
+        }
+    };
+
+    let completion = ChatCompletionResponse {
+        id: id.clone(),
         object: "chat.completion".to_string(),
-        created: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("should be able to get duration")
-            .as_secs(),
-        model: payload.model.unwrap_or_else(|| "gpt-3.5-turbo".to_string()),
-        choices: vec![Choice {
-            index: 0,
-            message: Message {
-                role: "assistant".to_string(),
-                content,
-            },
-            finish_reason: "stop".to_string(),
-        }],
+        created,
+        model: model.clone(),
+        system_fingerprint: snapshot.fingerprint().to_string(),
+        choices: generated
+            .iter()
+            .enumerate()
+            .map(|(index, (content, tool_call))| Choice {
+                index: index as u32,
+                message: Message {
+                    role: "assistant".to_string(),
+                    content: (tool_call.is_none() && refusal.is_none() && audios[index].is_none())
+                        .then(|| content.clone()),
+                    refusal: refusal.map(str::to_string),
+                    tool_calls: tool_call.clone().map(|call| vec![call]),
+                    audio: audios[index].as_ref().map(|(audio, _)| audio.clone()),
+                },
+                logprobs: top_logprobs.map(|top| {
+                    logprobs(
+                        content.split_inclusive(' ').filter(|_| tool_call.is_none()),
+                        refusal.is_some(),
+                        top,
+                    )
+                }),
+                finish_reason: finish_reason(index).to_string(),
+            })
+            .collect(),
         usage: Usage {
             prompt_tokens,
             completion_tokens,
             total_tokens,
+            completion_tokens_details: completion_tokens_details.clone(),
         },
     };
 
+    // Streams cut by the token budget never complete, so they aren't stored
+    if payload.store == Some(true) && cutoff.is_none() {
+        let mut stored = json!(completion);
+        stored["metadata"] = payload
+            ._other
+            .get("metadata")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        state.stored_completions().lock().unwrap().push(stored);
+    }
+
+    if stream_response {
+        let sequences = generated
+            .iter()
+            .enumerate()
+            .map(|(index, (content, tool_call))| {
+                let mut pieces = state.stream_pieces(content, language::stream_words);
+                if let Some(allowed_tokens) = cutoff {
+                    pieces.truncate(
+                        pieces.len() * allowed_tokens as usize / completion_tokens as usize,
+                    );
+                }
+                let mut deltas = choice_deltas(
+                    index as u32,
+                    &pieces,
+                    refusal.is_some(),
+                    top_logprobs,
+                    // The token budget ran out, the stream ends with an error instead
+                    tool_call.as_ref().filter(|_| cutoff.is_none()),
+                    directives.truncated_tool_arguments,
+                    cutoff.is_none().then_some(finish_reason(index)),
+                );
+                if let Some((audio, _)) = &audios[index] {
+                    speak(&mut deltas, audio);
+                }
+                deltas
+            })
+            .collect::<Vec<_>>();
+
+        let deltas = interleave(sequences, state.args().choice_order);
+        let last = deltas.len() - 1;
+        let chunk = |choices: Vec<ChunkChoice>, usage: Option<Usage>| {
+            let chunk = ChatCompletionChunk {
+                id: id.clone(),
+                object: "chat.completion.chunk".to_string(),
+                created,
+                model: model.clone(),
+                system_fingerprint: snapshot.fingerprint().to_string(),
+                choices,
+                usage,
+            };
+            Ok::<_, Infallible>(Event::default().data(serde_json::to_string(&chunk).unwrap()))
+        };
+
+        let mut events = vec![];
+        let mut streamed_tokens = 0;
+        for (position, choice) in deltas.into_iter().enumerate() {
+            streamed_tokens += choice
+                .delta
+                .content
+                .iter()
+                .chain(choice.delta.refusal.iter())
+                .chain(
+                    choice
+                        .delta
+                        .tool_calls
+                        .iter()
+                        .flatten()
+                        .map(|call| &call.function.arguments),
+                )
+                .map(|text| state.count_tokens(text).unwrap_or(0))
+                .sum::<u32>();
+            events.push(chunk(vec![choice], None));
+
+            // An extra chunk without choices reports the usage so far
+            if let Some(every) = state.args().continuous_usage_every {
+                if every > 0 && (position + 1) % every == 0 && position != last {
+                    let completion_tokens = streamed_tokens.min(completion_tokens);
+                    events.push(chunk(
+                        vec![],
+                        Some(Usage {
+                            prompt_tokens,
+                            completion_tokens,
+                            total_tokens: prompt_tokens + completion_tokens,
+                            completion_tokens_details: None,
+                        }),
+                    ));
+                }
+            }
+        }
+
+        if cutoff.is_some() {
+            let error = ApiError::rate_limit_exceeded(
+                "Rate limit reached for tokens per minute while generating the response.",
+            );
+            let error = state.noisy(error);
+            events.push(Ok(Event::default().data(error.body().to_string())));
+        } else {
+            // A last chunk without choices carries the usage of the whole completion, if asked
+            if include_usage(&payload._other) {
+                events.push(chunk(
+                    vec![],
+                    Some(Usage {
+                        prompt_tokens,
+                        completion_tokens,
+                        total_tokens,
+                        completion_tokens_details,
+                    }),
+                ));
+            }
+            events.push(Ok(Event::default().data("[DONE]")));
+        }
+
+        let stream = resume::store(&state, paced(state.0.clone(), events));
+        return (cost_headers, Sse::new(stream)).into_response();
+    }
+
+    state.simulate_generation_time(completion_tokens).await;
+
     let headers = state.get_rate_limit_headers();
-    (headers, Json(json!(response))).into_response()
+    (headers, cost_headers, Json(json!(completion))).into_response()
+}
+
+/// Returns a chat completion created with `store: true`.
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> impl IntoResponse {
+    let completion = state
+        .stored_completions()
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|completion| completion["id"] == id.as_str())
+        .cloned();
+    match completion {
+        Some(completion) => Json(completion).into_response(),
+        None => state.error_response(
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                format!("Chat completion with id '{}' not found.", id),
+            )
+            .with_param("completion_id"),
+        ),
+    }
 }
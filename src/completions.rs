@@ -0,0 +1,249 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{sse::Event, IntoResponse, Sse},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chat_completions::{include_usage, paced, seed, Usage};
+use crate::errors::ApiError;
+use crate::language;
+use crate::resume;
+use crate::server_state::ServerState;
+use crate::snapshot::{seeded_rng, Snapshot};
+
+#[derive(Deserialize, Serialize)]
+pub struct CompletionRequest {
+    pub prompt: Option<Value>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(flatten)]
+    pub _other: Value,
+}
+
+#[derive(Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub system_fingerprint: String,
+    pub choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Serialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: u32,
+    pub logprobs: Option<Value>,
+    pub finish_reason: Option<String>,
+}
+
+/// The legacy prompt can be a string or an array of strings.
+fn prompt_text(prompt: Option<&Value>) -> String {
+    match prompt {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+pub async fn completions(
+    state: State<ServerState>,
+    Json(payload): Json<CompletionRequest>,
+) -> impl IntoResponse {
+    let prompt_text = prompt_text(payload.prompt.as_ref());
+    if let Err(response) = state.admit(&prompt_text).await {
+        return response;
+    }
+
+    let prompt_tokens = state.count_tokens(&prompt_text).unwrap_or(0);
+    if let Some(error_code) = state.apply_size_rules(prompt_tokens).await {
+        return state.error_response(ApiError::simulated(error_code));
+    }
+
+    // Seeded requests get the same content for the same model and prompt, or for the same body
+    // with `--content-mode hashed`
+    let mut seeded = match seed(&payload._other) {
+        Ok(seed) => seed
+            .map(|seed| {
+                seeded_rng(
+                    seed,
+                    payload.model.as_deref().unwrap_or_default(),
+                    &prompt_text,
+                )
+            })
+            .or_else(|| state.hashed_rng(&payload)),
+        Err(error) => return state.error_response(error),
+    };
+    let snapshot = match seeded {
+        Some(_) => Snapshot::Current,
+        None => state.pick_snapshot(),
+    };
+    tokio::time::sleep(snapshot.extra_latency()).await;
+
+    let model = payload
+        .model
+        .clone()
+        .unwrap_or_else(|| "gpt-3.5-turbo-instruct".to_string());
+    let response_length = match &mut seeded {
+        Some(rng) => state.response_length_with(&model, rng),
+        None => state.get_response_length(&model),
+    };
+
+    // A matching fixture answers with its content and finish reason
+    let fixture = state.fixture(&prompt_text);
+    let fixture_content = fixture.as_ref().and_then(|fixture| fixture.content.clone());
+    let finish_reason = fixture
+        .as_ref()
+        .and_then(|fixture| fixture.finish_reason.clone())
+        .unwrap_or_else(|| "stop".to_string());
+
+    if response_length == 0 && fixture_content.is_none() {
+        let headers = state.get_rate_limit_headers();
+        return (StatusCode::NO_CONTENT, headers, Json(json!({}))).into_response();
+    }
+
+    let id = format!("cmpl-{}", rand::thread_rng().gen::<u32>());
+    let content = match (fixture_content, state.echo(&prompt_text)) {
+        (Some(content), _) | (None, Some(content)) => content,
+        (None, None) => {
+            let content = snapshot.restyle(match &mut seeded {
+                Some(rng) => state.generate_seeded_content(response_length, &prompt_text, rng),
+                None => state.generate_lorem_content(response_length, &prompt_text),
+            });
+            state.render_template(content, &model, &prompt_text, &id, prompt_tokens)
+        }
+    };
+    let completion_tokens = state.count_tokens(&content).unwrap_or(0);
+    let total_tokens = prompt_tokens + completion_tokens;
+
+    let stream_response = payload.stream.unwrap_or(false);
+
+    // When simulating TPM exhaustion mid-stream, the stream starts as long as the prompt fits
+    // and the completion is cut as soon as the remaining budget runs out.
+    let token_budget = (stream_response && state.args().midstream_tpm_exhaustion)
+        .then(|| state.remaining_token_budget().saturating_sub(prompt_tokens));
+    let cutoff = token_budget.filter(|budget| *budget < completion_tokens);
+
+    let requested_tokens = match token_budget {
+        Some(_) => prompt_tokens,
+        None => total_tokens,
+    };
+    if state.check_token_limit_exceeded(requested_tokens) {
+        return state.error_response(state.token_limit_error(requested_tokens));
+    }
+    state.add_token_usage(prompt_tokens + cutoff.unwrap_or(completion_tokens));
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs();
+    let cost_headers =
+        state.record_cost(&model, prompt_tokens, cutoff.unwrap_or(completion_tokens));
+
+    if stream_response {
+        let mut pieces = state.stream_pieces(&content, language::stream_words);
+        if let Some(allowed_tokens) = cutoff {
+            pieces.truncate(pieces.len() * allowed_tokens as usize / completion_tokens as usize);
+        }
+
+        let chunk = |text: String, finish_reason: Option<&str>, usage: Option<Usage>| {
+            let chunk = CompletionResponse {
+                id: id.clone(),
+                object: "text_completion".to_string(),
+                created,
+                model: model.clone(),
+                system_fingerprint: snapshot.fingerprint().to_string(),
+                choices: vec![CompletionChoice {
+                    text,
+                    index: 0,
+                    logprobs: None,
+                    finish_reason: finish_reason.map(String::from),
+                }],
+                usage,
+            };
+            Ok::<_, Infallible>(Event::default().data(serde_json::to_string(&chunk).unwrap()))
+        };
+
+        let mut events = pieces
+            .into_iter()
+            .map(|piece| chunk(piece, None, None))
+            .collect::<Vec<_>>();
+
+        if cutoff.is_some() {
+            // The token budget ran out, end the stream with an error
+            let error = ApiError::rate_limit_exceeded(
+                "Rate limit reached for tokens per minute while generating the response.",
+            );
+            let error = state.noisy(error);
+            events.push(Ok(Event::default().data(error.body().to_string())));
+        } else {
+            events.push(chunk(String::new(), Some(finish_reason.as_str()), None));
+            // A last chunk without choices carries the usage, if asked
+            if include_usage(&payload._other) {
+                let usage_chunk = CompletionResponse {
+                    id: id.clone(),
+                    object: "text_completion".to_string(),
+                    created,
+                    model: model.clone(),
+                    system_fingerprint: snapshot.fingerprint().to_string(),
+                    choices: vec![],
+                    usage: Some(Usage {
+                        prompt_tokens,
+                        completion_tokens,
+                        total_tokens,
+                        completion_tokens_details: None,
+                    }),
+                };
+                events.push(Ok(
+                    Event::default().data(serde_json::to_string(&usage_chunk).unwrap())
+                ));
+            }
+            events.push(Ok(Event::default().data("[DONE]")));
+        }
+
+        let stream = resume::store(&state, paced(state.0.clone(), events));
+        return (cost_headers, Sse::new(stream)).into_response();
+    }
+
+    state.simulate_generation_time(completion_tokens).await;
+
+    let response = CompletionResponse {
+        id,
+        object: "text_completion".to_string(),
+        created,
+        model,
+        system_fingerprint: snapshot.fingerprint().to_string(),
+        choices: vec![CompletionChoice {
+            text: content,
+            index: 0,
+            logprobs: None,
+            finish_reason: Some(finish_reason),
+        }],
+        usage: Some(Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            completion_tokens_details: None,
+        }),
+    };
+
+    let headers = state.get_rate_limit_headers();
+    (headers, cost_headers, Json(json!(response))).into_response()
+}
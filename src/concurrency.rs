@@ -0,0 +1,109 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::auth::api_key;
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+/// A streaming request in progress for an API key, released when the response body is dropped.
+pub struct StreamSlot {
+    streams: Arc<Mutex<HashMap<String, u32>>>,
+    key: String,
+}
+
+impl StreamSlot {
+    /// Takes a slot for `key`, unless it already has `max` streams in progress.
+    pub fn acquire(streams: Arc<Mutex<HashMap<String, u32>>>, key: &str, max: u32) -> Option<Self> {
+        {
+            let mut active = streams.lock().unwrap();
+            let count = active.entry(key.to_string()).or_default();
+            if *count >= max {
+                return None;
+            }
+            *count += 1;
+        }
+        Some(StreamSlot {
+            streams,
+            key: key.to_string(),
+        })
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut active = self.streams.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.key) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Rejects streaming requests exceeding `--max-streams-per-key` concurrent streams for the same
+/// API key with a 429.
+pub async fn limit_streams(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(max) = state.args().max_streams_per_key else {
+        return next.run(req).await;
+    };
+
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+    let is_stream = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|body| body.get("stream").and_then(Value::as_bool))
+        .unwrap_or(false);
+    if !is_stream {
+        return next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await;
+    }
+
+    let key = api_key(&parts.headers).unwrap_or_default().to_string();
+    let Some(slot) = StreamSlot::acquire(state.active_streams(), &key, max) else {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                format!(
+                    "Too many concurrent streams, the limit is {} per API key.",
+                    max
+                ),
+            )
+            .with_code("concurrent_streams_exceeded"),
+        );
+    };
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &slot;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
@@ -0,0 +1,204 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::Args;
+
+/// Behaviour loaded from the file passed with `--config`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named sets of overrides for the command line behaviour.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Path prefixes (e.g. `eu` for `/eu/v1/...`) mapped to profile names.
+    #[serde(default)]
+    pub regions: BTreeMap<String, String>,
+    /// Latency and errors injected on requests with large prompts.
+    #[serde(default)]
+    pub size_rules: Vec<SizeRule>,
+    /// Model catalog served by `/v1/models`, replacing the one passed with `--models`.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Profiles applied during daily wall-clock windows.
+    #[serde(default)]
+    pub schedule: Vec<ScheduleEntry>,
+    /// Per-model token prices used to estimate the cost of each request.
+    #[serde(default)]
+    pub prices: BTreeMap<String, Price>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let config: Config = serde_json::from_str(&content)?;
+
+        for (region, profile) in &config.regions {
+            if !config.profiles.contains_key(profile) {
+                anyhow::bail!("Region '{}' uses unknown profile '{}'", region, profile);
+            }
+        }
+        for entry in &config.schedule {
+            if !config.profiles.contains_key(&entry.profile) {
+                anyhow::bail!("Schedule uses unknown profile '{}'", entry.profile);
+            }
+            if entry.window().is_none() {
+                anyhow::bail!(
+                    "Invalid schedule window '{}'-'{}', use the 'HH:MM' format",
+                    entry.from,
+                    entry.to
+                );
+            }
+        }
+        for (model, price) in &config.prices {
+            if price.input < 0.0 || price.output < 0.0 {
+                anyhow::bail!("Prices for model '{}' can't be negative", model);
+            }
+        }
+        for rule in &config.size_rules {
+            if let Some(latency) = &rule.extra_latency {
+                humantime::parse_duration(latency).map_err(|err| {
+                    anyhow::anyhow!("Invalid extra_latency '{}': {}", latency, err)
+                })?;
+            }
+            if let Some(code) = rule.error_code {
+                if !crate::errors::is_error_status(code) {
+                    anyhow::bail!("Invalid error_code '{}', not an HTTP error status", code);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Overrides for the behaviour configured from the command line.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_length: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpm: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tpm: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slowdown: Option<String>,
+}
+
+impl Profile {
+    pub fn apply(&self, args: &Args) -> Args {
+        let mut args = args.clone();
+        if let Some(response_length) = &self.response_length {
+            args.response_length = Some(response_length.clone());
+        }
+        if let Some(error_code) = self.error_code {
+            args.error_code = Some(error_code);
+        }
+        if let Some(error_rate) = self.error_rate {
+            args.error_rate = Some(error_rate);
+        }
+        if let Some(rpm) = self.rpm {
+            args.rpm = rpm;
+        }
+        if let Some(tpm) = self.tpm {
+            args.tpm = tpm;
+        }
+        if let Some(slowdown) = &self.slowdown {
+            args.slowdown = Some(slowdown.clone());
+        }
+        args
+    }
+
+    /// The command line flags setting the same behaviour.
+    pub fn flags(&self) -> Vec<String> {
+        [
+            ("--response-length", self.response_length.clone()),
+            ("--error-code", self.error_code.map(|code| code.to_string())),
+            ("--error-rate", self.error_rate.map(|rate| rate.to_string())),
+            ("--rpm", self.rpm.map(|rpm| rpm.to_string())),
+            ("--tpm", self.tpm.map(|tpm| tpm.to_string())),
+            ("--slowdown", self.slowdown.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| Some([flag.to_string(), value?]))
+        .flatten()
+        .collect()
+    }
+}
+
+/// Behaviour for requests whose prompt is at least `min_prompt_tokens` long, e.g.
+/// `{"min_prompt_tokens": 50000, "extra_latency": "2s", "error_code": 500, "error_rate": 5}`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SizeRule {
+    pub min_prompt_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_latency: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<u32>,
+}
+
+impl SizeRule {
+    pub fn extra_latency(&self) -> Duration {
+        self.extra_latency
+            .as_deref()
+            .and_then(|latency| humantime::parse_duration(latency).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Prices in USD per million tokens, e.g. `{"input": 2.5, "output": 10.0}`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        (prompt_tokens as f64 * self.input + completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// A profile applied every day between two wall-clock times in the local timezone, e.g.
+/// `{"from": "14:00", "to": "15:00", "profile": "degraded"}`. Windows can span midnight.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    pub from: String,
+    pub to: String,
+    pub profile: String,
+}
+
+fn minute_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+impl ScheduleEntry {
+    /// Returns the start and the end of the window in minutes since midnight.
+    pub fn window(&self) -> Option<(u32, u32)> {
+        Some((minute_of_day(&self.from)?, minute_of_day(&self.to)?))
+    }
+
+    pub fn is_active_at(&self, minute: u32) -> bool {
+        match self.window() {
+            Some((from, to)) if from <= to => from <= minute && minute < to,
+            Some((from, to)) => minute >= from || minute < to,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header::CONNECTION, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+    serve::IncomingStream,
+};
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::Service;
+
+use crate::server_state::ServerState;
+
+/// Number of responses sent over a connection, shared by the requests it carries.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionResponses(Arc<AtomicU32>);
+
+/// Forces clients to re-establish connections by answering with `Connection: close` once a
+/// connection served `--max-requests-per-connection` responses.
+pub async fn limit_requests(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(max) = state.args().max_requests_per_connection else {
+        return next.run(req).await;
+    };
+    let responses = req.extensions().get::<ConnectionResponses>().cloned();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+
+    let mut response = next.run(req).await;
+
+    if let Some(ConnectionResponses(count)) = responses {
+        if count.fetch_add(1, Ordering::Relaxed) + 1 >= max {
+            if let Some(peer) = peer {
+                log::debug!("Closing connection with {}", peer);
+            }
+            response
+                .headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+    }
+
+    response
+}
+
+/// Wraps a make service to give each connection its own [`ConnectionResponses`] counter, which
+/// goes away with the connection.
+#[derive(Clone)]
+pub struct CountResponses<M> {
+    inner: M,
+}
+
+impl<M> CountResponses<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M> Service<IncomingStream<'a>> for CountResponses<M>
+where
+    M: Service<IncomingStream<'a>, Error = Infallible>,
+    M::Response: Send + 'static,
+    M::Future: Send + 'static,
+{
+    type Response = WithConnectionResponses<M::Response>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, stream: IncomingStream<'a>) -> Self::Future {
+        let future = self.inner.call(stream);
+        Box::pin(async move {
+            let inner = future.await?;
+            Ok(WithConnectionResponses {
+                inner,
+                responses: ConnectionResponses::default(),
+            })
+        })
+    }
+}
+
+/// The service of a connection, adding its response counter to each request.
+#[derive(Clone)]
+pub struct WithConnectionResponses<S> {
+    inner: S,
+    responses: ConnectionResponses,
+}
+
+impl<S, B> Service<Request<B>> for WithConnectionResponses<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.responses.clone());
+        self.inner.call(req)
+    }
+}
+
+/// Wraps a make service to delay serving each new connection. The kernel completes the TCP
+/// handshake as soon as the socket listens, so clients connect right away and then wait for the
+/// first response bytes; the server takes the next connection only once the service for the
+/// current one is ready.
+#[derive(Clone)]
+pub struct AcceptDelay<M> {
+    inner: M,
+    delay: Duration,
+}
+
+impl<M> AcceptDelay<M> {
+    pub fn new(inner: M, delay: Duration) -> Self {
+        Self { inner, delay }
+    }
+}
+
+impl<'a, M> Service<IncomingStream<'a>> for AcceptDelay<M>
+where
+    M: Service<IncomingStream<'a>, Error = Infallible>,
+    M::Response: Send + 'static,
+    M::Future: Send + 'static,
+{
+    type Response = M::Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<M::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, stream: IncomingStream<'a>) -> Self::Future {
+        log::debug!(
+            "Delaying connection from {} by {:?}",
+            stream.remote_addr(),
+            self.delay
+        );
+        let delay = self.delay;
+        let future = self.inner.call(stream);
+        Box::pin(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            future.await
+        })
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use rand::Rng;
+use std::path::{Path, PathBuf};
+
+/// Paragraphs of real text, e.g. docs, code or markdown, the generated content is cut from.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    paragraphs: Vec<String>,
+}
+
+impl Corpus {
+    /// Loads a text file, or every text file under a directory in path order. Hidden files and
+    /// files that aren't valid UTF-8 are skipped.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let texts = if path.is_dir() {
+            let mut files = vec![];
+            collect_files(path, &mut files)?;
+            files.sort();
+            files
+                .iter()
+                .filter_map(|file| std::fs::read_to_string(file).ok())
+                .collect::<Vec<_>>()
+        } else {
+            vec![std::fs::read_to_string(path)?]
+        };
+        Self::from_texts(texts.iter().map(String::as_str))
+            .ok_or_else(|| anyhow::anyhow!("Invalid corpus {}: no text found", path.display()))
+    }
+
+    /// Splits `texts` into paragraphs, or returns `None` when there's no text at all.
+    pub fn from_texts<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let paragraphs = texts
+            .into_iter()
+            .flat_map(|text| text.split("\n\n"))
+            .map(|paragraph| paragraph.trim_matches('\n').trim_end())
+            .filter(|paragraph| !paragraph.trim().is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        (!paragraphs.is_empty()).then_some(Self { paragraphs })
+    }
+
+    /// Returns consecutive paragraphs from a random one on, up to about `length` characters and
+    /// cut at a word boundary.
+    pub fn generate(&self, rng: &mut impl Rng, length: usize) -> String {
+        let start = rng.gen_range(0..self.paragraphs.len());
+        let mut text = String::new();
+        for paragraph in self.paragraphs.iter().cycle().skip(start) {
+            if text.len() >= length {
+                break;
+            }
+            if !text.is_empty() {
+                text.push_str("\n\n");
+            }
+            text.push_str(paragraph);
+        }
+
+        if text.len() > length {
+            let boundary = (0..=length)
+                .rev()
+                .find(|&index| text.is_char_boundary(index))
+                .unwrap_or(0);
+            let cut = text[..boundary]
+                .rfind(char::is_whitespace)
+                .filter(|_| !text[boundary..].starts_with(char::is_whitespace))
+                .unwrap_or(boundary);
+            text.truncate(cut);
+            text.truncate(text.trim_end().len());
+        }
+        text
+    }
+}
+
+/// Collects the files under `dir` and its subdirectories, skipping the hidden ones.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
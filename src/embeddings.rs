@@ -0,0 +1,224 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+#[derive(Deserialize)]
+pub struct EmbeddingRequest {
+    pub input: Value,
+    pub model: Option<String>,
+    pub dimensions: Option<u64>,
+    pub encoding_format: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingResponse {
+    pub object: String,
+    pub data: Vec<Embedding>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Serialize)]
+pub struct Embedding {
+    pub object: String,
+    pub index: u32,
+    pub embedding: Value,
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+/// A single input to embed, either text or an array of token ids.
+enum Input {
+    Text(String),
+    Tokens(Vec<Value>),
+}
+
+impl Input {
+    fn parse(input: &Value) -> Option<Vec<Input>> {
+        match input {
+            Value::String(text) => Some(vec![Input::Text(text.clone())]),
+            Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_number) => {
+                Some(vec![Input::Tokens(items.clone())])
+            }
+            Value::Array(items) if !items.is_empty() => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(text) => Some(Input::Text(text.clone())),
+                    Value::Array(tokens) => Some(Input::Tokens(tokens.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn seed_text(&self) -> String {
+        match self {
+            Input::Text(text) => text.clone(),
+            Input::Tokens(tokens) => Value::Array(tokens.clone()).to_string(),
+        }
+    }
+}
+
+/// FNV-1a, stable across builds so that vectors for the same input never change.
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Generates a unit-length vector seeded by the hash of the input, the same input always gets
+/// the same vector.
+pub(crate) fn generate_vector(text: &str, dimensions: usize) -> Vec<f32> {
+    let mut rng = StdRng::seed_from_u64(fnv1a(text));
+    let vector: Vec<f32> = (0..dimensions).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector;
+    }
+    vector.into_iter().map(|v| v / norm).collect()
+}
+
+pub(crate) fn default_dimensions(model: &str) -> usize {
+    if model.contains("large") {
+        3072
+    } else {
+        1536
+    }
+}
+
+/// Returns the `dimensions` of the vectors, which can shorten them down to one but not make them
+/// longer than the model's own.
+pub(crate) fn dimensions(model: &str, dimensions: Option<u64>) -> Result<usize, ApiError> {
+    let max = default_dimensions(model);
+    match dimensions {
+        None => Ok(max),
+        Some(dimensions) if (1..=max as u64).contains(&dimensions) => Ok(dimensions as usize),
+        Some(dimensions) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!(
+                "{} is not a valid value for 'dimensions', it must be between 1 and {} for {}",
+                dimensions, max, model
+            ),
+        )
+        .with_param("dimensions")),
+    }
+}
+
+pub async fn embeddings(
+    state: State<ServerState>,
+    Json(payload): Json<EmbeddingRequest>,
+) -> Response {
+    let Some(inputs) = Input::parse(&payload.input) else {
+        return state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "'input' must be a string, an array of strings or an array of tokens",
+            )
+            .with_param("input"),
+        );
+    };
+
+    let base64 = match payload.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(format) => {
+            return state.error_response(
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    format!("Invalid encoding_format '{}'", format),
+                )
+                .with_param("encoding_format"),
+            );
+        }
+    };
+
+    let model = payload
+        .model
+        .unwrap_or_else(|| "text-embedding-3-small".to_string());
+    let dimensions = match dimensions(&model, payload.dimensions) {
+        Ok(dimensions) => dimensions,
+        Err(error) => return state.error_response(error),
+    };
+
+    let prompt_text = inputs
+        .iter()
+        .filter_map(|input| match input {
+            Input::Text(text) => Some(text.as_str()),
+            Input::Tokens(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Err(response) = state.admit(&prompt_text).await {
+        return response;
+    }
+
+    // Token arrays are already tokenized, their length is the number of tokens
+    let prompt_tokens = inputs
+        .iter()
+        .map(|input| match input {
+            Input::Text(text) => state.count_tokens(text).unwrap_or(0),
+            Input::Tokens(tokens) => tokens.len() as u32,
+        })
+        .sum::<u32>();
+    if let Some(error_code) = state.apply_size_rules(prompt_tokens).await {
+        return state.error_response(ApiError::simulated(error_code));
+    }
+
+    if state.check_token_limit_exceeded(prompt_tokens) {
+        return state.error_response(state.token_limit_error(prompt_tokens));
+    }
+    state.add_token_usage(prompt_tokens);
+
+    let data = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let vector = generate_vector(&input.seed_text(), dimensions);
+            let embedding = if base64 {
+                let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+                Value::String(STANDARD.encode(bytes))
+            } else {
+                json!(vector)
+            };
+            Embedding {
+                object: "embedding".to_string(),
+                index: index as u32,
+                embedding,
+            }
+        })
+        .collect();
+
+    let response = EmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model,
+        usage: EmbeddingUsage {
+            prompt_tokens,
+            total_tokens: prompt_tokens,
+        },
+    };
+
+    let headers = state.get_rate_limit_headers();
+    (headers, Json(json!(response))).into_response()
+}
@@ -0,0 +1,228 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+/// Whether `code` is a client or server error status, the only ones Roy simulates.
+pub fn is_error_status(code: u16) -> bool {
+    (400..=599).contains(&code)
+}
+
+/// An error in the format returned by the OpenAI platform.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    pub error_type: String,
+    pub param: Option<String>,
+    pub code: Option<String>,
+    /// Fields the documented error format doesn't have, added to the error object.
+    pub extra: Map<String, Value>,
+    /// When the request can be retried, sent in the `retry-after` and `retry-after-ms` headers.
+    pub retry_after: Option<Duration>,
+    /// How much `retry_after` was pushed back by `--retry-after-jitter`, the rate limit reset
+    /// headers are pushed back as much.
+    pub jitter: Duration,
+}
+
+/// Translations of the messages clients are most likely to match on, in German, French,
+/// Spanish and Japanese. Other errors get a generic message.
+const TRANSLATIONS: [(&str, [&str; 4]); 3] = [
+    (
+        "rate_limit_exceeded",
+        [
+            "Ratenlimit erreicht. Bitte versuchen Sie es später erneut.",
+            "Limite de débit atteinte. Veuillez réessayer plus tard.",
+            "Se alcanzó el límite de solicitudes. Inténtelo de nuevo más tarde.",
+            "レート制限に達しました。しばらくしてから再試行してください。",
+        ],
+    ),
+    (
+        "insufficient_quota",
+        [
+            "Sie haben Ihr aktuelles Kontingent überschritten.",
+            "Vous avez dépassé votre quota actuel.",
+            "Ha superado su cuota actual.",
+            "現在のクォータを超えました。",
+        ],
+    ),
+    (
+        "invalid_api_key",
+        [
+            "Ungültiger API-Schlüssel angegeben.",
+            "Clé API fournie incorrecte.",
+            "Se proporcionó una clave de API incorrecta.",
+            "無効な API キーが指定されました。",
+        ],
+    ),
+];
+
+const GENERIC_TRANSLATIONS: [&str; 4] = [
+    "Bei der Verarbeitung Ihrer Anfrage ist ein Fehler aufgetreten.",
+    "Une erreur s'est produite lors du traitement de votre demande.",
+    "Se produjo un error al procesar su solicitud.",
+    "リクエストの処理中にエラーが発生しました。",
+];
+
+impl ApiError {
+    pub fn new(status: StatusCode, error_type: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            error_type: error_type.to_string(),
+            param: None,
+            code: None,
+            extra: Map::new(),
+            retry_after: None,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    pub fn rate_limit_exceeded(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", message)
+            .with_code("rate_limit_exceeded")
+    }
+
+    /// Unlike rate limit errors, quota errors never reset and clients must stop retrying.
+    pub fn insufficient_quota() -> Self {
+        Self::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "insufficient_quota",
+            "You exceeded your current quota, please check your plan and billing details.",
+        )
+        .with_code("insufficient_quota")
+    }
+
+    /// The prompt was flagged by the moderation that runs before generation, as opposed to a
+    /// completion stopped with the `content_filter` finish reason.
+    pub fn content_policy_violation() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "Your request was rejected as a result of our safety system. Your prompt may contain \
+             text that is not allowed by our content policy.",
+        )
+        .with_code("content_policy_violation")
+    }
+
+    /// The end user behind the `safety_identifier` of the request was flagged for abuse.
+    pub fn safety_identifier_blocked() -> Self {
+        Self::new(
+            StatusCode::FORBIDDEN,
+            "invalid_request_error",
+            "This request has been blocked because the safety identifier has been flagged for \
+             repeated violations of our usage policies.",
+        )
+        .with_code("safety_identifier_blocked")
+        .with_param("safety_identifier")
+    }
+
+    /// An error with the status `code`, or a 500 when `code` isn't an error status.
+    pub fn simulated(code: u16) -> Self {
+        let status = StatusCode::from_u16(code)
+            .ok()
+            .filter(|status| is_error_status(status.as_u16()))
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Self::new(
+            status,
+            "api_error",
+            format!("Simulated error with code {}", status.as_u16()),
+        )
+        .with_code(status.as_str())
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the `retry-after` headers, the seconds rounded up.
+    pub fn retry_after_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(retry_after) = self.retry_after {
+            let millis = retry_after.as_millis();
+            headers.insert(
+                "retry-after",
+                HeaderValue::from(millis.div_ceil(1000) as u64),
+            );
+            headers.insert("retry-after-ms", HeaderValue::from(millis as u64));
+        }
+        headers
+    }
+
+    pub fn with_param(mut self, param: &str) -> Self {
+        self.param = Some(param.to_string());
+        self
+    }
+
+    /// Changes the message and the shape of the error the way clients keying off `message`
+    /// instead of `code` don't expect: translating the message, reformatting it or adding
+    /// undocumented fields. The status, the type and the code are left untouched.
+    pub fn with_noise(mut self) -> Self {
+        let mut rng = rand::thread_rng();
+        match rng.gen_range(0..3) {
+            0 => {
+                let translations = TRANSLATIONS
+                    .iter()
+                    .find(|(code, _)| self.code.as_deref() == Some(*code))
+                    .map_or(&GENERIC_TRANSLATIONS, |(_, translations)| translations);
+                self.message = translations
+                    .choose(&mut rng)
+                    .expect("translations are never empty")
+                    .to_string();
+            }
+            1 => {
+                self.message = format!("  [{}] {}\n", self.error_type.to_uppercase(), self.message)
+            }
+            _ => {
+                self.extra.insert(
+                    "internal_trace_id".to_string(),
+                    json!(format!("trace-{:x}", rng.gen::<u64>())),
+                );
+                self.extra.insert(
+                    "details".to_string(),
+                    json!({"retryable": self.status.as_u16() >= 429, "docs": null}),
+                );
+            }
+        }
+        self
+    }
+
+    pub fn body(&self) -> Value {
+        let mut error = json!({
+            "message": self.message,
+            "type": self.error_type,
+            "code": self.code,
+        });
+        if let Some(param) = &self.param {
+            error["param"] = json!(param);
+        }
+        for (name, value) in &self.extra {
+            error[name] = value.clone();
+        }
+        json!({ "error": error })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, self.retry_after_headers(), Json(self.body())).into_response()
+    }
+}
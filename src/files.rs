@@ -0,0 +1,182 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+const PURPOSES: [&str; 6] = [
+    "assistants",
+    "batch",
+    "fine-tune",
+    "vision",
+    "user_data",
+    "evals",
+];
+
+/// A file uploaded to `/v1/files`, kept in memory for the lifetime of the server.
+#[derive(Serialize, Debug, Clone)]
+pub struct StoredFile {
+    pub id: String,
+    pub object: String,
+    pub bytes: usize,
+    pub created_at: u64,
+    pub filename: String,
+    pub purpose: String,
+    pub status: String,
+    #[serde(skip)]
+    pub content: Vec<u8>,
+}
+
+impl StoredFile {
+    pub fn new(filename: String, purpose: String, content: Vec<u8>) -> Self {
+        Self {
+            id: format!("file-{:x}", rand::thread_rng().gen::<u128>()),
+            object: "file".to_string(),
+            bytes: content.len(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("should be able to get duration")
+                .as_secs(),
+            filename,
+            purpose,
+            status: "processed".to_string(),
+            content,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListFilesQuery {
+    pub purpose: Option<String>,
+}
+
+fn invalid_request(message: impl Into<String>, param: &str) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param)
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("No such File object: {}", id),
+    )
+    .with_param("id")
+}
+
+pub async fn upload(state: State<ServerState>, mut multipart: Multipart) -> Response {
+    let mut file = None;
+    let mut purpose = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return state.error_response(invalid_request(err.body_text(), "file")),
+        };
+        let name = field.name().map(str::to_string);
+        match name.as_deref() {
+            Some("file") => {
+                let filename = field.file_name().unwrap_or("file").to_string();
+                match field.bytes().await {
+                    Ok(content) => file = Some((filename, content.to_vec())),
+                    Err(err) => {
+                        return state.error_response(invalid_request(err.body_text(), "file"))
+                    }
+                }
+            }
+            Some("purpose") => purpose = field.text().await.ok(),
+            _ => {}
+        }
+    }
+
+    let Some((filename, content)) = file else {
+        return state.error_response(invalid_request(
+            "Missing required parameter: 'file'",
+            "file",
+        ));
+    };
+    let purpose = match purpose {
+        Some(purpose) if PURPOSES.contains(&purpose.as_str()) => purpose,
+        Some(purpose) => {
+            return state.error_response(invalid_request(
+                format!("Invalid purpose '{}'", purpose),
+                "purpose",
+            ))
+        }
+        None => {
+            return state.error_response(invalid_request(
+                "Missing required parameter: 'purpose'",
+                "purpose",
+            ))
+        }
+    };
+
+    let file = StoredFile::new(filename, purpose, content);
+    state.files().lock().unwrap().push(file.clone());
+    Json(file).into_response()
+}
+
+/// Lists the uploaded files, most recent first.
+pub async fn list(
+    State(state): State<ServerState>,
+    Query(query): Query<ListFilesQuery>,
+) -> Response {
+    let files = state.files().lock().unwrap();
+    let data = files
+        .iter()
+        .rev()
+        .filter(|file| query.purpose.as_ref().is_none_or(|p| *p == file.purpose))
+        .collect::<Vec<_>>();
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "has_more": false,
+    }))
+    .into_response()
+}
+
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    match state.file(&id) {
+        Some(file) => Json(file).into_response(),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+pub async fn delete(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let removed = {
+        let mut files = state.files().lock().unwrap();
+        let before = files.len();
+        files.retain(|file| file.id != id);
+        files.len() < before
+    };
+    if !removed {
+        return state.error_response(not_found(&id));
+    }
+    Json(json!({
+        "id": id,
+        "object": "file",
+        "deleted": true,
+    }))
+    .into_response()
+}
+
+pub async fn content(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    match state.file(&id) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            file.content,
+        )
+            .into_response(),
+        None => state.error_response(not_found(&id)),
+    }
+}
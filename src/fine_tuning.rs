@@ -0,0 +1,329 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+/// Number of training steps reported while a job is running.
+const TRAINING_STEPS: u32 = 10;
+const DEFAULT_EPOCHS: u32 = 3;
+
+#[derive(Deserialize)]
+pub struct CreateJobRequest {
+    pub model: String,
+    pub training_file: String,
+    pub validation_file: Option<String>,
+    pub hyperparameters: Option<Value>,
+    pub suffix: Option<String>,
+    pub seed: Option<u64>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FineTuningEvent {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub level: String,
+    pub message: String,
+    pub data: Value,
+    #[serde(rename = "type")]
+    pub _type: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FineTuningJob {
+    pub id: String,
+    pub object: String,
+    pub model: String,
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+    pub fine_tuned_model: Option<String>,
+    pub organization_id: String,
+    pub result_files: Vec<String>,
+    pub status: String,
+    pub training_file: String,
+    pub validation_file: Option<String>,
+    pub hyperparameters: Value,
+    pub trained_tokens: Option<u64>,
+    pub error: Option<Value>,
+    pub seed: u64,
+    pub estimated_finish: Option<u64>,
+    pub suffix: Option<String>,
+    pub metadata: Option<Value>,
+    /// Events emitted so far, oldest first.
+    #[serde(skip)]
+    pub events: Vec<FineTuningEvent>,
+}
+
+impl FineTuningJob {
+    fn emit(&mut self, message: impl Into<String>, data: Value) {
+        let _type = if data.is_null() { "message" } else { "metrics" };
+        self.events.push(FineTuningEvent {
+            id: format!("ftevent-{:x}", rand::thread_rng().gen::<u64>()),
+            object: "fine_tuning.job.event".to_string(),
+            created_at: now(),
+            level: "info".to_string(),
+            message: message.into(),
+            data,
+            _type: _type.to_string(),
+        });
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "validating_files" | "queued" | "running"
+        )
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs()
+}
+
+fn invalid_request(message: impl Into<String>, param: &str) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param)
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("Could not find fine-tune: {}", id),
+    )
+    .with_param("fine_tuning_job_id")
+}
+
+/// Returns the items after the one with the `after` id, at most `limit` of them, and whether
+/// there are more.
+fn paginate<'a, T>(
+    items: Vec<&'a T>,
+    id: impl Fn(&T) -> &str,
+    query: &ListQuery,
+) -> (Vec<&'a T>, bool) {
+    let start = query.after.as_ref().map_or(0, |after| {
+        items
+            .iter()
+            .position(|item| id(item) == after)
+            .map_or(0, |position| position + 1)
+    });
+    let mut items = items.into_iter().skip(start).collect::<Vec<_>>();
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+    (items, has_more)
+}
+
+/// Moves the job through `validating_files → queued → running → succeeded` within
+/// `--fine-tuning-duration`, emitting the training events on the way. Cancelled jobs stop where
+/// they are.
+async fn train(state: ServerState, id: String) {
+    let duration = state.args().fine_tuning_duration;
+    let advance = |from: &str, to: &str, message: &str| {
+        state.update_fine_tuning_job(&id, |job| {
+            if job.status != from {
+                return false;
+            }
+            job.status = to.to_string();
+            job.emit(message, Value::Null);
+            true
+        }) == Some(true)
+    };
+
+    tokio::time::sleep(duration / 10).await;
+    if !advance(
+        "validating_files",
+        "queued",
+        "Files validated, moving job to queued state",
+    ) {
+        return;
+    }
+    tokio::time::sleep(duration / 10).await;
+    if !advance("queued", "running", "Fine-tuning job started") {
+        return;
+    }
+
+    let step_duration = (duration - duration / 5) / TRAINING_STEPS;
+    let mut loss = rand::thread_rng().gen_range(2.0..3.0);
+    for step in 1..=TRAINING_STEPS {
+        tokio::time::sleep(step_duration).await;
+        loss *= rand::thread_rng().gen_range(0.7..0.95);
+        let running = state.update_fine_tuning_job(&id, |job| {
+            if job.status != "running" {
+                return false;
+            }
+            job.emit(
+                format!(
+                    "Step {}/{}: training loss={:.4}",
+                    step, TRAINING_STEPS, loss
+                ),
+                json!({"step": step, "total_steps": TRAINING_STEPS, "train_loss": loss}),
+            );
+            true
+        });
+        if running != Some(true) {
+            return;
+        }
+    }
+
+    state.update_fine_tuning_job(&id, |job| {
+        if job.status != "running" {
+            return;
+        }
+        let fine_tuned_model = format!(
+            "ft:{}:roy:{}:{:x}",
+            job.model,
+            job.suffix.as_deref().unwrap_or_default(),
+            rand::thread_rng().gen::<u32>()
+        );
+        job.emit(
+            format!("New fine-tuned model created: {}", fine_tuned_model),
+            Value::Null,
+        );
+        job.emit("The job has successfully completed", Value::Null);
+        job.status = "succeeded".to_string();
+        job.finished_at = Some(now());
+        job.fine_tuned_model = Some(fine_tuned_model);
+    });
+}
+
+pub async fn create(state: State<ServerState>, Json(payload): Json<CreateJobRequest>) -> Response {
+    let Some(training_file) = state.file(&payload.training_file) else {
+        return state.error_response(invalid_request(
+            format!("Invalid file ID: {}", payload.training_file),
+            "training_file",
+        ));
+    };
+    if let Some(validation_file) = &payload.validation_file {
+        if state.file(validation_file).is_none() {
+            return state.error_response(invalid_request(
+                format!("Invalid file ID: {}", validation_file),
+                "validation_file",
+            ));
+        }
+    }
+
+    let hyperparameters = payload.hyperparameters.unwrap_or_else(
+        || json!({"n_epochs": "auto", "batch_size": "auto", "learning_rate_multiplier": "auto"}),
+    );
+    let epochs = hyperparameters["n_epochs"]
+        .as_u64()
+        .unwrap_or(DEFAULT_EPOCHS as u64);
+    let file_tokens = state
+        .count_tokens(&String::from_utf8_lossy(&training_file.content))
+        .unwrap_or(0) as u64;
+
+    let created_at = now();
+    let mut job = FineTuningJob {
+        id: format!("ftjob-{:x}", rand::thread_rng().gen::<u128>()),
+        object: "fine_tuning.job".to_string(),
+        model: payload.model,
+        created_at,
+        organization_id: "org-roy".to_string(),
+        status: "validating_files".to_string(),
+        training_file: payload.training_file,
+        validation_file: payload.validation_file,
+        hyperparameters,
+        trained_tokens: Some(file_tokens * epochs),
+        seed: payload
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen_range(0..1 << 31)),
+        estimated_finish: Some(created_at + state.args().fine_tuning_duration.as_secs()),
+        suffix: payload.suffix,
+        metadata: payload.metadata,
+        ..Default::default()
+    };
+    job.emit(
+        format!("Validating training file: {}", job.training_file),
+        Value::Null,
+    );
+    state.fine_tuning_jobs().lock().unwrap().push(job.clone());
+    tokio::spawn(train(state.0.clone(), job.id.clone()));
+
+    Json(job).into_response()
+}
+
+/// Lists the jobs, most recent first.
+pub async fn list(State(state): State<ServerState>, Query(query): Query<ListQuery>) -> Response {
+    let jobs = state.fine_tuning_jobs().lock().unwrap();
+    let (data, has_more) = paginate(jobs.iter().rev().collect(), |job| job.id.as_str(), &query);
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "has_more": has_more,
+    }))
+    .into_response()
+}
+
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    match state.update_fine_tuning_job(&id, |job| job.clone()) {
+        Some(job) => Json(job).into_response(),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+pub async fn cancel(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let job = state.update_fine_tuning_job(&id, |job| {
+        if job.is_active() {
+            job.status = "cancelled".to_string();
+            job.finished_at = Some(now());
+            job.emit("Fine-tuning job cancelled", Value::Null);
+        }
+        job.clone()
+    });
+    match job {
+        Some(job) if job.status == "cancelled" => Json(job).into_response(),
+        Some(job) => state.error_response(
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Job has already {}", job.status),
+            )
+            .with_param("fine_tuning_job_id"),
+        ),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+/// Lists the events of a job, most recent first.
+pub async fn events(
+    state: State<ServerState>,
+    Path(id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    let Some(events) = state.update_fine_tuning_job(&id, |job| job.events.clone()) else {
+        return state.error_response(not_found(&id));
+    };
+    let (data, has_more) = paginate(
+        events.iter().rev().collect(),
+        |event| event.id.as_str(),
+        &query,
+    );
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "has_more": has_more,
+    }))
+    .into_response()
+}
@@ -0,0 +1,110 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::path::Path;
+
+/// A canned answer, given to the requests whose messages or input match it. Missing patterns
+/// match anything, missing answers fall back to the generated ones.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    /// Text the messages or the input must contain.
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// Regular expression the messages or the input must match.
+    #[serde(default, deserialize_with = "regex")]
+    pub regex: Option<Regex>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_call: Option<FixtureToolCall>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// A function call answering the request, its arguments given as a JSON value or string.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureToolCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl FixtureToolCall {
+    /// The arguments as the JSON encoded string the API sends.
+    pub fn arguments(&self) -> String {
+        match &self.arguments {
+            Value::String(arguments) => arguments.clone(),
+            Value::Null => "{}".to_string(),
+            arguments => arguments.to_string(),
+        }
+    }
+}
+
+impl Fixture {
+    pub fn matches(&self, text: &str) -> bool {
+        self.contains
+            .as_ref()
+            .is_none_or(|needle| text.contains(needle.as_str()))
+            && self.regex.as_ref().is_none_or(|regex| regex.is_match(text))
+    }
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// A file holds a single fixture or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FixtureFile {
+    Many(Vec<Fixture>),
+    One(Fixture),
+}
+
+/// The fixtures of a directory, tried in the order of their files and, within a file, in the
+/// order they're listed.
+#[derive(Debug, Clone, Default)]
+pub struct Fixtures {
+    fixtures: Vec<Fixture>,
+}
+
+impl Fixtures {
+    /// Loads the JSON and YAML files of `dir`, sorted by name.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+
+        let mut fixtures = vec![];
+        for path in paths {
+            let name = path.display().to_string();
+            let file = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => serde_json::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|err| anyhow::anyhow!("Invalid fixture file {}: {}", name, err))?,
+                Some("yaml" | "yml") => serde_yaml::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|err| anyhow::anyhow!("Invalid fixture file {}: {}", name, err))?,
+                _ => continue,
+            };
+            match file {
+                FixtureFile::Many(many) => fixtures.extend(many),
+                FixtureFile::One(one) => fixtures.push(one),
+            }
+            log::info!("Loaded fixtures from {}", name);
+        }
+
+        Ok(Fixtures { fixtures })
+    }
+
+    /// Returns the first fixture matching `text`, the messages or input of a request.
+    pub fn find(&self, text: &str) -> Option<&Fixture> {
+        self.fixtures.iter().find(|fixture| fixture.matches(text))
+    }
+}
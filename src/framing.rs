@@ -0,0 +1,258 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+        HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::Response,
+};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::{stream, StreamExt};
+use rand::Rng;
+use std::convert::Infallible;
+use std::io::Write;
+
+use crate::server_state::ServerState;
+use crate::sse::SseParser;
+use crate::{BinaryCorruption, TransferEncoding};
+
+fn is_event_stream(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Audio, images and file contents, the downloads `--binary-corruption-rate` corrupts.
+fn is_binary(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.starts_with("audio/")
+                || value.starts_with("image/")
+                || value.starts_with("application/octet-stream")
+        })
+}
+
+/// Rewrites streamed responses, splitting JSON event data across multiple `data:` lines with
+/// `--sse-max-line-length` and cutting the stream into writes of `--sse-frame-size` bytes that
+/// don't respect event boundaries.
+pub async fn reframe(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let max_line_length = state.args().sse_max_line_length;
+    let frame_size = state.args().sse_frame_size.filter(|size| *size > 0);
+    if max_line_length.is_none() && frame_size.is_none() {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    if !is_event_stream(&response) {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let mut data_stream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        let mut parser = SseParser::default();
+        let mut pending = Vec::new();
+
+        while let Some(chunk) = data_stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    yield Err(err);
+                    return;
+                }
+            };
+            for event in parser.push(&String::from_utf8_lossy(&chunk)) {
+                pending.extend_from_slice(event.to_frame(max_line_length).as_bytes());
+            }
+
+            match frame_size {
+                Some(size) => {
+                    while pending.len() >= size {
+                        let rest = pending.split_off(size);
+                        yield Ok(Bytes::from(std::mem::replace(&mut pending, rest)));
+                    }
+                }
+                None if !pending.is_empty() => yield Ok(Bytes::from(std::mem::take(&mut pending))),
+                None => {}
+            }
+        }
+
+        if let Some(event) = parser.finish() {
+            pending.extend_from_slice(event.to_frame(max_line_length).as_bytes());
+        }
+        if !pending.is_empty() {
+            yield Ok(Bytes::from(pending));
+        }
+    };
+
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// Compresses streamed responses with gzip for clients accepting it, like some proxies in front
+/// of the real API do. The encoder is flushed after each write, so events aren't held back.
+pub async fn gzip(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let accepts_gzip = req
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|coding| coding.trim().starts_with("gzip"))
+        });
+    if !state.args().gzip_streams || !accepts_gzip {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    if !is_event_stream(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    parts
+        .headers
+        .insert(VARY, HeaderValue::from_static("accept-encoding"));
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let mut data_stream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        while let Some(chunk) = data_stream.next().await {
+            let compressed = chunk.map_err(std::io::Error::other).and_then(|chunk| {
+                encoder.write_all(&chunk)?;
+                encoder.flush()?;
+                Ok(Bytes::from(std::mem::take(encoder.get_mut())))
+            });
+            let failed = compressed.is_err();
+            yield compressed;
+            if failed {
+                return;
+            }
+        }
+
+        yield encoder.finish().map(Bytes::from);
+    };
+
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// Corrupts successful downloads of audio, images and files at `--binary-corruption-rate`,
+/// either cutting the body short of the Content-Length it announces or flipping some of its
+/// bytes, so that the integrity checks of download code are exercised.
+pub async fn corrupt_binary(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    let Some(rate) = state.args().binary_corruption_rate else {
+        return response;
+    };
+    if !response.status().is_success()
+        || !is_binary(&response)
+        || rand::thread_rng().gen_range(0..100) >= rate
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let mut data = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default()
+        .to_vec();
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(data.len()));
+    match state.args().binary_corruption {
+        BinaryCorruption::Truncate => data.truncate(data.len() / 2),
+        BinaryCorruption::Garble if !data.is_empty() => {
+            let mut rng = rand::thread_rng();
+            for _ in 0..(data.len() / 64).max(1) {
+                let index = rng.gen_range(0..data.len());
+                data[index] ^= 0xFF;
+            }
+        }
+        BinaryCorruption::Garble => {}
+    }
+    log::debug!(
+        "Corrupting a binary response, {} of {} bytes sent",
+        data.len(),
+        parts.headers[CONTENT_LENGTH].to_str().unwrap_or_default()
+    );
+
+    // A stream without a known size, so that the announced Content-Length is kept
+    let body = stream::once(async move { Ok::<_, Infallible>(Bytes::from(data)) });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Frames the bodies of non-streaming responses as `--transfer-encoding` asks, and announces a
+/// Content-Length longer than the body at `--overstated-content-length-rate`, which clients
+/// see as a connection closed before the end of the body.
+pub async fn transfer(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    let status = response.status();
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || is_event_stream(&response)
+    {
+        return response;
+    }
+    let overstate = state
+        .args()
+        .overstated_content_length_rate
+        .is_some_and(|rate| rand::thread_rng().gen_range(0..100) < rate);
+    let encoding = state.args().transfer_encoding;
+    if !overstate && encoding == TransferEncoding::Auto {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    if !overstate && encoding == TransferEncoding::Chunked {
+        // Bodies of unknown size are sent chunked
+        parts.headers.remove(CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::from_stream(body.into_data_stream()));
+    }
+
+    let data = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    if !overstate {
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(data.len()));
+        return Response::from_parts(parts, Body::from(data));
+    }
+
+    let announced = data.len() + rand::thread_rng().gen_range(1..=data.len().max(16));
+    log::debug!(
+        "Announcing a Content-Length of {} for a body of {} bytes",
+        announced,
+        data.len()
+    );
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(announced));
+    // A stream without a known size, so that the announced Content-Length is kept
+    let body = stream::once(async move { Ok::<_, Infallible>(data) });
+    Response::from_parts(parts, Body::from_stream(body))
+}
@@ -0,0 +1,221 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{sse::Event, IntoResponse, Response, Sse},
+    Json,
+};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+use crate::chat_completions::paced;
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+/// The message of the 429s of the Gemini API, rate limits and quotas alike.
+const RESOURCE_EXHAUSTED: &str = "Resource has been exhausted (e.g. check quota).";
+
+#[derive(Deserialize)]
+pub struct GenerateContentRequest {
+    #[serde(default)]
+    pub contents: Vec<Value>,
+    #[serde(rename = "systemInstruction")]
+    pub system_instruction: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct GenerateContentQuery {
+    /// `sse` to stream Server-Sent Events instead of a JSON array.
+    pub alt: Option<String>,
+}
+
+/// Returns the gRPC status name Google APIs report along with the HTTP status.
+fn google_status(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        500 => "INTERNAL",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Renders the error in the format of the Gemini API.
+fn error_response(state: &ServerState, error: ApiError) -> Response {
+    let error = state.noisy(error);
+    // Gemini words every rate limit the same way
+    let message = match error.code.as_deref() {
+        Some("rate_limit_exceeded") => RESOURCE_EXHAUSTED.to_string(),
+        _ => error.message,
+    };
+    let body = json!({
+        "error": {
+            "code": error.status.as_u16(),
+            "message": message,
+            "status": google_status(error.status),
+        }
+    });
+    (error.status, Json(body)).into_response()
+}
+
+/// Returns the text of the parts of a `Content` object.
+fn content_text(content: &Value) -> String {
+    content["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default()
+}
+
+fn usage_metadata(prompt_tokens: u32, completion_tokens: u32) -> Value {
+    json!({
+        "promptTokenCount": prompt_tokens,
+        "candidatesTokenCount": completion_tokens,
+        "totalTokenCount": prompt_tokens + completion_tokens,
+    })
+}
+
+/// Serves `v1beta/models/{model}:generateContent` and `:streamGenerateContent`, the model and
+/// the method being a single path segment.
+pub async fn generate_content(
+    state: State<ServerState>,
+    Path(target): Path<String>,
+    Query(query): Query<GenerateContentQuery>,
+    Json(payload): Json<GenerateContentRequest>,
+) -> Response {
+    let (model, stream) = match target.split_once(':') {
+        Some((model, "generateContent")) => (model.to_string(), false),
+        Some((model, "streamGenerateContent")) => (model.to_string(), true),
+        _ => {
+            return error_response(
+                &state,
+                ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    "invalid_request_error",
+                    format!("Method not found: {}", target),
+                ),
+            );
+        }
+    };
+
+    let prompt_text = payload
+        .system_instruction
+        .iter()
+        .chain(&payload.contents)
+        .map(content_text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let last_user_text = payload
+        .contents
+        .last()
+        .map(content_text)
+        .unwrap_or_default();
+    if let Err(error) = state.try_admit(&last_user_text).await {
+        return error_response(&state, error);
+    }
+
+    let prompt_tokens = state.count_tokens(&prompt_text).unwrap_or(0);
+    if let Some(error_code) = state.apply_size_rules(prompt_tokens).await {
+        return error_response(&state, ApiError::simulated(error_code));
+    }
+
+    // Gemini reports blocked prompts in a successful response, without candidates
+    if state.prompt_flagged() {
+        return Json(json!({
+            "promptFeedback": {"blockReason": "SAFETY"},
+            "usageMetadata": usage_metadata(prompt_tokens, 0),
+            "modelVersion": model,
+        }))
+        .into_response();
+    }
+
+    let response_id = format!("{:x}", rand::thread_rng().gen::<u64>());
+    let content = state.generate_lorem_content(state.get_response_length(&model), &prompt_text);
+    let content = state.render_template(
+        content,
+        &model,
+        &last_user_text,
+        &response_id,
+        prompt_tokens,
+    );
+    let completion_tokens = state.count_tokens(&content).unwrap_or(0);
+    if state.check_token_limit_exceeded(prompt_tokens + completion_tokens) {
+        return error_response(&state, ApiError::rate_limit_exceeded(RESOURCE_EXHAUSTED));
+    }
+    state.add_token_usage(prompt_tokens + completion_tokens);
+    let cost_headers = state.record_cost(&model, prompt_tokens, completion_tokens);
+
+    let candidate = |text: String, finish_reason: Option<&str>| {
+        let mut candidate = json!({
+            "content": {"parts": [{"text": text}], "role": "model"},
+            "index": 0,
+        });
+        if let Some(finish_reason) = finish_reason {
+            candidate["finishReason"] = json!(finish_reason);
+        }
+        candidate
+    };
+
+    if !stream {
+        state.simulate_generation_time(completion_tokens).await;
+        let response = json!({
+            "candidates": [candidate(content, Some("STOP"))],
+            "usageMetadata": usage_metadata(prompt_tokens, completion_tokens),
+            "modelVersion": model,
+            "responseId": response_id,
+        });
+        return (cost_headers, Json(response)).into_response();
+    }
+
+    let words = content.split_whitespace().collect::<Vec<_>>();
+    let last = words.len().saturating_sub(1);
+    let mut chunks = words
+        .iter()
+        .enumerate()
+        .map(|(index, word)| {
+            let done = index == last;
+            let mut chunk = json!({
+                "candidates": [candidate(format!("{} ", word), done.then_some("STOP"))],
+                "modelVersion": model,
+                "responseId": response_id,
+            });
+            if done {
+                chunk["usageMetadata"] = usage_metadata(prompt_tokens, completion_tokens);
+            }
+            chunk
+        })
+        .collect::<Vec<_>>();
+    if chunks.is_empty() {
+        chunks.push(json!({
+            "candidates": [candidate(String::new(), Some("STOP"))],
+            "usageMetadata": usage_metadata(prompt_tokens, completion_tokens),
+            "modelVersion": model,
+            "responseId": response_id,
+        }));
+    }
+
+    // Without `alt=sse` the chunks are sent as a single JSON array
+    if query.alt.as_deref() != Some("sse") {
+        state.simulate_generation_time(completion_tokens).await;
+        return (cost_headers, Json(chunks)).into_response();
+    }
+
+    let events = chunks
+        .into_iter()
+        .map(|chunk| Ok::<_, Infallible>(Event::default().data(chunk.to_string())))
+        .collect();
+    (cost_headers, Sse::new(paced(state.0.clone(), events))).into_response()
+}
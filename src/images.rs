@@ -0,0 +1,192 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+const MAX_IMAGES: u32 = 10;
+const MAX_SIDE: u32 = 4096;
+
+#[derive(Deserialize)]
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    pub model: Option<String>,
+    pub n: Option<u32>,
+    pub size: Option<String>,
+    pub response_format: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImageGenerationResponse {
+    pub created: u64,
+    pub data: Vec<Image>,
+}
+
+#[derive(Serialize)]
+pub struct Image {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    pub revised_prompt: String,
+}
+
+/// Parses sizes like `1024x1024`, `auto` being the default square.
+fn parse_size(size: Option<&str>) -> Option<(u32, u32)> {
+    match size {
+        None | Some("auto") => Some((1024, 1024)),
+        Some(size) => {
+            let (width, height) = size.split_once('x')?;
+            let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+            (0 < width && width <= MAX_SIDE && 0 < height && height <= MAX_SIDE)
+                .then_some((width, height))
+        }
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Encodes a gray PNG of the given size.
+pub fn placeholder_png(width: u32, height: u32) -> Vec<u8> {
+    let mut row = vec![0u8]; // no filter
+    row.extend(
+        std::iter::repeat([0xcc, 0xcc, 0xcc])
+            .take(width as usize)
+            .flatten(),
+    );
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for _ in 0..height {
+        encoder
+            .write_all(&row)
+            .expect("writing to a Vec should never fail");
+    }
+    let pixels = encoder
+        .finish()
+        .expect("writing to a Vec should never fail");
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGB, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &pixels);
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn invalid_request(message: String, param: &str) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param(param)
+}
+
+pub async fn generations(
+    state: State<ServerState>,
+    headers: HeaderMap,
+    Json(payload): Json<ImageGenerationRequest>,
+) -> Response {
+    let n = payload.n.unwrap_or(1);
+    if !(1..=MAX_IMAGES).contains(&n) {
+        return state.error_response(invalid_request(
+            format!("'n' must be between 1 and {}", MAX_IMAGES),
+            "n",
+        ));
+    }
+    let Some((width, height)) = parse_size(payload.size.as_deref()) else {
+        return state.error_response(invalid_request(
+            format!("Invalid size '{}'", payload.size.unwrap_or_default()),
+            "size",
+        ));
+    };
+    let model = payload.model.unwrap_or_else(|| "dall-e-2".to_string());
+    // GPT image models always return base64-encoded images
+    let b64_json = match payload.response_format.as_deref() {
+        Some("b64_json") => true,
+        Some("url") => false,
+        None => model.starts_with("gpt-image"),
+        Some(format) => {
+            return state.error_response(invalid_request(
+                format!("Invalid response_format '{}'", format),
+                "response_format",
+            ));
+        }
+    };
+
+    if let Err(response) = state.admit(&payload.prompt).await {
+        return response;
+    }
+
+    let prompt_tokens = state.count_tokens(&payload.prompt).unwrap_or(0);
+    if state.check_token_limit_exceeded(prompt_tokens) {
+        return state.error_response(state.token_limit_error(prompt_tokens));
+    }
+    state.add_token_usage(prompt_tokens);
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| format!("localhost:{}", state.args().port));
+    let image = || {
+        if b64_json {
+            Image {
+                url: None,
+                b64_json: Some(STANDARD.encode(placeholder_png(width, height))),
+                revised_prompt: payload.prompt.clone(),
+            }
+        } else {
+            Image {
+                url: Some(format!("http://{}/__images/{}x{}.png", host, width, height)),
+                b64_json: None,
+                revised_prompt: payload.prompt.clone(),
+            }
+        }
+    };
+
+    let response = ImageGenerationResponse {
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_secs(),
+        data: (0..n).map(|_| image()).collect(),
+    };
+
+    let headers = state.get_rate_limit_headers();
+    (headers, Json(json!(response))).into_response()
+}
+
+/// Serves the placeholder images linked by the URLs returned by the image generation API.
+pub async fn placeholder(Path(name): Path<String>) -> Response {
+    let size = name.strip_suffix(".png").unwrap_or(&name);
+    match parse_size(Some(size)) {
+        Some((width, height)) => (
+            [(header::CONTENT_TYPE, "image/png")],
+            placeholder_png(width, height),
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "Not Found".to_string()).into_response(),
+    }
+}
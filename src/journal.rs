@@ -0,0 +1,109 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, OriginalUri, State},
+    http::{HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::{Instant, SystemTime};
+
+use crate::server_state::ServerState;
+
+/// Number of requests kept around for verification and stats, the oldest ones are dropped.
+pub const MAX_RECORDED_REQUESTS: usize = 10_000;
+
+/// The identifier of a request, also returned to clients with the `x-request-id` header.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn generate_id() -> String {
+        format!("req_{:032x}", rand::thread_rng().gen::<u128>())
+    }
+}
+
+/// A request received by the server, kept around for verification and stats.
+#[derive(Serialize, Debug, Clone)]
+pub struct RequestRecord {
+    pub id: String,
+    pub timestamp: SystemTime,
+    pub method: String,
+    pub path: String,
+    /// The route serving the request, like `/v1/responses/:response_id`.
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub status: u16,
+    /// Time until the response headers were sent, injected delays included.
+    pub latency_ms: u64,
+    /// Headers identifying the client SDK, like `User-Agent`, `OpenAI-Beta` and `x-stainless-*`.
+    pub client_headers: BTreeMap<String, String>,
+}
+
+fn is_client_header(name: &str) -> bool {
+    name == "user-agent" || name == "openai-beta" || name.starts_with("x-stainless-")
+}
+
+pub async fn record(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let (mut parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+
+    let model = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|body| body.get("model")?.as_str().map(str::to_string));
+    let client_headers = parts
+        .headers
+        .iter()
+        .filter(|(name, _)| is_client_header(name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let method = parts.method.to_string();
+    // Nested routers (e.g. regions) strip their prefix from the URI
+    let path = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(parts.uri.path(), |uri| uri.path())
+        .to_string();
+    let endpoint = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map_or(path.as_str(), |matched| matched.as_str())
+        .to_string();
+
+    let id = RequestId::generate_id();
+    parts.extensions.insert(RequestId(id.clone()));
+
+    let started = Instant::now();
+    let mut response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+
+    state.record_request(RequestRecord {
+        id,
+        timestamp: SystemTime::now(),
+        method,
+        path,
+        endpoint,
+        model,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        client_headers,
+    });
+
+    response
+}
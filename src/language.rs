@@ -0,0 +1,214 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use crate::ContentLanguage;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const ARABIC: &[&str] = &[
+    "في",
+    "من",
+    "على",
+    "إلى",
+    "هذا",
+    "التي",
+    "الذي",
+    "كان",
+    "بين",
+    "بعد",
+    "عن",
+    "مع",
+    "كل",
+    "قد",
+    "العالم",
+    "اليوم",
+    "الكتاب",
+    "المدينة",
+    "الطريق",
+    "البيت",
+    "الماء",
+    "الوقت",
+    "العمل",
+    "الناس",
+    "جديد",
+    "كبير",
+    "جميل",
+    "قريب",
+    "بعيد",
+    "سريع",
+    "يكتب",
+    "يقرأ",
+    "يعمل",
+    "يذهب",
+    "يعرف",
+    "نحن",
+    "هم",
+    "أيضا",
+    "دائما",
+    "كثيرا",
+];
+
+const JAPANESE: &[&str] = &[
+    "私は",
+    "今日は",
+    "明日",
+    "東京で",
+    "友達と",
+    "本を",
+    "読みます",
+    "水を",
+    "飲みました",
+    "とても",
+    "新しい",
+    "大きな",
+    "静かな",
+    "電車に",
+    "乗って",
+    "学校へ",
+    "行きます",
+    "天気が",
+    "いいです",
+    "カメラ",
+    "コンピューター",
+    "ソフトウェア",
+    "データを",
+    "確認して",
+    "ください",
+    "時間が",
+    "かかります",
+    "問題は",
+    "ありません",
+    "会議で",
+    "話しました",
+    "そして",
+    "しかし",
+    "猫が",
+    "好きです",
+    "日本語",
+    "勉強して",
+    "います",
+    "簡単な",
+    "説明",
+];
+
+const CYRILLIC: &[&str] = &[
+    "в",
+    "и",
+    "на",
+    "с",
+    "по",
+    "это",
+    "как",
+    "для",
+    "что",
+    "мы",
+    "они",
+    "время",
+    "город",
+    "дом",
+    "работа",
+    "вода",
+    "книга",
+    "дорога",
+    "день",
+    "люди",
+    "мир",
+    "новый",
+    "большой",
+    "красивый",
+    "быстрый",
+    "старый",
+    "всегда",
+    "сегодня",
+    "завтра",
+    "очень",
+    "читать",
+    "писать",
+    "знать",
+    "думать",
+    "говорить",
+    "делать",
+    "видеть",
+    "хорошо",
+    "тоже",
+    "здесь",
+];
+
+/// Characters streamed together from scripts that don't put spaces between words.
+const CHARS_PER_CHUNK: usize = 4;
+
+/// Picks the language of the answer to `prompt`: the most frequent non-Latin script among its
+/// letters, Latin when there's none.
+pub fn detect(prompt: &str) -> ContentLanguage {
+    let (mut arabic, mut japanese, mut cyrillic) = (0, 0, 0);
+    for c in prompt.chars() {
+        match c {
+            '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' => arabic += 1,
+            c if is_japanese(c) => japanese += 1,
+            '\u{0400}'..='\u{04FF}' => cyrillic += 1,
+            _ => {}
+        }
+    }
+    match arabic.max(japanese).max(cyrillic) {
+        0 => ContentLanguage::Latin,
+        most if most == arabic => ContentLanguage::Arabic,
+        most if most == japanese => ContentLanguage::Japanese,
+        _ => ContentLanguage::Cyrillic,
+    }
+}
+
+/// Generates sentences of about `length` characters in a non-Latin script, or `None` for the
+/// languages lorem ipsum already covers.
+pub fn generate(language: ContentLanguage, rng: &mut impl Rng, length: usize) -> Option<String> {
+    let (words, separator, full_stop) = match language {
+        ContentLanguage::Arabic => (ARABIC, " ", "."),
+        ContentLanguage::Japanese => (JAPANESE, "", "。"),
+        ContentLanguage::Cyrillic => (CYRILLIC, " ", "."),
+        ContentLanguage::Latin | ContentLanguage::Auto => return None,
+    };
+
+    let mut text = String::new();
+    while text.chars().count() < length {
+        let sentence = (0..rng.gen_range(4..12))
+            .map(|_| *words.choose(rng).unwrap())
+            .collect::<Vec<_>>()
+            .join(separator);
+        if !text.is_empty() {
+            text.push_str(separator);
+        }
+        // Cyrillic sentences start with a capital letter, the other scripts have none
+        let mut letters = sentence.chars();
+        text.extend(letters.next().into_iter().flat_map(char::to_uppercase));
+        text.extend(letters);
+        text.push_str(full_stop);
+    }
+    Some(
+        text.chars()
+            .take(length)
+            .collect::<String>()
+            .trim_end()
+            .to_string(),
+    )
+}
+
+fn is_japanese(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{4E00}'..='\u{9FFF}')
+}
+
+/// Splits `text` into the words streamed one per delta, each with the whitespace following it.
+/// Japanese, which has no spaces between words, is cut every few characters instead.
+pub fn stream_words(text: &str) -> Vec<String> {
+    text.split_inclusive(char::is_whitespace)
+        .flat_map(|word| {
+            let characters = word.chars().collect::<Vec<_>>();
+            if characters.iter().copied().any(is_japanese) {
+                characters
+                    .chunks(CHARS_PER_CHUNK)
+                    .map(|chunk| chunk.iter().collect())
+                    .collect()
+            } else {
+                vec![word.to_string()]
+            }
+        })
+        .collect()
+}
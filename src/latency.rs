@@ -0,0 +1,99 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hdrhistogram::{serialization::Deserializer, Histogram};
+use rand::Rng;
+use std::path::Path;
+
+/// A latency distribution recorded in production, loaded from the file passed with
+/// `--latency-histogram`. Values are in milliseconds.
+#[derive(Debug, Clone)]
+pub enum LatencyDistribution {
+    /// An HDR histogram, either binary or base64 encoded.
+    Histogram(Histogram<u64>),
+    /// Latencies at increasing quantiles, e.g. `[(0.5, 120.0), (0.99, 800.0)]`.
+    Quantiles(Vec<(f64, f64)>),
+}
+
+impl LatencyDistribution {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read(path)?;
+
+        let text = String::from_utf8_lossy(&content);
+        let text = text.trim();
+        if text.starts_with("HIST") {
+            let bytes = STANDARD.decode(text)?;
+            return Self::from_histogram_bytes(&bytes);
+        }
+        if std::str::from_utf8(&content).is_err() {
+            return Self::from_histogram_bytes(&content);
+        }
+
+        Self::parse_quantiles(text)
+    }
+
+    fn from_histogram_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let histogram = Deserializer::new().deserialize(&mut &bytes[..])?;
+        Ok(LatencyDistribution::Histogram(histogram))
+    }
+
+    /// Parses lines like `0.99 800` or `p99 800`, mapping a quantile to a latency in milliseconds.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse_quantiles(text: &str) -> anyhow::Result<Self> {
+        let mut quantiles = vec![];
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(|c: char| c.is_whitespace() || c == ',' || c == ':');
+            let (Some(quantile), Some(latency)) =
+                (fields.next(), fields.find(|field| !field.is_empty()))
+            else {
+                anyhow::bail!("Invalid quantile line '{}'", line);
+            };
+
+            let quantile = match quantile.strip_prefix('p') {
+                Some(percentile) => percentile.parse::<f64>()? / 100.0,
+                None => quantile.parse::<f64>()?,
+            };
+            if !(0.0..=1.0).contains(&quantile) {
+                anyhow::bail!("Quantile out of range in line '{}'", line);
+            }
+            quantiles.push((quantile, latency.parse::<f64>()?));
+        }
+
+        if quantiles.is_empty() {
+            anyhow::bail!("No quantiles found");
+        }
+        quantiles.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(LatencyDistribution::Quantiles(quantiles))
+    }
+
+    /// Samples a latency in milliseconds.
+    pub fn sample_ms(&self) -> u64 {
+        let quantile = rand::thread_rng().gen::<f64>();
+        match self {
+            LatencyDistribution::Histogram(histogram) => histogram.value_at_quantile(quantile),
+            LatencyDistribution::Quantiles(quantiles) => Self::interpolate(quantiles, quantile),
+        }
+    }
+
+    /// Interpolates linearly between the known quantiles, starting from no latency at all.
+    fn interpolate(quantiles: &[(f64, f64)], quantile: f64) -> u64 {
+        let mut previous = (0.0, 0.0);
+        for &(q, latency) in quantiles {
+            if quantile <= q {
+                let span = q - previous.0;
+                let ratio = if span > 0.0 {
+                    (quantile - previous.0) / span
+                } else {
+                    1.0
+                };
+                return (previous.1 + (latency - previous.1) * ratio) as u64;
+            }
+            previous = (q, latency);
+        }
+        previous.1 as u64
+    }
+}
@@ -6,22 +6,72 @@ use axum::{
     http::{Request, Uri},
     middleware::{self, Next},
     response::Response,
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use clap::Parser;
 use clap_verbosity_flag::Verbosity;
 use colored::Colorize;
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::time::Duration;
+use tower::ServiceExt;
 use tower_http::timeout::TimeoutLayer;
 
+pub mod admin;
+pub mod assistants;
+pub mod audio;
+pub mod auth;
+pub mod batches;
+pub mod capture;
 pub mod chat_completions;
+pub mod completions;
+pub mod concurrency;
+pub mod config;
+pub mod connection;
+pub mod corpus;
+pub mod embeddings;
+pub mod errors;
+pub mod files;
+pub mod fine_tuning;
+pub mod fixtures;
+pub mod framing;
+pub mod gemini;
+pub mod images;
+pub mod journal;
+pub mod language;
+pub mod latency;
+pub mod loadgen;
+pub mod magic;
+pub mod markov;
+pub mod mirror;
+pub mod models;
+pub mod orchestrate;
+pub mod playback;
+pub mod realtime;
 pub mod responses;
+pub mod resume;
+pub mod scenario;
 pub mod server_state;
+pub mod snapshot;
+pub mod sse;
+pub mod stats;
+pub mod stubs;
+pub mod template;
+pub mod tools;
+pub mod vector_stores;
+pub mod verify;
+pub mod vision;
+use crate::config::Config;
+use crate::corpus::Corpus;
+use crate::fixtures::Fixtures;
+use crate::latency::LatencyDistribution;
+use crate::markov::MarkovChain;
+use crate::playback::Playback;
+use crate::scenario::Scenario;
 use crate::server_state::ServerState;
 
-#[derive(Parser, Clone)]
+#[derive(Parser, Clone, Debug)]
 #[command(name = "roy")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(
@@ -31,27 +81,39 @@ pub struct Args {
     #[command(flatten)]
     pub verbosity: Verbosity,
 
-    #[arg(long, help = "Port to listen on", default_value = "8000")]
+    #[arg(
+        long,
+        global = true,
+        help = "Port to listen on",
+        default_value = "8000"
+    )]
     pub port: u16,
 
-    #[arg(long, help = "Address to listen on", default_value = "0.0.0.0")]
+    #[arg(
+        long,
+        global = true,
+        help = "Address to listen on",
+        default_value = "0.0.0.0"
+    )]
     pub address: IpAddr,
 
     #[arg(
         long,
-        help = "Length of response (fixed number or range like '10:100')",
+        global = true,
+        help = "Length of response (fixed number or range like '10:100'), optionally per model like 'gpt-4o=800:2000,gpt-4o-mini=100:400'",
         default_value = "250"
     )]
     pub response_length: Option<String>,
 
-    #[arg(long, help = "HTTP error code to return")]
+    #[arg(long, global = true, help = "HTTP error code to return")]
     pub error_code: Option<u16>,
 
-    #[arg(long, help = "Error rate percentage (0-100)")]
+    #[arg(long, global = true, help = "Error rate percentage (0-100)")]
     pub error_rate: Option<u32>,
 
     #[arg(
         long,
+        global = true,
         help = "Maximum number of requests per minute",
         default_value = "500"
     )]
@@ -59,6 +121,7 @@ pub struct Args {
 
     #[arg(
         long,
+        global = true,
         help = "Maximum number of tokens per minute",
         default_value = "30000"
     )]
@@ -66,12 +129,825 @@ pub struct Args {
 
     #[arg(
         long,
+        global = true,
         help = "Slowdown in milliseconds (fixed number or range like '10:100')"
     )]
     pub slowdown: Option<String>,
 
-    #[arg(long, help = "Timeout in milliseconds")]
+    #[arg(long, global = true, help = "Timeout in milliseconds")]
     pub timeout: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Degrade the server for a window after startup (e.g. '30s:tripled-latency,halved-rpm')"
+    )]
+    pub warmup: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "URL where a copy of each request and its response summary is posted"
+    )]
+    pub mirror_to: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Scenario file declaring the requests expected during a test run"
+    )]
+    pub scenario: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Track conversations by 'conversation_id' or 'user' and number the replies"
+    )]
+    pub track_conversations: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Cut streaming responses with a rate limit error once the TPM budget runs out"
+    )]
+    pub midstream_tpm_exhaustion: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Permanently return 'insufficient_quota' errors after this many tokens were consumed"
+    )]
+    pub quota_exceeded_after: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "API key clients must send as a Bearer token"
+    )]
+    pub api_key: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time after which the API key expires and is rotated (e.g. '10m')",
+        value_parser = humantime::parse_duration,
+        requires = "api_key"
+    )]
+    pub api_key_ttl: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Configuration file with behaviour profiles and regions"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Hold non-streaming responses for the generation time at this many tokens per second"
+    )]
+    pub long_poll_tps: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Close connections with 'Connection: close' after this many responses"
+    )]
+    pub max_requests_per_connection: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Delay in milliseconds before serving each new connection, after the TCP handshake"
+    )]
+    pub accept_delay: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Directory where each request/response pair is written as a JSONL file"
+    )]
+    pub capture_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Directory with SSE transcripts and capture files played back on matching requests"
+    )]
+    pub playback_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Preserve the recorded timing of played back events, scaled by this factor (e.g. '2' is twice as fast)"
+    )]
+    pub playback_speed: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Directory with JSON or YAML fixtures answering the requests whose messages or input match them"
+    )]
+    pub fixtures: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Grow the delay between streamed chunks by a factor every N chunks (e.g. '10' or '10:1.5')"
+    )]
+    pub stream_decay: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How tokens are counted for usage and rate limits",
+        value_enum,
+        default_value = "bpe"
+    )]
+    pub usage_model: UsageModel,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of responses missing the rate limit headers (0-100)"
+    )]
+    pub missing_ratelimit_headers_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Rate limit headers to omit (e.g. 'reset-requests,remaining-tokens'), all of them by default",
+        value_delimiter = ',',
+        requires = "missing_ratelimit_headers_rate"
+    )]
+    pub missing_ratelimit_headers: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of responses with edge-case rate limit header values, like '0s', '8760h' or missing units (0-100)"
+    )]
+    pub odd_ratelimit_headers_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "HDR histogram or quantile file with production latencies in milliseconds to sample the slowdown from",
+        conflicts_with = "slowdown"
+    )]
+    pub latency_histogram: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Models listed by the models endpoint",
+        value_delimiter = ',',
+        default_value = "gpt-4o,gpt-4o-mini,gpt-3.5-turbo,gpt-5-2025-08-07"
+    )]
+    pub models: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Split the JSON data of streamed events into 'data:' lines of about this many characters"
+    )]
+    pub sse_max_line_length: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Send streamed responses in writes of this many bytes, regardless of event boundaries"
+    )]
+    pub sse_frame_size: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Compress streamed responses with gzip, flushing each write, for clients accepting it"
+    )]
+    pub gzip_streams: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Translate, reformat or add unexpected fields to error messages, keeping their codes"
+    )]
+    pub error_noise: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of chat and responses requests rejected by the content policy before generation (0-100)"
+    )]
+    pub moderation_block_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of chat and responses answers replaced by a refusal (0-100)"
+    )]
+    pub refusal_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Safety identifiers flagged for abuse, their chat and responses requests get 403 policy errors (e.g. 'user-123,user-456')",
+        value_delimiter = ','
+    )]
+    pub flagged_safety_identifiers: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of the requests of flagged safety identifiers rejected (0-100), all of them by default",
+        requires = "flagged_safety_identifiers"
+    )]
+    pub abuse_error_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Reject streaming requests beyond this many concurrent streams per API key"
+    )]
+    pub max_streams_per_key: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Duration of the audio returned by the speech API and chat completions, estimated from the text by default (e.g. '5s')",
+        value_parser = humantime::parse_duration
+    )]
+    pub audio_duration: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time a batch takes to go from 'validating' to 'completed' (e.g. '1m')",
+        value_parser = humantime::parse_duration,
+        default_value = "30s"
+    )]
+    pub batch_duration: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of requests served by a new model snapshot with its own fingerprint, latency and style (0-100)"
+    )]
+    pub new_snapshot_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time a fine-tuning job takes to go from 'validating_files' to 'succeeded' (e.g. '5m')",
+        value_parser = humantime::parse_duration,
+        default_value = "1m"
+    )]
+    pub fine_tuning_duration: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time an Assistants API run takes to complete when not streamed (e.g. '10s')",
+        value_parser = humantime::parse_duration,
+        default_value = "2s"
+    )]
+    pub run_duration: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of requests rejected with a 429 and no requests or tokens left until --long-reset (0-100)"
+    )]
+    pub long_reset_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Reset time advertised by the 429 errors of --long-reset-rate (e.g. '10m')",
+        value_parser = humantime::parse_duration,
+        default_value = "5m"
+    )]
+    pub long_reset: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Push back the retry advice of each rate limit 429 by a random amount up to this window, so clients honoring it don't retry in sync (e.g. '5s')",
+        value_parser = humantime::parse_duration
+    )]
+    pub retry_after_jitter: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time the files attached to a vector store take to be ingested (e.g. '30s')",
+        value_parser = humantime::parse_duration,
+        default_value = "2s"
+    )]
+    pub ingestion_duration: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time taken by background responses to complete (e.g. '10s')",
+        value_parser = humantime::parse_duration,
+        default_value = "5s"
+    )]
+    pub background_duration: Duration,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Whether responses are stored when the request doesn't set 'store'",
+        value_name = "BOOL",
+        action = clap::ArgAction::Set,
+        default_value_t = false
+    )]
+    pub store_default: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Time after which stored responses expire and can no longer be retrieved (e.g. '1h')",
+        value_parser = humantime::parse_duration
+    )]
+    pub store_ttl: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How the chunks of different choices are ordered when streaming with n > 1",
+        value_enum,
+        default_value = "round-robin"
+    )]
+    pub choice_order: ChoiceOrder,
+
+    #[arg(
+        long,
+        global = true,
+        help = "What the deltas of chat, completions and responses streams are made of",
+        value_enum,
+        default_value = "chunk"
+    )]
+    pub stream_granularity: StreamGranularity,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How the text of the answers is generated",
+        value_enum,
+        default_value = "lorem"
+    )]
+    pub text_generator: TextGenerator,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Text file the markov generator is trained on, a bundled English corpus by default"
+    )]
+    pub markov_corpus: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Text file, or directory of text files, the generated answers are cut from instead of lorem ipsum"
+    )]
+    pub corpus: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Language of the generated text, or auto to answer in the script of the prompt",
+        value_enum,
+        default_value = "latin"
+    )]
+    pub content_language: ContentLanguage,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Where the text of the answers comes from",
+        value_enum,
+        default_value = "generated"
+    )]
+    pub content_mode: ContentMode,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How --content-mode echo transforms the user's message",
+        value_enum,
+        default_value = "none"
+    )]
+    pub echo_transform: EchoTransform,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Jinja template of the generated text, with the model, prompt, request_id, prompt_tokens and content variables"
+    )]
+    pub response_template: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of realtime responses cut by dropping the WebSocket connection (0-100)"
+    )]
+    pub realtime_disconnect_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of audio, image and file downloads corrupted while keeping a success status (0-100)"
+    )]
+    pub binary_corruption_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How --binary-corruption-rate corrupts the downloads",
+        value_enum,
+        default_value = "truncate"
+    )]
+    pub binary_corruption: BinaryCorruption,
+
+    #[arg(
+        long,
+        global = true,
+        help = "How non-streaming response bodies are framed",
+        value_enum,
+        default_value = "auto"
+    )]
+    pub transfer_encoding: TransferEncoding,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Percentage of non-streaming responses announcing a Content-Length longer than their body (0-100)"
+    )]
+    pub overstated_content_length_rate: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Experimental: when streaming chat completions, send the usage so far every N chunks"
+    )]
+    pub continuous_usage_every: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Check that the function call outputs sent to Responses answer the calls made, and all of them"
+    )]
+    pub tool_round_trips: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Validate the configuration and print it, without serving"
+    )]
+    pub check: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What roy does, serving the API unless told otherwise.
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Serve the simulated API (the default)
+    Serve,
+    /// Serve the simulated API, writing each request/response pair to a directory
+    Record {
+        /// Directory where the captures are written
+        dir: PathBuf,
+    },
+    /// Serve the simulated API, playing back the recorded responses matching the requests
+    Replay {
+        /// Directory with SSE transcripts and capture files
+        dir: PathBuf,
+        /// Preserve the recorded timing, scaled by this factor (e.g. '2' is twice as fast)
+        #[arg(long)]
+        speed: Option<f64>,
+    },
+    /// Drive canned requests through a client or gateway forwarding to a running roy, and report
+    /// the behaviours it mishandles
+    Verify {
+        /// Base URL of the client or gateway under test (e.g. 'http://localhost:4000')
+        base_url: String,
+    },
+    /// Send OpenAI-shaped traffic to a target, e.g. a gateway in front of another roy, and
+    /// report the statuses and latencies
+    Loadgen {
+        /// Base URL of the target (e.g. 'http://localhost:4000')
+        #[arg(long)]
+        target: String,
+        /// Requests sent per second
+        #[arg(long, default_value = "10")]
+        rps: u32,
+        /// Percentage of streaming requests (e.g. '30%')
+        #[arg(long, default_value = "0%", value_parser = loadgen::parse_percentage)]
+        streaming: u32,
+        /// Stop after this long (e.g. '1m'), runs until Ctrl-C otherwise
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+        /// Model of the requests
+        #[arg(long, default_value = "gpt-4o-mini")]
+        model: String,
+    },
+    /// Launch the roy listeners of a topology file, e.g. a primary, a fallback and a degraded
+    /// one, print their addresses and stop them together
+    Orchestrate {
+        /// JSON or YAML file with the listeners, their ports and behaviour
+        file: PathBuf,
+    },
+    /// Inspect the behaviour profiles of the configuration file
+    Profiles {
+        #[command(subcommand)]
+        command: ProfilesCommand,
+    },
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum ProfilesCommand {
+    /// List the profiles with their overrides, and where they are used
+    List,
+}
+
+impl Args {
+    /// Folds the serving subcommands into the flags they stand for.
+    pub fn resolve(mut self) -> Self {
+        match self.command.take() {
+            Some(Command::Record { dir }) => self.capture_dir = Some(dir),
+            Some(Command::Replay { dir, speed }) => {
+                self.playback_dir = Some(dir);
+                self.playback_speed = speed.or(self.playback_speed);
+            }
+            command => self.command = command,
+        }
+        self
+    }
+
+    /// Rejects values that would otherwise fall back to defaults, or panic, while serving.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(response_length) = &self.response_length {
+            response_length_range(response_length, "")
+                .map_err(|err| anyhow::anyhow!("Invalid --response-length: {}", err))?;
+        }
+        if let Some(slowdown) = &self.slowdown {
+            parse_range(slowdown, MAX_SLOWDOWN_MS)
+                .map_err(|err| anyhow::anyhow!("Invalid --slowdown: {}", err))?;
+        }
+        if let Some(error_code) = self.error_code {
+            if !errors::is_error_status(error_code) {
+                anyhow::bail!(
+                    "Invalid --error-code '{}', not an HTTP error status (400-599)",
+                    error_code
+                );
+            }
+        }
+        if self.error_rate.is_some() && self.error_code.is_none() {
+            anyhow::bail!("--error-rate needs --error-code to know which error to return");
+        }
+        let rates = [
+            ("--error-rate", self.error_rate),
+            (
+                "--missing-ratelimit-headers-rate",
+                self.missing_ratelimit_headers_rate,
+            ),
+            (
+                "--odd-ratelimit-headers-rate",
+                self.odd_ratelimit_headers_rate,
+            ),
+            ("--moderation-block-rate", self.moderation_block_rate),
+            ("--refusal-rate", self.refusal_rate),
+            ("--abuse-error-rate", self.abuse_error_rate),
+            ("--new-snapshot-rate", self.new_snapshot_rate),
+            ("--long-reset-rate", self.long_reset_rate),
+            ("--realtime-disconnect-rate", self.realtime_disconnect_rate),
+            ("--binary-corruption-rate", self.binary_corruption_rate),
+            (
+                "--overstated-content-length-rate",
+                self.overstated_content_length_rate,
+            ),
+        ];
+        for (flag, rate) in rates {
+            if rate.is_some_and(|rate| rate > 100) {
+                anyhow::bail!(
+                    "Invalid {} '{}', use a percentage (0-100)",
+                    flag,
+                    rate.unwrap()
+                );
+            }
+        }
+        if let Some(template) = &self.response_template {
+            template::check(template)?;
+        }
+        if let Some(warmup) = &self.warmup {
+            if server_state::Warmup::parse(warmup).is_none() {
+                anyhow::bail!(
+                    "Invalid --warmup '{}', use a duration and effects like '30s:tripled-latency', factors above zero",
+                    warmup
+                );
+            }
+        }
+        if let Some(stream_decay) = &self.stream_decay {
+            if server_state::StreamDecay::parse(stream_decay).is_none() {
+                anyhow::bail!(
+                    "Invalid --stream-decay '{}', use 'N' or 'N:FACTOR' like '20:1.5', with N above zero and a positive FACTOR",
+                    stream_decay
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Length of the responses to models left out of `--response-length`, in characters.
+pub const DEFAULT_RESPONSE_LENGTH: u64 = 250;
+/// Upper bound of open-ended `--response-length` ranges, in characters.
+pub const MAX_RESPONSE_LENGTH: u64 = 10_000;
+/// Upper bound of open-ended `--slowdown` ranges, in milliseconds.
+pub const MAX_SLOWDOWN_MS: u64 = 600_000;
+
+/// Parses a fixed number or an inclusive range like `10:100`, where a missing bound is `0` or
+/// `upper` (`:500`, `100:`).
+pub fn parse_range(value: &str, upper: u64) -> anyhow::Result<(u64, u64)> {
+    let bound = |bound: &str, default: u64| match bound.trim() {
+        "" => Ok(default),
+        bound => bound.parse::<u64>().map_err(|_| {
+            anyhow::anyhow!(
+                "'{}' is not a number or a range like '10:100', ':500' or '100:'",
+                value
+            )
+        }),
+    };
+    let (min, max) = match value.split_once(':') {
+        Some((min, max)) => (bound(min, 0)?, bound(max, upper)?),
+        None if !value.trim().is_empty() => {
+            let fixed = bound(value, 0)?;
+            (fixed, fixed)
+        }
+        None => anyhow::bail!("the value is empty"),
+    };
+    if min > max {
+        anyhow::bail!("the range '{}' starts after it ends", value);
+    }
+    Ok((min, max))
+}
+
+/// Returns the response length range for `model` out of `--response-length`, which is a range
+/// for all models (`10:100`), ranges for some of them (`gpt-4o=800:2000,gpt-4o-mini=100:400`)
+/// or both.
+pub fn response_length_range(spec: &str, model: &str) -> anyhow::Result<(u64, u64)> {
+    let mut default = (DEFAULT_RESPONSE_LENGTH, DEFAULT_RESPONSE_LENGTH);
+    let mut for_model = None;
+    for entry in spec.split(',').map(str::trim) {
+        match entry.split_once('=') {
+            Some((name, _)) if name.trim().is_empty() => {
+                anyhow::bail!("'{}' has no model name", entry)
+            }
+            Some((name, range)) => {
+                let range = parse_range(range, MAX_RESPONSE_LENGTH)?;
+                if name.trim() == model {
+                    for_model = Some(range);
+                }
+            }
+            None => default = parse_range(entry, MAX_RESPONSE_LENGTH)?,
+        }
+    }
+    Ok(for_model.unwrap_or(default))
+}
+
+/// Prints the profiles of the configuration file along with the regions and the schedule
+/// windows using them.
+pub fn list_profiles(args: &Args) -> anyhow::Result<()> {
+    let Some(path) = &args.config else {
+        anyhow::bail!("Profiles are defined in the configuration file, pass it with --config");
+    };
+    let config = Config::load(path)?;
+    if config.profiles.is_empty() {
+        println!("No profiles defined in {}", path.display());
+        return Ok(());
+    }
+
+    for (name, profile) in &config.profiles {
+        println!("{} {}", name.bold(), serde_json::to_string(profile)?);
+        for (region, _) in config.regions.iter().filter(|(_, p)| *p == name) {
+            println!("  region: /{}", region);
+        }
+        for entry in config.schedule.iter().filter(|e| e.profile == *name) {
+            println!("  schedule: {}-{}", entry.from, entry.to);
+        }
+    }
+    Ok(())
+}
+
+/// The rule used to count tokens.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum UsageModel {
+    /// The tokenizer used by the OpenAI models
+    #[default]
+    Bpe,
+    /// One token per whitespace-separated word, for predictable numbers in tests
+    Simple,
+}
+
+/// The order in which the chunks of multiple choices are streamed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChoiceOrder {
+    /// One chunk of each choice in turn
+    #[default]
+    RoundRobin,
+    /// Every chunk of a choice before moving to the next one
+    Grouped,
+    /// A random choice for every chunk
+    Random,
+}
+
+/// The generator of the text of the answers.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TextGenerator {
+    /// Lorem ipsum
+    #[default]
+    Lorem,
+    /// Natural-looking English sentences of varying length, from a word-level Markov chain
+    Markov,
+}
+
+/// Where the text of the answers comes from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ContentMode {
+    /// Text from the generator and language in use
+    #[default]
+    Generated,
+    /// The last message of the user, mirrored back
+    Echo,
+    /// Generated text seeded by a hash of the request body, the same body getting the same text
+    Hashed,
+}
+
+/// The transformation of the echoed messages.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum EchoTransform {
+    /// The message as it is
+    #[default]
+    None,
+    /// The message in upper case
+    Uppercase,
+    /// The message in lower case
+    Lowercase,
+    /// The message with its characters in reverse order
+    Reverse,
+}
+
+/// How the body of a non-streaming response is framed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TransferEncoding {
+    /// A Content-Length when the size is known upfront, chunked otherwise
+    #[default]
+    Auto,
+    /// Always a Content-Length, buffering the bodies of unknown size
+    ContentLength,
+    /// Always chunked
+    Chunked,
+}
+
+/// How a download is corrupted.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BinaryCorruption {
+    /// Cut the body short of the Content-Length it announces
+    #[default]
+    Truncate,
+    /// Flip some bytes, keeping the length
+    Garble,
+}
+
+/// The language, and script, of the generated text.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ContentLanguage {
+    /// Lorem ipsum, or English with the markov generator
+    #[default]
+    Latin,
+    /// Arabic, written right to left
+    Arabic,
+    /// Japanese, mixing kana and kanji without spaces between words
+    Japanese,
+    /// Russian in the Cyrillic script
+    Cyrillic,
+    /// The script most used in the prompt, Latin when it has none
+    Auto,
+}
+
+/// What each delta of a text stream carries.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum StreamGranularity {
+    /// A word or a few characters, depending on the API
+    #[default]
+    Chunk,
+    /// A token of the text, as counted in the usage
+    Token,
 }
 
 pub async fn not_found(uri: Uri) -> (axum::http::StatusCode, String) {
@@ -103,29 +979,295 @@ async fn shutdown_signal() {
     log::info!("Signal received, starting graceful shutdown");
 }
 
-pub async fn run(args: Args) -> anyhow::Result<()> {
-    let state = ServerState::new(args.clone());
-
-    async fn slowdown(
-        State(state): State<ServerState>,
-        req: Request<axum::body::Body>,
-        next: Next,
-    ) -> Response {
-        let slowdown = state.get_slodown_ms();
-        log::debug!("Slowing down request by {}ms", slowdown);
-        tokio::time::sleep(std::time::Duration::from_millis(slowdown)).await;
-        next.run(req).await
-    }
+async fn slowdown(
+    State(state): State<ServerState>,
+    req: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let slowdown = state.get_slodown_ms();
+    log::debug!("Slowing down request by {}ms", slowdown);
+    tokio::time::sleep(std::time::Duration::from_millis(slowdown)).await;
+    next.run(req).await
+}
 
-    let mut app = Router::new()
+/// Routes emulating the OpenAI API, wrapped by the middlewares simulating the server behaviour.
+fn api_router(state: ServerState) -> Router {
+    let mut router = Router::new()
         .route(
             "/v1/chat/completions",
             post(chat_completions::chat_completions),
         )
+        .route(
+            "/v1/chat/completions/:completion_id",
+            get(chat_completions::retrieve),
+        )
+        .route("/v1/completions", post(completions::completions))
         .route("/v1/responses", post(responses::responses))
+        .route(
+            "/v1/responses/:response_id",
+            get(responses::retrieve).delete(responses::delete),
+        )
+        .route(
+            "/v1/responses/:response_id/input_items",
+            get(responses::input_items),
+        )
+        .route("/v1/responses/:response_id/cancel", post(responses::cancel))
+        .route("/v1/embeddings", post(embeddings::embeddings))
+        .route("/v1/images/generations", post(images::generations))
+        .route("/v1/audio/speech", post(audio::speech))
+        .route("/v1/files", post(files::upload).get(files::list))
+        .route(
+            "/v1/files/:file_id",
+            get(files::retrieve).delete(files::delete),
+        )
+        .route("/v1/files/:file_id/content", get(files::content))
+        .route("/v1/batches", post(batches::create).get(batches::list))
+        .route("/v1/batches/:batch_id", get(batches::retrieve))
+        .route("/v1/batches/:batch_id/cancel", post(batches::cancel))
+        .route(
+            "/v1/fine_tuning/jobs",
+            post(fine_tuning::create).get(fine_tuning::list),
+        )
+        .route("/v1/fine_tuning/jobs/:job_id", get(fine_tuning::retrieve))
+        .route(
+            "/v1/fine_tuning/jobs/:job_id/cancel",
+            post(fine_tuning::cancel),
+        )
+        .route(
+            "/v1/fine_tuning/jobs/:job_id/events",
+            get(fine_tuning::events),
+        )
+        .route(
+            "/v1/assistants",
+            post(assistants::create_assistant).get(assistants::list_assistants),
+        )
+        .route(
+            "/v1/assistants/:assistant_id",
+            get(assistants::retrieve_assistant).delete(assistants::delete_assistant),
+        )
+        .route("/v1/threads", post(assistants::create_thread))
+        .route(
+            "/v1/threads/:thread_id",
+            get(assistants::retrieve_thread).delete(assistants::delete_thread),
+        )
+        .route(
+            "/v1/threads/:thread_id/messages",
+            post(assistants::create_message).get(assistants::list_messages),
+        )
+        .route(
+            "/v1/threads/:thread_id/runs",
+            post(assistants::create_run).get(assistants::list_runs),
+        )
+        .route(
+            "/v1/threads/:thread_id/runs/:run_id",
+            get(assistants::retrieve_run),
+        )
+        .route(
+            "/v1/threads/:thread_id/runs/:run_id/cancel",
+            post(assistants::cancel_run),
+        )
+        .route(
+            "/v1/vector_stores",
+            post(vector_stores::create).get(vector_stores::list),
+        )
+        .route(
+            "/v1/vector_stores/:vector_store_id",
+            get(vector_stores::retrieve).delete(vector_stores::delete),
+        )
+        .route(
+            "/v1/vector_stores/:vector_store_id/files",
+            post(vector_stores::attach_file).get(vector_stores::list_files),
+        )
+        .route(
+            "/v1/vector_stores/:vector_store_id/files/:file_id",
+            get(vector_stores::retrieve_file).delete(vector_stores::detach_file),
+        )
+        .route(
+            "/v1/vector_stores/:vector_store_id/search",
+            post(vector_stores::search),
+        )
+        .route("/v1/realtime", get(realtime::realtime))
+        .route("/v1beta/models/:model", post(gemini::generate_content))
+        .route("/v1/models", get(models::list_models))
+        .route("/v1/models/:model", get(models::retrieve_model))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            resume::resume,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            playback::playback,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), stubs::serve))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            framing::reframe,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), framing::gzip))
         .route_layer(middleware::from_fn_with_state(state.clone(), slowdown))
-        .fallback(not_found)
-        .with_state(state);
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            concurrency::limit_streams,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_api_key,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            capture::capture,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            journal::record,
+        ));
+
+    if state.args().mirror_to.is_some() {
+        router = router.route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            mirror::mirror,
+        ));
+    }
+
+    router.with_state(state)
+}
+
+/// All the routes, with the API served under the prefix of each region too.
+fn app_router(state: ServerState, regions: &[(String, ServerState)]) -> Router {
+    let mut app = Router::new()
+        .route("/__admin/verify", get(admin::verify))
+        .route("/__admin/api_key", get(admin::api_key))
+        .route("/__admin/count_tokens", post(admin::count_tokens))
+        .route("/__admin/pricing", get(admin::pricing))
+        .route("/__admin/limits", get(admin::limits))
+        .route("/__admin/stub", post(stubs::prime))
+        .route("/__admin/scenarios", get(admin::scenarios))
+        .route("/__admin/scenarios/reset", post(admin::reset_scenarios))
+        .route("/__images/:name", get(images::placeholder))
+        .route("/__stats", get(stats::stats))
+        .route("/__stats/timeseries", get(stats::timeseries))
+        .route("/__stats/report", get(stats::report))
+        .route("/__stats/metrics", get(stats::metrics))
+        .with_state(state.clone())
+        .merge(api_router(state));
+
+    for (region, region_state) in regions {
+        app = app.nest(&format!("/{}", region), api_router(region_state.clone()));
+    }
+
+    app.fallback(not_found)
+}
+
+/// Resolves the time-of-day schedule once per request: each schedule entry gets its own copy of
+/// the routes, fixed on its behaviour, so that a request never mixes two profiles.
+fn scheduled_router(state: &ServerState, regions: &[(String, ServerState)]) -> Router {
+    let mut routers = std::iter::once(None)
+        .chain((0..state.schedule_len()).map(Some))
+        .map(|index| {
+            let regions = regions
+                .iter()
+                .map(|(region, region_state)| (region.clone(), region_state.scheduled(index)))
+                .collect::<Vec<_>>();
+            app_router(state.scheduled(index), &regions)
+        })
+        .collect::<Vec<_>>();
+    if routers.len() == 1 {
+        return routers.remove(0);
+    }
+
+    let state = state.clone();
+    Router::new().fallback_service(tower::service_fn(move |req: Request<axum::body::Body>| {
+        let index = state.active_schedule_entry().map_or(0, |index| index + 1);
+        routers[index].clone().oneshot(req)
+    }))
+}
+
+pub async fn run(mut args: Args) -> anyhow::Result<()> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    if !config.models.is_empty() {
+        args.models = config.models.clone();
+    }
+    args.validate()?;
+    for (name, profile) in &config.profiles {
+        profile
+            .apply(&args)
+            .validate()
+            .map_err(|err| anyhow::anyhow!("Profile '{}': {}", name, err))?;
+    }
+
+    let mut state = ServerState::new(args.clone());
+    if let Some(path) = &args.scenario {
+        state = state.with_scenario(Scenario::load(path)?);
+    }
+    if let Some(path) = &args.playback_dir {
+        state = state.with_playback(Playback::load(path)?);
+    }
+    if let Some(path) = &args.fixtures {
+        state = state.with_fixtures(Fixtures::load(path)?);
+    }
+    if let Some(path) = &args.latency_histogram {
+        state = state.with_latency_distribution(LatencyDistribution::load(path)?);
+    }
+    if let Some(path) = &args.markov_corpus {
+        state = state.with_markov_chain(MarkovChain::load(path)?);
+    }
+    if let Some(path) = &args.corpus {
+        state = state.with_corpus(Corpus::load(path)?);
+    }
+    if let Some(capture_dir) = &args.capture_dir {
+        std::fs::create_dir_all(capture_dir)?;
+    }
+    state = state.with_size_rules(config.size_rules.clone());
+    state = state.with_schedule(&config.schedule, &config.profiles);
+    state = state.with_prices(config.prices.clone());
+
+    if args.check {
+        println!("{:#?}", args);
+        if args.config.is_some() {
+            println!("{:#?}", config);
+        }
+        println!("{}", "Configuration is valid".green());
+        return Ok(());
+    }
+
+    let regions = config
+        .regions
+        .iter()
+        .map(|(region, profile)| {
+            log::info!("Serving region '{}' with profile '{}'", region, profile);
+            (
+                region.clone(),
+                state.with_profile(&config.profiles[profile]),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut app = scheduled_router(&state, &regions);
+
+    if args.max_requests_per_connection.is_some() {
+        app = app.layer(middleware::from_fn_with_state(
+            state.clone(),
+            connection::limit_requests,
+        ));
+    }
+
+    if args.transfer_encoding != TransferEncoding::Auto
+        || args.overstated_content_length_rate.is_some()
+    {
+        app = app.layer(middleware::from_fn_with_state(
+            state.clone(),
+            framing::transfer,
+        ));
+    }
+
+    if args.binary_corruption_rate.is_some() {
+        app = app.layer(middleware::from_fn_with_state(
+            state.clone(),
+            framing::corrupt_binary,
+        ));
+    }
 
     if let Some(timeout) = args.timeout {
         app = app.layer(TimeoutLayer::new(Duration::from_millis(timeout)));
@@ -139,9 +1281,22 @@ pub async fn run(args: Args) -> anyhow::Result<()> {
         format!("http://{}", addr).blue()
     );
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let accept_delay = Duration::from_millis(args.accept_delay.unwrap_or(0));
+    axum::serve(
+        listener,
+        connection::AcceptDelay::new(
+            connection::CountResponses::new(
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            ),
+            accept_delay,
+        ),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
 
+    let report = stats::Report::new(&state);
+    if report.requests > 0 {
+        report.print();
+    }
     Ok(())
 }
@@ -0,0 +1,181 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use colored::Colorize;
+use hdrhistogram::Histogram;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+use crate::chat_completions::ChatCompletionRequest;
+
+/// Number of words of the generated prompts.
+const PROMPT_WORDS: usize = 30;
+
+/// The traffic to generate.
+#[derive(Debug, Clone)]
+pub struct Load {
+    pub target: String,
+    pub rps: u32,
+    /// Percentage of streaming requests.
+    pub streaming: u32,
+    pub duration: Option<Duration>,
+    pub model: String,
+    pub api_key: Option<String>,
+}
+
+/// What happened to the requests sent so far.
+struct Report {
+    sent: u32,
+    statuses: BTreeMap<u16, u32>,
+    failures: u32,
+    latency: Histogram<u64>,
+}
+
+impl Report {
+    fn new() -> Self {
+        Self {
+            sent: 0,
+            statuses: BTreeMap::new(),
+            failures: 0,
+            latency: Histogram::new(3).expect("3 significant figures are supported"),
+        }
+    }
+
+    fn print(&self, elapsed: Duration) {
+        println!();
+        println!(
+            "{} requests in {:.1}s ({:.1} rps)",
+            self.sent,
+            elapsed.as_secs_f64(),
+            self.sent as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+        for (status, count) in &self.statuses {
+            let status = match status {
+                200..=299 => status.to_string().green(),
+                _ => status.to_string().red(),
+            };
+            println!("  {}: {}", status, count);
+        }
+        if self.failures > 0 {
+            println!("  {}: {}", "failed".red(), self.failures);
+        }
+        if !self.latency.is_empty() {
+            println!(
+                "Latency p50 {}ms, p95 {}ms, p99 {}ms, max {}ms",
+                self.latency.value_at_quantile(0.5),
+                self.latency.value_at_quantile(0.95),
+                self.latency.value_at_quantile(0.99),
+                self.latency.max()
+            );
+        }
+    }
+}
+
+/// Parses a percentage like `30%` or `30`.
+pub fn parse_percentage(value: &str) -> Result<u32, String> {
+    let percentage = value
+        .trim_end_matches('%')
+        .parse::<u32>()
+        .map_err(|err| err.to_string())?;
+    if percentage > 100 {
+        return Err("must be between 0% and 100%".to_string());
+    }
+    Ok(percentage)
+}
+
+/// Sends a chat completion built from the request model roy serves, reading the whole response.
+async fn send(client: reqwest::Client, load: Arc<Load>, report: Arc<Mutex<Report>>) {
+    let stream = rand::thread_rng().gen_range(0..100) < load.streaming;
+    let body = ChatCompletionRequest {
+        messages: Some(vec![json!({
+            "role": "user",
+            "content": lipsum::lipsum_words_with_rng(rand::thread_rng(), PROMPT_WORDS),
+        })]),
+        model: Some(load.model.clone()),
+        stream: Some(stream),
+        tools: None,
+        n: None,
+        store: None,
+        _other: Value::Object(Default::default()),
+    };
+
+    let mut request = client
+        .post(format!(
+            "{}/v1/chat/completions",
+            load.target.trim_end_matches('/')
+        ))
+        .json(&body);
+    if let Some(api_key) = &load.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let started = Instant::now();
+    let result = match request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            response.bytes().await.map(|_| status)
+        }
+        Err(err) => Err(err),
+    };
+    let elapsed = started.elapsed().as_millis() as u64;
+
+    let mut report = report.lock().unwrap();
+    match result {
+        Ok(status) => {
+            *report.statuses.entry(status).or_default() += 1;
+            report.latency.saturating_record(elapsed);
+        }
+        Err(err) => {
+            log::debug!("Request failed: {}", err);
+            report.failures += 1;
+        }
+    }
+}
+
+/// Sends OpenAI-shaped chat completions to the target at a constant rate until `duration`
+/// elapses or Ctrl-C is pressed, then prints what happened.
+pub async fn loadgen(load: Load) -> anyhow::Result<()> {
+    if load.rps == 0 {
+        anyhow::bail!("--rps must be greater than 0");
+    }
+
+    println!(
+        "Sending {} rps to {} ({}% streaming), press Ctrl-C to stop",
+        load.rps,
+        load.target.blue(),
+        load.streaming
+    );
+    let client = reqwest::Client::new();
+    let load = Arc::new(load);
+    let report = Arc::new(Mutex::new(Report::new()));
+    let started = Instant::now();
+    let deadline = load.duration.map(|duration| started + duration);
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / load.rps as f64));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut in_flight = JoinSet::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    break;
+                }
+                report.lock().unwrap().sent += 1;
+                in_flight.spawn(send(client.clone(), load.clone(), report.clone()));
+                // Reap the finished requests as we go
+                while in_flight.try_join_next().is_some() {}
+            }
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    while in_flight.join_next().await.is_some() {}
+    report.lock().unwrap().print(started.elapsed());
+    Ok(())
+}
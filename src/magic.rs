@@ -0,0 +1,56 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use std::time::Duration;
+
+use crate::errors::is_error_status;
+
+/// Per-request behaviours triggered by magic tokens like `[ROY:500]` found in the prompt.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Directives {
+    pub error_code: Option<u16>,
+    pub delay: Option<Duration>,
+    pub tool_call: bool,
+    pub truncated_tool_arguments: bool,
+}
+
+impl Directives {
+    pub fn parse(text: &str) -> Self {
+        let mut directives = Directives::default();
+        let mut rest = text;
+
+        while let Some(start) = rest.find("[ROY:") {
+            rest = &rest[start + 5..];
+            let Some(end) = rest.find(']') else {
+                break;
+            };
+            let token = rest[..end].trim();
+            rest = &rest[end + 1..];
+
+            if let Ok(code) = token.parse::<u16>() {
+                if is_error_status(code) {
+                    directives.error_code = Some(code);
+                } else {
+                    log::warn!(
+                        "Ignoring magic token [ROY:{}], error codes go from 400 to 599",
+                        token
+                    );
+                }
+            } else if let Some(delay) = token.strip_prefix("DELAY=") {
+                match humantime::parse_duration(delay) {
+                    Ok(delay) => directives.delay = Some(delay),
+                    Err(err) => log::warn!("Invalid delay in magic token '{}': {}", token, err),
+                }
+            } else if token == "TOOL_CALL" {
+                directives.tool_call = true;
+            } else if token == "TOOL_CALL_TRUNCATED" {
+                directives.tool_call = true;
+                directives.truncated_tool_arguments = true;
+            } else {
+                log::warn!("Unknown magic token: [ROY:{}]", token);
+            }
+        }
+
+        directives
+    }
+}
@@ -2,16 +2,38 @@
 // SPDX-License-Identifier: MIT
 
 use clap::Parser;
-use roy_cli::{run, Args};
+use roy_cli::{list_profiles, loadgen, orchestrate, run, verify, Args, Command};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let args = Args::parse().resolve();
 
     let mut builder = env_logger::Builder::new();
     let filter = args.verbosity.log_level_filter();
     builder.filter_level(filter);
     builder.init();
 
-    run(args).await
+    match args.command.clone() {
+        Some(Command::Verify { base_url }) => verify::verify(&base_url, args.api_key).await,
+        Some(Command::Loadgen {
+            target,
+            rps,
+            streaming,
+            duration,
+            model,
+        }) => {
+            let load = loadgen::Load {
+                target,
+                rps,
+                streaming,
+                duration,
+                model,
+                api_key: args.api_key,
+            };
+            loadgen::loadgen(load).await
+        }
+        Some(Command::Orchestrate { file }) => orchestrate::orchestrate(&file, args.address).await,
+        Some(Command::Profiles { .. }) => list_profiles(&args),
+        _ => run(args).await,
+    }
 }
@@ -0,0 +1,107 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The text the chain is trained on unless another one is given.
+const BUNDLED_CORPUS: &str = include_str!("corpus/markov.txt");
+
+/// Sentences stop growing after this many words even if the chain could go on.
+const MAX_SENTENCE_WORDS: usize = 60;
+
+/// A word-level Markov chain of order 2, generating sentences that read more naturally than
+/// lorem ipsum.
+#[derive(Debug, Clone)]
+pub struct MarkovChain {
+    transitions: HashMap<(String, String), Vec<String>>,
+    starts: Vec<(String, String)>,
+}
+
+impl MarkovChain {
+    /// A chain trained on a small bundled corpus of plain English.
+    pub fn bundled() -> Self {
+        Self::train(BUNDLED_CORPUS).expect("the bundled corpus should be long enough")
+    }
+
+    /// Trains a chain on the text of a file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::train(&text).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Invalid corpus {}: at least three words are needed",
+                path.display()
+            )
+        })
+    }
+
+    /// Trains a chain on `text`, or returns `None` when it's too short to generate anything.
+    pub fn train(text: &str) -> Option<Self> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        if words.len() < 3 {
+            return None;
+        }
+        let mut transitions = HashMap::<_, Vec<_>>::new();
+        for window in words.windows(3) {
+            transitions
+                .entry((window[0].to_string(), window[1].to_string()))
+                .or_default()
+                .push(window[2].to_string());
+        }
+        // Sentences start with the first word of the text and any word following a full stop
+        let starts = (0..words.len() - 1)
+            .filter(|&index| index == 0 || ends_sentence(words[index - 1]))
+            .map(|index| (words[index].to_string(), words[index + 1].to_string()))
+            .collect();
+        Some(Self {
+            transitions,
+            starts,
+        })
+    }
+
+    /// Generates whole sentences up to about `length` characters, cut at a word boundary.
+    pub fn generate(&self, rng: &mut impl Rng, length: usize) -> String {
+        let mut text = String::new();
+        while text.len() < length {
+            let Some((first, second)) = self.starts.choose(rng) else {
+                break;
+            };
+            let mut sentence = vec![first.clone(), second.clone()];
+            while sentence.len() < MAX_SENTENCE_WORDS
+                && !ends_sentence(&sentence[sentence.len() - 1])
+            {
+                let key = (
+                    sentence[sentence.len() - 2].clone(),
+                    sentence[sentence.len() - 1].clone(),
+                );
+                match self.transitions.get(&key).and_then(|next| next.choose(rng)) {
+                    Some(word) => sentence.push(word.clone()),
+                    None => break,
+                }
+            }
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&sentence.join(" "));
+        }
+
+        if text.len() > length {
+            let boundary = (0..=length)
+                .rev()
+                .find(|&index| text.is_char_boundary(index))
+                .unwrap_or(0);
+            let cut = match text.as_bytes()[boundary] {
+                b' ' => boundary,
+                _ => text[..boundary].rfind(' ').unwrap_or(boundary),
+            };
+            text.truncate(cut);
+        }
+        text
+    }
+}
+
+fn ends_sentence(word: &str) -> bool {
+    word.ends_with(['.', '!', '?'])
+}
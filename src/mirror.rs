@@ -0,0 +1,73 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::server_state::ServerState;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+pub async fn mirror(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let Some(target) = state.args().mirror_to.clone() else {
+        return next.run(req).await;
+    };
+
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+
+    let method = parts.method.to_string();
+    let path = parts.uri.path().to_string();
+    let request_body = serde_json::from_slice::<Value>(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+
+    let started = Instant::now();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let mut headers = Map::new();
+    for (name, value) in response.headers() {
+        if let Ok(value) = value.to_str() {
+            headers.insert(name.to_string(), Value::String(value.to_string()));
+        }
+    }
+
+    let record = json!({
+        "timestamp": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_secs(),
+        "method": method,
+        "path": path,
+        "request": request_body,
+        "response": {
+            "status": response.status().as_u16(),
+            "headers": headers,
+            "latency_ms": latency_ms,
+        },
+    });
+
+    tokio::spawn(async move {
+        if let Err(err) = CLIENT.post(&target).json(&record).send().await {
+            log::warn!("Failed to mirror request to {}: {}", target, err);
+        }
+    });
+
+    response
+}
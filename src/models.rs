@@ -0,0 +1,68 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+#[derive(Serialize)]
+pub struct Model {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub owned_by: String,
+}
+
+#[derive(Serialize)]
+pub struct ModelList {
+    pub object: String,
+    pub data: Vec<Model>,
+}
+
+fn model(state: &ServerState, id: &str) -> Model {
+    Model {
+        id: id.to_string(),
+        object: "model".to_string(),
+        created: state
+            .started_at()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_secs(),
+        owned_by: "roy".to_string(),
+    }
+}
+
+pub async fn list_models(State(state): State<ServerState>) -> impl IntoResponse {
+    Json(ModelList {
+        object: "list".to_string(),
+        data: state
+            .args()
+            .models
+            .iter()
+            .map(|id| model(&state, id))
+            .collect(),
+    })
+}
+
+pub async fn retrieve_model(State(state): State<ServerState>, Path(id): Path<String>) -> Response {
+    if !state.args().models.contains(&id) {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            "invalid_request_error",
+            format!("The model '{}' does not exist", id),
+        )
+        .with_code("model_not_found")
+        .with_param("model")
+        .into_response();
+    }
+
+    Json(model(&state, &id)).into_response()
+}
@@ -0,0 +1,172 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use futures_util::future::select_all;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+use crate::config::Profile;
+
+/// How long a listener has to start accepting connections.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Several roy listeners run together, e.g. a primary, a fallback and a degraded one.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Topology {
+    pub listeners: Vec<Listener>,
+}
+
+/// A roy listener of a topology, its behaviour set by a profile and any other flag.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Listener {
+    pub name: String,
+    pub port: u16,
+    #[serde(default)]
+    pub profile: Profile,
+    /// Command line flags, e.g. `["--stream-granularity", "token"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Listener {
+    /// The command line of the listener, listening on `address`.
+    pub fn command_args(&self, address: IpAddr) -> Vec<String> {
+        let mut args = vec![
+            "serve".to_string(),
+            "--address".to_string(),
+            address.to_string(),
+            "--port".to_string(),
+            self.port.to_string(),
+        ];
+        args.extend(self.profile.flags());
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+impl Topology {
+    /// Loads a JSON or YAML topology file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let topology: Topology = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)?,
+            _ => serde_json::from_str(&content)?,
+        };
+
+        if topology.listeners.is_empty() {
+            anyhow::bail!("The topology has no listeners");
+        }
+        let mut names = HashSet::new();
+        let mut ports = HashSet::new();
+        for listener in &topology.listeners {
+            if !names.insert(&listener.name) {
+                anyhow::bail!("Listener '{}' is declared twice", listener.name);
+            }
+            if !ports.insert(listener.port) {
+                anyhow::bail!(
+                    "Listener '{}' uses port {}, already taken by another listener",
+                    listener.name,
+                    listener.port
+                );
+            }
+        }
+        Ok(topology)
+    }
+}
+
+/// Launches a roy process for each listener of the topology file, prints their addresses once
+/// they accept connections and stops them all on Ctrl-C, or as soon as one of them exits.
+pub async fn orchestrate(path: &Path, address: IpAddr) -> anyhow::Result<()> {
+    let topology = Topology::load(path)?;
+    let exe = std::env::current_exe()?;
+
+    // A port held by another process would pass for a listener that's up
+    for listener in &topology.listeners {
+        if let Err(err) = std::net::TcpListener::bind(SocketAddr::new(address, listener.port)) {
+            anyhow::bail!(
+                "Listener '{}' can't use port {}: {}",
+                listener.name,
+                listener.port,
+                err
+            );
+        }
+    }
+
+    let mut children = vec![];
+    for listener in &topology.listeners {
+        let child = Command::new(&exe)
+            .args(listener.command_args(address))
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        children.push((listener.name.clone(), child));
+    }
+
+    let host = match address {
+        IpAddr::V4(ip) if ip.is_unspecified() => "localhost".to_string(),
+        IpAddr::V6(ip) if ip.is_unspecified() => "localhost".to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+        ip => ip.to_string(),
+    };
+    let probe = match address {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    for ((name, child), listener) in children.iter_mut().zip(&topology.listeners) {
+        wait_until_listening(name, child, SocketAddr::new(probe, listener.port)).await?;
+        println!("{:<16} http://{}:{}", name, host, listener.port);
+    }
+
+    let outcome = {
+        let exited = select_all(
+            children
+                .iter_mut()
+                .map(|(name, child)| Box::pin(async move { (name.clone(), child.wait().await) })),
+        );
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => Ok(()),
+            ((name, status), _, _) = exited => Err(anyhow::anyhow!(
+                "Listener '{}' exited ({}), stopping the others",
+                name,
+                status.map_or_else(|err| err.to_string(), |status| status.to_string())
+            )),
+        }
+    };
+
+    for (_, child) in children.iter_mut() {
+        let _ = child.kill().await;
+    }
+    outcome
+}
+
+/// Waits for a listener to accept connections on `addr`, failing if it exits first.
+async fn wait_until_listening(
+    name: &str,
+    child: &mut Child,
+    addr: SocketAddr,
+) -> anyhow::Result<()> {
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("Listener '{}' exited before listening ({})", name, status);
+        }
+        if tokio::net::TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    anyhow::bail!(
+        "Listener '{}' isn't listening on {} after {:?}",
+        name,
+        addr,
+        STARTUP_TIMEOUT
+    )
+}
@@ -0,0 +1,330 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::capture::CaptureLine;
+use crate::server_state::ServerState;
+
+/// The requests a transcript is played back for. Missing fields match anything.
+#[derive(Debug, Default, Clone)]
+pub struct Matcher {
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub model: Option<String>,
+    pub stream: Option<bool>,
+    pub prompt: Option<String>,
+    pub prompt_version: Option<String>,
+}
+
+impl Matcher {
+    /// Parses the `key=value` pairs of a `: roy-match path=/v1/chat/completions model=gpt-4o` line.
+    fn parse(spec: &str) -> Self {
+        let mut matcher = Matcher::default();
+        for pair in spec.split_whitespace() {
+            match pair.split_once('=') {
+                Some(("method", value)) => matcher.method = Some(value.to_string()),
+                Some(("path", value)) => matcher.path = Some(value.to_string()),
+                Some(("model", value)) => matcher.model = Some(value.to_string()),
+                Some(("stream", value)) => matcher.stream = value.parse().ok(),
+                Some(("prompt", value)) => matcher.prompt = Some(value.to_string()),
+                Some(("prompt_version", value)) => matcher.prompt_version = Some(value.to_string()),
+                _ => log::warn!("Ignoring unknown playback matcher '{}'", pair),
+            }
+        }
+        matcher
+    }
+
+    pub fn matches(&self, method: &str, path: &str, body: &Value) -> bool {
+        self.method
+            .as_ref()
+            .is_none_or(|m| m.eq_ignore_ascii_case(method))
+            && self.path.as_ref().is_none_or(|p| p == path)
+            && self
+                .model
+                .as_ref()
+                .is_none_or(|m| body.get("model").and_then(Value::as_str) == Some(m))
+            && self.stream.is_none_or(|stream| {
+                body.get("stream").and_then(Value::as_bool).unwrap_or(false) == stream
+            })
+            && self
+                .prompt
+                .as_ref()
+                .is_none_or(|id| body["prompt"]["id"].as_str() == Some(id))
+            && self
+                .prompt_version
+                .as_ref()
+                .is_none_or(|version| body["prompt"]["version"].as_str() == Some(version))
+    }
+}
+
+/// A server-sent event as it was received, along with its offset from the start of the request
+/// when known.
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    pub offset_ms: Option<u64>,
+    pub raw: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum RecordedBody {
+    Full(String),
+    Events(Vec<RecordedEvent>),
+}
+
+/// A recorded response, played back verbatim for the matching requests.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub name: String,
+    pub matcher: Matcher,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: RecordedBody,
+}
+
+impl Transcript {
+    /// Parses a raw SSE transcript, e.g. the response body of a mitmproxy dump. The optional
+    /// `: roy-match` comment restricts the requests it's played for, and `: roy-offset-ms <N>`
+    /// comments inside an event restore its original timing.
+    pub fn from_sse(name: &str, content: &str) -> Self {
+        let content = content.replace("\r\n", "\n");
+        let mut matcher = Matcher {
+            stream: Some(true),
+            ..Matcher::default()
+        };
+        let mut events = vec![];
+
+        for block in content.split("\n\n") {
+            let mut offset_ms = None;
+            let mut lines = vec![];
+            for line in block.lines() {
+                if let Some(spec) = line.strip_prefix(": roy-match") {
+                    matcher = Matcher {
+                        stream: Some(true),
+                        ..Matcher::parse(spec)
+                    };
+                } else if let Some(offset) = line.strip_prefix(": roy-offset-ms") {
+                    offset_ms = offset.trim().parse().ok();
+                } else {
+                    lines.push(line);
+                }
+            }
+            if lines.iter().any(|line| !line.is_empty()) {
+                events.push(RecordedEvent {
+                    offset_ms,
+                    raw: lines.join("\n") + "\n\n",
+                });
+            }
+        }
+
+        Transcript {
+            name: name.to_string(),
+            matcher,
+            status: 200,
+            content_type: Some("text/event-stream".to_string()),
+            body: RecordedBody::Events(events),
+        }
+    }
+
+    /// Builds a transcript from a file written with `--capture-dir`, matching requests with the
+    /// same method, path, model, streaming mode and prompt template as the captured one.
+    pub fn from_capture(name: &str, content: &str) -> anyhow::Result<Self> {
+        let mut matcher = Matcher::default();
+        let mut status = 200;
+        let mut content_type = None;
+        let mut full = None;
+        let mut events = vec![];
+
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<CaptureLine>(line)? {
+                CaptureLine::Request {
+                    method, path, body, ..
+                } => {
+                    matcher = Matcher {
+                        method: Some(method),
+                        path: Some(path),
+                        model: body.get("model").and_then(Value::as_str).map(String::from),
+                        stream: Some(body.get("stream").and_then(Value::as_bool).unwrap_or(false)),
+                        prompt: body["prompt"]["id"].as_str().map(String::from),
+                        prompt_version: body["prompt"]["version"].as_str().map(String::from),
+                    };
+                }
+                CaptureLine::Response {
+                    status: code,
+                    headers,
+                } => {
+                    status = code;
+                    content_type = headers.get(CONTENT_TYPE.as_str()).cloned();
+                }
+                CaptureLine::Body { body } => {
+                    full = Some(match body {
+                        Value::String(text) => text,
+                        other => other.to_string(),
+                    });
+                }
+                CaptureLine::Event {
+                    offset_ms,
+                    event,
+                    data,
+                    id,
+                } => {
+                    let mut raw = String::new();
+                    if let Some(event) = event {
+                        raw.push_str(&format!("event: {}\n", event));
+                    }
+                    if let Some(id) = id {
+                        raw.push_str(&format!("id: {}\n", id));
+                    }
+                    for line in data.split('\n') {
+                        raw.push_str(&format!("data: {}\n", line));
+                    }
+                    raw.push('\n');
+                    events.push(RecordedEvent {
+                        offset_ms: Some(offset_ms),
+                        raw,
+                    });
+                }
+            }
+        }
+
+        Ok(Transcript {
+            name: name.to_string(),
+            matcher,
+            status,
+            content_type,
+            body: match full {
+                Some(full) => RecordedBody::Full(full),
+                None => RecordedBody::Events(events),
+            },
+        })
+    }
+
+    fn into_response(self, speed: Option<f64>) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        let mut builder = Response::builder().status(status);
+        if let Some(content_type) = &self.content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+
+        let body = match self.body {
+            RecordedBody::Full(full) => Body::from(full),
+            RecordedBody::Events(events) => {
+                let stream = async_stream::stream! {
+                    let started = tokio::time::Instant::now();
+                    for event in events {
+                        if let (Some(speed), Some(offset_ms)) = (speed, event.offset_ms) {
+                            let offset = Duration::from_secs_f64(offset_ms as f64 / 1000.0 / speed);
+                            tokio::time::sleep_until(started + offset).await;
+                        }
+                        yield Ok::<_, std::convert::Infallible>(event.raw);
+                    }
+                };
+                Body::from_stream(stream)
+            }
+        };
+
+        builder
+            .body(body)
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+    }
+}
+
+/// The transcripts loaded from the directory passed with `--playback-dir`.
+#[derive(Debug, Default)]
+pub struct Playback {
+    transcripts: Vec<Transcript>,
+    next: AtomicUsize,
+}
+
+impl Playback {
+    /// Loads the `.sse` transcripts and the `.jsonl` capture files found in `dir`.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+
+        let mut transcripts = vec![];
+        for path in paths {
+            let name = path.display().to_string();
+            let transcript = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("sse") => Transcript::from_sse(&name, &std::fs::read_to_string(&path)?),
+                Some("jsonl") => Transcript::from_capture(&name, &std::fs::read_to_string(&path)?)
+                    .map_err(|err| anyhow::anyhow!("Invalid capture file {}: {}", name, err))?,
+                _ => continue,
+            };
+            log::info!("Loaded playback transcript {}", name);
+            transcripts.push(transcript);
+        }
+
+        Ok(Playback {
+            transcripts,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transcripts.is_empty()
+    }
+
+    /// Returns one of the transcripts matching the request, cycling through them when more than
+    /// one does.
+    pub fn find(&self, method: &str, path: &str, body: &Value) -> Option<&Transcript> {
+        let matching: Vec<_> = self
+            .transcripts
+            .iter()
+            .filter(|t| t.matcher.matches(method, path, body))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % matching.len();
+        Some(matching[index])
+    }
+}
+
+pub async fn playback(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if state.playback().is_empty() {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+    let request_body = serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null);
+
+    match state
+        .playback()
+        .find(parts.method.as_str(), parts.uri.path(), &request_body)
+    {
+        Some(transcript) => {
+            log::debug!("Playing back {}", transcript.name);
+            transcript
+                .clone()
+                .into_response(state.args().playback_speed)
+        }
+        None => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+    }
+}
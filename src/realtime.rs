@@ -0,0 +1,365 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::StatusCode,
+    response::Response,
+};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+const DEFAULT_MODEL: &str = "gpt-realtime";
+
+#[derive(Deserialize)]
+pub struct RealtimeQuery {
+    pub model: Option<String>,
+}
+
+fn generate_id(prefix: &str) -> String {
+    format!("{}_{:x}", prefix, rand::thread_rng().gen::<u64>())
+}
+
+/// Sends a server event, stamping it with its own event id.
+async fn send(socket: &mut WebSocket, mut event: Value) -> Result<(), axum::Error> {
+    event["event_id"] = json!(generate_id("event"));
+    socket.send(Message::Text(event.to_string())).await
+}
+
+/// Sends an `error` event, the realtime protocol reports errors without closing the session.
+async fn send_error(
+    socket: &mut WebSocket,
+    state: &ServerState,
+    error: ApiError,
+    client_event_id: Option<&str>,
+) -> Result<(), axum::Error> {
+    let mut body = state.noisy(error).body()["error"].clone();
+    body["event_id"] = json!(client_event_id);
+    send(socket, json!({"type": "error", "error": body})).await
+}
+
+fn invalid_event(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+}
+
+/// Returns the text of the input parts of a conversation item.
+fn item_text(item: &Value) -> String {
+    item["content"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|part| part["text"].as_str().or(part["transcript"].as_str()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default()
+}
+
+/// Upgrades the connection and runs a realtime session over it.
+pub async fn realtime(
+    State(state): State<ServerState>,
+    Query(query): Query<RealtimeQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let model = query.model.unwrap_or_else(|| DEFAULT_MODEL.to_string());
+    ws.on_upgrade(move |socket| async move {
+        if let Err(err) = session(socket, state, model).await {
+            log::debug!("Realtime session ended: {}", err);
+        }
+    })
+}
+
+async fn session(
+    mut socket: WebSocket,
+    state: ServerState,
+    model: String,
+) -> Result<(), axum::Error> {
+    let mut session = json!({
+        "id": generate_id("sess"),
+        "object": "realtime.session",
+        "model": model,
+        "modalities": ["text"],
+        "instructions": "",
+        "voice": "alloy",
+        "input_audio_format": "pcm16",
+        "output_audio_format": "pcm16",
+        "turn_detection": null,
+        "tools": [],
+        "tool_choice": "auto",
+        "temperature": 0.8,
+        "max_response_output_tokens": "inf",
+    });
+    send(
+        &mut socket,
+        json!({"type": "session.created", "session": session}),
+    )
+    .await?;
+
+    let mut items: Vec<Value> = Vec::new();
+    while let Some(message) = socket.recv().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(event) = serde_json::from_str::<Value>(&text) else {
+            let error = invalid_event("The server had an error parsing the event");
+            send_error(&mut socket, &state, error, None).await?;
+            continue;
+        };
+        let client_event_id = event["event_id"].as_str();
+
+        match event["type"].as_str().unwrap_or_default() {
+            "session.update" => {
+                if let (Some(session), Some(update)) =
+                    (session.as_object_mut(), event["session"].as_object())
+                {
+                    session.extend(update.clone());
+                }
+                send(
+                    &mut socket,
+                    json!({"type": "session.updated", "session": session}),
+                )
+                .await?;
+            }
+            "conversation.item.create" => {
+                let mut item = event["item"].clone();
+                if !item.is_object() {
+                    let error =
+                        invalid_event("Missing required parameter: 'item'").with_param("item");
+                    send_error(&mut socket, &state, error, client_event_id).await?;
+                    continue;
+                }
+                if item["id"].is_null() {
+                    item["id"] = json!(generate_id("item"));
+                }
+                item["object"] = json!("realtime.item");
+                item["status"] = json!("completed");
+                let previous_item_id = items.last().map(|item| item["id"].clone());
+                send(
+                    &mut socket,
+                    json!({
+                        "type": "conversation.item.created",
+                        "previous_item_id": previous_item_id,
+                        "item": item,
+                    }),
+                )
+                .await?;
+                items.push(item);
+            }
+            "response.create" => {
+                let flow = respond(
+                    &mut socket,
+                    &state,
+                    &session,
+                    &mut items,
+                    &event["response"],
+                    client_event_id,
+                )
+                .await?;
+                if flow.is_break() {
+                    // Drop the connection without a close frame, as a network failure would
+                    return Ok(());
+                }
+            }
+            "response.cancel" => {
+                // Responses are sent in full before the next event is read
+                let error = invalid_event("Cancellation failed: no active response found")
+                    .with_code("response_cancel_not_active");
+                send_error(&mut socket, &state, error, client_event_id).await?;
+            }
+            other => {
+                let error = invalid_event(format!(
+                    "Invalid value: '{}'. Supported values are: 'session.update', \
+                     'conversation.item.create', 'response.create' and 'response.cancel'.",
+                    other
+                ))
+                .with_code("invalid_value")
+                .with_param("type");
+                send_error(&mut socket, &state, error, client_event_id).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates a text response to the conversation, streaming it as `response.output_text.delta`
+/// events. Returns `Break` when the connection must be dropped halfway, as asked by
+/// `--realtime-disconnect-rate`.
+async fn respond(
+    socket: &mut WebSocket,
+    state: &ServerState,
+    session: &Value,
+    items: &mut Vec<Value>,
+    options: &Value,
+    client_event_id: Option<&str>,
+) -> Result<ControlFlow<()>, axum::Error> {
+    let instructions = options["instructions"]
+        .as_str()
+        .or(session["instructions"].as_str())
+        .unwrap_or_default();
+    let prompt_text = items
+        .iter()
+        .map(item_text)
+        .chain(std::iter::once(instructions.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let last_user_text = items
+        .iter()
+        .rev()
+        .find(|item| item["role"] == "user")
+        .map(item_text)
+        .unwrap_or_default();
+    if let Err(error) = state.try_admit(&last_user_text).await {
+        send_error(socket, state, error, client_event_id).await?;
+        return Ok(ControlFlow::Continue(()));
+    }
+
+    let input_tokens = state.count_tokens(&prompt_text).unwrap_or(0);
+    let model = session["model"].as_str().unwrap_or(DEFAULT_MODEL);
+    let content = state.generate_lorem_content(state.get_response_length(model), &prompt_text);
+    let output_tokens = state.count_tokens(&content).unwrap_or(0);
+    if state.check_token_limit_exceeded(input_tokens + output_tokens) {
+        let error = state.token_limit_error(input_tokens + output_tokens);
+        send_error(socket, state, error, client_event_id).await?;
+        return Ok(ControlFlow::Continue(()));
+    }
+    state.add_token_usage(input_tokens + output_tokens);
+    state.record_cost(model, input_tokens, output_tokens);
+
+    let words = content.split_whitespace().collect::<Vec<_>>();
+    let disconnect_after = state
+        .args()
+        .realtime_disconnect_rate
+        .filter(|rate| rand::thread_rng().gen_range(0..100) < *rate)
+        .map(|_| rand::thread_rng().gen_range(0..=words.len()));
+
+    let response_id = generate_id("resp");
+    let item_id = generate_id("item");
+    let mut response = json!({
+        "id": response_id,
+        "object": "realtime.response",
+        "status": "in_progress",
+        "status_details": null,
+        "output": [],
+        "usage": null,
+    });
+    let mut item = json!({
+        "id": item_id,
+        "object": "realtime.item",
+        "type": "message",
+        "status": "in_progress",
+        "role": "assistant",
+        "content": [],
+    });
+    let position = json!({
+        "response_id": response_id,
+        "item_id": item_id,
+        "output_index": 0,
+        "content_index": 0,
+    });
+    let event = |_type: &str, fields: Value| {
+        let mut event = position.clone();
+        event["type"] = json!(_type);
+        if let (Some(event), Some(fields)) = (event.as_object_mut(), fields.as_object()) {
+            event.extend(fields.clone());
+        }
+        event
+    };
+
+    send(
+        socket,
+        json!({"type": "response.created", "response": response}),
+    )
+    .await?;
+    send(
+        socket,
+        json!({
+            "type": "response.output_item.added",
+            "response_id": response_id,
+            "output_index": 0,
+            "item": item,
+        }),
+    )
+    .await?;
+    send(
+        socket,
+        event(
+            "response.content_part.added",
+            json!({"part": {"type": "output_text", "text": ""}}),
+        ),
+    )
+    .await?;
+
+    for (index, word) in words.iter().enumerate() {
+        if disconnect_after == Some(index) {
+            return Ok(ControlFlow::Break(()));
+        }
+        let delay = state.chunk_delay(Duration::ZERO, index);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        send(
+            socket,
+            event(
+                "response.output_text.delta",
+                json!({"delta": format!("{} ", word)}),
+            ),
+        )
+        .await?;
+    }
+    if disconnect_after.is_some() {
+        return Ok(ControlFlow::Break(()));
+    }
+
+    let part = json!({"type": "output_text", "text": content});
+    send(
+        socket,
+        event("response.output_text.done", json!({"text": content})),
+    )
+    .await?;
+    send(
+        socket,
+        event("response.content_part.done", json!({"part": part})),
+    )
+    .await?;
+
+    item["status"] = json!("completed");
+    item["content"] = json!([part]);
+    send(
+        socket,
+        json!({
+            "type": "response.output_item.done",
+            "response_id": response_id,
+            "output_index": 0,
+            "item": item,
+        }),
+    )
+    .await?;
+
+    response["status"] = json!("completed");
+    response["output"] = json!([item]);
+    response["usage"] = json!({
+        "total_tokens": input_tokens + output_tokens,
+        "input_tokens": input_tokens,
+        "output_tokens": output_tokens,
+    });
+    send(
+        socket,
+        json!({"type": "response.done", "response": response}),
+    )
+    .await?;
+
+    items.push(item);
+    Ok(ControlFlow::Continue(()))
+}
@@ -1,9 +1,15 @@
 // Copyright 2025 Massimiliano Pippi
 // SPDX-License-Identifier: MIT
 
+use crate::chat_completions::{check_metadata, token_cap};
+use crate::errors::ApiError;
+use crate::files::StoredFile;
+use crate::resume;
 use crate::server_state::ServerState;
+use crate::tools;
+use crate::vision;
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{sse::Event, IntoResponse, Json, Sse},
 };
@@ -15,12 +21,19 @@ use std::convert::Infallible;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::time::sleep;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ResponsesRequest {
     pub model: Option<String>,
-    pub input: Option<String>,
+    /// A string or a list of input items.
+    pub input: Option<Value>,
     pub instructions: Option<String>,
+    pub tools: Option<Vec<Value>>,
     pub stream: Option<bool>,
+    pub store: Option<bool>,
+    pub background: Option<bool>,
+    pub previous_response_id: Option<String>,
+    /// A reference to a prompt template: `{id, version, variables}`.
+    pub prompt: Option<Value>,
     #[serde(flatten)]
     pub _other: Value,
 }
@@ -32,15 +45,9 @@ fn generate_id(prefix: &str) -> String {
 
 // Data Models
 
-#[derive(Serialize, Clone, Default)]
-struct ResponseFormatText {
-    #[serde(rename = "type")]
-    _type: String,
-}
-
 #[derive(Serialize, Clone, Default)]
 struct ResponseTextConfig {
-    format: ResponseFormatText,
+    format: Value,
     verbosity: String,
 }
 
@@ -71,21 +78,118 @@ struct ResponseOutputText {
     logprobs: Vec<Value>,
 }
 
+#[derive(Serialize, Clone, Debug)]
+struct ResponseOutputRefusal {
+    #[serde(rename = "type")]
+    _type: String,
+    refusal: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+enum ResponseContent {
+    OutputText(ResponseOutputText),
+    Refusal(ResponseOutputRefusal),
+}
+
 #[derive(Serialize, Clone, Debug)]
 struct ResponseOutputMessage {
     id: String,
     #[serde(rename = "type")]
     _type: String,
-    content: Vec<ResponseOutputText>,
+    content: Vec<ResponseContent>,
     role: String,
     status: String,
 }
 
+#[derive(Serialize, Clone, Debug)]
+struct ResponseFunctionToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    call_id: String,
+    name: String,
+    arguments: String,
+    status: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseMcpListTools {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    server_label: String,
+    tools: Vec<Value>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseMcpCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    server_label: String,
+    name: String,
+    arguments: String,
+    output: Option<String>,
+    error: Option<String>,
+    approval_request_id: Option<String>,
+    status: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseCodeInterpreterCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    status: String,
+    code: String,
+    container_id: String,
+    outputs: Vec<Value>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseWebSearchCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    status: String,
+    action: Value,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseFileSearchCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    status: String,
+    queries: Vec<String>,
+    results: Vec<Value>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResponseComputerToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    call_id: String,
+    action: Value,
+    pending_safety_checks: Vec<Value>,
+    status: String,
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[serde(untagged)]
 enum ResponseOutputItem {
     Reasoning(ResponseReasoningItem),
     Message(ResponseOutputMessage),
+    FunctionCall(ResponseFunctionToolCall),
+    McpListTools(ResponseMcpListTools),
+    McpCall(ResponseMcpCall),
+    CodeInterpreterCall(ResponseCodeInterpreterCall),
+    WebSearchCall(ResponseWebSearchCall),
+    FileSearchCall(ResponseFileSearchCall),
+    ComputerCall(ResponseComputerToolCall),
 }
 
 #[derive(Serialize, Clone)]
@@ -107,9 +211,10 @@ struct ResponseUsage {
     total_tokens: u32,
 }
 
+/// A response of the Responses API, kept in memory when created with `store: true`.
 #[derive(Serialize, Clone, Default)]
-struct Response {
-    id: String,
+pub struct Response {
+    pub id: String,
     created_at: f64,
     error: Option<Value>,
     incomplete_details: Option<Value>,
@@ -127,7 +232,7 @@ struct Response {
     max_output_tokens: Option<u32>,
     max_tool_calls: Option<u32>,
     previous_response_id: Option<String>,
-    prompt: Option<String>,
+    prompt: Option<Value>,
     prompt_cache_key: Option<String>,
     reasoning: Reasoning,
     safety_identifier: Option<String>,
@@ -140,6 +245,20 @@ struct Response {
     usage: Option<ResponseUsage>,
     user: Option<String>,
     store: bool,
+    /// The items of the input, served by `/v1/responses/{id}/input_items`.
+    #[serde(skip)]
+    input_items: Vec<Value>,
+}
+
+impl Response {
+    /// Whether the response was created more than `ttl` ago.
+    pub fn expired(&self, ttl: Duration) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_secs_f64();
+        now - self.created_at >= ttl.as_secs_f64()
+    }
 }
 
 // SSE
@@ -157,6 +276,29 @@ struct ResponseEvent {
 enum OutputItem {
     Reasoning(ResponseReasoningItem),
     Message(ResponseOutputMessage),
+    FunctionCall(ResponseFunctionToolCall),
+    McpListTools(ResponseMcpListTools),
+    McpCall(ResponseMcpCall),
+    CodeInterpreterCall(ResponseCodeInterpreterCall),
+    WebSearchCall(ResponseWebSearchCall),
+    FileSearchCall(ResponseFileSearchCall),
+    ComputerCall(ResponseComputerToolCall),
+}
+
+impl From<ResponseOutputItem> for OutputItem {
+    fn from(item: ResponseOutputItem) -> Self {
+        match item {
+            ResponseOutputItem::Reasoning(item) => OutputItem::Reasoning(item),
+            ResponseOutputItem::Message(item) => OutputItem::Message(item),
+            ResponseOutputItem::FunctionCall(item) => OutputItem::FunctionCall(item),
+            ResponseOutputItem::McpListTools(item) => OutputItem::McpListTools(item),
+            ResponseOutputItem::McpCall(item) => OutputItem::McpCall(item),
+            ResponseOutputItem::CodeInterpreterCall(item) => OutputItem::CodeInterpreterCall(item),
+            ResponseOutputItem::WebSearchCall(item) => OutputItem::WebSearchCall(item),
+            ResponseOutputItem::FileSearchCall(item) => OutputItem::FileSearchCall(item),
+            ResponseOutputItem::ComputerCall(item) => OutputItem::ComputerCall(item),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -185,7 +327,7 @@ struct ResponseContentPartAddedEvent {
     output_index: u32,
     item_id: String,
     content_index: u32,
-    part: ResponseOutputText,
+    part: ResponseContent,
 }
 
 #[derive(Serialize)]
@@ -201,6 +343,46 @@ struct ResponseTextDeltaEvent {
     obfuscation: String,
 }
 
+#[derive(Serialize)]
+struct ResponseFunctionCallArgumentsDeltaEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+    delta: String,
+    obfuscation: String,
+}
+
+#[derive(Serialize)]
+struct ResponseFunctionCallArgumentsDoneEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+    arguments: String,
+}
+
+#[derive(Serialize)]
+struct ResponseToolCallEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+}
+
+#[derive(Serialize)]
+struct ResponseCodeInterpreterCallCodeDoneEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+    code: String,
+}
+
 #[derive(Serialize)]
 struct ResponseTextDoneEvent {
     #[serde(rename = "type")]
@@ -221,84 +403,269 @@ struct ResponseContentPartDoneEvent {
     output_index: u32,
     item_id: String,
     content_index: u32,
-    part: ResponseOutputText,
+    part: ResponseContent,
+}
+
+#[derive(Serialize)]
+struct ResponseRefusalDeltaEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+    content_index: u32,
+    delta: String,
+}
+
+#[derive(Serialize)]
+struct ResponseRefusalDoneEvent {
+    #[serde(rename = "type")]
+    _type: String,
+    sequence_number: u32,
+    output_index: u32,
+    item_id: String,
+    content_index: u32,
+    refusal: String,
 }
 
 pub async fn responses(
     state: State<ServerState>,
     Json(payload): Json<ResponsesRequest>,
 ) -> impl IntoResponse {
-    if state.check_request_limit_exceeded() {
-        let headers = state.get_rate_limit_headers();
-        let error_body = json!({
-            "error": {
-                "message": "Too many requests",
-                "type": "rate_limit_error",
-                "code": "rate_limit_exceeded"
-            }
-        });
-        return (StatusCode::TOO_MANY_REQUESTS, headers, Json(error_body)).into_response();
+    if let Some(prompt) = &payload.prompt {
+        if let Err(error) = check_prompt(prompt) {
+            return state.error_response(error);
+        }
+    }
+    // The variables of a prompt template are part of the input, along with the input itself
+    let prompt_text = payload
+        .prompt
+        .iter()
+        .filter_map(|prompt| prompt["variables"].as_object())
+        .flat_map(|variables| variables.values())
+        .filter_map(|variable| variable.as_str().or(variable["text"].as_str()))
+        .map(String::from)
+        .chain(payload.input.as_ref().map(input_text))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Magic tokens only count in the new input, not in the template or the history it replays
+    let last_user_message = payload
+        .input
+        .as_ref()
+        .map(input_items)
+        .and_then(|items| items.into_iter().rev().find(|item| item["role"] == "user"))
+        .map(|item| item_text(&item))
+        .unwrap_or_default();
+    if let Err(response) = state.admit(&last_user_message).await {
+        return response;
+    }
+    if let Err(response) = state.moderate(payload._other["safety_identifier"].as_str()) {
+        return response;
     }
-    state.increment_request_count();
 
-    if let Some(error_code) = state.should_return_error() {
-        let headers = state.get_rate_limit_headers();
-        let status_code =
-            StatusCode::from_u16(error_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-
-        let error_body = json!({
-            "error": {
-                "message": format!("Simulated error with code {}", error_code),
-                "type": "api_error",
-                "code": error_code.to_string()
-            }
-        });
+    // A chained response gets the whole conversation so far as input
+    let previous_tokens = match &payload.previous_response_id {
+        Some(id) => match conversation_tokens(&state, id) {
+            Some(tokens) => tokens,
+            None => return state.error_response(previous_response_not_found(id)),
+        },
+        None => 0,
+    };
+    let tool_outputs = match check_tool_outputs(&state, &payload) {
+        Ok(tool_outputs) => tool_outputs,
+        Err(error) => return state.error_response(error),
+    };
+    if let Err(error) = check_metadata(&payload._other["metadata"]) {
+        return state.error_response(error);
+    }
+    let metadata = match &payload._other["metadata"] {
+        Value::Null => json!({}),
+        metadata => metadata.clone(),
+    };
+    let max_output_tokens = match token_cap(&payload._other, "max_output_tokens", 16) {
+        Ok(max_output_tokens) => max_output_tokens,
+        Err(error) => return state.error_response(error),
+    };
 
-        return (status_code, headers, Json(error_body)).into_response();
+    // Only the tools `tool_choice` allows are called
+    let allowed_tools =
+        match tools::allowed_tools(payload.tools.as_ref(), payload._other.get("tool_choice")) {
+            Ok(allowed_tools) => allowed_tools,
+            Err(error) => return state.error_response(error),
+        };
+    let image_tokens = payload.input.as_ref().map_or(0, vision::count_image_tokens);
+    let prompt_tokens =
+        previous_tokens + state.count_tokens(&prompt_text).unwrap_or(0) + image_tokens;
+    if let Some(error_code) = state.apply_size_rules(prompt_tokens).await {
+        return state.error_response(ApiError::simulated(error_code));
     }
 
-    let response_length = state.get_response_length();
+    // A matching fixture answers with its content or function call
+    let fixture = state.fixture(&prompt_text);
+    let fixture_content = fixture.as_ref().and_then(|fixture| fixture.content.clone());
+
+    // Declared functions, or else the computer, are called first, the answer comes once their
+    // output is sent back
+    let has_functions = allowed_tools.iter().any(|tool| tool["type"] == "function");
+    let tool_choice_none = payload._other.get("tool_choice") == Some(&json!("none"));
+    let tool_call = if let Some(call) = fixture.as_ref().and_then(|f| f.tool_call.as_ref()) {
+        Some(ResponseOutputItem::FunctionCall(ResponseFunctionToolCall {
+            id: generate_id("fc"),
+            _type: "function_call".to_string(),
+            call_id: generate_id("call"),
+            name: call.name.clone(),
+            arguments: call.arguments(),
+            status: "completed".to_string(),
+        }))
+    } else if fixture_content.is_some() || tool_choice_none || tool_outputs > 0 {
+        None
+    } else if has_functions {
+        let (name, parameters) = tools::pick_function(Some(&allowed_tools));
+        Some(ResponseOutputItem::FunctionCall(ResponseFunctionToolCall {
+            id: generate_id("fc"),
+            _type: "function_call".to_string(),
+            call_id: generate_id("call"),
+            name,
+            arguments: tools::generate_arguments(parameters.as_ref()),
+            status: "completed".to_string(),
+        }))
+    } else {
+        computer_call(Some(&allowed_tools)).map(ResponseOutputItem::ComputerCall)
+    };
+
+    // Hosted tools run before the answer: MCP servers are never contacted, the tools they
+    // list are made up, the code interpreter leaves a file to download and the searches find
+    // made up results, cited by the message
+    let mut hosted_items = mcp_items(Some(&allowed_tools), !tool_choice_none);
+    let mut annotations = vec![];
+    if !tool_choice_none {
+        if let Some((call, annotation)) = code_interpreter_call(&state, Some(&allowed_tools)) {
+            hosted_items.push(ResponseOutputItem::CodeInterpreterCall(call));
+            annotations.push(annotation);
+        }
+        let (search_items, citations) = search_calls(&state, Some(&allowed_tools), &prompt_text);
+        hosted_items.extend(search_items);
+        annotations.extend(citations);
+    }
+    let message_index = 1 + hosted_items.len() as u32;
+
+    let model = payload
+        .model
+        .clone()
+        .unwrap_or_else(|| "gpt-5-2025-08-07".to_string());
+    // With `--content-mode hashed` the same body always gets the same content
+    let mut hashed = state.hashed_rng(&payload);
+    let response_length = match &mut hashed {
+        Some(rng) => state.response_length_with(&model, rng),
+        None => state.get_response_length(&model),
+    };
 
-    if response_length == 0 {
+    if response_length == 0 && tool_call.is_none() && fixture_content.is_none() {
         let headers = state.get_rate_limit_headers();
         return (StatusCode::NO_CONTENT, headers, Json(json!({}))).into_response();
     }
 
-    let content = state.generate_lorem_content(response_length);
+    let conversation = match payload._other.get("conversation") {
+        Some(Value::Object(conversation)) => conversation.get("id").and_then(Value::as_str),
+        Some(conversation) => conversation.as_str(),
+        None => payload._other.get("user").and_then(Value::as_str),
+    };
+    // Structured outputs get JSON, of the requested schema if any, instead of prose
+    let text_format = match &payload._other["text"]["format"] {
+        format @ Value::Object(_) => format.clone(),
+        _ => json!({"type": "text"}),
+    };
+    let response_id = generate_id("resp");
+    let refusal = (tool_call.is_none() && fixture_content.is_none())
+        .then(|| state.refusal())
+        .flatten();
+    let content = match (&tool_call, refusal, text_format["type"].as_str()) {
+        (Some(_), _, _) => String::new(),
+        (None, _, _) if fixture_content.is_some() => fixture_content.unwrap_or_default(),
+        (None, Some(refusal), _) => refusal.to_string(),
+        (None, None, Some("json_schema")) => {
+            tools::generate_value(&text_format["schema"], &text_format["schema"]).to_string()
+        }
+        (None, None, Some("json_object")) => tools::generate_json_object(response_length),
+        (None, None, _) => match state.echo(&last_user_message) {
+            Some(echo) => echo,
+            None => state.render_template(
+                format!(
+                    "{}{}",
+                    state.conversation_preamble(conversation),
+                    match &mut hashed {
+                        Some(rng) => {
+                            state.generate_seeded_content(response_length, &prompt_text, rng)
+                        }
+                        None => state.generate_lorem_content(response_length, &prompt_text),
+                    }
+                ),
+                &model,
+                &last_user_message,
+                &response_id,
+                prompt_tokens,
+            ),
+        },
+    };
+
+    // `max_output_tokens` leaves the response incomplete when the text doesn't fit
+    let (content, incomplete) = match max_output_tokens
+        .filter(|_| tool_call.is_none())
+        .and_then(|max_output_tokens| state.truncate_to_tokens(&content, max_output_tokens))
+    {
+        Some(cut) => (cut, true),
+        None => (content, false),
+    };
+    let status = if incomplete {
+        "incomplete"
+    } else {
+        "completed"
+    };
+    let incomplete_details = incomplete.then(|| json!({"reason": "max_output_tokens"}));
 
-    let prompt_text = payload.input.clone().unwrap_or_else(|| "".to_string());
-    let prompt_tokens = state.count_tokens(&prompt_text).unwrap_or(0) as u32;
-    let completion_tokens = state.count_tokens(&content).unwrap_or(0) as u32;
+    let completion_tokens = match &tool_call {
+        Some(ResponseOutputItem::FunctionCall(call)) => state
+            .count_tokens(&format!("{}{}", call.name, call.arguments))
+            .unwrap_or(0),
+        Some(ResponseOutputItem::ComputerCall(call)) => {
+            state.count_tokens(&call.action.to_string()).unwrap_or(0)
+        }
+        _ => state.count_tokens(&content).unwrap_or(0),
+    };
     let total_tokens = prompt_tokens + completion_tokens;
 
-    if state.check_token_limit_exceeded(total_tokens) {
-        let headers = state.get_rate_limit_headers();
-        let error_body = json!({
-            "error": {
-                "message": "You have exceeded your token quota.",
-                "type": "rate_limit_error",
-                "code": "rate_limit_exceeded"
-            }
-        });
-        return (StatusCode::TOO_MANY_REQUESTS, headers, Json(error_body)).into_response();
+    let stream_response = payload.stream.unwrap_or(false);
+
+    // When simulating TPM exhaustion mid-stream, the stream starts as long as the prompt fits
+    // and the completion is cut as soon as the remaining budget runs out.
+    let token_budget = (stream_response && state.args().midstream_tpm_exhaustion)
+        .then(|| state.remaining_token_budget().saturating_sub(prompt_tokens));
+    let cutoff = token_budget.filter(|budget| *budget < completion_tokens);
+
+    let requested_tokens = match token_budget {
+        Some(_) => prompt_tokens,
+        None => total_tokens,
+    };
+    if state.check_token_limit_exceeded(requested_tokens) {
+        return state.error_response(state.token_limit_error(requested_tokens));
     }
-    state.add_token_usage(total_tokens);
+    state.add_token_usage(prompt_tokens + cutoff.unwrap_or(completion_tokens));
 
     let headers = state.get_rate_limit_headers();
-    let model = payload
-        .model
-        .clone()
-        .unwrap_or_else(|| "gpt-5-2025-08-07".to_string());
-    let response_id = generate_id("resp");
+    let cost_headers =
+        state.record_cost(&model, prompt_tokens, cutoff.unwrap_or(completion_tokens));
     let message_id = generate_id("msg");
     let created_at = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("should be able to get duration")
         .as_secs_f64();
+    let store = payload.store.unwrap_or(state.args().store_default);
+    let input_items = payload.input.as_ref().map(input_items).unwrap_or_default();
 
-    let stream_response = payload.stream.unwrap_or(false);
     if stream_response {
         let reasoning_item_id = generate_id("rs");
+        let stream_state = state.0.clone();
         let stream = async_stream::stream! {
             let mut sequence_number = 0;
             let mut response = Response {
@@ -319,12 +686,18 @@ pub async fn responses(
                 },
                 service_tier: "auto".to_string(),
                 text: ResponseTextConfig {
-                    format: ResponseFormatText { _type: "text".to_string() },
+                    format: text_format.clone(),
                     verbosity: "medium".to_string(),
                 },
                 top_logprobs: 0,
                 truncation: "disabled".to_string(),
-                store: false,
+                store,
+                previous_response_id: payload.previous_response_id.clone(),
+                prompt: payload.prompt.clone(),
+                metadata: metadata.clone(),
+                prompt_cache_key: payload._other["prompt_cache_key"].as_str().map(String::from),
+                safety_identifier: payload._other["safety_identifier"].as_str().map(String::from),
+                input_items: input_items.clone(),
                 ..Default::default()
             };
 
@@ -375,6 +748,227 @@ pub async fn responses(
             yield Ok::<_, Infallible>(Event::default().event("response.output_item.done").data(serde_json::to_string(&output_item_done_event).unwrap()));
             sequence_number += 1;
 
+            // 4a. Hosted tools run before the answer
+            for (index, item) in hosted_items.iter().enumerate() {
+                let output_index = 1 + index as u32;
+                let (kind, item_id, added, done) = match item {
+                    ResponseOutputItem::McpListTools(list) => (
+                        "mcp_list_tools",
+                        list.id.clone(),
+                        OutputItem::McpListTools(ResponseMcpListTools { tools: vec![], ..list.clone() }),
+                        OutputItem::McpListTools(list.clone()),
+                    ),
+                    ResponseOutputItem::McpCall(call) => (
+                        "mcp_call",
+                        call.id.clone(),
+                        OutputItem::McpCall(ResponseMcpCall {
+                            output: None,
+                            status: "in_progress".to_string(),
+                            ..call.clone()
+                        }),
+                        OutputItem::McpCall(call.clone()),
+                    ),
+                    ResponseOutputItem::CodeInterpreterCall(call) => (
+                        "code_interpreter_call",
+                        call.id.clone(),
+                        OutputItem::CodeInterpreterCall(ResponseCodeInterpreterCall {
+                            status: "in_progress".to_string(),
+                            code: String::new(),
+                            outputs: vec![],
+                            ..call.clone()
+                        }),
+                        OutputItem::CodeInterpreterCall(call.clone()),
+                    ),
+                    ResponseOutputItem::WebSearchCall(call) => (
+                        "web_search_call",
+                        call.id.clone(),
+                        OutputItem::WebSearchCall(ResponseWebSearchCall {
+                            status: "in_progress".to_string(),
+                            ..call.clone()
+                        }),
+                        OutputItem::WebSearchCall(call.clone()),
+                    ),
+                    ResponseOutputItem::FileSearchCall(call) => (
+                        "file_search_call",
+                        call.id.clone(),
+                        OutputItem::FileSearchCall(ResponseFileSearchCall {
+                            status: "in_progress".to_string(),
+                            results: vec![],
+                            ..call.clone()
+                        }),
+                        OutputItem::FileSearchCall(call.clone()),
+                    ),
+                    _ => continue,
+                };
+
+                let output_item_added_event = ResponseOutputItemAddedEvent {
+                    _type: "response.output_item.added".to_string(),
+                    sequence_number,
+                    output_index,
+                    item: added,
+                };
+                yield Ok::<_, Infallible>(Event::default().event("response.output_item.added").data(serde_json::to_string(&output_item_added_event).unwrap()));
+                sequence_number += 1;
+
+                let in_progress_event = ResponseToolCallEvent {
+                    _type: format!("response.{}.in_progress", kind),
+                    sequence_number,
+                    output_index,
+                    item_id: item_id.clone(),
+                };
+                yield Ok::<_, Infallible>(Event::default().event(&in_progress_event._type).data(serde_json::to_string(&in_progress_event).unwrap()));
+                sequence_number += 1;
+
+                if let ResponseOutputItem::McpCall(call) = item {
+                    let arguments_done_event = ResponseFunctionCallArgumentsDoneEvent {
+                        _type: "response.mcp_call_arguments.done".to_string(),
+                        sequence_number,
+                        output_index,
+                        item_id: item_id.clone(),
+                        arguments: call.arguments.clone(),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("response.mcp_call_arguments.done").data(serde_json::to_string(&arguments_done_event).unwrap()));
+                    sequence_number += 1;
+                }
+                if matches!(item, ResponseOutputItem::WebSearchCall(_) | ResponseOutputItem::FileSearchCall(_)) {
+                    let searching_event = ResponseToolCallEvent {
+                        _type: format!("response.{}.searching", kind),
+                        sequence_number,
+                        output_index,
+                        item_id: item_id.clone(),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event(&searching_event._type).data(serde_json::to_string(&searching_event).unwrap()));
+                    sequence_number += 1;
+                }
+                if let ResponseOutputItem::CodeInterpreterCall(call) = item {
+                    let code_done_event = ResponseCodeInterpreterCallCodeDoneEvent {
+                        _type: "response.code_interpreter_call_code.done".to_string(),
+                        sequence_number,
+                        output_index,
+                        item_id: item_id.clone(),
+                        code: call.code.clone(),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("response.code_interpreter_call_code.done").data(serde_json::to_string(&code_done_event).unwrap()));
+                    sequence_number += 1;
+
+                    let interpreting_event = ResponseToolCallEvent {
+                        _type: "response.code_interpreter_call.interpreting".to_string(),
+                        sequence_number,
+                        output_index,
+                        item_id: item_id.clone(),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("response.code_interpreter_call.interpreting").data(serde_json::to_string(&interpreting_event).unwrap()));
+                    sequence_number += 1;
+                }
+                sleep(Duration::from_millis(10)).await;
+
+                let completed_event = ResponseToolCallEvent {
+                    _type: format!("response.{}.completed", kind),
+                    sequence_number,
+                    output_index,
+                    item_id,
+                };
+                yield Ok::<_, Infallible>(Event::default().event(&completed_event._type).data(serde_json::to_string(&completed_event).unwrap()));
+                sequence_number += 1;
+
+                let output_item_done_event = ResponseOutputItemDoneEvent {
+                    _type: "response.output_item.done".to_string(),
+                    sequence_number,
+                    output_index,
+                    item: done,
+                };
+                response.output.push(item.clone());
+                yield Ok::<_, Infallible>(Event::default().event("response.output_item.done").data(serde_json::to_string(&output_item_done_event).unwrap()));
+                sequence_number += 1;
+            }
+
+            // 4b. The tool call takes the place of the message
+            if let Some(item) = tool_call.clone() {
+                let added = match &item {
+                    ResponseOutputItem::FunctionCall(call) => ResponseOutputItem::FunctionCall(ResponseFunctionToolCall {
+                        arguments: String::new(),
+                        status: "in_progress".to_string(),
+                        ..call.clone()
+                    }),
+                    ResponseOutputItem::ComputerCall(call) => ResponseOutputItem::ComputerCall(ResponseComputerToolCall {
+                        status: "in_progress".to_string(),
+                        ..call.clone()
+                    }),
+                    item => item.clone(),
+                };
+                let output_item_added_event = ResponseOutputItemAddedEvent {
+                    _type: "response.output_item.added".to_string(),
+                    sequence_number,
+                    output_index: message_index,
+                    item: added.into(),
+                };
+                yield Ok::<_, Infallible>(Event::default().event("response.output_item.added").data(serde_json::to_string(&output_item_added_event).unwrap()));
+                sequence_number += 1;
+
+                if let ResponseOutputItem::FunctionCall(call) = &item {
+                    let characters = call.arguments.chars().collect::<Vec<_>>();
+                    let chunks = characters.chunks(5).collect::<Vec<_>>();
+                    for (index, chunk) in chunks.into_iter().enumerate() {
+                        let obfuscation: String = rand::thread_rng()
+                            .sample_iter(&Alphanumeric)
+                            .take(10)
+                            .map(char::from)
+                            .collect();
+                        let delta_event = ResponseFunctionCallArgumentsDeltaEvent {
+                            _type: "response.function_call_arguments.delta".to_string(),
+                            sequence_number,
+                            output_index: message_index,
+                            item_id: call.id.clone(),
+                            delta: chunk.iter().collect(),
+                            obfuscation,
+                        };
+                        yield Ok::<_, Infallible>(Event::default().event("response.function_call_arguments.delta").data(serde_json::to_string(&delta_event).unwrap()));
+                        sequence_number += 1;
+                        sleep(state.chunk_delay(Duration::from_millis(10), index)).await;
+                    }
+
+                    let arguments_done_event = ResponseFunctionCallArgumentsDoneEvent {
+                        _type: "response.function_call_arguments.done".to_string(),
+                        sequence_number,
+                        output_index: message_index,
+                        item_id: call.id.clone(),
+                        arguments: call.arguments.clone(),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("response.function_call_arguments.done").data(serde_json::to_string(&arguments_done_event).unwrap()));
+                    sequence_number += 1;
+                }
+
+                let output_item_done_event = ResponseOutputItemDoneEvent {
+                    _type: "response.output_item.done".to_string(),
+                    sequence_number,
+                    output_index: message_index,
+                    item: item.clone().into(),
+                };
+                response.output.push(item);
+                yield Ok::<_, Infallible>(Event::default().event("response.output_item.done").data(serde_json::to_string(&output_item_done_event).unwrap()));
+                sequence_number += 1;
+
+                response.status = "completed".to_string();
+                response.usage = Some(ResponseUsage {
+                    input_tokens: prompt_tokens,
+                    input_tokens_details: InputTokensDetails { cached_tokens: 0 },
+                    output_tokens: completion_tokens,
+                    output_tokens_details: OutputTokensDetails { reasoning_tokens: 0 },
+                    total_tokens,
+                });
+                let completed_event = ResponseEvent {
+                    _type: "response.completed".to_string(),
+                    sequence_number,
+                    response: response.clone(),
+                };
+                yield Ok::<_, Infallible>(Event::default().event("response.completed").data(serde_json::to_string(&completed_event).unwrap()));
+                if store {
+                    state.stored_responses().lock().unwrap().push(response);
+                }
+                yield Ok::<_, Infallible>(Event::default().data("[DONE]"));
+                return;
+            }
+
             // 5. response.output_item.added (message)
             let message_item = ResponseOutputMessage {
                 id: message_id.clone(),
@@ -386,7 +980,7 @@ pub async fn responses(
             let output_item_added_event = ResponseOutputItemAddedEvent {
                 _type: "response.output_item.added".to_string(),
                 sequence_number,
-                output_index: 1,
+                output_index: message_index,
                 item: OutputItem::Message(message_item.clone()),
             };
             response.output.push(ResponseOutputItem::Message(message_item.clone()));
@@ -394,30 +988,44 @@ pub async fn responses(
             sequence_number += 1;
 
             // 6. response.content_part.added
-            let part = ResponseOutputText {
-                _type: "output_text".to_string(),
-                text: "".to_string(),
-                annotations: vec![],
-                logprobs: vec![],
-            };
+            let part = content_part(refusal.is_some(), String::new(), vec![]);
             let content_part_added_event = ResponseContentPartAddedEvent {
                 _type: "response.content_part.added".to_string(),
                 sequence_number,
-                output_index: 1,
+                output_index: message_index,
                 item_id: message_id.clone(),
                 content_index: 0,
                 part: part.clone(),
             };
-            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(1) {
+            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(message_index as usize) {
                 msg.content.push(part);
             }
             yield Ok::<_, Infallible>(Event::default().event("response.content_part.added").data(serde_json::to_string(&content_part_added_event).unwrap()));
             sequence_number += 1;
 
-            // 7. response.output_text.delta
-            let chunks = content.as_bytes().chunks(5);
-            for chunk in chunks {
-                let delta = String::from_utf8_lossy(chunk).to_string();
+            // 7. response.output_text.delta, or response.refusal.delta
+            let mut chunks = state.stream_pieces(&content, |content| {
+                let characters = content.chars().collect::<Vec<_>>();
+                characters.chunks(5).map(|chunk| chunk.iter().collect()).collect()
+            });
+            if let Some(allowed_tokens) = cutoff {
+                chunks.truncate(chunks.len() * allowed_tokens as usize / completion_tokens as usize);
+            }
+            for (index, delta) in chunks.into_iter().enumerate() {
+                if refusal.is_some() {
+                    let delta_event = ResponseRefusalDeltaEvent {
+                        _type: "response.refusal.delta".to_string(),
+                        sequence_number,
+                        output_index: message_index,
+                        item_id: message_id.clone(),
+                        content_index: 0,
+                        delta,
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("response.refusal.delta").data(serde_json::to_string(&delta_event).unwrap()));
+                    sequence_number += 1;
+                    sleep(state.chunk_delay(Duration::from_millis(10), index)).await;
+                    continue;
+                }
                 let obfuscation: String = rand::thread_rng()
                     .sample_iter(&Alphanumeric)
                     .take(10)
@@ -426,7 +1034,7 @@ pub async fn responses(
                 let delta_event = ResponseTextDeltaEvent {
                     _type: "response.output_text.delta".to_string(),
                     sequence_number,
-                    output_index: 1,
+                    output_index: message_index,
                     item_id: message_id.clone(),
                     content_index: 0,
                     delta,
@@ -435,41 +1043,61 @@ pub async fn responses(
                 };
                 yield Ok::<_, Infallible>(Event::default().event("response.output_text.delta").data(serde_json::to_string(&delta_event).unwrap()));
                 sequence_number += 1;
-                sleep(Duration::from_millis(10)).await;
+                sleep(state.chunk_delay(Duration::from_millis(10), index)).await;
             }
 
-            // 8. response.output_text.done
-            let text_done_event = ResponseTextDoneEvent {
-                _type: "response.output_text.done".to_string(),
-                sequence_number,
-                output_index: 1,
-                item_id: message_id.clone(),
-                content_index: 0,
-                text: content.clone(),
-                logprobs: vec![],
-            };
-            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(1) {
+            // 7b. The token budget ran out, end the stream with an error
+            if cutoff.is_some() {
+                let error_event = json!({
+                    "type": "error",
+                    "code": "rate_limit_exceeded",
+                    "message": "Rate limit reached for tokens per minute while generating the response.",
+                    "param": null,
+                    "sequence_number": sequence_number,
+                });
+                yield Ok::<_, Infallible>(Event::default().event("error").data(error_event.to_string()));
+                return;
+            }
+
+            // 8. response.output_text.done, or response.refusal.done
+            let part = content_part(refusal.is_some(), content.clone(), annotations.clone());
+            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(message_index as usize) {
                 if let Some(p) = msg.content.get_mut(0) {
-                    p.text = content.clone();
+                    *p = part.clone();
                 }
             }
-            yield Ok::<_, Infallible>(Event::default().event("response.output_text.done").data(serde_json::to_string(&text_done_event).unwrap()));
+            if refusal.is_some() {
+                let refusal_done_event = ResponseRefusalDoneEvent {
+                    _type: "response.refusal.done".to_string(),
+                    sequence_number,
+                    output_index: message_index,
+                    item_id: message_id.clone(),
+                    content_index: 0,
+                    refusal: content.clone(),
+                };
+                yield Ok::<_, Infallible>(Event::default().event("response.refusal.done").data(serde_json::to_string(&refusal_done_event).unwrap()));
+            } else {
+                let text_done_event = ResponseTextDoneEvent {
+                    _type: "response.output_text.done".to_string(),
+                    sequence_number,
+                    output_index: message_index,
+                    item_id: message_id.clone(),
+                    content_index: 0,
+                    text: content.clone(),
+                    logprobs: vec![],
+                };
+                yield Ok::<_, Infallible>(Event::default().event("response.output_text.done").data(serde_json::to_string(&text_done_event).unwrap()));
+            }
             sequence_number += 1;
 
             // 9. response.content_part.done
-            let part = ResponseOutputText {
-                _type: "output_text".to_string(),
-                text: content.clone(),
-                annotations: vec![],
-                logprobs: vec![],
-            };
             let content_part_done_event = ResponseContentPartDoneEvent {
                 _type: "response.content_part.done".to_string(),
                 sequence_number,
-                output_index: 1,
+                output_index: message_index,
                 item_id: message_id.clone(),
                 content_index: 0,
-                part,
+                part: part.clone(),
             };
             yield Ok::<_, Infallible>(Event::default().event("response.content_part.done").data(serde_json::to_string(&content_part_done_event).unwrap()));
             sequence_number += 1;
@@ -478,29 +1106,25 @@ pub async fn responses(
             let final_message_item = ResponseOutputMessage {
                 id: message_id.clone(),
                 _type: "message".to_string(),
-                content: vec![ResponseOutputText {
-                    _type: "output_text".to_string(),
-                    text: content.clone(),
-                    annotations: vec![],
-                    logprobs: vec![],
-                }],
+                content: vec![part],
                 role: "assistant".to_string(),
-                status: "completed".to_string(),
+                status: status.to_string(),
             };
             let output_item_done_event = ResponseOutputItemDoneEvent {
                 _type: "response.output_item.done".to_string(),
                 sequence_number,
-                output_index: 1,
+                output_index: message_index,
                 item: OutputItem::Message(final_message_item.clone()),
             };
-            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(1) {
+            if let Some(ResponseOutputItem::Message(msg)) = response.output.get_mut(message_index as usize) {
                 *msg = final_message_item;
             }
             yield Ok::<_, Infallible>(Event::default().event("response.output_item.done").data(serde_json::to_string(&output_item_done_event).unwrap()));
             sequence_number += 1;
 
-            // 11. response.completed
-            response.status = "completed".to_string();
+            // 11. response.completed, or response.incomplete
+            response.status = status.to_string();
+            response.incomplete_details = incomplete_details.clone();
             response.usage = Some(ResponseUsage {
                 input_tokens: prompt_tokens,
                 input_tokens_details: InputTokensDetails { cached_tokens: 0 },
@@ -509,30 +1133,37 @@ pub async fn responses(
                 total_tokens: total_tokens + 128,
             });
             let completed_event = ResponseEvent {
-                _type: "response.completed".to_string(),
+                _type: format!("response.{}", status),
                 sequence_number,
                 response: response.clone(),
             };
-            yield Ok::<_, Infallible>(Event::default().event("response.completed").data(serde_json::to_string(&completed_event).unwrap()));
+            yield Ok::<_, Infallible>(Event::default().event(&completed_event._type).data(serde_json::to_string(&completed_event).unwrap()));
+            if store {
+                state.stored_responses().lock().unwrap().push(response);
+            }
 
             // End of stream
             yield Ok::<_, Infallible>(Event::default().data("[DONE]"));
         };
 
-        return Sse::new(stream).into_response();
+        return (cost_headers, Sse::new(resume::store(&stream_state, stream))).into_response();
     } else {
-        let output_text = ResponseOutputText {
-            _type: "output_text".to_string(),
-            text: content.clone(),
-            ..Default::default()
-        };
+        let background = payload.background.unwrap_or(false);
+        if !background {
+            state.simulate_generation_time(completion_tokens).await;
+        }
 
-        let message_item = ResponseOutputMessage {
-            id: message_id,
-            _type: "message".to_string(),
-            content: vec![output_text],
-            role: "assistant".to_string(),
-            status: "completed".to_string(),
+        let part = content_part(refusal.is_some(), content.clone(), annotations);
+
+        let output = match tool_call {
+            Some(item) => item,
+            None => ResponseOutputItem::Message(ResponseOutputMessage {
+                id: message_id,
+                _type: "message".to_string(),
+                content: vec![part],
+                role: "assistant".to_string(),
+                status: status.to_string(),
+            }),
         };
 
         let response = Response {
@@ -540,8 +1171,9 @@ pub async fn responses(
             object: "response".to_string(),
             created_at,
             model,
-            status: "completed".to_string(),
-            output: vec![ResponseOutputItem::Message(message_item)],
+            status: status.to_string(),
+            incomplete_details,
+            output: hosted_items.into_iter().chain([output]).collect(),
             usage: Some(ResponseUsage {
                 input_tokens: prompt_tokens,
                 input_tokens_details: InputTokensDetails { cached_tokens: 0 },
@@ -556,24 +1188,641 @@ pub async fn responses(
             temperature: 1.0,
             tool_choice: "auto".to_string(),
             top_p: 1.0,
-            background: false,
+            background,
             reasoning: Reasoning {
                 effort: "medium".to_string(),
                 ..Default::default()
             },
             service_tier: "auto".to_string(),
             text: ResponseTextConfig {
-                format: ResponseFormatText {
-                    _type: "text".to_string(),
-                },
+                format: text_format,
                 verbosity: "medium".to_string(),
             },
             top_logprobs: 0,
             truncation: "disabled".to_string(),
-            store: false,
+            store,
+            previous_response_id: payload.previous_response_id.clone(),
+            prompt: payload.prompt.clone(),
+            metadata,
+            prompt_cache_key: payload._other["prompt_cache_key"]
+                .as_str()
+                .map(String::from),
+            safety_identifier: payload._other["safety_identifier"]
+                .as_str()
+                .map(String::from),
+            input_items,
             ..Default::default()
         };
 
-        return (headers, Json(json!(response))).into_response();
+        // Background responses are returned right away and kept to be polled until completed
+        if background {
+            let queued = Response {
+                status: "queued".to_string(),
+                output: vec![],
+                usage: None,
+                ..response.clone()
+            };
+            state
+                .stored_responses()
+                .lock()
+                .unwrap()
+                .push(queued.clone());
+            tokio::spawn(run_in_background(state.0.clone(), response));
+            return (headers, cost_headers, Json(json!(queued))).into_response();
+        }
+
+        if store {
+            state
+                .stored_responses()
+                .lock()
+                .unwrap()
+                .push(response.clone());
+        }
+
+        return (headers, cost_headers, Json(json!(response))).into_response();
+    }
+}
+
+/// Returns the text of an input item: the content of messages, the arguments of function calls
+/// and the output of function calls.
+fn item_text(item: &Value) -> String {
+    match &item["content"] {
+        Value::String(text) => return text.clone(),
+        Value::Array(parts) => {
+            return parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        _ => {}
+    }
+    item["arguments"]
+        .as_str()
+        .or(item["output"].as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Returns the text of the input, a string or a list of items.
+fn input_text(input: &Value) -> String {
+    match input {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(item_text).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Returns the input as a list of items, a string being a single user message.
+fn input_items(input: &Value) -> Vec<Value> {
+    let items = match input {
+        Value::Array(items) => items.clone(),
+        input => vec![json!({
+            "type": "message",
+            "role": "user",
+            "content": [{"type": "input_text", "text": input_text(input)}],
+        })],
+    };
+    items
+        .into_iter()
+        .map(|mut item| {
+            if item["id"].is_null() {
+                item["id"] = json!(generate_id("msg"));
+            }
+            if item["status"].is_null() {
+                item["status"] = json!("completed");
+            }
+            item
+        })
+        .collect()
+}
+
+/// Returns the content of the message, the text or a refusal.
+fn content_part(refusal: bool, text: String, annotations: Vec<Value>) -> ResponseContent {
+    if refusal {
+        return ResponseContent::Refusal(ResponseOutputRefusal {
+            _type: "refusal".to_string(),
+            refusal: text,
+        });
+    }
+    ResponseContent::OutputText(ResponseOutputText {
+        _type: "output_text".to_string(),
+        text,
+        annotations,
+        logprobs: vec![],
+    })
+}
+
+/// Lists the made up tools of each MCP server declared with a URL and, when `call` is set,
+/// calls the first tool of the first server.
+fn mcp_items(tools: Option<&Vec<Value>>, call: bool) -> Vec<ResponseOutputItem> {
+    let mut items = vec![];
+    let mut mcp_call = None;
+    for server in tools
+        .into_iter()
+        .flatten()
+        .filter(|tool| tool["type"] == "mcp" && tool["server_url"].is_string())
+    {
+        let server_label = server["server_label"].as_str().unwrap_or("mcp").to_string();
+        let listed = tools::mcp_tools(server.get("allowed_tools"));
+        if call && mcp_call.is_none() {
+            mcp_call = listed.first().map(|tool| ResponseMcpCall {
+                id: generate_id("mcp"),
+                _type: "mcp_call".to_string(),
+                server_label: server_label.clone(),
+                name: tool["name"].as_str().unwrap_or_default().to_string(),
+                arguments: tools::generate_arguments(tool.get("input_schema")),
+                output: Some(lipsum::lipsum_words(20)),
+                error: None,
+                approval_request_id: None,
+                status: "completed".to_string(),
+            });
+        }
+        items.push(ResponseOutputItem::McpListTools(ResponseMcpListTools {
+            id: generate_id("mcpl"),
+            _type: "mcp_list_tools".to_string(),
+            server_label,
+            tools: listed,
+            error: None,
+        }));
+    }
+    items.extend(mcp_call.map(ResponseOutputItem::McpCall));
+    items
+}
+
+/// Runs made up code in the container of the `code_interpreter` tool, if declared. The CSV file
+/// the code writes is stored like an uploaded one, and returned along with the message annotation
+/// citing it.
+fn code_interpreter_call(
+    state: &ServerState,
+    tools: Option<&Vec<Value>>,
+) -> Option<(ResponseCodeInterpreterCall, Value)> {
+    let tool = tools
+        .into_iter()
+        .flatten()
+        .find(|tool| tool["type"] == "code_interpreter")?;
+    let container_id = match &tool["container"] {
+        Value::String(id) => id.clone(),
+        _ => generate_id("cntr"),
+    };
+
+    let mut rng = rand::thread_rng();
+    let rows: Vec<(u32, u32)> = (0..rng.gen_range(3..8))
+        .map(|x| (x, rng.gen_range(0..100)))
+        .collect();
+    let csv = rows.iter().fold("x,y\n".to_string(), |csv, (x, y)| {
+        format!("{}{},{}\n", csv, x, y)
+    });
+    let file = StoredFile::new(
+        "output.csv".to_string(),
+        "assistants_output".to_string(),
+        csv.into_bytes(),
+    );
+    state.files().lock().unwrap().push(file.clone());
+
+    let code = format!(
+        "import random\n\nrows = [(x, random.randint(0, 99)) for x in range({})]\n\
+         with open(\"/mnt/data/output.csv\", \"w\") as f:\n    f.write(\"x,y\\n\")\n    \
+         f.writelines(f\"{{x}},{{y}}\\n\" for x, y in rows)\nprint(rows)",
+        rows.len()
+    );
+    let logs = format!(
+        "[{}]",
+        rows.iter()
+            .map(|(x, y)| format!("({}, {})", x, y))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let annotation = json!({
+        "type": "container_file_citation",
+        "container_id": container_id,
+        "file_id": file.id,
+        "filename": file.filename,
+        "start_index": 0,
+        "end_index": 0,
+    });
+
+    Some((
+        ResponseCodeInterpreterCall {
+            id: generate_id("ci"),
+            _type: "code_interpreter_call".to_string(),
+            status: "completed".to_string(),
+            code,
+            container_id,
+            outputs: vec![json!({"type": "logs", "logs": logs})],
+        },
+        annotation,
+    ))
+}
+
+/// Searches the web and the vector stores when the `web_search` and `file_search` tools are
+/// declared, returning the calls with made up results and the message annotations citing them.
+/// File search finds the files of the vector stores, if there are any.
+fn search_calls(
+    state: &ServerState,
+    tools: Option<&Vec<Value>>,
+    prompt: &str,
+) -> (Vec<ResponseOutputItem>, Vec<Value>) {
+    let mut items = vec![];
+    let mut annotations = vec![];
+    let query = match prompt.split_whitespace().take(8).collect::<Vec<_>>() {
+        words if words.is_empty() => lipsum::lipsum_words(4),
+        words => words.join(" "),
+    };
+    let mut rng = rand::thread_rng();
+
+    let declared = |types: &[&str]| {
+        tools
+            .into_iter()
+            .flatten()
+            .find(|tool| types.iter().any(|_type| tool["type"] == *_type))
+    };
+
+    if declared(&["web_search", "web_search_preview"]).is_some() {
+        let sources: Vec<Value> = ["example.com", "example.org", "example.net"]
+            .iter()
+            .map(|domain| {
+                let slug = lipsum::lipsum_words(3).to_lowercase().replace(['.', ','], "");
+                json!({"type": "url", "url": format!("https://{}/{}", domain, slug.replace(' ', "-"))})
+            })
+            .collect();
+        for source in &sources {
+            annotations.push(json!({
+                "type": "url_citation",
+                "url": source["url"],
+                "title": lipsum::lipsum_title(),
+                "start_index": 0,
+                "end_index": 0,
+            }));
+        }
+        items.push(ResponseOutputItem::WebSearchCall(ResponseWebSearchCall {
+            id: generate_id("ws"),
+            _type: "web_search_call".to_string(),
+            status: "completed".to_string(),
+            action: json!({"type": "search", "query": query, "sources": sources}),
+        }));
+    }
+
+    if let Some(tool) = declared(&["file_search"]) {
+        let vector_store_ids: Vec<&str> = tool["vector_store_ids"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let mut files: Vec<(String, String)> = state
+            .vector_stores()
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|store| vector_store_ids.contains(&store.id.as_str()))
+            .flat_map(|store| &store.files)
+            .map(|file| (file.id.clone(), file.filename.clone()))
+            .collect();
+        if files.is_empty() {
+            files.push((
+                format!("file-{:x}", rng.gen::<u128>()),
+                "document.pdf".to_string(),
+            ));
+        }
+        files.truncate(tool["max_num_results"].as_u64().unwrap_or(10) as usize);
+
+        let mut score = 1.0;
+        let results = files
+            .iter()
+            .map(|(file_id, filename)| {
+                score -= rng.gen_range(0.01..0.1);
+                json!({
+                    "file_id": file_id,
+                    "filename": filename,
+                    "score": score,
+                    "text": lipsum::lipsum_words(30),
+                    "attributes": {},
+                })
+            })
+            .collect();
+        for (index, (file_id, filename)) in files.iter().enumerate() {
+            annotations.push(json!({
+                "type": "file_citation",
+                "index": index,
+                "file_id": file_id,
+                "filename": filename,
+            }));
+        }
+        items.push(ResponseOutputItem::FileSearchCall(ResponseFileSearchCall {
+            id: generate_id("fs"),
+            _type: "file_search_call".to_string(),
+            status: "completed".to_string(),
+            queries: vec![query],
+            results,
+        }));
+    }
+
+    (items, annotations)
+}
+
+fn tool_round_trip_error(message: String) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message).with_param("input")
+}
+
+/// Returns the number of `function_call_output` and `computer_call_output` items of the input,
+/// the latter must carry a screenshot. With `--tool-round-trips`, each of them must answer a call
+/// of the previous response or of the input, and each of those calls must be answered.
+fn check_tool_outputs(state: &ServerState, payload: &ResponsesRequest) -> Result<usize, ApiError> {
+    let items = match &payload.input {
+        Some(Value::Array(items)) => items.as_slice(),
+        _ => &[],
+    };
+    if let Some(item) = items.iter().find(|item| {
+        item["type"] == "computer_call_output" && item["output"]["type"] != "computer_screenshot"
+    }) {
+        return Err(tool_round_trip_error(format!(
+            "Computer call output with call_id {} must be a computer_screenshot.",
+            item["call_id"].as_str().unwrap_or_default()
+        )));
+    }
+    let outputs = items
+        .iter()
+        .filter(|item| {
+            item["type"] == "function_call_output" || item["type"] == "computer_call_output"
+        })
+        .filter_map(|item| Some((item["type"].as_str()?, item["call_id"].as_str()?)))
+        .collect::<Vec<_>>();
+    if !state.args().tool_round_trips {
+        return Ok(outputs.len());
+    }
+
+    let mut calls = items
+        .iter()
+        .filter(|item| item["type"] == "function_call" || item["type"] == "computer_call")
+        .filter_map(|item| {
+            Some((
+                item["type"].as_str()?.to_string(),
+                item["call_id"].as_str()?.to_string(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    if let Some(id) = &payload.previous_response_id {
+        let responses = state.stored_responses().lock().unwrap();
+        if let Some(previous) = responses.iter().find(|response| &response.id == id) {
+            calls.extend(previous.output.iter().filter_map(|item| match item {
+                ResponseOutputItem::FunctionCall(call) => {
+                    Some((call._type.clone(), call.call_id.clone()))
+                }
+                ResponseOutputItem::ComputerCall(call) => {
+                    Some((call._type.clone(), call.call_id.clone()))
+                }
+                _ => None,
+            }));
+        }
+    }
+
+    if let Some((kind, output)) = outputs
+        .iter()
+        .find(|(_, output)| !calls.iter().any(|(_, call)| call == output))
+    {
+        return Err(tool_round_trip_error(format!(
+            "No tool call found for {} with call_id {}.",
+            kind.replace('_', " "),
+            output
+        )));
+    }
+    if let Some((kind, call)) = calls
+        .iter()
+        .find(|(_, call)| !outputs.iter().any(|(_, output)| output == call))
+    {
+        return Err(tool_round_trip_error(format!(
+            "No tool output found for {} {}.",
+            kind.replace('_', " "),
+            call
+        )));
+    }
+    Ok(outputs.len())
+}
+
+/// Asks to act on the computer when the computer use tool is declared.
+fn computer_call(tools: Option<&Vec<Value>>) -> Option<ResponseComputerToolCall> {
+    let tool = tools
+        .into_iter()
+        .flatten()
+        .find(|tool| tool["type"] == "computer_use_preview" || tool["type"] == "computer_use")?;
+    Some(ResponseComputerToolCall {
+        id: generate_id("cu"),
+        _type: "computer_call".to_string(),
+        call_id: generate_id("call"),
+        action: tools::computer_action(
+            tool["display_width"].as_u64().unwrap_or(1024),
+            tool["display_height"].as_u64().unwrap_or(768),
+        ),
+        pending_safety_checks: vec![],
+        status: "completed".to_string(),
+    })
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("Response with id '{}' not found.", id),
+    )
+    .with_param("response_id")
+}
+
+/// Checks the shape of a prompt template reference: a string `id`, an optional string `version`
+/// and an optional object of `variables`.
+fn check_prompt(prompt: &Value) -> Result<(), ApiError> {
+    let invalid = |param: &str, expected: &str, value: &Value| {
+        Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!(
+                "Invalid type for '{}': expected {}, but got {} instead.",
+                param, expected, value
+            ),
+        )
+        .with_param(param))
+    };
+    if !prompt.is_object() {
+        return invalid("prompt", "an object", prompt);
+    }
+    match &prompt["id"] {
+        Value::String(_) => {}
+        Value::Null => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "Missing required parameter: 'prompt.id'.",
+            )
+            .with_code("missing_required_parameter")
+            .with_param("prompt.id"))
+        }
+        id => return invalid("prompt.id", "a string", id),
+    }
+    match &prompt["version"] {
+        Value::Null | Value::String(_) => {}
+        version => return invalid("prompt.version", "a string", version),
+    }
+    match &prompt["variables"] {
+        Value::Null | Value::Object(_) => Ok(()),
+        variables => invalid("prompt.variables", "an object", variables),
+    }
+}
+
+fn previous_response_not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_REQUEST,
+        "invalid_request_error",
+        format!("Previous response with id '{}' not found.", id),
+    )
+    .with_code("previous_response_not_found")
+    .with_param("previous_response_id")
+}
+
+/// Returns the tokens of the conversation ending with the stored response `id`, its input
+/// included, or `None` if there's no such response.
+fn conversation_tokens(state: &ServerState, id: &str) -> Option<u32> {
+    let responses = state.stored_responses().lock().unwrap();
+    let response = responses.iter().find(|response| response.id == id)?;
+    Some(
+        response
+            .usage
+            .as_ref()
+            .map_or(0, |usage| usage.total_tokens),
+    )
+}
+
+/// Moves a background response through `queued → in_progress → completed` within
+/// `--background-duration`. Cancelled responses stop where they are.
+async fn run_in_background(state: ServerState, completed: Response) {
+    let duration = state.args().background_duration;
+    tokio::time::sleep(duration / 10).await;
+    let started = state.update_stored_response(&completed.id, |response| {
+        if response.status != "queued" {
+            return false;
+        }
+        response.status = "in_progress".to_string();
+        true
+    });
+    if started != Some(true) {
+        return;
+    }
+
+    tokio::time::sleep(duration - duration / 10).await;
+    let id = completed.id.clone();
+    state.update_stored_response(&id, |response| {
+        if response.status == "in_progress" {
+            *response = completed;
+        }
+    });
+}
+
+/// Returns a response created with `store: true` or `background: true`.
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> impl IntoResponse {
+    let response = state
+        .stored_responses()
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|response| response.id == id)
+        .cloned();
+    match response {
+        Some(response) => Json(response).into_response(),
+        None => state.error_response(not_found(&id)),
+    }
+}
+
+/// Deletes a stored response, a background one stops where it is.
+pub async fn delete(state: State<ServerState>, Path(id): Path<String>) -> impl IntoResponse {
+    let removed = {
+        let mut responses = state.stored_responses().lock().unwrap();
+        let before = responses.len();
+        responses.retain(|response| response.id != id);
+        responses.len() < before
+    };
+    if !removed {
+        return state.error_response(not_found(&id));
+    }
+    Json(json!({
+        "id": id,
+        "object": "response",
+        "deleted": true,
+    }))
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub order: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Lists the input items of a stored response, newest first unless `order` is `asc`.
+pub async fn input_items(
+    state: State<ServerState>,
+    Path(id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
+    let response = state
+        .stored_responses()
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|response| response.id == id)
+        .cloned();
+    let Some(response) = response else {
+        return state.error_response(not_found(&id));
+    };
+
+    let mut items = response.input_items;
+    if query.order.as_deref() != Some("asc") {
+        items.reverse();
+    }
+    if let Some(after) = &query.after {
+        let start = items
+            .iter()
+            .position(|item| item["id"] == after.as_str())
+            .map_or(0, |position| position + 1);
+        items.drain(..start);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+
+    Json(json!({
+        "object": "list",
+        "data": items,
+        "first_id": items.first().map(|item| item["id"].clone()),
+        "last_id": items.last().map(|item| item["id"].clone()),
+        "has_more": has_more,
+    }))
+    .into_response()
+}
+
+/// Cancels a background response that isn't finished yet.
+pub async fn cancel(state: State<ServerState>, Path(id): Path<String>) -> impl IntoResponse {
+    let response = state.update_stored_response(&id, |response| {
+        if response.background && matches!(response.status.as_str(), "queued" | "in_progress") {
+            response.status = "cancelled".to_string();
+        }
+        response.clone()
+    });
+    match response {
+        Some(response) if response.status == "cancelled" => Json(response).into_response(),
+        Some(response) => {
+            let message = if response.background {
+                format!(
+                    "Cannot cancel a response with status '{}'.",
+                    response.status
+                )
+            } else {
+                "Only responses created with background=true can be cancelled.".to_string()
+            };
+            state.error_response(
+                ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+                    .with_param("response_id"),
+            )
+        }
+        None => state.error_response(not_found(&id)),
     }
 }
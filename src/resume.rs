@@ -0,0 +1,144 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{sse::Event, IntoResponse, Response, Sse},
+};
+use futures_util::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::Notify;
+
+use crate::server_state::ServerState;
+
+/// Number of streams kept around for clients to resume.
+const MAX_STORED_STREAMS: usize = 100;
+
+/// The events of a stream, kept while it's being generated so that clients reconnecting with
+/// `Last-Event-ID` can resume it.
+#[derive(Default)]
+pub struct StoredStream {
+    events: Mutex<Vec<(u64, Event)>>,
+    done: AtomicBool,
+    notify: Notify,
+}
+
+/// The most recent streams, along with the counter for the ids of their events.
+#[derive(Default)]
+pub struct StreamStore {
+    next_id: u64,
+    streams: VecDeque<Arc<StoredStream>>,
+}
+
+impl StreamStore {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Returns the stream containing the event with the given id and the position right after it.
+    fn find(&self, last_event_id: u64) -> Option<(Arc<StoredStream>, usize)> {
+        self.streams.iter().find_map(|stream| {
+            let events = stream.events.lock().unwrap();
+            events
+                .iter()
+                .position(|(id, _)| *id == last_event_id)
+                .map(|position| (stream.clone(), position + 1))
+        })
+    }
+}
+
+/// Tags the events of `source` with monotonically increasing ids and stores them. The stream is
+/// generated to completion even when the client goes away, so it can be resumed later.
+pub fn store<S>(state: &ServerState, source: S) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let stored = Arc::new(StoredStream::default());
+    let store = state.stream_store();
+    {
+        let mut store = store.lock().unwrap();
+        if store.streams.len() >= MAX_STORED_STREAMS {
+            store.streams.pop_front();
+        }
+        store.streams.push_back(stored.clone());
+    }
+
+    let producer = stored.clone();
+    tokio::spawn(async move {
+        futures_util::pin_mut!(source);
+        while let Some(Ok(event)) = source.next().await {
+            let id = store.lock().unwrap().next_id();
+            producer
+                .events
+                .lock()
+                .unwrap()
+                .push((id, event.id(id.to_string())));
+            producer.notify.notify_waiters();
+        }
+        producer.done.store(true, Ordering::SeqCst);
+        producer.notify.notify_waiters();
+    });
+
+    follow(stored, 0)
+}
+
+/// Sends the events of a stored stream starting at `position`, waiting for the new ones until
+/// the stream is complete.
+fn follow(
+    stored: Arc<StoredStream>,
+    mut position: usize,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    async_stream::stream! {
+        loop {
+            let notified = stored.notify.notified();
+            let done = stored.done.load(Ordering::SeqCst);
+            let pending = stored.events.lock().unwrap()[position..]
+                .iter()
+                .map(|(_, event)| event.clone())
+                .collect::<Vec<_>>();
+
+            if pending.is_empty() {
+                if done {
+                    break;
+                }
+                notified.await;
+                continue;
+            }
+
+            position += pending.len();
+            for event in pending {
+                yield Ok(event);
+            }
+        }
+    }
+}
+
+/// Resumes a stored stream right after the event sent with the `Last-Event-ID` header.
+pub async fn resume(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    let last_event_id = req
+        .headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let Some(last_event_id) = last_event_id else {
+        return next.run(req).await;
+    };
+
+    let found = state.stream_store().lock().unwrap().find(last_event_id);
+    match found {
+        Some((stored, position)) => {
+            log::debug!("Resuming stream after event {}", last_event_id);
+            Sse::new(follow(stored, position)).into_response()
+        }
+        None => next.run(req).await,
+    }
+}
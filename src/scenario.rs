@@ -0,0 +1,62 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::journal::RequestRecord;
+use crate::stubs::Stub;
+
+/// Test fixtures loaded from the file passed with `--scenario`.
+#[derive(Deserialize, Debug, Default)]
+pub struct Scenario {
+    #[serde(default)]
+    pub expectations: Vec<Expectation>,
+    /// Responses served to the matching requests, see `/__admin/stub`.
+    #[serde(default)]
+    pub stubs: Vec<Stub>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// The number of calls a test run is expected to make to an endpoint, e.g.
+/// `{"path": "/v1/chat/completions", "model": "gpt-4o", "count": 3}`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Expectation {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at_least: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at_most: Option<usize>,
+}
+
+impl Expectation {
+    pub fn matches(&self, record: &RequestRecord) -> bool {
+        record.path == self.path
+            && self
+                .method
+                .as_ref()
+                .is_none_or(|method| method.eq_ignore_ascii_case(&record.method))
+            && self
+                .model
+                .as_ref()
+                .is_none_or(|model| record.model.as_ref() == Some(model))
+    }
+
+    pub fn is_satisfied_by(&self, actual: usize) -> bool {
+        self.count.is_none_or(|count| actual == count)
+            && self.at_least.is_none_or(|min| actual >= min)
+            && self.at_most.is_none_or(|max| actual <= max)
+    }
+}
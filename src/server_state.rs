@@ -1,36 +1,624 @@
 // Copyright 2025 Massimiliano Pippi
 // SPDX-License-Identifier: MIT
 
-use axum::http::HeaderMap;
+use axum::{
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Timelike;
 use humantime;
+use once_cell::sync::Lazy;
 use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use serde_json::Value;
 use std::{
-    collections::VecDeque,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, SystemTime},
 };
-use tiktoken_rs::cl100k_base;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+
+use crate::assistants::AssistantsStore;
+use crate::auth::IssuedKey;
+use crate::batches::Batch;
+use crate::config::{Price, Profile, ScheduleEntry, SizeRule};
+use crate::corpus::Corpus;
+use crate::errors::ApiError;
+use crate::files::StoredFile;
+use crate::fine_tuning::FineTuningJob;
+use crate::fixtures::{Fixture, Fixtures};
+use crate::journal::{RequestRecord, MAX_RECORDED_REQUESTS};
+use crate::language;
+use crate::latency::LatencyDistribution;
+use crate::magic::Directives;
+use crate::markov::MarkovChain;
+use crate::playback::Playback;
+use crate::responses::Response as StoredResponse;
+use crate::resume::StreamStore;
+use crate::scenario::Scenario;
+use crate::snapshot::{self, Snapshot};
+use crate::stats::{Cost, DurationHistogram, Timeseries};
+use crate::stubs::Stub;
+use crate::template;
+use crate::vector_stores::VectorStore;
+use crate::vision;
+use crate::{
+    parse_range, response_length_range, Args, ContentLanguage, ContentMode, EchoTransform,
+    StreamGranularity, TextGenerator, UsageModel, MAX_SLOWDOWN_MS,
+};
+
+/// Delay between streamed chunks a decaying stream starts from.
+const STREAM_DECAY_BASE_MS: u64 = 10;
+/// Upper bound for the delay between the chunks of a decaying stream.
+const STREAM_DECAY_MAX_SECS: f64 = 60.0;
+/// Latency a slower warm-up multiplies when neither `--slowdown` nor `--latency` set one.
+const WARMUP_BASE_LATENCY_MS: u64 = 100;
+
+/// Reset values real responses have carried, replacing the regular ones with
+/// `--odd-ratelimit-headers-rate`: zero, fractional, sub-second, compound, huge and unitless.
+const ODD_RESET_VALUES: [&str; 8] = ["0s", "0.5s", "1.234s", "250ms", "6m0.5s", "8760h", "60", ""];
+
+/// The tokenizer of the OpenAI models, built once as loading its ranks takes a while.
+static BPE: Lazy<Option<CoreBPE>> = Lazy::new(|| cl100k_base().ok());
+
+fn bpe() -> anyhow::Result<&'static CoreBPE> {
+    BPE.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Failed to load the cl100k_base tokenizer"))
+}
+
+/// What the model says instead of answering, with `--refusal-rate`.
+const REFUSAL: &str = "I'm sorry, but I can't help with that.";
+
+/// Degraded behaviour applied during the warm-up window right after startup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Warmup {
+    pub duration: Duration,
+    pub latency_factor: f64,
+    pub rate_limit_factor: f64,
+}
+
+impl Warmup {
+    /// Parses specs like `30s:tripled-latency` or `1m:2.5x-latency,halved-rpm`. Latency factors
+    /// must be finite numbers above zero.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (duration_str, effects) = match spec.find(':') {
+            Some(pos) => (&spec[..pos], &spec[pos + 1..]),
+            None => (spec, "tripled-latency"),
+        };
+        let duration = humantime::parse_duration(duration_str).ok()?;
+
+        let mut warmup = Warmup {
+            duration,
+            latency_factor: 1.0,
+            rate_limit_factor: 1.0,
+        };
+        for effect in effects.split(',').map(str::trim) {
+            match effect {
+                "doubled-latency" => warmup.latency_factor = 2.0,
+                "tripled-latency" => warmup.latency_factor = 3.0,
+                "quadrupled-latency" => warmup.latency_factor = 4.0,
+                "halved-rpm" => warmup.rate_limit_factor = 0.5,
+                _ => {
+                    let factor = effect.strip_suffix("x-latency")?;
+                    warmup.latency_factor = factor
+                        .parse()
+                        .ok()
+                        .filter(|factor: &f64| factor.is_finite() && *factor > 0.0)?;
+                }
+            }
+        }
+        Some(warmup)
+    }
+}
 
-use crate::Args;
+/// Growth of the delay between the chunks of a stream, with `--stream-decay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamDecay {
+    pub every: usize,
+    pub factor: f64,
+}
+
+impl StreamDecay {
+    /// Parses specs like `10`, doubling the delay every 10 chunks, or `10:1.5`. The period must
+    /// be positive and the factor a finite number above zero.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (every, factor) = match spec.split_once(':') {
+            Some((every, factor)) => (every.trim().parse().ok()?, factor.trim().parse().ok()?),
+            None => (spec.trim().parse().ok()?, 2.0),
+        };
+        (every > 0 && f64::is_finite(factor) && factor > 0.0).then_some(Self { every, factor })
+    }
+}
 
 #[derive(Clone)]
 pub struct ServerState {
     args: Args,
+    stream_decay: Option<StreamDecay>,
+    warmup: Option<Warmup>,
+    started_at: SystemTime,
     request_timestamps: Arc<Mutex<VecDeque<SystemTime>>>,
     token_usage_timestamps: Arc<Mutex<VecDeque<(SystemTime, u32)>>>,
+    scenario: Arc<Scenario>,
+    playback: Arc<Playback>,
+    fixtures: Arc<Fixtures>,
+    size_rules: Arc<Vec<SizeRule>>,
+    journal: Arc<Mutex<VecDeque<RequestRecord>>>,
+    request_durations: Arc<Mutex<BTreeMap<(String, String), DurationHistogram>>>,
+    conversation_turns: Arc<Mutex<HashMap<String, u32>>>,
+    lifetime_token_usage: Arc<AtomicU64>,
+    api_key: Arc<Mutex<Option<IssuedKey>>>,
+    stream_store: Arc<Mutex<StreamStore>>,
+    timeseries: Arc<Mutex<Timeseries>>,
+    latency: Option<Arc<LatencyDistribution>>,
+    markov: Arc<MarkovChain>,
+    corpus: Option<Arc<Corpus>>,
+    stubs: Arc<Mutex<Vec<Stub>>>,
+    scenario_states: Arc<Mutex<HashMap<String, String>>>,
+    active_streams: Arc<Mutex<HashMap<String, u32>>>,
+    schedule: Arc<Vec<(ScheduleEntry, Profile, Args)>>,
+    prices: Arc<BTreeMap<String, Price>>,
+    costs: Arc<Mutex<BTreeMap<String, Cost>>>,
+    files: Arc<Mutex<Vec<StoredFile>>>,
+    batches: Arc<Mutex<Vec<Batch>>>,
+    fine_tuning_jobs: Arc<Mutex<Vec<FineTuningJob>>>,
+    assistants_store: Arc<Mutex<AssistantsStore>>,
+    vector_stores: Arc<Mutex<Vec<VectorStore>>>,
+    stored_responses: Arc<Mutex<Vec<StoredResponse>>>,
+    stored_completions: Arc<Mutex<Vec<Value>>>,
 }
 
 impl ServerState {
     pub fn new(args: Args) -> Self {
+        let api_key = args.api_key.clone().map(IssuedKey::new);
+        let stream_decay = args.stream_decay.as_deref().and_then(StreamDecay::parse);
+        let warmup = args.warmup.as_deref().and_then(Warmup::parse);
+        Self {
+            args,
+            stream_decay,
+            warmup,
+            started_at: SystemTime::now(),
+            request_timestamps: Arc::new(Mutex::new(VecDeque::new())),
+            token_usage_timestamps: Arc::new(Mutex::new(VecDeque::new())),
+            scenario: Arc::new(Scenario::default()),
+            playback: Arc::new(Playback::default()),
+            fixtures: Arc::new(Fixtures::default()),
+            size_rules: Arc::new(Vec::new()),
+            journal: Arc::new(Mutex::new(VecDeque::new())),
+            request_durations: Arc::new(Mutex::new(BTreeMap::new())),
+            conversation_turns: Arc::new(Mutex::new(HashMap::new())),
+            lifetime_token_usage: Arc::new(AtomicU64::new(0)),
+            api_key: Arc::new(Mutex::new(api_key)),
+            stream_store: Arc::new(Mutex::new(StreamStore::default())),
+            timeseries: Arc::new(Mutex::new(Timeseries::default())),
+            latency: None,
+            markov: Arc::new(MarkovChain::bundled()),
+            corpus: None,
+            stubs: Arc::new(Mutex::new(Vec::new())),
+            scenario_states: Arc::new(Mutex::new(HashMap::new())),
+            active_streams: Arc::new(Mutex::new(HashMap::new())),
+            schedule: Arc::new(Vec::new()),
+            prices: Arc::new(BTreeMap::new()),
+            costs: Arc::new(Mutex::new(BTreeMap::new())),
+            files: Arc::new(Mutex::new(Vec::new())),
+            batches: Arc::new(Mutex::new(Vec::new())),
+            fine_tuning_jobs: Arc::new(Mutex::new(Vec::new())),
+            assistants_store: Arc::new(Mutex::new(AssistantsStore::default())),
+            vector_stores: Arc::new(Mutex::new(Vec::new())),
+            stored_responses: Arc::new(Mutex::new(Vec::new())),
+            stored_completions: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns a state with the behaviour overridden by `profile` and its own rate limit windows,
+    /// sharing everything else with this one.
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        let args = profile.apply(&self.args);
+        let schedule = self
+            .schedule
+            .iter()
+            .map(|(entry, scheduled, _)| (entry.clone(), scheduled.clone(), scheduled.apply(&args)))
+            .collect();
         Self {
             args,
             request_timestamps: Arc::new(Mutex::new(VecDeque::new())),
             token_usage_timestamps: Arc::new(Mutex::new(VecDeque::new())),
+            schedule: Arc::new(schedule),
+            ..self.clone()
+        }
+    }
+
+    /// Applies the profiles of `schedule` on top of the current behaviour while their window is
+    /// open. The first matching entry wins.
+    pub fn with_schedule(
+        mut self,
+        schedule: &[ScheduleEntry],
+        profiles: &BTreeMap<String, Profile>,
+    ) -> Self {
+        let schedule = schedule
+            .iter()
+            .map(|entry| {
+                let profile = profiles[&entry.profile].clone();
+                let args = profile.apply(&self.args);
+                (entry.clone(), profile, args)
+            })
+            .collect();
+        self.schedule = Arc::new(schedule);
+        self
+    }
+
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        for stub in &scenario.stubs {
+            self.add_stub(stub.clone());
+        }
+        self.scenario = Arc::new(scenario);
+        self
+    }
+
+    pub fn scenario(&self) -> &Scenario {
+        &self.scenario
+    }
+
+    pub fn with_playback(mut self, playback: Playback) -> Self {
+        self.playback = Arc::new(playback);
+        self
+    }
+
+    pub fn playback(&self) -> &Playback {
+        &self.playback
+    }
+
+    pub fn with_fixtures(mut self, fixtures: Fixtures) -> Self {
+        self.fixtures = Arc::new(fixtures);
+        self
+    }
+
+    /// Returns the fixture answering a request whose messages or input are `text`, if any.
+    pub fn fixture(&self, text: &str) -> Option<Fixture> {
+        self.fixtures.find(text).cloned()
+    }
+
+    pub fn stream_store(&self) -> Arc<Mutex<StreamStore>> {
+        self.stream_store.clone()
+    }
+
+    pub fn with_latency_distribution(mut self, latency: LatencyDistribution) -> Self {
+        self.latency = Some(Arc::new(latency));
+        self
+    }
+
+    /// Trains the markov generator on another corpus than the bundled one.
+    pub fn with_markov_chain(mut self, markov: MarkovChain) -> Self {
+        self.markov = Arc::new(markov);
+        self
+    }
+
+    /// Cuts the generated content from `corpus` instead of lorem ipsum.
+    pub fn with_corpus(mut self, corpus: Corpus) -> Self {
+        self.corpus = Some(Arc::new(corpus));
+        self
+    }
+
+    /// Number of streams in progress for each API key.
+    pub fn active_streams(&self) -> Arc<Mutex<HashMap<String, u32>>> {
+        self.active_streams.clone()
+    }
+
+    pub fn add_stub(&self, mut stub: Stub) -> String {
+        if stub.id.is_empty() {
+            stub.id = Stub::generate_id();
+        }
+        let id = stub.id.clone();
+        self.stubs.lock().unwrap().push(stub);
+        id
+    }
+
+    pub fn has_stubs(&self) -> bool {
+        !self.stubs.lock().unwrap().is_empty()
+    }
+
+    /// Removes and returns the oldest stub matching the request in the current scenario states,
+    /// moving its scenario to the next state.
+    pub fn take_stub(&self, path: &str, body: &str) -> Option<Stub> {
+        let mut stubs = self.stubs.lock().unwrap();
+        let mut states = self.scenario_states.lock().unwrap();
+        let index = stubs
+            .iter()
+            .position(|stub| stub.is_enabled(&states) && stub.request.matches(path, body))?;
+        let stub = stubs.remove(index);
+
+        if let (Some(scenario), Some(new_state)) = (&stub.scenario, &stub.new_state) {
+            log::debug!("Scenario '{}' moved to state '{}'", scenario, new_state);
+            states.insert(scenario.clone(), new_state.clone());
+        }
+        Some(stub)
+    }
+
+    pub fn scenario_states(&self) -> HashMap<String, String> {
+        self.scenario_states.lock().unwrap().clone()
+    }
+
+    pub fn reset_scenario_states(&self) {
+        self.scenario_states.lock().unwrap().clear();
+    }
+
+    pub fn timeseries(&self) -> &Mutex<Timeseries> {
+        &self.timeseries
+    }
+
+    /// The durations of every request served, by method and endpoint.
+    pub fn request_durations(&self) -> &Mutex<BTreeMap<(String, String), DurationHistogram>> {
+        &self.request_durations
+    }
+
+    pub fn with_prices(mut self, prices: BTreeMap<String, Price>) -> Self {
+        self.prices = Arc::new(prices);
+        self
+    }
+
+    pub fn prices(&self) -> &BTreeMap<String, Price> {
+        &self.prices
+    }
+
+    pub fn costs(&self) -> &Mutex<BTreeMap<String, Cost>> {
+        &self.costs
+    }
+
+    /// Estimates the cost of a request with the price table, adding it to the spend reported by
+    /// `/__stats`. The cost is returned in the `x-roy-cost-usd` header, which is omitted for
+    /// models without a price.
+    pub fn record_cost(
+        &self,
+        model: &str,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    ) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let Some(price) = self.prices.get(model) else {
+            return headers;
+        };
+        let usd = price.cost(prompt_tokens, completion_tokens);
+
+        let mut costs = self.costs.lock().unwrap();
+        let cost = costs.entry(model.to_string()).or_default();
+        cost.requests += 1;
+        cost.prompt_tokens += prompt_tokens as u64;
+        cost.completion_tokens += completion_tokens as u64;
+        cost.usd += usd;
+
+        headers.insert(
+            "x-roy-cost-usd",
+            format!("{:.6}", usd)
+                .parse()
+                .expect("a number is a valid header"),
+        );
+        headers
+    }
+
+    pub fn files(&self) -> &Mutex<Vec<StoredFile>> {
+        &self.files
+    }
+
+    pub fn file(&self, id: &str) -> Option<StoredFile> {
+        let files = self.files.lock().unwrap();
+        files.iter().find(|file| file.id == id).cloned()
+    }
+
+    pub fn batches(&self) -> &Mutex<Vec<Batch>> {
+        &self.batches
+    }
+
+    /// Applies `update` to the batch with the given id, returning its result if the batch exists.
+    pub fn update_batch<T>(&self, id: &str, update: impl FnOnce(&mut Batch) -> T) -> Option<T> {
+        let mut batches = self.batches.lock().unwrap();
+        batches.iter_mut().find(|batch| batch.id == id).map(update)
+    }
+
+    pub fn fine_tuning_jobs(&self) -> &Mutex<Vec<FineTuningJob>> {
+        &self.fine_tuning_jobs
+    }
+
+    /// Applies `update` to the fine-tuning job with the given id, returning its result if the
+    /// job exists.
+    pub fn update_fine_tuning_job<T>(
+        &self,
+        id: &str,
+        update: impl FnOnce(&mut FineTuningJob) -> T,
+    ) -> Option<T> {
+        let mut jobs = self.fine_tuning_jobs.lock().unwrap();
+        jobs.iter_mut().find(|job| job.id == id).map(update)
+    }
+
+    pub fn assistants_store(&self) -> &Mutex<AssistantsStore> {
+        &self.assistants_store
+    }
+
+    pub fn vector_stores(&self) -> &Mutex<Vec<VectorStore>> {
+        &self.vector_stores
+    }
+
+    /// Returns the responses created with `store: true`, the ones older than `--store-ttl` are
+    /// dropped.
+    pub fn stored_responses(&self) -> &Mutex<Vec<StoredResponse>> {
+        if let Some(ttl) = self.args().store_ttl {
+            self.stored_responses
+                .lock()
+                .unwrap()
+                .retain(|response| !response.expired(ttl));
+        }
+        &self.stored_responses
+    }
+
+    /// Applies `update` to the stored response with the given id, returning its result if the
+    /// response exists.
+    pub fn update_stored_response<T>(
+        &self,
+        id: &str,
+        update: impl FnOnce(&mut StoredResponse) -> T,
+    ) -> Option<T> {
+        let mut responses = self.stored_responses().lock().unwrap();
+        responses
+            .iter_mut()
+            .find(|response| response.id == id)
+            .map(update)
+    }
+
+    /// Returns the chat completions created with `store: true`.
+    pub fn stored_completions(&self) -> &Mutex<Vec<Value>> {
+        &self.stored_completions
+    }
+
+    pub fn with_size_rules(mut self, size_rules: Vec<SizeRule>) -> Self {
+        self.size_rules = Arc::new(size_rules);
+        self
+    }
+
+    /// Applies the size rule with the highest threshold the prompt reaches, waiting for its
+    /// extra latency and returning the error code to respond with, if any.
+    pub async fn apply_size_rules(&self, prompt_tokens: u32) -> Option<u16> {
+        let rule = self
+            .size_rules
+            .iter()
+            .filter(|rule| prompt_tokens >= rule.min_prompt_tokens)
+            .max_by_key(|rule| rule.min_prompt_tokens)?;
+
+        let latency = rule.extra_latency();
+        if !latency.is_zero() {
+            log::debug!(
+                "Delaying request with {} prompt tokens by {:?}",
+                prompt_tokens,
+                latency
+            );
+            tokio::time::sleep(latency).await;
+        }
+
+        match (rule.error_code, rule.error_rate) {
+            (Some(code), Some(rate)) if rand::thread_rng().gen_range(0..100) < rate => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Records a request in the journal, which keeps the last `MAX_RECORDED_REQUESTS`, and in
+    /// the duration histograms, which count them all.
+    pub fn record_request(&self, record: RequestRecord) {
+        self.request_durations
+            .lock()
+            .unwrap()
+            .entry((record.method.clone(), record.endpoint.clone()))
+            .or_default()
+            .record(&record);
+
+        let mut journal = self.journal.lock().unwrap();
+        if journal.len() >= MAX_RECORDED_REQUESTS {
+            journal.pop_front();
+        }
+        journal.push_back(record);
+    }
+
+    pub fn recorded_requests(&self) -> Vec<RequestRecord> {
+        self.journal.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn args(&self) -> &Args {
+        &self.args
+    }
+
+    /// Number of entries of the time-of-day schedule.
+    pub fn schedule_len(&self) -> usize {
+        self.schedule.len()
+    }
+
+    /// Returns the index of the schedule entry whose window is open at `minute` past midnight,
+    /// the first one when windows overlap.
+    pub fn schedule_entry_at(&self, minute: u32) -> Option<usize> {
+        self.schedule
+            .iter()
+            .position(|(entry, _, _)| entry.is_active_at(minute))
+    }
+
+    /// Returns the index of the schedule entry in effect right now, in the local timezone.
+    pub fn active_schedule_entry(&self) -> Option<usize> {
+        let now = chrono::Local::now();
+        self.schedule_entry_at(now.hour() * 60 + now.minute())
+    }
+
+    /// Returns a state fixed on the behaviour of the schedule entry at `index`, or on the
+    /// unscheduled one, sharing everything else with this one.
+    pub fn scheduled(&self, index: Option<usize>) -> Self {
+        let args = match index {
+            Some(index) => self.schedule[index].2.clone(),
+            None => self.args.clone(),
+        };
+        Self {
+            args,
+            schedule: Arc::new(Vec::new()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the API key clients must use, if any. Once `--api-key-ttl` elapses the key
+    /// becomes invalid and is replaced by a freshly generated one.
+    pub fn current_api_key(&self) -> Option<IssuedKey> {
+        let mut api_key = self.api_key.lock().unwrap();
+        let issued = api_key.as_mut()?;
+
+        if let Some(ttl) = self.args().api_key_ttl {
+            if issued.issued_at.elapsed().unwrap_or(Duration::ZERO) >= ttl {
+                *issued = IssuedKey::generate();
+                log::info!("API key expired, the new key is {}", issued.key);
+            }
+        }
+
+        Some(issued.clone())
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Returns the warm-up settings if the server is still inside the warm-up window.
+    pub fn active_warmup(&self) -> Option<Warmup> {
+        let warmup = self.warmup?;
+        let uptime = self.started_at.elapsed().unwrap_or(Duration::ZERO);
+        (uptime < warmup.duration).then_some(warmup)
+    }
+
+    /// Returns the requests per minute allowed right now, reduced during the warm-up window.
+    pub fn rpm_limit(&self) -> u32 {
+        match self.active_warmup() {
+            Some(warmup) => (self.args().rpm as f64 * warmup.rate_limit_factor) as u32,
+            None => self.args().rpm,
+        }
+    }
+
+    /// Returns the delay before sending the chunk at `index` of a stream, which is `base` unless
+    /// `--stream-decay` makes it grow over the lifetime of the stream. Chunks come slower during
+    /// a warm-up with a latency effect.
+    pub fn chunk_delay(&self, base: Duration, index: usize) -> Duration {
+        let delay = match self.stream_decay {
+            Some(StreamDecay { every, factor }) => {
+                let base = base.max(Duration::from_millis(STREAM_DECAY_BASE_MS));
+                let exponent = i32::try_from(index / every).unwrap_or(i32::MAX);
+                let delay = (base.as_secs_f64() * f64::powi(factor, exponent))
+                    .min(STREAM_DECAY_MAX_SECS)
+                    .max(0.0);
+                Duration::from_secs_f64(delay)
+            }
+            None => base,
+        };
+
+        match self.active_warmup() {
+            Some(warmup) => delay.mul_f64(warmup.latency_factor),
+            None => delay,
         }
     }
 
     pub fn should_return_error(&self) -> Option<u16> {
-        if let (Some(code), Some(rate)) = (self.args.error_code, self.args.error_rate) {
+        if let (Some(code), Some(rate)) = (self.args().error_code, self.args().error_rate) {
             let mut rng = rand::thread_rng();
             if rng.gen_range(0..100) < rate {
                 return Some(code);
@@ -39,49 +627,288 @@ impl ServerState {
         None
     }
 
-    pub fn get_response_length(&self) -> usize {
-        match &self.args.response_length {
-            Some(length_str) => {
-                if let Some(pos) = length_str.find(':') {
-                    let min: usize = length_str[..pos].parse().unwrap_or(0);
-                    let max: usize = length_str[pos + 1..].parse().unwrap_or(100);
-                    rand::thread_rng().gen_range(min..=max)
-                } else {
-                    length_str.parse().unwrap_or(0)
-                }
-            }
-            None => 0,
-        }
+    /// Returns whether the prompt should be rejected by the simulated moderation, as asked by
+    /// `--moderation-block-rate`.
+    pub fn prompt_flagged(&self) -> bool {
+        self.args()
+            .moderation_block_rate
+            .is_some_and(|rate| rand::thread_rng().gen_range(0..100) < rate)
+    }
+
+    /// Whether a request on behalf of one of the `--flagged-safety-identifiers` is rejected, as
+    /// often as `--abuse-error-rate` asks.
+    pub fn safety_identifier_blocked(&self, safety_identifier: Option<&str>) -> bool {
+        safety_identifier.is_some_and(|identifier| {
+            self.args()
+                .flagged_safety_identifiers
+                .iter()
+                .any(|flagged| flagged == identifier)
+        }) && self
+            .args()
+            .abuse_error_rate
+            .is_none_or(|rate| rand::thread_rng().gen_range(0..100) < rate)
+    }
+
+    /// Returns the refusal replacing the answer, as often as `--refusal-rate` asks.
+    pub fn refusal(&self) -> Option<&'static str> {
+        self.args()
+            .refusal_rate
+            .is_some_and(|rate| rand::thread_rng().gen_range(0..100) < rate)
+            .then_some(REFUSAL)
+    }
+
+    /// Picks the length of the response to `model` within `--response-length`, validated at
+    /// startup.
+    pub fn get_response_length(&self, model: &str) -> usize {
+        self.response_length_with(model, &mut rand::thread_rng())
+    }
+
+    /// Like `get_response_length`, drawing the length from `rng`.
+    pub fn response_length_with(&self, model: &str, rng: &mut impl Rng) -> usize {
+        self.args()
+            .response_length
+            .as_deref()
+            .and_then(|length| response_length_range(length, model).ok())
+            .map_or(0, |(min, max)| rng.gen_range(min..=max) as usize)
     }
 
     pub fn get_slodown_ms(&self) -> u64 {
-        match &self.args.slowdown {
-            Some(slowdown_str) => {
-                if let Some(pos) = slowdown_str.find(':') {
-                    let min: u64 = slowdown_str[..pos].parse().unwrap_or(0);
-                    let max: u64 = slowdown_str[pos + 1..].parse().unwrap_or(600000); // 10 minutes
-                    rand::thread_rng().gen_range(min..=max)
+        let slowdown = match &self.args().slowdown {
+            Some(slowdown) => parse_range(slowdown, MAX_SLOWDOWN_MS)
+                .map_or(0, |(min, max)| rand::thread_rng().gen_range(min..=max)),
+            None => match &self.latency {
+                Some(latency) => latency.sample_ms(),
+                None => 0, // default is zero, no slowdown
+            },
+        };
+
+        match self.active_warmup() {
+            // A cold server is slow even when no latency was asked for
+            Some(warmup) if warmup.latency_factor != 1.0 => {
+                let base = if slowdown == 0 {
+                    WARMUP_BASE_LATENCY_MS
                 } else {
-                    slowdown_str.parse().unwrap_or(0)
-                }
+                    slowdown
+                };
+                (base as f64 * warmup.latency_factor) as u64
             }
-            None => 0, // default is zero, no slowdown
+            _ => slowdown,
+        }
+    }
+
+    /// Holds non-streaming responses for the time the generation would take at the throughput
+    /// set with `--long-poll-tps`.
+    pub async fn simulate_generation_time(&self, completion_tokens: u32) {
+        if let Some(tps) = self.args().long_poll_tps.filter(|tps| *tps > 0) {
+            let generation_time = Duration::from_secs_f64(completion_tokens as f64 / tps as f64);
+            log::debug!("Holding the response for {:?}", generation_time);
+            tokio::time::sleep(generation_time).await;
+        }
+    }
+
+    /// Picks the model snapshot serving a request according to `--new-snapshot-rate`.
+    pub fn pick_snapshot(&self) -> Snapshot {
+        match self.args().new_snapshot_rate {
+            Some(rate) if rand::thread_rng().gen_range(0..100) < rate => Snapshot::New,
+            _ => Snapshot::Current,
         }
     }
 
-    pub fn generate_lorem_content(&self, length: usize) -> String {
+    /// Generates content of about `length` characters in the language of `--content-language`,
+    /// or of `prompt` when it's auto, cut from `--corpus` when given.
+    pub fn generate_lorem_content(&self, length: usize, prompt: &str) -> String {
         if length == 0 {
             return String::new();
         }
+        if let Some(content) = language::generate(
+            self.content_language(prompt),
+            &mut rand::thread_rng(),
+            length,
+        ) {
+            return content;
+        }
+        if let Some(corpus) = &self.corpus {
+            return corpus.generate(&mut rand::thread_rng(), length);
+        }
+        if self.args().text_generator == TextGenerator::Markov {
+            return self.markov.generate(&mut rand::thread_rng(), length);
+        }
         let word_count = length / 5;
         let mut content = lipsum::lipsum(word_count);
         content.truncate(length);
         content
     }
 
+    /// Generates content of about `length` characters drawn from `rng`, the same generator state
+    /// always giving the same content.
+    pub fn generate_seeded_content(
+        &self,
+        length: usize,
+        prompt: &str,
+        rng: &mut impl Rng,
+    ) -> String {
+        if length == 0 {
+            return String::new();
+        }
+        if let Some(content) = language::generate(self.content_language(prompt), rng, length) {
+            return content;
+        }
+        if let Some(corpus) = &self.corpus {
+            return corpus.generate(rng, length);
+        }
+        if self.args().text_generator == TextGenerator::Markov {
+            return self.markov.generate(rng, length);
+        }
+        let mut content = lipsum::lipsum_words_with_rng(&mut *rng, (length / 5).max(1));
+        content.truncate(length);
+        content
+    }
+
+    /// Returns the answer to `message`, the last message of the user, when `--content-mode echo`
+    /// mirrors it back instead of generating text.
+    pub fn echo(&self, message: &str) -> Option<String> {
+        if self.args().content_mode != ContentMode::Echo {
+            return None;
+        }
+        Some(match self.args().echo_transform {
+            EchoTransform::None => message.to_string(),
+            EchoTransform::Uppercase => message.to_uppercase(),
+            EchoTransform::Lowercase => message.to_lowercase(),
+            EchoTransform::Reverse => message.chars().rev().collect(),
+        })
+    }
+
+    /// Renders `--response-template` with the generated `content` and the details of the request,
+    /// or returns the content as it is without a template.
+    pub fn render_template(
+        &self,
+        content: String,
+        model: &str,
+        prompt: &str,
+        request_id: &str,
+        prompt_tokens: u32,
+    ) -> String {
+        let Some(template) = &self.args().response_template else {
+            return content;
+        };
+        template::render(
+            template,
+            minijinja::context! {
+                model,
+                prompt,
+                request_id,
+                prompt_tokens,
+                content,
+            },
+        )
+    }
+
+    /// Returns the generator of the content for `body` with `--content-mode hashed`, seeded by a
+    /// hash of the body.
+    pub fn hashed_rng(&self, body: &impl Serialize) -> Option<ChaCha8Rng> {
+        (self.args().content_mode == ContentMode::Hashed).then(|| snapshot::hashed_rng(body))
+    }
+
+    fn content_language(&self, prompt: &str) -> ContentLanguage {
+        match self.args().content_language {
+            ContentLanguage::Auto => language::detect(prompt),
+            language => language,
+        }
+    }
+
+    /// Returns a preamble like "This is reply #4 in conversation abc." to be prepended to the
+    /// generated content, so that dropped history or wrong threading show up in client tests.
+    pub fn conversation_preamble(&self, conversation: Option<&str>) -> String {
+        let Some(conversation) = conversation.filter(|_| self.args().track_conversations) else {
+            return String::new();
+        };
+
+        let mut turns = self.conversation_turns.lock().unwrap();
+        let turn = turns.entry(conversation.to_string()).or_insert(0);
+        *turn += 1;
+        format!("This is reply #{} in conversation {}. ", turn, conversation)
+    }
+
     pub fn count_tokens(&self, text: &str) -> anyhow::Result<u32> {
-        let bpe = cl100k_base()?;
-        Ok(bpe.encode_with_special_tokens(text).len() as u32)
+        if self.args().usage_model == UsageModel::Simple {
+            return Ok(text.split_whitespace().count() as u32);
+        }
+        Ok(bpe()?.encode_with_special_tokens(text).len() as u32)
+    }
+
+    /// Splits `text` into the pieces streamed one per delta: `chunks` of it by default, or its
+    /// tokens with `--stream-granularity token`. Tokens that are only part of a character are
+    /// sent along with the rest of it.
+    pub fn stream_pieces(
+        &self,
+        text: &str,
+        chunks: impl FnOnce(&str) -> Vec<String>,
+    ) -> Vec<String> {
+        if self.args().stream_granularity == StreamGranularity::Chunk {
+            return chunks(text);
+        }
+        if self.args().usage_model == UsageModel::Simple {
+            return text.split_inclusive(' ').map(String::from).collect();
+        }
+        let Ok(bpe) = bpe() else {
+            return chunks(text);
+        };
+        let mut pieces = vec![];
+        let mut pending = vec![];
+        for bytes in bpe._decode_native_and_split(bpe.encode_with_special_tokens(text)) {
+            pending.extend(bytes);
+            if let Ok(piece) = std::str::from_utf8(&pending) {
+                pieces.push(piece.to_string());
+                pending.clear();
+            }
+        }
+        pieces
+    }
+
+    /// Cuts `text` to its first `max_tokens` tokens, or returns `None` when it already fits. A
+    /// character split across the last tokens is left out whole.
+    pub fn truncate_to_tokens(&self, text: &str, max_tokens: u32) -> Option<String> {
+        let max_tokens = max_tokens as usize;
+        if self.args().usage_model == UsageModel::Simple {
+            let words = text.split_whitespace().collect::<Vec<_>>();
+            return (words.len() > max_tokens).then(|| words[..max_tokens].join(" "));
+        }
+
+        let bpe = bpe().ok()?;
+        let tokens = bpe.encode_with_special_tokens(text);
+        if tokens.len() <= max_tokens {
+            return None;
+        }
+        // The tokens decode back to the bytes they were encoded from, in order
+        let mut end = bpe._decode_native(&tokens[..max_tokens]).len();
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Some(text[..end].trim_end().to_string())
+    }
+
+    /// Counts the tokens of the `messages` of a chat completion request, images included. With
+    /// the simple usage model only the words of the message contents are counted as text.
+    pub fn count_message_tokens(&self, messages: &[Value]) -> anyhow::Result<u32> {
+        let images = vision::count_image_tokens(&Value::from(messages));
+        if self.args().usage_model == UsageModel::Bpe {
+            let mut messages = Value::from(messages);
+            vision::strip_images(&mut messages);
+            return Ok(self.count_tokens(&serde_json::to_string(&messages)?)? + images);
+        }
+
+        let contents = messages
+            .iter()
+            .flat_map(|message| match message.get("content") {
+                Some(Value::String(text)) => vec![text.as_str()],
+                Some(Value::Array(parts)) => parts
+                    .iter()
+                    .filter_map(|part| part.get("text").and_then(Value::as_str))
+                    .collect(),
+                _ => vec![],
+            })
+            .collect::<Vec<_>>();
+        Ok(self.count_tokens(&contents.join(" "))? + images)
     }
 
     pub fn check_request_limit_exceeded(&self) -> bool {
@@ -99,7 +926,101 @@ impl ServerState {
         }
 
         // Check limit
-        timestamps.len() as u32 >= self.args.rpm
+        timestamps.len() as u32 >= self.rpm_limit()
+    }
+
+    /// Returns how many tokens can still be consumed in the current one-minute window.
+    pub fn remaining_token_budget(&self) -> u32 {
+        let mut timestamps = self.token_usage_timestamps.lock().unwrap();
+        let sixty_seconds_ago = SystemTime::now() - Duration::from_secs(60);
+        while let Some((t, _)) = timestamps.front() {
+            if *t < sixty_seconds_ago {
+                timestamps.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let current_token_usage: u32 = timestamps.iter().map(|(_, tokens)| tokens).sum();
+        self.args().tpm.saturating_sub(current_token_usage)
+    }
+
+    /// Returns the 429 for a request over the requests limit, telling when the oldest request
+    /// of the window expires the way the OpenAI platform does, since SDKs parse it for backoff.
+    pub fn request_limit_error(&self) -> ApiError {
+        let timestamps = self.request_timestamps.lock().unwrap();
+        let limit = self.rpm_limit();
+        let retry_after = timestamps
+            .front()
+            .and_then(|oldest| {
+                (*oldest + Duration::from_secs(60))
+                    .duration_since(SystemTime::now())
+                    .ok()
+            })
+            .unwrap_or_default();
+        let jitter = self.retry_after_jitter();
+        let retry_after = retry_after + jitter;
+        ApiError::rate_limit_exceeded(format!(
+            "Rate limit reached for requests per min (RPM): Limit {}, Used {}, Requested 1. \
+             Please try again in {}.",
+            limit,
+            timestamps.len(),
+            format_retry_after(retry_after)
+        ))
+        .with_retry_after(retry_after)
+        .with_jitter(jitter)
+    }
+
+    /// Returns the 429 for a request of `requested` tokens over the tokens limit, telling when
+    /// enough tokens of the window expire for it to fit.
+    pub fn token_limit_error(&self, requested: u32) -> ApiError {
+        let timestamps = self.token_usage_timestamps.lock().unwrap();
+        let limit = self.args().tpm;
+        let used: u32 = timestamps.iter().map(|(_, tokens)| tokens).sum();
+        let now = SystemTime::now();
+
+        let mut freed = 0;
+        let mut retry_after = Duration::from_secs(60);
+        if used + requested > limit && requested <= limit {
+            for (timestamp, tokens) in timestamps.iter() {
+                freed += tokens;
+                if used - freed + requested <= limit {
+                    retry_after = (*timestamp + Duration::from_secs(60))
+                        .duration_since(now)
+                        .unwrap_or_default();
+                    break;
+                }
+            }
+        }
+        let jitter = self.retry_after_jitter();
+        let retry_after = retry_after + jitter;
+        ApiError::rate_limit_exceeded(format!(
+            "Rate limit reached for tokens per min (TPM): Limit {}, Used {}, Requested {}. \
+             Please try again in {}.",
+            limit,
+            used,
+            requested,
+            format_retry_after(retry_after)
+        ))
+        .with_retry_after(retry_after)
+        .with_jitter(jitter)
+    }
+
+    /// Returns a random amount within `--retry-after-jitter` to push the advice of a 429 back by,
+    /// so that the clients limited at the same time aren't told to come back at the same time.
+    /// It's rounded to milliseconds, the precision of the headers.
+    fn retry_after_jitter(&self) -> Duration {
+        match self
+            .args()
+            .retry_after_jitter
+            .filter(|window| !window.is_zero())
+        {
+            Some(window) => {
+                let jitter = window.mul_f64(rand::thread_rng().gen::<f64>());
+                Duration::from_millis(jitter.as_millis() as u64)
+            }
+            None => Duration::ZERO,
+        }
     }
 
     pub fn check_token_limit_exceeded(&self, new_tokens: u32) -> bool {
@@ -119,10 +1040,12 @@ impl ServerState {
         let current_token_usage: u32 = timestamps.iter().map(|(_, tokens)| tokens).sum();
 
         // Check limit
-        (current_token_usage + new_tokens) > self.args.tpm
+        (current_token_usage + new_tokens) > self.args().tpm
     }
 
     pub fn increment_request_count(&self) {
+        self.timeseries.lock().unwrap().record_request();
+
         let mut timestamps = self.request_timestamps.lock().unwrap();
         let now = SystemTime::now();
         let sixty_seconds_ago = now - Duration::from_secs(60);
@@ -136,7 +1059,18 @@ impl ServerState {
         timestamps.push_back(now);
     }
 
+    /// Returns true once the tokens consumed since startup reach `--quota-exceeded-after`.
+    pub fn quota_exceeded(&self) -> bool {
+        self.args()
+            .quota_exceeded_after
+            .is_some_and(|quota| self.lifetime_token_usage.load(Ordering::Relaxed) >= quota)
+    }
+
     pub fn add_token_usage(&self, tokens: u32) {
+        self.lifetime_token_usage
+            .fetch_add(tokens as u64, Ordering::Relaxed);
+        self.timeseries.lock().unwrap().record_tokens(tokens);
+
         let mut timestamps = self.token_usage_timestamps.lock().unwrap();
         let now = SystemTime::now();
         let sixty_seconds_ago = now - Duration::from_secs(60);
@@ -150,6 +1084,123 @@ impl ServerState {
         timestamps.push_back((now, tokens));
     }
 
+    /// Runs the checks every request goes through before it's answered: the quota, the long
+    /// resets, the RPM limit, and the delays and errors asked by the magic tokens of `prompt` or
+    /// `--error-rate`. Returns the directives of the prompt, or the response rejecting the request.
+    pub async fn admit(&self, prompt: &str) -> Result<Directives, Response> {
+        if self.quota_exceeded() {
+            return Err(self.error_response(ApiError::insufficient_quota()));
+        }
+        if let Some(response) = self.long_reset_rate_limit() {
+            return Err(response);
+        }
+        self.pass_limits(prompt)
+            .await
+            .map_err(|error| self.error_response(error))
+    }
+
+    /// [`admit`](Self::admit) for the endpoints answering errors in their own format, which
+    /// have no rate limit headers to claim a long reset with.
+    pub async fn try_admit(&self, prompt: &str) -> Result<Directives, ApiError> {
+        if self.quota_exceeded() {
+            return Err(ApiError::insufficient_quota());
+        }
+        self.pass_limits(prompt).await
+    }
+
+    async fn pass_limits(&self, prompt: &str) -> Result<Directives, ApiError> {
+        let directives = Directives::parse(prompt);
+        if self.check_request_limit_exceeded() {
+            return Err(self.request_limit_error());
+        }
+        self.increment_request_count();
+
+        if let Some(delay) = directives.delay {
+            log::debug!("Delaying request by {:?} as asked by the prompt", delay);
+            tokio::time::sleep(delay).await;
+        }
+        if let Some(error_code) = directives.error_code.or_else(|| self.should_return_error()) {
+            return Err(ApiError::simulated(error_code));
+        }
+        Ok(directives)
+    }
+
+    /// Rejects the prompts of chat and responses requests with the simulated moderation, and
+    /// the requests on behalf of a flagged `safety_identifier`.
+    pub fn moderate(&self, safety_identifier: Option<&str>) -> Result<(), Response> {
+        if self.prompt_flagged() {
+            return Err(self.error_response(ApiError::content_policy_violation()));
+        }
+        if self.safety_identifier_blocked(safety_identifier) {
+            return Err(self.error_response(ApiError::safety_identifier_blocked()));
+        }
+        Ok(())
+    }
+
+    /// With `--long-reset-rate`, returns a 429 claiming that no requests or tokens are left until
+    /// `--long-reset` elapses, regardless of the actual usage.
+    pub fn long_reset_rate_limit(&self) -> Option<Response> {
+        let rate = self.args().long_reset_rate?;
+        if rand::thread_rng().gen_range(0..100) >= rate {
+            return None;
+        }
+
+        let jitter = self.retry_after_jitter();
+        let retry_after = self.args().long_reset + jitter;
+        let reset = humantime::format_duration(retry_after).to_string();
+        let mut headers = self.get_rate_limit_headers();
+        for (name, value) in [
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-remaining-tokens", "0"),
+            ("x-ratelimit-reset-requests", reset.as_str()),
+            ("x-ratelimit-reset-tokens", reset.as_str()),
+        ] {
+            // Headers dropped with --missing-ratelimit-headers stay missing
+            if headers.contains_key(name) {
+                headers.insert(name, value.parse().expect("a valid header value"));
+            }
+        }
+
+        let error = self.noisy(
+            ApiError::rate_limit_exceeded(format!(
+                "Rate limit reached for requests. Please try again in {}.",
+                reset
+            ))
+            .with_retry_after(retry_after),
+        );
+        Some(
+            (
+                error.status,
+                headers,
+                error.retry_after_headers(),
+                Json(error.body()),
+            )
+                .into_response(),
+        )
+    }
+
+    /// Adds noise to the error when running with `--error-noise`.
+    pub fn noisy(&self, error: ApiError) -> ApiError {
+        if self.args().error_noise {
+            error.with_noise()
+        } else {
+            error
+        }
+    }
+
+    pub fn error_response(&self, error: ApiError) -> Response {
+        let error = self.noisy(error);
+        let mut headers = self.get_rate_limit_headers();
+        push_back_resets(&mut headers, error.jitter);
+        (
+            error.status,
+            headers,
+            error.retry_after_headers(),
+            Json(error.body()),
+        )
+            .into_response()
+    }
+
     pub fn get_rate_limit_headers(&self) -> HeaderMap {
         let mut headers = HeaderMap::new();
         let now = SystemTime::now();
@@ -167,7 +1218,7 @@ impl ServerState {
         }
 
         let request_count = timestamps.len() as u32;
-        let limit = self.args.rpm;
+        let limit = self.rpm_limit();
         let remaining = limit.saturating_sub(request_count);
 
         let reset_duration = if request_count < limit {
@@ -210,7 +1261,7 @@ impl ServerState {
         }
 
         let current_token_usage: u32 = token_timestamps.iter().map(|(_, tokens)| tokens).sum();
-        let token_limit = self.args.tpm;
+        let token_limit = self.args().tpm;
         let remaining_tokens = token_limit.saturating_sub(current_token_usage);
 
         let token_reset_duration = if current_token_usage < token_limit {
@@ -242,6 +1293,90 @@ impl ServerState {
                 .expect("x-ratelimit-reset-tokens must be a valid header value"),
         );
 
+        self.skew_rate_limit_headers(&mut headers);
+        self.drop_rate_limit_headers(&mut headers);
         headers
     }
+
+    /// Replaces the values of the rate limit headers with edge cases seen in the wild on the
+    /// fraction of responses set with `--odd-ratelimit-headers-rate`.
+    fn skew_rate_limit_headers(&self, headers: &mut HeaderMap) {
+        let Some(rate) = self.args().odd_ratelimit_headers_rate else {
+            return;
+        };
+        let mut rng = rand::thread_rng();
+        if rng.gen_range(0..100) >= rate {
+            return;
+        }
+
+        for name in ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"] {
+            let value = ODD_RESET_VALUES[rng.gen_range(0..ODD_RESET_VALUES.len())];
+            headers.insert(name, value.parse().expect("a valid header value"));
+        }
+        let remaining = match rng.gen_range(0..3) {
+            0 => "0".to_string(),
+            1 => u64::MAX.to_string(),
+            _ => format!("{}.0", self.args().tpm),
+        };
+        headers.insert(
+            "x-ratelimit-remaining-tokens",
+            remaining.parse().expect("a valid header value"),
+        );
+    }
+
+    /// Removes the rate limit headers on the fraction of responses set with
+    /// `--missing-ratelimit-headers-rate`, like real responses occasionally do.
+    fn drop_rate_limit_headers(&self, headers: &mut HeaderMap) {
+        let Some(rate) = self.args().missing_ratelimit_headers_rate else {
+            return;
+        };
+        if rand::thread_rng().gen_range(0..100) >= rate {
+            return;
+        }
+
+        if self.args().missing_ratelimit_headers.is_empty() {
+            headers.clear();
+            return;
+        }
+        for name in &self.args().missing_ratelimit_headers {
+            let name = name.trim().to_lowercase();
+            if name.starts_with("x-ratelimit-") {
+                headers.remove(name.as_str());
+            } else {
+                headers.remove(format!("x-ratelimit-{}", name).as_str());
+            }
+        }
+    }
+}
+
+/// Formats a wait like the platform's rate limit messages: `120ms`, `7.066s` or `1m3.5s`.
+fn format_retry_after(wait: Duration) -> String {
+    let millis = wait.as_millis();
+    if millis < 1000 {
+        return format!("{}ms", millis);
+    }
+    let seconds = (millis % 60_000) as f64 / 1000.0;
+    match millis / 60_000 {
+        0 => format!("{}s", seconds),
+        minutes => format!("{}m{}s", minutes, seconds),
+    }
+}
+
+/// Pushes the `x-ratelimit-reset-*` headers back by `jitter`, like the Retry-After of the same
+/// 429. Values that aren't durations, e.g. with `--odd-ratelimit-headers-rate`, are left alone.
+fn push_back_resets(headers: &mut HeaderMap, jitter: Duration) {
+    if jitter.is_zero() {
+        return;
+    }
+    for name in ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"] {
+        let Some(reset) = headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| humantime::parse_duration(value).ok())
+        else {
+            continue;
+        };
+        let reset = humantime::format_duration(reset + jitter).to_string();
+        headers.insert(name, reset.parse().expect("a valid header value"));
+    }
 }
@@ -0,0 +1,59 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::embeddings::fnv1a;
+
+/// A random generator seeded by the `seed` of a request along with its model and prompt, so that
+/// the same request is always answered with the same content. Both the hash and the generator
+/// are portable, so the content doesn't change across builds either.
+pub fn seeded_rng(seed: i64, model: &str, prompt: &str) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(fnv1a(&format!("{}\0{}\0{}", seed, model, prompt)))
+}
+
+/// A random generator seeded by the whole body of a request, so that the same body is always
+/// answered with the same content, with or without a `seed`.
+pub fn hashed_rng(body: &impl Serialize) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(fnv1a(&serde_json::to_string(body).unwrap_or_default()))
+}
+
+/// The model snapshot serving a request. With `--new-snapshot-rate` a fraction of the requests
+/// is served by a newer snapshot, as happens while a provider rolls out a model update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snapshot {
+    Current,
+    New,
+}
+
+impl Snapshot {
+    pub fn fingerprint(&self) -> &'static str {
+        match self {
+            Snapshot::Current => "fp_roy_c0ffee01",
+            Snapshot::New => "fp_roy_5eed0002",
+        }
+    }
+
+    /// The new snapshot is slightly slower to answer.
+    pub fn extra_latency(&self) -> Duration {
+        match self {
+            Snapshot::Current => Duration::ZERO,
+            Snapshot::New => Duration::from_millis(rand::thread_rng().gen_range(50..250)),
+        }
+    }
+
+    /// The new snapshot answers with a bullet list, one sentence per line.
+    pub fn restyle(&self, content: String) -> String {
+        match self {
+            Snapshot::Current => content,
+            Snapshot::New => content
+                .split_inclusive(". ")
+                .map(|sentence| format!("- {}", sentence.trim()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
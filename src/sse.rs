@@ -0,0 +1,128 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use serde::{Deserialize, Serialize};
+
+/// A server-sent event as seen on the wire.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl SseEvent {
+    /// Parses the lines of a single event, returning `None` for blocks carrying no data.
+    pub fn parse(block: &str) -> Option<Self> {
+        let mut event = SseEvent::default();
+        let mut data = vec![];
+
+        for line in block.lines() {
+            // Lines starting with a colon are comments
+            if line.is_empty() || line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.find(':') {
+                Some(pos) => (
+                    &line[..pos],
+                    line[pos + 1..]
+                        .strip_prefix(' ')
+                        .unwrap_or(&line[pos + 1..]),
+                ),
+                None => (line, ""),
+            };
+            match field {
+                "event" => event.event = Some(value.to_string()),
+                "data" => data.push(value),
+                "id" => event.id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        if data.is_empty() {
+            return None;
+        }
+        event.data = data.join("\n");
+        Some(event)
+    }
+
+    /// Serializes the event, splitting JSON data into `data:` lines of about `max_line_length`
+    /// characters. Lines are only broken between JSON tokens, so joining them back with newlines
+    /// as the SSE spec mandates gives the same document.
+    pub fn to_frame(&self, max_line_length: Option<usize>) -> String {
+        let mut frame = String::new();
+        if let Some(event) = &self.event {
+            frame.push_str(&format!("event: {}\n", event));
+        }
+        let lines = match max_line_length {
+            Some(max) if serde_json::from_str::<serde_json::Value>(&self.data).is_ok() => {
+                split_json(&self.data, max)
+            }
+            _ => self.data.split('\n').map(String::from).collect(),
+        };
+        for line in lines {
+            frame.push_str(&format!("data: {}\n", line));
+        }
+        if let Some(id) = &self.id {
+            frame.push_str(&format!("id: {}\n", id));
+        }
+        frame.push('\n');
+        frame
+    }
+}
+
+/// Breaks a JSON document after a structural character once the line is at least `max` long.
+fn split_json(json: &str, max: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in json.chars() {
+        line.push(c);
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            ',' | ':' | '{' | '[' if line.len() >= max => lines.push(std::mem::take(&mut line)),
+            _ => {}
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Splits a stream of text into events as they complete.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+}
+
+impl SseParser {
+    pub fn push(&mut self, chunk: &str) -> Vec<SseEvent> {
+        self.buffer.push_str(&chunk.replace("\r\n", "\n"));
+
+        let mut events = vec![];
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let block: String = self.buffer.drain(..pos + 2).collect();
+            events.extend(SseEvent::parse(&block));
+        }
+        events
+    }
+
+    /// Returns the last event when the stream didn't end with a blank line.
+    pub fn finish(self) -> Option<SseEvent> {
+        SseEvent::parse(&self.buffer)
+    }
+}
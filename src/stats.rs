@@ -0,0 +1,436 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use colored::Colorize;
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::journal::RequestRecord;
+use crate::server_state::ServerState;
+
+/// Summarizes the requests received so far, including the SDK fingerprints of the clients.
+pub async fn stats(State(state): State<ServerState>) -> impl IntoResponse {
+    let costs = state.costs().lock().unwrap().clone();
+    let total_usd: f64 = costs.values().map(|cost| cost.usd).sum();
+
+    let requests = state.recorded_requests();
+
+    let mut by_path: BTreeMap<String, usize> = BTreeMap::new();
+    let mut clients: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    for request in &requests {
+        *by_path.entry(request.path.clone()).or_default() += 1;
+        for (name, value) in &request.client_headers {
+            *clients
+                .entry(name.clone())
+                .or_default()
+                .entry(value.clone())
+                .or_default() += 1;
+        }
+    }
+
+    Json(json!({
+        "requests": requests.len(),
+        "paths": by_path,
+        "clients": clients,
+        "cost": {
+            "total_usd": total_usd,
+            "models": costs,
+        },
+    }))
+}
+
+/// The estimated spend on one model, priced with the `prices` table of the config file.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Cost {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub usd: f64,
+}
+
+/// Number of seconds of traffic kept for `/__stats/timeseries`.
+const TIMESERIES_SECONDS: u64 = 60 * 60;
+
+/// The traffic received during one second.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub timestamp: u64,
+    pub requests: u32,
+    pub tokens: u32,
+}
+
+/// Per-second request and token counts for the last hour.
+#[derive(Debug, Default)]
+pub struct Timeseries {
+    buckets: VecDeque<Bucket>,
+}
+
+impl Timeseries {
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should be able to get duration")
+            .as_secs()
+    }
+
+    fn bucket(&mut self, timestamp: u64) -> &mut Bucket {
+        if self.buckets.back().is_none_or(|b| b.timestamp < timestamp) {
+            self.buckets.push_back(Bucket {
+                timestamp,
+                ..Default::default()
+            });
+        }
+        while self
+            .buckets
+            .front()
+            .is_some_and(|b| b.timestamp + TIMESERIES_SECONDS <= timestamp)
+        {
+            self.buckets.pop_front();
+        }
+        self.buckets.back_mut().expect("a bucket was just added")
+    }
+
+    pub fn record_request(&mut self) {
+        self.bucket(Self::now()).requests += 1;
+    }
+
+    pub fn record_tokens(&mut self, tokens: u32) {
+        self.bucket(Self::now()).tokens += tokens;
+    }
+
+    /// Returns the most requests and the most tokens seen within any minute of the last hour.
+    pub fn peak_minute(&self) -> (u32, u32) {
+        let mut peak = (0, 0);
+        for (start, bucket) in self.buckets.iter().enumerate() {
+            let minute = self
+                .buckets
+                .range(start..)
+                .take_while(|b| b.timestamp < bucket.timestamp + 60);
+            let (requests, tokens) = minute.fold((0, 0), |(requests, tokens), b| {
+                (requests + b.requests, tokens + b.tokens)
+            });
+            peak = (peak.0.max(requests), peak.1.max(tokens));
+        }
+        peak
+    }
+
+    /// Returns one bucket per second for the last `seconds` seconds, including the quiet ones.
+    pub fn last(&self, seconds: u64) -> Vec<Bucket> {
+        let now = Self::now();
+        let start = now + 1 - seconds.clamp(1, TIMESERIES_SECONDS);
+        let recorded: BTreeMap<u64, Bucket> = self
+            .buckets
+            .iter()
+            .filter(|b| b.timestamp >= start)
+            .map(|b| (b.timestamp, *b))
+            .collect();
+        (start..=now)
+            .map(|timestamp| {
+                recorded.get(&timestamp).copied().unwrap_or(Bucket {
+                    timestamp,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TimeseriesQuery {
+    pub minutes: Option<u64>,
+}
+
+/// Returns the per-second traffic of the last minutes, 5 unless asked otherwise with `?minutes=N`.
+pub async fn timeseries(
+    State(state): State<ServerState>,
+    Query(query): Query<TimeseriesQuery>,
+) -> impl IntoResponse {
+    let minutes = query.minutes.unwrap_or(5);
+    let points = state
+        .timeseries()
+        .lock()
+        .unwrap()
+        .last(minutes.saturating_mul(60));
+
+    Json(json!({
+        "interval": "1s",
+        "points": points,
+    }))
+}
+
+/// Latency percentiles, in milliseconds.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Percentiles {
+    fn new(latencies: impl Iterator<Item = u64>) -> Self {
+        let mut histogram = Histogram::<u64>::new(3).expect("3 significant figures are supported");
+        for latency in latencies {
+            histogram.saturating_record(latency);
+        }
+        if histogram.is_empty() {
+            return Self::default();
+        }
+        Self {
+            p50: histogram.value_at_quantile(0.5),
+            p95: histogram.value_at_quantile(0.95),
+            p99: histogram.value_at_quantile(0.99),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// The requests served by one route.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct EndpointReport {
+    pub requests: usize,
+    pub statuses: BTreeMap<u16, usize>,
+    pub latency_ms: Percentiles,
+}
+
+/// How close the traffic got to a rate limit.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Utilization {
+    pub peak: u32,
+    pub limit: u32,
+    pub percent: f64,
+}
+
+impl Utilization {
+    fn new(peak: u32, limit: u32) -> Self {
+        let percent = match limit {
+            0 => 0.0,
+            limit => peak as f64 * 100.0 / limit as f64,
+        };
+        Self {
+            peak,
+            limit,
+            percent,
+        }
+    }
+}
+
+/// A summary of the session: what was requested, how fast it was served and what failed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub requests: usize,
+    pub endpoints: BTreeMap<String, EndpointReport>,
+    /// Number of error responses, by status.
+    pub errors: BTreeMap<u16, usize>,
+    pub latency_ms: Percentiles,
+    pub rpm: Utilization,
+    pub tpm: Utilization,
+}
+
+impl Report {
+    pub fn new(state: &ServerState) -> Self {
+        let requests = state.recorded_requests();
+        let mut endpoints: BTreeMap<String, EndpointReport> = BTreeMap::new();
+        let mut latencies: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        let mut errors: BTreeMap<u16, usize> = BTreeMap::new();
+        for request in &requests {
+            let name = format!("{} {}", request.method, request.endpoint);
+            latencies
+                .entry(name.clone())
+                .or_default()
+                .push(request.latency_ms);
+            let endpoint = endpoints.entry(name).or_default();
+            endpoint.requests += 1;
+            *endpoint.statuses.entry(request.status).or_default() += 1;
+            if request.status >= 400 {
+                *errors.entry(request.status).or_default() += 1;
+            }
+        }
+        for (name, endpoint) in endpoints.iter_mut() {
+            endpoint.latency_ms = Percentiles::new(latencies[name].iter().copied());
+        }
+
+        let (peak_requests, peak_tokens) = state.timeseries().lock().unwrap().peak_minute();
+        Self {
+            requests: requests.len(),
+            endpoints,
+            errors,
+            latency_ms: Percentiles::new(requests.iter().map(|request| request.latency_ms)),
+            rpm: Utilization::new(peak_requests, state.args().rpm),
+            tpm: Utilization::new(peak_tokens, state.args().tpm),
+        }
+    }
+
+    pub fn print(&self) {
+        let percentiles = |latency: &Percentiles| {
+            format!(
+                "p50 {}ms, p95 {}ms, p99 {}ms, max {}ms",
+                latency.p50, latency.p95, latency.p99, latency.max
+            )
+        };
+
+        println!("{}", "Session report".bold());
+        println!(
+            "{} requests, latency {}",
+            self.requests,
+            percentiles(&self.latency_ms)
+        );
+        for (name, endpoint) in &self.endpoints {
+            let statuses = endpoint
+                .statuses
+                .iter()
+                .map(|(status, count)| {
+                    let status = match status {
+                        200..=299 => status.to_string().green(),
+                        _ => status.to_string().red(),
+                    };
+                    format!("{}: {}", status, count)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("  {} ({})", name.blue(), statuses);
+            println!("    latency {}", percentiles(&endpoint.latency_ms));
+        }
+        if !self.errors.is_empty() {
+            let errors = self
+                .errors
+                .iter()
+                .map(|(status, count)| format!("{}: {}", status, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("Errors {}", errors.red());
+        }
+        println!(
+            "Peak RPM {} of {} ({:.0}%), peak TPM {} of {} ({:.0}%)",
+            self.rpm.peak,
+            self.rpm.limit,
+            self.rpm.percent,
+            self.tpm.peak,
+            self.tpm.limit,
+            self.tpm.percent
+        );
+    }
+}
+
+/// Returns the report printed when Roy shuts down.
+pub async fn report(State(state): State<ServerState>) -> impl IntoResponse {
+    Json(Report::new(&state))
+}
+
+/// Upper bounds of the request duration buckets, in seconds.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// The durations of the requests to an endpoint, counted in `DURATION_BUCKETS` and an overflow
+/// bucket, each one with the latest request it counted as an exemplar.
+#[derive(Debug, Clone, Default)]
+pub struct DurationHistogram {
+    counts: [u64; DURATION_BUCKETS.len() + 1],
+    exemplars: [Option<Exemplar>; DURATION_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+/// A request standing for the bucket it fell in.
+#[derive(Debug, Clone)]
+struct Exemplar {
+    request_id: String,
+    seconds: f64,
+    timestamp: f64,
+}
+
+impl DurationHistogram {
+    pub fn record(&mut self, request: &RequestRecord) {
+        let seconds = request.latency_ms as f64 / 1000.0;
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|upper| seconds <= *upper)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.exemplars[bucket] = Some(Exemplar {
+            request_id: request.id.clone(),
+            seconds,
+            timestamp: request
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+        });
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Serves the request durations as an OpenMetrics histogram per endpoint. Each bucket carries
+/// the latest request it counted as an exemplar, so a spike can be traced to the request id,
+/// which is also the name of its capture file with `--capture-dir`.
+pub async fn metrics(State(state): State<ServerState>) -> impl IntoResponse {
+    let histograms = state.request_durations().lock().unwrap().clone();
+
+    let mut body = String::new();
+    body.push_str("# TYPE roy_request_duration_seconds histogram\n");
+    body.push_str("# UNIT roy_request_duration_seconds seconds\n");
+    body.push_str(
+        "# HELP roy_request_duration_seconds Time until the response headers were sent, \
+         injected delays included.\n",
+    );
+    for ((method, endpoint), histogram) in &histograms {
+        let labels = format!("method=\"{}\",endpoint=\"{}\"", method, endpoint);
+        let mut count = 0;
+        for (bucket, upper) in DURATION_BUCKETS
+            .into_iter()
+            .chain([f64::INFINITY])
+            .enumerate()
+        {
+            count += histogram.counts[bucket];
+            let le = if upper.is_infinite() {
+                "+Inf".to_string()
+            } else {
+                format!("{:?}", upper)
+            };
+            let _ = write!(
+                body,
+                "roy_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, le, count
+            );
+            if let Some(exemplar) = &histogram.exemplars[bucket] {
+                let _ = write!(
+                    body,
+                    " # {{request_id=\"{}\"}} {:?} {:.3}",
+                    exemplar.request_id, exemplar.seconds, exemplar.timestamp
+                );
+            }
+            body.push('\n');
+        }
+        let _ = writeln!(
+            body,
+            "roy_request_duration_seconds_sum{{{}}} {:?}",
+            labels, histogram.sum
+        );
+        let _ = writeln!(
+            body,
+            "roy_request_duration_seconds_count{{{}}} {}",
+            labels, histogram.count
+        );
+    }
+    body.push_str("# EOF\n");
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        body,
+    )
+}
@@ -0,0 +1,173 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::server_state::ServerState;
+
+/// The requests a stub is served to. Missing fields match anything.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StubRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_contains: Option<String>,
+    /// The id of the prompt template the request references.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+impl StubRequest {
+    pub fn matches(&self, path: &str, body: &str) -> bool {
+        let field = |pointer: &str| {
+            serde_json::from_str::<Value>(body).ok().and_then(|body| {
+                body.pointer(pointer)
+                    .and_then(Value::as_str)
+                    .map(String::from)
+            })
+        };
+        self.path.as_ref().is_none_or(|p| p == path)
+            && self
+                .model
+                .as_ref()
+                .is_none_or(|model| field("/model").as_ref() == Some(model))
+            && self
+                .prompt
+                .as_ref()
+                .is_none_or(|id| field("/prompt/id").as_ref() == Some(id))
+            && self
+                .prompt_version
+                .as_ref()
+                .is_none_or(|version| field("/prompt/version").as_ref() == Some(version))
+            && self
+                .body_contains
+                .as_ref()
+                .is_none_or(|needle| body.contains(needle.as_str()))
+    }
+}
+
+/// The exact response returned by a stub. String bodies are sent as they are, anything else
+/// is sent as JSON.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StubResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Value,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl IntoResponse for StubResponse {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        let mut response = match self.body {
+            Value::String(text) => (status, text).into_response(),
+            Value::Null => status.into_response(),
+            body => (status, Json(body)).into_response(),
+        };
+        for (name, value) in &self.headers {
+            match (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    response.headers_mut().insert(name, value);
+                }
+                _ => log::warn!("Ignoring invalid stub header '{}: {}'", name, value),
+            }
+        }
+        response
+    }
+}
+
+/// The state scenarios start in.
+pub const STARTED: &str = "Started";
+
+/// A response primed by a test for the next matching request, e.g.
+/// `{"request": {"model": "gpt-4o"}, "response": {"status": 503, "body": "overloaded"}}`.
+///
+/// Stubs belonging to a `scenario` are only served while the scenario is in `required_state`,
+/// and move it to `new_state` once served, e.g. to fail the first call and let the retry succeed.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Stub {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub request: StubRequest,
+    pub response: StubResponse,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_state: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_state: Option<String>,
+}
+
+impl Stub {
+    pub fn generate_id() -> String {
+        format!("stub_{:x}", rand::thread_rng().gen::<u64>())
+    }
+
+    /// Returns whether the stub can be served with the scenarios in the given states.
+    pub fn is_enabled(&self, states: &HashMap<String, String>) -> bool {
+        let (Some(scenario), Some(required)) = (&self.scenario, &self.required_state) else {
+            return true;
+        };
+        states.get(scenario).map_or(STARTED, String::as_str) == required
+    }
+}
+
+pub async fn prime(State(state): State<ServerState>, Json(stub): Json<Stub>) -> impl IntoResponse {
+    let id = state.add_stub(stub);
+    (StatusCode::CREATED, Json(json!({"id": id})))
+}
+
+/// Serves the first stub matching the request, consuming it.
+pub async fn serve(State(state): State<ServerState>, req: Request<Body>, next: Next) -> Response {
+    if !state.has_stubs() {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed to read request body: {}", err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+
+    match state.take_stub(parts.uri.path(), &String::from_utf8_lossy(&bytes)) {
+        Some(stub) => {
+            log::debug!("Serving stub {}", stub.id);
+            stub.response.into_response()
+        }
+        None => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use minijinja::{Environment, UndefinedBehavior, Value};
+
+/// The variables a response template can use.
+pub const VARIABLES: [&str; 5] = ["model", "prompt", "request_id", "prompt_tokens", "content"];
+
+/// A Jinja environment for plain text, failing on undefined variables instead of rendering them
+/// empty.
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env
+}
+
+/// Checks that `template` is valid Jinja and only uses known variables.
+pub fn check(template: &str) -> anyhow::Result<()> {
+    let env = environment();
+    let compiled = env
+        .template_from_str(template)
+        .map_err(|err| anyhow::anyhow!("Invalid template, {}", err))?;
+
+    let mut unknown = compiled
+        .undeclared_variables(false)
+        .into_iter()
+        .filter(|name| !VARIABLES.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        unknown.sort();
+        anyhow::bail!(
+            "Invalid template, unknown variables '{}', use any of: {}",
+            unknown.join("', '"),
+            VARIABLES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Renders `template` with the variables of `context`, e.g. built with `minijinja::context!`.
+/// Errors left for rendering time, like a filter applied to the wrong type, are logged and the
+/// template is returned as it is.
+pub fn render(template: &str, context: Value) -> String {
+    environment()
+        .render_str(template, context)
+        .unwrap_or_else(|err| {
+            log::warn!("Failed to render the response template: {}", err);
+            template.to_string()
+        })
+}
@@ -0,0 +1,363 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::http::StatusCode;
+use rand::Rng;
+use serde_json::{json, Map, Value};
+
+use crate::errors::ApiError;
+
+const DEFAULT_FUNCTION_NAME: &str = "roy_tool";
+
+/// Nesting depth after which generated arrays stop growing, so that recursive schemas terminate.
+const MAX_DEPTH: usize = 5;
+
+/// Returns the name and the JSON schema parameters of the first function tool declared in the
+/// request. Both the chat completions (`{"function": {...}}`) and the responses (flat) tool
+/// shapes are supported.
+pub fn pick_function(tools: Option<&Vec<Value>>) -> (String, Option<Value>) {
+    let function = tools.and_then(|tools| {
+        tools
+            .iter()
+            .find(|tool| tool.get("type").and_then(Value::as_str) == Some("function"))
+            .map(|tool| tool.get("function").unwrap_or(tool))
+    });
+
+    match function {
+        Some(function) => (
+            function
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_FUNCTION_NAME)
+                .to_string(),
+            function.get("parameters").cloned(),
+        ),
+        None => (DEFAULT_FUNCTION_NAME.to_string(), None),
+    }
+}
+
+/// Returns the declared tools the model may call according to `tool_choice`: the one it names,
+/// the `allowed_tools` subset, or all of them. Both the chat completions and the responses
+/// shapes are supported, and naming a tool that isn't declared is rejected.
+pub fn allowed_tools(
+    tools: Option<&Vec<Value>>,
+    tool_choice: Option<&Value>,
+) -> Result<Vec<Value>, ApiError> {
+    let tools = tools.cloned().unwrap_or_default();
+    let Some(choice) = tool_choice.filter(|choice| !choice.is_null()) else {
+        return Ok(tools);
+    };
+    if tools.is_empty() && *choice != "none" {
+        return Err(tool_choice_error(
+            "Invalid value for 'tool_choice': 'tool_choice' is only allowed when 'tools' are specified.",
+        ));
+    }
+
+    let references: Vec<&Value> = match choice {
+        Value::String(mode) if ["none", "auto", "required"].contains(&mode.as_str()) => {
+            return Ok(tools)
+        }
+        Value::String(mode) => {
+            return Err(tool_choice_error(format!(
+                "Invalid value: '{}'. Supported values are: 'none', 'auto', and 'required'.",
+                mode
+            )))
+        }
+        choice if choice["type"] == "allowed_tools" => {
+            let allowed = choice.get("allowed_tools").unwrap_or(choice);
+            if !matches!(allowed["mode"].as_str(), Some("auto" | "required")) {
+                return Err(tool_choice_error(
+                    "Invalid value for 'tool_choice.mode': expected 'auto' or 'required'.",
+                ));
+            }
+            match allowed["tools"].as_array() {
+                Some(references) => references.iter().collect(),
+                None => {
+                    return Err(tool_choice_error(
+                        "Missing required parameter: 'tool_choice.tools'.",
+                    ))
+                }
+            }
+        }
+        choice => vec![choice],
+    };
+
+    let mut allowed = vec![];
+    for reference in references {
+        let matching = tools.iter().filter(|tool| same_tool(tool, reference));
+        let count = allowed.len();
+        allowed.extend(matching.cloned());
+        if allowed.len() == count {
+            return Err(tool_choice_error(format!(
+                "Tool choice '{}' not found in 'tools' parameter.",
+                function_name(reference)
+                    .or_else(|| reference["type"].as_str())
+                    .unwrap_or_default()
+            )));
+        }
+    }
+    Ok(allowed)
+}
+
+fn tool_choice_error(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+        .with_param("tool_choice")
+}
+
+/// Returns the name of a function tool, or of a tool choice naming one, in either shape.
+fn function_name(tool: &Value) -> Option<&str> {
+    tool.get("function").unwrap_or(tool)["name"].as_str()
+}
+
+/// Tells if the declared `tool` is the one `reference` names.
+fn same_tool(tool: &Value, reference: &Value) -> bool {
+    tool["type"] == reference["type"]
+        && match reference["type"].as_str() {
+            Some("function") => function_name(tool) == function_name(reference),
+            Some("mcp") => {
+                reference.get("server_label").is_none()
+                    || tool["server_label"] == reference["server_label"]
+            }
+            _ => true,
+        }
+}
+
+/// Returns the tools listed by a simulated MCP server: the ones named in `allowed_tools`, either
+/// a list of names or a `{"tool_names": [...]}` filter, or a few generic ones.
+pub fn mcp_tools(allowed_tools: Option<&Value>) -> Vec<Value> {
+    let known = [
+        (
+            "search",
+            "Searches the documents available on the server.",
+            json!({"type": "object", "properties": {"query": {"type": "string"}}, "required": ["query"]}),
+        ),
+        (
+            "fetch",
+            "Fetches the full content of a document by id.",
+            json!({"type": "object", "properties": {"id": {"type": "string"}}, "required": ["id"]}),
+        ),
+        (
+            "list_resources",
+            "Lists the resources available on the server.",
+            json!({"type": "object", "properties": {"limit": {"type": "integer"}}}),
+        ),
+    ];
+    let tool = |name: &str, description: &str, input_schema: Value| {
+        json!({
+            "name": name,
+            "description": description,
+            "input_schema": input_schema,
+            "annotations": {"read_only": true},
+        })
+    };
+
+    let names = match allowed_tools {
+        Some(Value::Array(names)) => Some(names),
+        Some(filter) => filter.get("tool_names").and_then(Value::as_array),
+        None => None,
+    };
+    match names {
+        Some(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .map(
+                |name| match known.iter().find(|(known, _, _)| *known == name) {
+                    Some((_, description, input_schema)) => {
+                        tool(name, description, input_schema.clone())
+                    }
+                    None => tool(
+                        name,
+                        &lipsum::lipsum_words(6),
+                        json!({"type": "object", "properties": {"input": {"type": "string"}}}),
+                    ),
+                },
+            )
+            .collect(),
+        None => known
+            .into_iter()
+            .map(|(name, description, input_schema)| tool(name, description, input_schema))
+            .collect(),
+    }
+}
+
+/// Returns a random computer use action on a display of the given size: a click, a scroll,
+/// some typing, a key press, a wait or a screenshot.
+pub fn computer_action(width: u64, height: u64) -> Value {
+    let mut rng = rand::thread_rng();
+    let x = rng.gen_range(0..width.max(1));
+    let y = rng.gen_range(0..height.max(1));
+    match rng.gen_range(0..7) {
+        0 => json!({"type": "click", "button": "left", "x": x, "y": y}),
+        1 => json!({"type": "double_click", "x": x, "y": y}),
+        2 => {
+            json!({"type": "scroll", "x": x, "y": y, "scroll_x": 0, "scroll_y": rng.gen_range(1..10) * 100})
+        }
+        3 => json!({"type": "type", "text": lipsum::lipsum_words(3)}),
+        4 => json!({"type": "keypress", "keys": ["CTRL", "L"]}),
+        5 => json!({"type": "wait"}),
+        _ => json!({"type": "screenshot"}),
+    }
+}
+
+/// Generates a JSON-encoded object of about `length` characters, with values of every JSON type.
+pub fn generate_json_object(length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let mut object = Map::new();
+    // The braces, then the quoted key, the colon, the value and the comma of each entry
+    let mut size = 2;
+
+    while size < length {
+        let key = format!(
+            "{}_{}",
+            lipsum::lipsum_words(1)
+                .to_lowercase()
+                .trim_matches(|c: char| !c.is_alphabetic()),
+            object.len()
+        );
+        let value = match rng.gen_range(0..6) {
+            0 => Value::from(rng.gen_range(0..1000)),
+            1 => Value::from(rng.gen_range(0.0..100.0)),
+            2 => Value::Bool(rng.gen()),
+            3 => Value::Null,
+            4 => json!([lipsum::lipsum_words(1), rng.gen_range(0..10)]),
+            _ => Value::String(lipsum::lipsum_words(rng.gen_range(1..6))),
+        };
+        size += key.len() + value.to_string().len() + 4;
+        object.insert(key, value);
+    }
+
+    Value::Object(object).to_string()
+}
+
+/// Generates a JSON-encoded arguments object filling in the properties declared in `parameters`.
+pub fn generate_arguments(parameters: Option<&Value>) -> String {
+    match parameters {
+        Some(parameters) if parameters.get("properties").is_some() => {
+            generate_value(parameters, parameters).to_string()
+        }
+        _ => Value::Object(Map::new()).to_string(),
+    }
+}
+
+/// Generates a value conforming to `schema`: types, `enum`s and `const`s, string formats,
+/// bounds, nested objects and arrays, `anyOf`/`oneOf` alternatives and local `$ref`s into
+/// `root` are supported.
+pub fn generate_value(schema: &Value, root: &Value) -> Value {
+    generate_value_at(schema, root, 0)
+}
+
+fn generate_value_at(schema: &Value, root: &Value, depth: usize) -> Value {
+    let mut rng = rand::thread_rng();
+
+    if let Some(reference) = schema["$ref"].as_str() {
+        return match root.pointer(reference.trim_start_matches('#')) {
+            Some(schema) => generate_value_at(schema, root, depth),
+            None => Value::Null,
+        };
+    }
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(values) = schema["enum"]
+        .as_array()
+        .filter(|values| !values.is_empty())
+    {
+        return values[rng.gen_range(0..values.len())].clone();
+    }
+    if let Some(alternatives) = schema["anyOf"]
+        .as_array()
+        .or_else(|| schema["oneOf"].as_array())
+        .filter(|alternatives| !alternatives.is_empty())
+    {
+        let alternative = &alternatives[rng.gen_range(0..alternatives.len())];
+        return generate_value_at(alternative, root, depth);
+    }
+
+    let _type = match &schema["type"] {
+        Value::String(_type) => _type.as_str(),
+        // Nullable values like `["string", "null"]` are filled in
+        Value::Array(types) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|_type| *_type != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ if schema.get("items").is_some() => "array",
+        _ => "string",
+    };
+    match _type {
+        "object" => {
+            let mut object = Map::new();
+            for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+                object.insert(name.clone(), generate_value_at(property, root, depth + 1));
+            }
+            Value::Object(object)
+        }
+        "array" => {
+            let min = schema["minItems"].as_u64().unwrap_or(1);
+            let max = schema["maxItems"].as_u64().unwrap_or(3).max(min);
+            // Recursive schemas end with the fewest items allowed
+            let count = if depth < MAX_DEPTH {
+                rng.gen_range(min..=max)
+            } else {
+                schema["minItems"].as_u64().unwrap_or(0)
+            };
+            (0..count)
+                .map(|_| generate_value_at(&schema["items"], root, depth + 1))
+                .collect()
+        }
+        "integer" => {
+            let min = schema["minimum"].as_i64().unwrap_or(0);
+            let max = schema["maximum"].as_i64().unwrap_or(min + 100).max(min);
+            Value::from(rng.gen_range(min..=max))
+        }
+        "number" => {
+            let min = schema["minimum"].as_f64().unwrap_or(0.0);
+            let max = schema["maximum"].as_f64().unwrap_or(min + 100.0);
+            Value::from(if max > min {
+                rng.gen_range(min..max)
+            } else {
+                min
+            })
+        }
+        "boolean" => Value::Bool(rng.gen()),
+        "null" => Value::Null,
+        _ => Value::String(generate_string(schema)),
+    }
+}
+
+fn generate_string(schema: &Value) -> String {
+    let mut rng = rand::thread_rng();
+    let mut string = match schema["format"].as_str() {
+        Some("date-time") => format!(
+            "2025-{:02}-{:02}T{:02}:{:02}:00Z",
+            rng.gen_range(1..=12),
+            rng.gen_range(1..=28),
+            rng.gen_range(0..24),
+            rng.gen_range(0..60)
+        ),
+        Some("date") => format!(
+            "2025-{:02}-{:02}",
+            rng.gen_range(1..=12),
+            rng.gen_range(1..=28)
+        ),
+        Some("email") => "roy@example.com".to_string(),
+        Some("uri") => "https://example.com/roy".to_string(),
+        Some("uuid") => {
+            let hex = format!("{:032x}", rng.gen::<u128>());
+            format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            )
+        }
+        _ => lipsum::lipsum_words(rng.gen_range(1..6)),
+    };
+    if let Some(max_length) = schema["maxLength"].as_u64() {
+        string.truncate(max_length as usize);
+    }
+    string
+}
@@ -0,0 +1,418 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::embeddings::fnv1a;
+use crate::errors::ApiError;
+use crate::server_state::ServerState;
+
+const DEFAULT_SEARCH_RESULTS: usize = 10;
+const CHUNK_WORDS: usize = 80;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FileCounts {
+    pub in_progress: u32,
+    pub completed: u32,
+    pub failed: u32,
+    pub cancelled: u32,
+    pub total: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VectorStore {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub name: Option<String>,
+    pub usage_bytes: usize,
+    pub file_counts: FileCounts,
+    pub status: String,
+    pub expires_after: Option<Value>,
+    pub last_active_at: u64,
+    pub metadata: Value,
+    #[serde(skip)]
+    pub files: Vec<VectorStoreFile>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VectorStoreFile {
+    pub id: String,
+    pub object: String,
+    pub usage_bytes: usize,
+    pub created_at: u64,
+    pub vector_store_id: String,
+    pub status: String,
+    pub last_error: Option<Value>,
+    pub chunking_strategy: Value,
+    #[serde(skip)]
+    pub filename: String,
+    #[serde(skip)]
+    pub attached_at: Option<SystemTime>,
+}
+
+impl VectorStore {
+    /// Completes the ingestion of the files attached more than `ingestion` ago and updates
+    /// the counters, the store is `completed` once every file is.
+    fn refresh(&mut self, ingestion: Duration) {
+        let mut counts = FileCounts::default();
+        let mut usage_bytes = 0;
+        for file in &mut self.files {
+            let elapsed = file
+                .attached_at
+                .and_then(|at| at.elapsed().ok())
+                .unwrap_or_default();
+            if file.status == "in_progress" && elapsed >= ingestion {
+                file.status = "completed".to_string();
+            }
+            match file.status.as_str() {
+                "in_progress" => counts.in_progress += 1,
+                "completed" => {
+                    counts.completed += 1;
+                    usage_bytes += file.usage_bytes;
+                }
+                "cancelled" => counts.cancelled += 1,
+                _ => counts.failed += 1,
+            }
+            counts.total += 1;
+        }
+        self.status = if counts.in_progress > 0 {
+            "in_progress"
+        } else {
+            "completed"
+        }
+        .to_string();
+        self.file_counts = counts;
+        self.usage_bytes = usage_bytes;
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateVectorStoreRequest {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub file_ids: Vec<String>,
+    pub expires_after: Option<Value>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct AttachFileRequest {
+    pub file_id: String,
+    pub chunking_strategy: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    pub query: Value,
+    pub max_num_results: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub order: Option<String>,
+    pub after: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("should be able to get duration")
+        .as_secs()
+}
+
+fn not_found(kind: &str, id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("No {} found with id '{}'.", kind, id),
+    )
+}
+
+/// Lists items sorted by creation, newest first unless `order` is `asc`.
+fn list_page<T: Serialize>(
+    mut items: Vec<&T>,
+    id: impl Fn(&T) -> &str,
+    query: &ListQuery,
+) -> Response {
+    if query.order.as_deref() != Some("asc") {
+        items.reverse();
+    }
+    if let Some(after) = &query.after {
+        let start = items
+            .iter()
+            .position(|item| id(item) == after)
+            .map_or(0, |position| position + 1);
+        items.drain(..start);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+
+    Json(json!({
+        "object": "list",
+        "data": items,
+        "first_id": items.first().map(|item| id(item)),
+        "last_id": items.last().map(|item| id(item)),
+        "has_more": has_more,
+    }))
+    .into_response()
+}
+
+/// Attaches an uploaded file to a store, its ingestion starts right away.
+fn attach(
+    state: &ServerState,
+    vector_store_id: &str,
+    file_id: &str,
+    chunking_strategy: Option<Value>,
+) -> Result<VectorStoreFile, ApiError> {
+    let file = state
+        .file(file_id)
+        .ok_or_else(|| not_found("file", file_id).with_param("file_id"))?;
+    Ok(VectorStoreFile {
+        id: file.id,
+        object: "vector_store.file".to_string(),
+        usage_bytes: file.bytes,
+        created_at: now(),
+        vector_store_id: vector_store_id.to_string(),
+        status: "in_progress".to_string(),
+        last_error: None,
+        chunking_strategy: chunking_strategy.unwrap_or_else(|| {
+            json!({
+                "type": "static",
+                "static": {"max_chunk_size_tokens": 800, "chunk_overlap_tokens": 400},
+            })
+        }),
+        filename: file.filename,
+        attached_at: Some(SystemTime::now()),
+    })
+}
+
+/// Runs `f` on the store with the given id after bringing its ingestion progress up to date.
+fn with_store<T>(
+    state: &ServerState,
+    id: &str,
+    f: impl FnOnce(&mut VectorStore) -> T,
+) -> Result<T, ApiError> {
+    let ingestion = state.args().ingestion_duration;
+    let mut stores = state.vector_stores().lock().unwrap();
+    let store = stores
+        .iter_mut()
+        .find(|store| store.id == id)
+        .ok_or_else(|| not_found("vector store", id))?;
+    store.refresh(ingestion);
+    let result = f(store);
+    store.refresh(ingestion);
+    Ok(result)
+}
+
+pub async fn create(
+    state: State<ServerState>,
+    Json(payload): Json<CreateVectorStoreRequest>,
+) -> Response {
+    let id = format!("vs_{:x}", rand::thread_rng().gen::<u128>());
+    let files = match payload
+        .file_ids
+        .iter()
+        .map(|file_id| attach(&state, &id, file_id, None))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(files) => files,
+        Err(error) => return state.error_response(error),
+    };
+
+    let created_at = now();
+    let mut store = VectorStore {
+        id,
+        object: "vector_store".to_string(),
+        created_at,
+        name: payload.name,
+        usage_bytes: 0,
+        file_counts: FileCounts::default(),
+        status: "in_progress".to_string(),
+        expires_after: payload.expires_after,
+        last_active_at: created_at,
+        metadata: payload.metadata.unwrap_or_else(|| json!({})),
+        files,
+    };
+    store.refresh(state.args().ingestion_duration);
+    state.vector_stores().lock().unwrap().push(store.clone());
+    Json(store).into_response()
+}
+
+pub async fn list(State(state): State<ServerState>, Query(query): Query<ListQuery>) -> Response {
+    let ingestion = state.args().ingestion_duration;
+    let mut stores = state.vector_stores().lock().unwrap();
+    for store in stores.iter_mut() {
+        store.refresh(ingestion);
+    }
+    list_page(stores.iter().collect(), |store| store.id.as_str(), &query)
+}
+
+pub async fn retrieve(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    match with_store(&state, &id, |store| store.clone()) {
+        Ok(store) => Json(store).into_response(),
+        Err(error) => state.error_response(error),
+    }
+}
+
+pub async fn delete(state: State<ServerState>, Path(id): Path<String>) -> Response {
+    let mut stores = state.vector_stores().lock().unwrap();
+    let before = stores.len();
+    stores.retain(|store| store.id != id);
+    if stores.len() == before {
+        return state.error_response(not_found("vector store", &id));
+    }
+    Json(json!({"id": id, "object": "vector_store.deleted", "deleted": true})).into_response()
+}
+
+pub async fn attach_file(
+    state: State<ServerState>,
+    Path(id): Path<String>,
+    Json(payload): Json<AttachFileRequest>,
+) -> Response {
+    let file = match attach(&state, &id, &payload.file_id, payload.chunking_strategy) {
+        Ok(file) => file,
+        Err(error) => return state.error_response(error),
+    };
+    let attached = with_store(&state, &id, |store| {
+        store.files.retain(|attached| attached.id != file.id);
+        store.files.push(file.clone());
+        store.last_active_at = now();
+    });
+    match attached {
+        Ok(()) => Json(file).into_response(),
+        Err(error) => state.error_response(error),
+    }
+}
+
+pub async fn list_files(
+    state: State<ServerState>,
+    Path(id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    match with_store(&state, &id, |store| store.files.clone()) {
+        Ok(files) => list_page(files.iter().collect(), |file| file.id.as_str(), &query),
+        Err(error) => state.error_response(error),
+    }
+}
+
+pub async fn retrieve_file(
+    state: State<ServerState>,
+    Path((id, file_id)): Path<(String, String)>,
+) -> Response {
+    let file = with_store(&state, &id, |store| {
+        store.files.iter().find(|file| file.id == file_id).cloned()
+    });
+    match file {
+        Ok(Some(file)) => Json(file).into_response(),
+        Ok(None) => state.error_response(not_found("file", &file_id)),
+        Err(error) => state.error_response(error),
+    }
+}
+
+pub async fn detach_file(
+    state: State<ServerState>,
+    Path((id, file_id)): Path<(String, String)>,
+) -> Response {
+    let detached = with_store(&state, &id, |store| {
+        let before = store.files.len();
+        store.files.retain(|file| file.id != file_id);
+        store.files.len() < before
+    });
+    match detached {
+        Ok(true) => Json(json!({
+            "id": file_id,
+            "object": "vector_store.file.deleted",
+            "deleted": true,
+        }))
+        .into_response(),
+        Ok(false) => state.error_response(not_found("file", &file_id)),
+        Err(error) => state.error_response(error),
+    }
+}
+
+/// Returns lorem chunks of the ingested files with decreasing scores. Results are seeded by the
+/// query, the same search always returns the same chunks.
+pub async fn search(
+    state: State<ServerState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SearchRequest>,
+) -> Response {
+    let query = match &payload.query {
+        Value::String(query) => query.clone(),
+        Value::Array(queries) => queries
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => {
+            return state.error_response(
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    "'query' must be a string or an array of strings",
+                )
+                .with_param("query"),
+            );
+        }
+    };
+
+    let files = match with_store(&state, &id, |store| {
+        store.last_active_at = now();
+        store
+            .files
+            .iter()
+            .filter(|file| file.status == "completed")
+            .cloned()
+            .collect::<Vec<_>>()
+    }) {
+        Ok(files) => files,
+        Err(error) => return state.error_response(error),
+    };
+
+    let mut rng = StdRng::seed_from_u64(fnv1a(&query));
+    let max_results = payload
+        .max_num_results
+        .unwrap_or(DEFAULT_SEARCH_RESULTS)
+        .clamp(1, 50);
+    let mut score: f64 = rng.gen_range(0.7..0.95);
+    let data = files
+        .iter()
+        .cycle()
+        .take(max_results)
+        .map(|file| {
+            let result = json!({
+                "file_id": file.id,
+                "filename": file.filename,
+                "score": score,
+                "attributes": {},
+                "content": [{
+                    "type": "text",
+                    "text": lipsum::lipsum_words_with_rng(&mut rng, CHUNK_WORDS),
+                }],
+            });
+            score *= rng.gen_range(0.8..0.99);
+            result
+        })
+        .collect::<Vec<_>>();
+
+    Json(json!({
+        "object": "vector_store.search_results.page",
+        "search_query": query,
+        "data": data,
+        "has_more": false,
+        "next_page": null,
+    }))
+    .into_response()
+}
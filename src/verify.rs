@@ -0,0 +1,291 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use colored::Colorize;
+use serde_json::{json, Value};
+
+/// The weather tool offered to the model when asking for tool calls.
+const TOOLS: &str = r#"[{"type": "function", "function": {"name": "get_weather", "parameters": {
+    "type": "object",
+    "properties": {"city": {"type": "string"}},
+    "required": ["city"]
+}}}]"#;
+
+/// Sends chat completion requests to the client or gateway under test, which is expected to
+/// forward them to a running roy.
+pub struct Target {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+/// What a streamed chat completion was made of.
+#[derive(Debug, Default)]
+struct Stream {
+    content: String,
+    tool_arguments: String,
+    finish_reason: Option<String>,
+    done: bool,
+}
+
+type Outcome = Result<(), String>;
+
+impl Target {
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// Sends a chat completion for `prompt`, the magic tokens in it choosing roy's behaviour.
+    async fn chat(&self, prompt: &str, stream: bool, tools: bool) -> Result<Response, String> {
+        let mut body = json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": prompt}],
+            "stream": stream,
+        });
+        if tools {
+            body["tools"] = serde_json::from_str(TOOLS).expect("TOOLS is valid JSON");
+        }
+
+        let mut request = self
+            .client
+            .post(format!("{}/v1/chat/completions", self.base_url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("request failed: {}", err))?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| format!("reading the response failed: {}", err))?;
+        Ok(Response {
+            status,
+            content_type: headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            body,
+        })
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: String,
+    body: String,
+}
+
+impl Response {
+    fn expect_success(&self) -> Outcome {
+        match self.status {
+            200..=299 => Ok(()),
+            status => Err(format!("expected a success, got {}: {}", status, self.body)),
+        }
+    }
+
+    fn json(&self) -> Result<Value, String> {
+        serde_json::from_str(&self.body).map_err(|err| format!("invalid JSON body: {}", err))
+    }
+
+    /// Reassembles a chat completion stream.
+    fn stream(&self) -> Result<Stream, String> {
+        if !self.content_type.starts_with("text/event-stream") {
+            return Err(format!(
+                "expected an event stream, got content type '{}'",
+                self.content_type
+            ));
+        }
+        let mut stream = Stream::default();
+        for data in self
+            .body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+        {
+            let data = data.trim();
+            if stream.done {
+                return Err("events were sent after [DONE]".to_string());
+            }
+            if data == "[DONE]" {
+                stream.done = true;
+                continue;
+            }
+            let chunk: Value =
+                serde_json::from_str(data).map_err(|err| format!("invalid chunk: {}", err))?;
+            let Some(choice) = chunk["choices"].get(0) else {
+                continue;
+            };
+            if let Some(content) = choice["delta"]["content"].as_str() {
+                stream.content.push_str(content);
+            }
+            if let Some(arguments) =
+                choice["delta"]["tool_calls"][0]["function"]["arguments"].as_str()
+            {
+                stream.tool_arguments.push_str(arguments);
+            }
+            if let Some(finish_reason) = choice["finish_reason"].as_str() {
+                stream.finish_reason = Some(finish_reason.to_string());
+            }
+        }
+        if !stream.done {
+            return Err("the stream didn't end with [DONE]".to_string());
+        }
+        Ok(stream)
+    }
+}
+
+async fn completion(target: &Target) -> Outcome {
+    let response = target.chat("Hello", false, false).await?;
+    response.expect_success()?;
+    let body = response.json()?;
+    if !body["choices"][0]["message"]["content"].is_string() {
+        return Err("the completion has no content".to_string());
+    }
+    if !body["usage"]["total_tokens"].is_u64() {
+        return Err("the usage is missing".to_string());
+    }
+    Ok(())
+}
+
+async fn streaming(target: &Target) -> Outcome {
+    let response = target.chat("Hello", true, false).await?;
+    response.expect_success()?;
+    let stream = response.stream()?;
+    if stream.content.is_empty() {
+        return Err("the stream has no content".to_string());
+    }
+    if stream.finish_reason.as_deref() != Some("stop") {
+        return Err(format!(
+            "expected finish reason 'stop', got {:?}",
+            stream.finish_reason
+        ));
+    }
+    Ok(())
+}
+
+/// The error must reach the caller with its status and the error object.
+async fn error_passthrough(target: &Target, code: u16) -> Outcome {
+    let response = target
+        .chat(&format!("Hello [ROY:{}]", code), false, false)
+        .await?;
+    if response.status != code {
+        return Err(format!(
+            "the {} from upstream was returned as {}",
+            code, response.status
+        ));
+    }
+    let body = response.json()?;
+    if !body["error"]["message"].is_string() {
+        return Err(format!("the error object was lost: {}", response.body));
+    }
+    Ok(())
+}
+
+async fn slow_response(target: &Target) -> Outcome {
+    let response = target.chat("Hello [ROY:DELAY=5s]", false, false).await?;
+    response.expect_success()
+}
+
+async fn tool_call(target: &Target) -> Outcome {
+    let response = target.chat("Weather? [ROY:TOOL_CALL]", false, true).await?;
+    response.expect_success()?;
+    let body = response.json()?;
+    let choice = &body["choices"][0];
+    if choice["finish_reason"] != "tool_calls" {
+        return Err(format!(
+            "expected finish reason 'tool_calls', got {}",
+            choice["finish_reason"]
+        ));
+    }
+    let arguments = choice["message"]["tool_calls"][0]["function"]["arguments"]
+        .as_str()
+        .ok_or("the tool call has no arguments")?;
+    serde_json::from_str::<Value>(arguments)
+        .map(|_| ())
+        .map_err(|err| format!("the tool arguments are not valid JSON: {}", err))
+}
+
+async fn streamed_tool_call(target: &Target) -> Outcome {
+    let response = target.chat("Weather? [ROY:TOOL_CALL]", true, true).await?;
+    response.expect_success()?;
+    let stream = response.stream()?;
+    if stream.finish_reason.as_deref() != Some("tool_calls") {
+        return Err(format!(
+            "expected finish reason 'tool_calls', got {:?}",
+            stream.finish_reason
+        ));
+    }
+    serde_json::from_str::<Value>(&stream.tool_arguments)
+        .map(|_| ())
+        .map_err(|err| format!("the reassembled tool arguments are not valid JSON: {}", err))
+}
+
+/// Broken tool arguments are the model's fault, they must reach the caller as they are.
+async fn truncated_tool_call(target: &Target) -> Outcome {
+    let response = target
+        .chat("Weather? [ROY:TOOL_CALL_TRUNCATED]", true, true)
+        .await?;
+    response.expect_success()?;
+    let stream = response.stream()?;
+    if serde_json::from_str::<Value>(&stream.tool_arguments).is_ok() {
+        return Err("the truncated tool arguments were altered".to_string());
+    }
+    Ok(())
+}
+
+/// Drives the canned requests through the client or gateway at `base_url` and reports the
+/// behaviours it mishandled. Fails if any did.
+pub async fn verify(base_url: &str, api_key: Option<String>) -> anyhow::Result<()> {
+    let target = Target::new(base_url, api_key);
+    let outcomes = [
+        ("Chat completion", completion(&target).await),
+        ("Streamed chat completion", streaming(&target).await),
+        ("Server errors (500)", error_passthrough(&target, 500).await),
+        (
+            "Unavailable errors (503)",
+            error_passthrough(&target, 503).await,
+        ),
+        (
+            "Rate limit errors (429)",
+            error_passthrough(&target, 429).await,
+        ),
+        ("Slow responses (5s)", slow_response(&target).await),
+        ("Tool calls", tool_call(&target).await),
+        ("Streamed tool calls", streamed_tool_call(&target).await),
+        (
+            "Truncated tool arguments",
+            truncated_tool_call(&target).await,
+        ),
+    ];
+
+    let mut failed = 0;
+    for (name, outcome) in &outcomes {
+        match outcome {
+            Ok(()) => println!("{} {}", "✔".green(), name),
+            Err(reason) => {
+                failed += 1;
+                println!("{} {}: {}", "✘".red(), name, reason);
+            }
+        }
+    }
+
+    println!();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} behaviours were mishandled",
+            failed,
+            outcomes.len()
+        );
+    }
+    println!("All {} behaviours were handled correctly", outcomes.len());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+
+/// Tokens every image costs, whatever its size.
+const BASE_TOKENS: u32 = 85;
+/// Tokens of every 512px tile of an image in high detail.
+const TILE_TOKENS: u32 = 170;
+const TILE_SIZE: u32 = 512;
+/// Images are scaled to fit a 2048px square, then down to 768px on their shorter side.
+const MAX_SIDE: u32 = 2048;
+const SHORT_SIDE: u32 = 768;
+/// The size assumed for the images whose dimensions can't be read, like remote ones.
+const DEFAULT_SIZE: (u32, u32) = (1024, 1024);
+
+/// Estimates the tokens of an image input: a flat amount in low detail, otherwise one more
+/// amount for every 512px tile of the scaled image.
+pub fn image_tokens(url: &str, detail: &str) -> u32 {
+    if detail == "low" {
+        return BASE_TOKENS;
+    }
+    let (mut width, mut height) = dimensions(url).unwrap_or(DEFAULT_SIZE);
+    if width.max(height) > MAX_SIDE {
+        let scale = MAX_SIDE as f64 / width.max(height) as f64;
+        width = (width as f64 * scale) as u32;
+        height = (height as f64 * scale) as u32;
+    }
+    if width.min(height) > SHORT_SIDE {
+        let scale = SHORT_SIDE as f64 / width.min(height) as f64;
+        width = (width as f64 * scale) as u32;
+        height = (height as f64 * scale) as u32;
+    }
+    BASE_TOKENS + TILE_TOKENS * width.div_ceil(TILE_SIZE) * height.div_ceil(TILE_SIZE)
+}
+
+/// Adds up the tokens of the images in chat messages (`image_url` parts) or Responses input
+/// (`input_image` parts), wherever they're nested.
+pub fn count_image_tokens(value: &Value) -> u32 {
+    if let Some((url, detail)) = image(value) {
+        return image_tokens(url, detail);
+    }
+    match value {
+        Value::Array(items) => items.iter().map(count_image_tokens).sum(),
+        Value::Object(object) => object.values().map(count_image_tokens).sum(),
+        _ => 0,
+    }
+}
+
+/// Drops the URLs, often whole base64 encoded images, of the image parts in `value` so that
+/// they aren't counted as text.
+pub fn strip_images(value: &mut Value) {
+    if image(value).is_some() {
+        value["image_url"] = Value::Null;
+        return;
+    }
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(strip_images),
+        Value::Object(object) => object.values_mut().for_each(strip_images),
+        _ => {}
+    }
+}
+
+/// Returns the URL and detail level of an image part.
+fn image(part: &Value) -> Option<(&str, &str)> {
+    match part["type"].as_str()? {
+        "image_url" => Some((
+            part["image_url"]["url"].as_str()?,
+            part["image_url"]["detail"].as_str().unwrap_or("auto"),
+        )),
+        "input_image" => Some((
+            part["image_url"].as_str().unwrap_or_default(),
+            part["detail"].as_str().unwrap_or("auto"),
+        )),
+        _ => None,
+    }
+}
+
+/// Reads the width and height of a PNG, GIF or JPEG image sent as a base64 data URL.
+fn dimensions(url: &str) -> Option<(u32, u32)> {
+    let (_, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+    let bytes = STANDARD.decode(data).ok()?;
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x89PNG") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"GIF") {
+        let le16 =
+            |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+        return Some((le16(6)?, le16(8)?));
+    }
+    if bytes.starts_with(b"\xFF\xD8") {
+        // Walk the segments up to the start of frame, which holds the size
+        let mut at = 2;
+        while at + 9 < bytes.len() {
+            if bytes[at] != 0xFF {
+                return None;
+            }
+            let marker = bytes[at + 1];
+            if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
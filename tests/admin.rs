@@ -0,0 +1,146 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{
+        admin, chat_completions, config::Price, journal, scenario::Scenario,
+        server_state::ServerState, Args,
+    };
+    use std::collections::BTreeMap;
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_verify() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"expectations":[{"path":"/v1/chat/completions","model":"gpt-4o","count":1}]}"#,
+        )
+        .unwrap();
+        let state = ServerState::new(common::args()).with_scenario(scenario);
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                journal::record,
+            ))
+            .route("/__admin/verify", get(admin::verify))
+            .with_state(state);
+
+        let verify = || {
+            Request::builder()
+                .uri("/__admin/verify")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(verify()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::EXPECTATION_FAILED);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(verify()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/__admin/count_tokens", post(admin::count_tokens))
+            .with_state(state.clone());
+
+        let count = |body: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri("/__admin/count_tokens")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(count(r#"{"text":"Hello world"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["tokens"], state.count_tokens("Hello world").unwrap());
+
+        let response = app.oneshot(count("{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_pricing_and_limits() {
+        let prices = BTreeMap::from([(
+            "gpt-4o".to_string(),
+            Price {
+                input: 2.5,
+                output: 10.0,
+            },
+        )]);
+        let args = Args {
+            rpm: 60,
+            max_streams_per_key: Some(2),
+            ..common::args()
+        };
+        let state = ServerState::new(args).with_prices(prices);
+        let app = Router::new()
+            .route("/__admin/pricing", get(admin::pricing))
+            .route("/__admin/limits", get(admin::limits))
+            .with_state(state);
+
+        let get_json = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        let pricing = get_json("/__admin/pricing").await;
+        assert_eq!(pricing["models"]["gpt-4o"]["output"], 10.0);
+
+        let limits = get_json("/__admin/limits").await;
+        assert_eq!(limits["rpm"], 60);
+        assert_eq!(limits["max_streams_per_key"], 2);
+        assert!(limits["quota_exceeded_after"].is_null());
+    }
+}
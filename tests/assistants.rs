@@ -0,0 +1,130 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{assistants, server_state::ServerState};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    fn app() -> Router {
+        Router::new()
+            .route("/v1/assistants", post(assistants::create_assistant))
+            .route("/v1/threads", post(assistants::create_thread))
+            .route(
+                "/v1/threads/:thread_id/messages",
+                post(assistants::create_message).get(assistants::list_messages),
+            )
+            .route("/v1/threads/:thread_id/runs", post(assistants::create_run))
+            .route(
+                "/v1/threads/:thread_id/runs/:run_id",
+                get(assistants::retrieve_run),
+            )
+            .with_state(ServerState::new(common::args()))
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, body: &str) -> axum::response::Response {
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_lifecycle() {
+        let app = app();
+        let assistant =
+            json_body(call(&app, "POST", "/v1/assistants", r#"{"model":"gpt-4o"}"#).await).await;
+        let thread = json_body(
+            call(
+                &app,
+                "POST",
+                "/v1/threads",
+                r#"{"messages":[{"role":"user","content":"Hello"}]}"#,
+            )
+            .await,
+        )
+        .await;
+        let thread_id = thread["id"].as_str().unwrap();
+
+        let response = call(
+            &app,
+            "POST",
+            &format!("/v1/threads/{}/runs", thread_id),
+            &format!(
+                r#"{{"assistant_id":"{}"}}"#,
+                assistant["id"].as_str().unwrap()
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let run = json_body(response).await;
+        assert_eq!(run["status"], "queued");
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        let uri = format!(
+            "/v1/threads/{}/runs/{}",
+            thread_id,
+            run["id"].as_str().unwrap()
+        );
+        let run = json_body(call(&app, "GET", &uri, "").await).await;
+        assert_eq!(run["status"], "completed");
+
+        let uri = format!("/v1/threads/{}/messages", thread_id);
+        let messages = json_body(call(&app, "GET", &uri, "").await).await;
+        assert_eq!(messages["data"][0]["role"], "assistant");
+        assert_eq!(messages["data"][1]["content"][0]["text"]["value"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_streamed_run() {
+        let app = app();
+        let assistant =
+            json_body(call(&app, "POST", "/v1/assistants", r#"{"model":"gpt-4o"}"#).await).await;
+        let thread = json_body(call(&app, "POST", "/v1/threads", "{}").await).await;
+
+        let response = call(
+            &app,
+            "POST",
+            &format!("/v1/threads/{}/runs", thread["id"].as_str().unwrap()),
+            &format!(
+                r#"{{"assistant_id":"{}","stream":true}}"#,
+                assistant["id"].as_str().unwrap()
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("event: thread.run.created\n"));
+        assert!(body.contains("event: thread.message.delta\n"));
+        assert!(body.contains("event: thread.run.completed\n"));
+        assert!(body.contains("event: done\ndata: [DONE]\n"));
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use roy_cli::{audio, chat_completions, server_state::ServerState, Args};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_speech_wav() {
+        let state = ServerState::new(Args {
+            audio_duration: Some(Duration::from_secs(1)),
+            ..common::args()
+        });
+        let app = Router::new()
+            .route("/v1/audio/speech", post(audio::speech))
+            .with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/audio/speech")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"model":"tts-1","voice":"alloy","input":"Hello","response_format":"wav"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "audio/wav");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(b"RIFF"));
+        // One second of 16-bit mono samples at 24 kHz, plus the header
+        assert_eq!(body.len(), 44 + 48_000);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_audio_output() {
+        let state = ServerState::new(Args {
+            audio_duration: Some(Duration::from_secs(2)),
+            ..common::args()
+        });
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(state);
+        let complete = |audio: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(
+                                serde_json::json!({
+                                    "model": "gpt-4o-audio-preview",
+                                    "modalities": ["text", "audio"],
+                                    "audio": audio,
+                                    "messages": [{"role": "user", "content": "Hello"}],
+                                })
+                                .to_string(),
+                            ))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+
+        let (status, body) = complete(serde_json::json!({"voice": "alloy", "format": "wav"})).await;
+        assert_eq!(status, StatusCode::OK);
+        let message = &body["choices"][0]["message"];
+        assert!(message["content"].is_null());
+        assert!(message["audio"]["id"]
+            .as_str()
+            .unwrap()
+            .starts_with("audio_"));
+        assert!(!message["audio"]["transcript"].as_str().unwrap().is_empty());
+        let data = STANDARD
+            .decode(message["audio"]["data"].as_str().unwrap())
+            .unwrap();
+        assert!(data.starts_with(b"RIFF"));
+        assert_eq!(data.len(), 44 + 2 * 24_000 * 2);
+        assert_eq!(
+            body["usage"]["completion_tokens_details"]["audio_tokens"],
+            20
+        );
+
+        let (status, body) = complete(serde_json::json!({"voice": "alloy", "format": "ogg"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["param"], "audio.format");
+
+        let (status, body) = complete(serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["param"], "audio");
+    }
+}
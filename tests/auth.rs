@@ -0,0 +1,83 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use roy_cli::{admin, auth, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_api_key_rotation() {
+        let args = Args {
+            api_key: Some("sk-initial".to_string()),
+            api_key_ttl: Some(std::time::Duration::from_millis(200)),
+            ..common::args()
+        };
+        let state = ServerState::new(args);
+        let app = Router::new()
+            .route("/v1/models", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth::require_api_key,
+            ))
+            .route("/__admin/api_key", get(admin::api_key))
+            .with_state(state);
+        let status = |key: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    Request::builder()
+                        .uri("/v1/models")
+                        .header("Authorization", format!("Bearer {}", key))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            }
+        };
+        let current_key = || {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/__admin/api_key")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                body["api_key"].as_str().unwrap().to_string()
+            }
+        };
+
+        assert_eq!(current_key().await, "sk-initial");
+        assert_eq!(status("sk-initial".to_string()).await, StatusCode::OK);
+
+        // Once the TTL elapses the key is replaced and the old one is rejected
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert_eq!(
+            status("sk-initial".to_string()).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let rotated = current_key().await;
+        assert!(rotated.starts_with("sk-roy-"));
+        assert_eq!(status(rotated).await, StatusCode::OK);
+    }
+}
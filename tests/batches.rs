@@ -0,0 +1,95 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{batches, files::StoredFile, server_state::ServerState};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_batch_lifecycle() {
+        let state = ServerState::new(common::args());
+        let input = StoredFile::new(
+            "input.jsonl".to_string(),
+            "batch".to_string(),
+            concat!(
+                r#"{"custom_id":"a","method":"POST","url":"/v1/chat/completions","body":{"model":"gpt-4o","messages":[{"role":"user","content":"Hi"}]}}"#,
+                "\n",
+                r#"{"custom_id":"b","method":"POST","url":"/v1/chat/completions","body":{"model":"gpt-4o","messages":[{"role":"user","content":"Bye"}]}}"#,
+                "\n",
+            )
+            .as_bytes()
+            .to_vec(),
+        );
+        let input_file_id = input.id.clone();
+        state.files().lock().unwrap().push(input);
+
+        let app = Router::new()
+            .route("/v1/batches", post(batches::create).get(batches::list))
+            .route("/v1/batches/:batch_id", get(batches::retrieve))
+            .with_state(state.clone());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/batches")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"input_file_id":"{}","endpoint":"/v1/chat/completions","completion_window":"24h"}}"#,
+                        input_file_id
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let batch = json_body(response).await;
+        assert_eq!(batch["status"], "validating");
+        assert_eq!(batch["request_counts"]["total"], 2);
+        let id = batch["id"].as_str().unwrap().to_string();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/batches/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let batch = json_body(response).await;
+        assert_eq!(batch["status"], "completed");
+        assert_eq!(batch["request_counts"]["completed"], 2);
+
+        let output = state
+            .file(batch["output_file_id"].as_str().unwrap())
+            .unwrap();
+        let lines = String::from_utf8(output.content).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["custom_id"], "a");
+        assert_eq!(first["response"]["status_code"], 200);
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{
+            header::{CONTENT_ENCODING, CONTENT_TYPE},
+            Request,
+        },
+        middleware,
+        response::Response,
+        routing::post,
+        Router,
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use futures_util::stream;
+    use roy_cli::{capture, server_state::ServerState};
+    use serde_json::Value;
+    use std::io::Write;
+    use std::path::Path;
+    use tower::ServiceExt; // for `oneshot`
+
+    /// Streams `chunks` through the capture middleware and returns the lines of the capture file.
+    async fn capture_stream(dir: &Path, chunks: Vec<Vec<u8>>, gzip: bool) -> Vec<Value> {
+        let mut args = common::args();
+        args.capture_dir = Some(dir.to_path_buf());
+        let state = ServerState::new(args);
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(move || async move {
+                    let chunks = chunks.into_iter().map(Ok::<_, std::io::Error>);
+                    let mut response =
+                        Response::builder().header(CONTENT_TYPE, "text/event-stream");
+                    if gzip {
+                        response = response.header(CONTENT_ENCODING, "gzip");
+                    }
+                    response
+                        .body(Body::from_stream(stream::iter(chunks)))
+                        .unwrap()
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                capture::capture,
+            ))
+            .with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let file = std::fs::read_dir(dir).unwrap().next().unwrap().unwrap();
+        std::fs::read_to_string(file.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_characters_split_across_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let event = "data: データ\n\n".as_bytes();
+        // The second write starts in the middle of the first character
+        let chunks = vec![event[..7].to_vec(), event[7..].to_vec()];
+
+        let lines = capture_stream(dir.path(), chunks, false).await;
+        let events = lines
+            .iter()
+            .filter(|line| line["type"] == "event")
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["data"], "データ");
+    }
+
+    #[tokio::test]
+    async fn test_gzip_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let chunks = ["data: {\"a\":1}\n\n", "data: [DONE]\n\n"]
+            .iter()
+            .map(|event| {
+                encoder.write_all(event.as_bytes()).unwrap();
+                encoder.flush().unwrap();
+                std::mem::take(encoder.get_mut())
+            })
+            .collect();
+
+        let lines = capture_stream(dir.path(), chunks, true).await;
+        let data = lines
+            .iter()
+            .filter(|line| line["type"] == "event")
+            .map(|line| line["data"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(data, ["{\"a\":1}", "[DONE]"]);
+    }
+}
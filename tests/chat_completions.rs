@@ -1,32 +1,28 @@
 // Copyright 2025 Massimiliano Pippi
 // SPDX-License-Identifier: MIT
 
+mod common;
+
 #[cfg(test)]
 mod tests {
+    use crate::common;
     use axum::{
         body::Body,
         http::{Request, StatusCode},
-        routing::post,
+        routing::{get, post},
         Router,
     };
-    use clap_verbosity_flag::Verbosity;
-    use roy_cli::{chat_completions, server_state::ServerState, Args};
+    use roy_cli::{
+        chat_completions, config::Price, server_state::ServerState, snapshot::Snapshot, Args,
+        ChoiceOrder, ContentLanguage, ContentMode, EchoTransform, StreamGranularity,
+    };
+    use std::collections::BTreeMap;
+    use std::time::Duration;
     use tower::ServiceExt; // for `oneshot`
 
     #[tokio::test]
     async fn test_chat_completions() {
-        let args = Args {
-            verbosity: Verbosity::new(0, 0),
-            port: 8000,
-            address: "127.0.0.1".parse().unwrap(),
-            response_length: Some("10".to_string()),
-            error_code: None,
-            error_rate: None,
-            rpm: 60,
-            tpm: 150000,
-            slowdown: Some("0".to_string()),
-            timeout: None,
-        };
+        let args = common::args();
         let state = ServerState::new(args);
         let app = Router::new()
             .route(
@@ -50,4 +46,1231 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_chat_completions_cost() {
+        let prices = BTreeMap::from([(
+            "gpt-4o".to_string(),
+            Price {
+                input: 1_000_000.0,
+                output: 0.0,
+            },
+        )]);
+        let state = ServerState::new(common::args()).with_prices(prices);
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(state.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let cost = state.costs().lock().unwrap()["gpt-4o"];
+        assert_eq!(cost.requests, 1);
+        assert!(cost.usd > 0.0);
+        assert_eq!(
+            response.headers()["x-roy-cost-usd"],
+            format!("{:.6}", cost.usd).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_new_snapshot() {
+        let args = Args {
+            response_length: Some("200".to_string()),
+            new_snapshot_rate: Some(100),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["system_fingerprint"], Snapshot::New.fingerprint());
+        let content = body["choices"][0]["message"]["content"].as_str().unwrap();
+        assert!(content.starts_with("- "));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_long_reset() {
+        let args = Args {
+            long_reset_rate: Some(100),
+            long_reset: Duration::from_secs(600),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["x-ratelimit-remaining-requests"], "0");
+        assert_eq!(response.headers()["x-ratelimit-reset-requests"], "10m");
+        assert_eq!(response.headers()["retry-after"], "600");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_odd_ratelimit_headers() {
+        let args = Args {
+            odd_ratelimit_headers_rate: Some(100),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let remaining = response.headers()["x-ratelimit-remaining-tokens"]
+            .to_str()
+            .unwrap();
+        assert!(["0", "18446744073709551615", "150000.0"].contains(&remaining));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_multiple_choices() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","n":3}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let indices = body["choices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|choice| choice["index"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2]);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","n":0}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_multiple_choices_streaming() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","n":2,"stream":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let choices = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .flat_map(|chunk| chunk["choices"].as_array().cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        // Every choice is streamed in full, with its own role and finish reason
+        for index in 0..2 {
+            let deltas = choices
+                .iter()
+                .filter(|choice| choice["index"] == index)
+                .collect::<Vec<_>>();
+            assert_eq!(deltas.first().unwrap()["delta"]["role"], "assistant");
+            assert_eq!(deltas.last().unwrap()["finish_reason"], "stop");
+        }
+        // Round-robin by default, the first chunks alternate between the choices
+        assert_eq!(choices[0]["index"], 0);
+        assert_eq!(choices[1]["index"], 1);
+    }
+
+    #[test]
+    fn test_interleave() {
+        let sequences = || vec![vec![(0, 0), (0, 1)], vec![(1, 0), (1, 1), (1, 2)]];
+
+        assert_eq!(
+            chat_completions::interleave(sequences(), ChoiceOrder::RoundRobin),
+            vec![(0, 0), (1, 0), (0, 1), (1, 1), (1, 2)]
+        );
+        assert_eq!(
+            chat_completions::interleave(sequences(), ChoiceOrder::Grouped),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1), (1, 2)]
+        );
+
+        // Random order still keeps the chunks of each choice in sequence
+        let merged = chat_completions::interleave(sequences(), ChoiceOrder::Random);
+        assert_eq!(merged.len(), 5);
+        for choice in 0..2 {
+            let positions = merged
+                .iter()
+                .filter(|(index, _)| *index == choice)
+                .map(|(_, position)| *position)
+                .collect::<Vec<_>>();
+            assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_moderation_block() {
+        let args = Args {
+            moderation_block_rate: Some(100),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "content_policy_violation");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_continuous_usage() {
+        let args = Args {
+            continuous_usage_every: Some(3),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true,"stream_options":{"include_usage":true}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let chunks = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .collect::<Vec<_>>();
+
+        // Role, 10 words and the finish reason, with the usage so far every 3 chunks
+        let usage_so_far = chunks
+            .iter()
+            .take(chunks.len() - 1)
+            .filter(|chunk| chunk["choices"].as_array().unwrap().is_empty())
+            .map(|chunk| chunk["usage"]["completion_tokens"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(usage_so_far.len(), 3);
+        assert!(usage_so_far.windows(2).all(|pair| pair[0] <= pair[1]));
+        let total = chunks.last().unwrap()["usage"]["completion_tokens"]
+            .as_u64()
+            .unwrap();
+        assert!(usage_so_far.iter().all(|tokens| *tokens <= total));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_retry_after() {
+        let args = Args {
+            rpm: 1,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let request = || {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((59..=60).contains(&retry_after));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("Limit 1, Used 1, Requested 1. Please try again in "));
+    }
+
+    #[tokio::test]
+    async fn test_stored_chat_completions() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route(
+                "/v1/chat/completions/:completion_id",
+                get(chat_completions::retrieve),
+            )
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","store":true,"metadata":{"team":"search"}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let id = created["id"].as_str().unwrap();
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/chat/completions/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stored: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stored["object"], "chat.completion");
+        assert_eq!(stored["choices"], created["choices"]);
+        assert_eq!(stored["usage"], created["usage"]);
+        assert_eq!(stored["metadata"]["team"], "search");
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/chat/completions/chatcmpl-unknown")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_json_object() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let request = |content: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "messages": [{"role": "user", "content": content}],
+                        "model": "gpt-4o",
+                        "stream": true,
+                        "response_format": {"type": "json_object"},
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("Hello")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.oneshot(request("Answer in JSON please")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let content = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .filter_map(|chunk| {
+                chunk["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .collect::<String>();
+        let object: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert!(object.is_object());
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_json_schema() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "title": {"type": "string", "maxLength": 20},
+                "rating": {"type": "integer", "minimum": 1, "maximum": 5},
+                "genre": {"type": "string", "enum": ["drama", "comedy"]},
+                "released": {"type": "string", "format": "date"},
+                "director": {"$ref": "#/$defs/person"},
+                "cast": {"type": "array", "items": {"$ref": "#/$defs/person"}, "minItems": 2},
+                "sequel": {"type": ["string", "null"]},
+            },
+            "required": ["title", "rating", "genre", "released", "director", "cast", "sequel"],
+            "additionalProperties": false,
+            "$defs": {
+                "person": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}, "age": {"type": "number"}},
+                },
+            },
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "messages": [{"role": "user", "content": "Review a movie"}],
+                    "model": "gpt-4o",
+                    "response_format": {
+                        "type": "json_schema",
+                        "json_schema": {"name": "review", "strict": true, "schema": schema},
+                    },
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let review: serde_json::Value =
+            serde_json::from_str(body["choices"][0]["message"]["content"].as_str().unwrap())
+                .unwrap();
+        assert!(review["title"].as_str().unwrap().len() <= 20);
+        assert!((1..=5).contains(&review["rating"].as_i64().unwrap()));
+        assert!(["drama", "comedy"].contains(&review["genre"].as_str().unwrap()));
+        assert_eq!(review["released"].as_str().unwrap().len(), 10);
+        assert!(review["director"]["name"].is_string());
+        assert!(review["director"]["age"].is_number());
+        assert!(review["cast"].as_array().unwrap().len() >= 2);
+        assert!(review["sequel"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_refusal() {
+        let args = Args {
+            refusal_rate: Some(100),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let message = &body["choices"][0]["message"];
+        assert!(message["content"].is_null());
+        assert_eq!(message["refusal"], "I'm sorry, but I can't help with that.");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_max_tokens() {
+        let args = Args {
+            response_length: Some("1000".to_string()),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let request = |max_tokens: u32| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "messages": [{"role": "user", "content": "Hello"}],
+                        "model": "gpt-4o",
+                        "max_completion_tokens": max_tokens,
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(0)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.oneshot(request(5)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["choices"][0]["finish_reason"], "length");
+        assert!(body["usage"]["completion_tokens"].as_u64().unwrap() <= 5);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_max_tokens_without_spaces() {
+        let args = Args {
+            response_length: Some("1000".to_string()),
+            content_language: ContentLanguage::Japanese,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "messages": [{"role": "user", "content": "Hello"}],
+                            "model": "gpt-4o",
+                            "max_tokens": 20,
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["choices"][0]["finish_reason"], "length");
+        // Cut on token boundaries rather than spaces, so the content isn't lost
+        let content = body["choices"][0]["message"]["content"].as_str().unwrap();
+        assert!(!content.is_empty());
+        assert!(!content.contains('\u{fffd}'));
+        let completion_tokens = body["usage"]["completion_tokens"].as_u64().unwrap();
+        assert!(completion_tokens > 10 && completion_tokens <= 20);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_logprobs() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let request = |body: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let messages = serde_json::json!([{"role": "user", "content": "Hello"}]);
+
+        let response = app
+            .clone()
+            .oneshot(request(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": messages,
+                "top_logprobs": 2,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(request(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": messages,
+                "logprobs": true,
+                "top_logprobs": 2,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let choice = &body["choices"][0];
+        let tokens = choice["logprobs"]["content"].as_array().unwrap();
+        let text = tokens
+            .iter()
+            .map(|token| token["token"].as_str().unwrap())
+            .collect::<String>();
+        assert_eq!(text, choice["message"]["content"]);
+        for token in tokens {
+            let logprob = token["logprob"].as_f64().unwrap();
+            assert!(logprob <= 0.0);
+            let top = token["top_logprobs"].as_array().unwrap();
+            assert_eq!(top.len(), 2);
+            assert_eq!(top[0]["token"], token["token"]);
+            assert!(top[1]["logprob"].as_f64().unwrap() < logprob);
+        }
+
+        let response = app
+            .oneshot(request(serde_json::json!({
+                "model": "gpt-4o",
+                "messages": messages,
+                "logprobs": true,
+                "stream": true,
+            })))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let choices = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .flat_map(|chunk| chunk["choices"].as_array().cloned().unwrap_or_default())
+            .filter(|choice| choice["delta"]["content"].is_string())
+            .collect::<Vec<_>>();
+        assert!(!choices.is_empty());
+        for choice in choices {
+            assert_eq!(
+                choice["logprobs"]["content"][0]["token"],
+                choice["delta"]["content"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_seed() {
+        let args = Args {
+            response_length: Some("100:200".to_string()),
+            new_snapshot_rate: Some(50),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let complete = |seed: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(
+                                serde_json::json!({
+                                    "model": "gpt-4o",
+                                    "messages": [{"role": "user", "content": "Hello"}],
+                                    "seed": seed,
+                                })
+                                .to_string(),
+                            ))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+
+        let (_, first) = complete(serde_json::json!(42)).await;
+        for _ in 0..5 {
+            let (_, again) = complete(serde_json::json!(42)).await;
+            assert_eq!(again["choices"], first["choices"]);
+            assert_eq!(again["system_fingerprint"], first["system_fingerprint"]);
+        }
+        let (_, other) = complete(serde_json::json!(7)).await;
+        assert_ne!(other["choices"], first["choices"]);
+
+        let (status, _) = complete(serde_json::json!("42")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_sampling_parameters() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let status = |parameters: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let mut body = serde_json::json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": "Hello"}],
+                });
+                body.as_object_mut()
+                    .unwrap()
+                    .extend(parameters.as_object().unwrap().clone());
+                app.oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            }
+        };
+
+        let valid = serde_json::json!({
+            "frequency_penalty": 1.5,
+            "presence_penalty": -2,
+            "logit_bias": {"50256": -100, "1234": 5.5},
+        });
+        assert_eq!(status(valid).await, StatusCode::OK);
+        for invalid in [
+            serde_json::json!({"frequency_penalty": 2.5}),
+            serde_json::json!({"presence_penalty": -3}),
+            serde_json::json!({"presence_penalty": "high"}),
+            serde_json::json!({"logit_bias": {"hello": 1}}),
+            serde_json::json!({"logit_bias": {"50256": 101}}),
+            serde_json::json!({"logit_bias": [1, 2]}),
+        ] {
+            assert_eq!(status(invalid).await, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_token_granularity() {
+        let args = Args {
+            response_length: Some("200".to_string()),
+            stream_granularity: StreamGranularity::Token,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true,"stream_options":{"include_usage":true}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let chunks = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .collect::<Vec<_>>();
+
+        // One delta per token of the content, as counted in the usage
+        let deltas = chunks
+            .iter()
+            .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+            .collect::<Vec<_>>();
+        let usage = &chunks.last().unwrap()["usage"];
+        assert_eq!(
+            deltas.len() as u64,
+            usage["completion_tokens"].as_u64().unwrap()
+        );
+        assert!(deltas.concat().len() <= 200);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_include_usage() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let stream = |body: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec())
+                    .unwrap()
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data: "))
+                    .filter(|data| *data != "[DONE]")
+                    .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let chunks = stream(
+            r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true}"#,
+        )
+        .await;
+        assert!(chunks.iter().all(|chunk| chunk.get("usage").is_none()));
+
+        let chunks = stream(
+            r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true,"stream_options":{"include_usage":true}}"#,
+        )
+        .await;
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|chunk| chunk.get("usage").is_none()));
+        assert_eq!(last["choices"], serde_json::json!([]));
+        assert!(last["usage"]["total_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_metadata_limits() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        let create = |store: bool, metadata: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(
+                                serde_json::json!({
+                                    "model": "gpt-4o",
+                                    "messages": [{"role": "user", "content": "Hello"}],
+                                    "store": store,
+                                    "metadata": metadata,
+                                })
+                                .to_string(),
+                            ))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (status, body["error"]["param"].clone())
+            }
+        };
+
+        let (status, _) = create(true, serde_json::json!({"team": "search"})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, param) = create(false, serde_json::json!({"team": "search"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(param, "metadata");
+
+        let too_many = (0..17)
+            .map(|index| (format!("key{}", index), serde_json::json!("value")))
+            .collect::<serde_json::Map<_, _>>();
+        let (status, param) = create(true, serde_json::Value::Object(too_many)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(param, "metadata");
+
+        let (status, param) = create(true, serde_json::json!({"team": "x".repeat(513)})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(param, "metadata.team");
+
+        let (status, param) = create(true, serde_json::json!({"team": 1})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(param, "metadata.team");
+
+        let key = "k".repeat(65);
+        let (status, param) = create(true, serde_json::json!({ key.clone(): "value" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(param, format!("metadata.{}", key));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_echo() {
+        let args = Args {
+            content_mode: ContentMode::Echo,
+            echo_transform: EchoTransform::Uppercase,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "model": "gpt-4o",
+                            "messages": [
+                                {"role": "system", "content": "Be brief"},
+                                {"role": "user", "content": "First question"},
+                                {"role": "assistant", "content": "First answer"},
+                                {"role": "user", "content": [{"type": "text", "text": "Second question"}]},
+                            ],
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "SECOND QUESTION");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_retry_after_jitter() {
+        let args = Args {
+            rpm: 1,
+            retry_after_jitter: Some(Duration::from_secs(30)),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let request = || {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut advice = vec![];
+        for _ in 0..10 {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            let retry_after_ms: u64 = response.headers()["retry-after-ms"]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            advice.push(retry_after_ms);
+
+            // The reset headers are pushed back as much as the Retry-After
+            let reset = response.headers()["x-ratelimit-reset-requests"]
+                .to_str()
+                .unwrap();
+            let reset_ms = humantime::parse_duration(reset).unwrap().as_millis() as u64;
+            assert!(
+                retry_after_ms.abs_diff(reset_ms) <= 1_000,
+                "{} {}",
+                retry_after_ms,
+                reset
+            );
+        }
+        assert!(advice.iter().all(|ms| (59_000..=90_000).contains(ms)));
+        assert!(advice.iter().any(|ms| *ms > 61_000));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_hashed_content() {
+        let args = Args {
+            response_length: Some("100:200".to_string()),
+            content_mode: ContentMode::Hashed,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let complete = |content: &str| {
+            let app = app.clone();
+            let body = serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": content}],
+            });
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(body.to_string()))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                body["choices"][0]["message"]["content"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            }
+        };
+
+        let first = complete("Hello").await;
+        assert_eq!(complete("Hello").await, first);
+        assert_ne!(complete("Goodbye").await, first);
+    }
 }
@@ -0,0 +1,127 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use roy_cli::{Args, Command};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_bare_roy_serves() {
+        let args = Args::try_parse_from(["roy", "--rpm", "10"])
+            .unwrap()
+            .resolve();
+        assert!(args.command.is_none());
+        assert_eq!(args.rpm, 10);
+    }
+
+    #[test]
+    fn test_flags_after_subcommand() {
+        let args = Args::try_parse_from(["roy", "serve", "--rpm", "10"])
+            .unwrap()
+            .resolve();
+        assert!(matches!(args.command, Some(Command::Serve)));
+        assert_eq!(args.rpm, 10);
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let args = Args::try_parse_from(["roy", "record", "captures"])
+            .unwrap()
+            .resolve();
+        assert!(args.command.is_none());
+        assert_eq!(args.capture_dir, Some(PathBuf::from("captures")));
+
+        let args = Args::try_parse_from(["roy", "replay", "captures", "--speed", "2"])
+            .unwrap()
+            .resolve();
+        assert!(args.command.is_none());
+        assert_eq!(args.playback_dir, Some(PathBuf::from("captures")));
+        assert_eq!(args.playback_speed, Some(2.0));
+    }
+
+    #[test]
+    fn test_verify() {
+        let args = Args::try_parse_from(["roy", "--api-key", "sk-test", "verify", "http://gw"])
+            .unwrap()
+            .resolve();
+        assert!(matches!(
+            args.command,
+            Some(Command::Verify { base_url }) if base_url == "http://gw"
+        ));
+        assert_eq!(args.api_key.as_deref(), Some("sk-test"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let parse = |flags: &[&str]| {
+            Args::try_parse_from(["roy"].iter().chain(flags))
+                .unwrap()
+                .resolve()
+        };
+        assert!(parse(&[]).validate().is_ok());
+        assert!(parse(&["--response-length", "10:100"]).validate().is_ok());
+        assert!(parse(&["--response-length", "abc:def"]).validate().is_err());
+        assert!(parse(&["--slowdown", "100:10"]).validate().is_err());
+        assert!(parse(&["--error-code", "503", "--error-rate", "150"])
+            .validate()
+            .is_err());
+        assert!(parse(&["--error-rate", "50"]).validate().is_err());
+        assert!(parse(&["--error-code", "200"]).validate().is_err());
+        assert!(parse(&["--error-code", "999"]).validate().is_err());
+        assert!(parse(&["--warmup", "soon"]).validate().is_err());
+        assert!(parse(&["--warmup", "30s:infx-latency"]).validate().is_err());
+        assert!(parse(&["--warmup", "30s:-2x-latency"]).validate().is_err());
+        assert!(parse(&["--stream-decay", "10:1.5"]).validate().is_ok());
+        for stream_decay in ["0", "10:-2", "10:0", "10:NaN", "10:inf"] {
+            assert!(parse(&["--stream-decay", stream_decay]).validate().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_does_not_serve() {
+        // The port is taken, only serving would fail
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let args =
+            Args::try_parse_from(["roy", "--address", "127.0.0.1", "--port", &port, "--check"])
+                .unwrap()
+                .resolve();
+        roy_cli::run(args).await.unwrap();
+
+        let args = Args::try_parse_from(["roy", "--response-length", "abc:def", "--check"])
+            .unwrap()
+            .resolve();
+        assert!(roy_cli::run(args).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(roy_cli::parse_range("100", 1000).unwrap(), (100, 100));
+        assert_eq!(roy_cli::parse_range("10:100", 1000).unwrap(), (10, 100));
+        assert_eq!(roy_cli::parse_range(":500", 1000).unwrap(), (0, 500));
+        assert_eq!(roy_cli::parse_range("100:", 1000).unwrap(), (100, 1000));
+        assert!(roy_cli::parse_range("abc", 1000).is_err());
+        assert!(roy_cli::parse_range("10:abc", 1000).is_err());
+        assert!(roy_cli::parse_range("100:10", 1000).is_err());
+        assert!(roy_cli::parse_range("", 1000).is_err());
+    }
+
+    #[test]
+    fn test_response_length_per_model() {
+        let spec = "gpt-4o=800:2000,gpt-4o-mini=100:400";
+        let range = |model| roy_cli::response_length_range(spec, model).unwrap();
+        assert_eq!(range("gpt-4o"), (800, 2000));
+        assert_eq!(range("gpt-4o-mini"), (100, 400));
+        assert_eq!(range("o3"), (250, 250));
+
+        let spec = "gpt-4o=800:2000,10:20";
+        assert_eq!(
+            roy_cli::response_length_range(spec, "o3").unwrap(),
+            (10, 20)
+        );
+        assert!(roy_cli::response_length_range("gpt-4o=abc", "gpt-4o").is_err());
+        assert!(roy_cli::response_length_range("=100", "gpt-4o").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::Request,
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{chat_completions, journal, server_state::ServerState, stats};
+    use tower::ServiceExt; // for `oneshot`
+
+    fn chat(content: &str) -> Request<Body> {
+        let body = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": content}],
+        });
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_fingerprints() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                journal::record,
+            ))
+            .route("/__stats", get(stats::stats))
+            .with_state(state);
+
+        for (user_agent, lang) in [
+            ("OpenAI/Python 1.99.1", "python"),
+            ("OpenAI/Python 1.99.1", "python"),
+            ("OpenAI/JS 5.12.0", "js"),
+        ] {
+            let mut request = chat("Hello");
+            let headers = request.headers_mut();
+            headers.insert("user-agent", user_agent.parse().unwrap());
+            headers.insert("x-stainless-lang", lang.parse().unwrap());
+            headers.insert("x-unrelated", "ignored".parse().unwrap());
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/__stats")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats["requests"], 3);
+        let clients = &stats["clients"];
+        assert_eq!(clients["user-agent"]["OpenAI/Python 1.99.1"], 2);
+        assert_eq!(clients["user-agent"]["OpenAI/JS 5.12.0"], 1);
+        assert_eq!(clients["x-stainless-lang"]["python"], 2);
+        assert_eq!(clients["x-stainless-lang"]["js"], 1);
+        assert!(clients.get("x-unrelated").is_none());
+    }
+}
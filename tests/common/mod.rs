@@ -0,0 +1,87 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+use clap_verbosity_flag::Verbosity;
+use roy_cli::{
+    Args, BinaryCorruption, ChoiceOrder, ContentLanguage, ContentMode, EchoTransform,
+    StreamGranularity, TextGenerator, TransferEncoding, UsageModel,
+};
+use std::time::Duration;
+
+/// Arguments for a quiet server with deterministic behaviour, tests override what they need.
+pub fn args() -> Args {
+    Args {
+        verbosity: Verbosity::new(0, 0),
+        port: 8000,
+        address: "127.0.0.1".parse().unwrap(),
+        response_length: Some("10".to_string()),
+        error_code: None,
+        error_rate: None,
+        rpm: 60,
+        tpm: 150000,
+        slowdown: Some("0".to_string()),
+        timeout: None,
+        warmup: None,
+        mirror_to: None,
+        scenario: None,
+        track_conversations: false,
+        midstream_tpm_exhaustion: false,
+        quota_exceeded_after: None,
+        api_key: None,
+        api_key_ttl: None,
+        config: None,
+        long_poll_tps: None,
+        max_requests_per_connection: None,
+        accept_delay: None,
+        capture_dir: None,
+        playback_dir: None,
+        playback_speed: None,
+        fixtures: None,
+        stream_decay: None,
+        usage_model: UsageModel::Bpe,
+        missing_ratelimit_headers_rate: None,
+        missing_ratelimit_headers: vec![],
+        latency_histogram: None,
+        models: vec!["gpt-4o".to_string()],
+        sse_max_line_length: None,
+        sse_frame_size: None,
+        gzip_streams: false,
+        max_streams_per_key: None,
+        audio_duration: None,
+        batch_duration: Duration::from_millis(50),
+        new_snapshot_rate: None,
+        error_noise: false,
+        fine_tuning_duration: Duration::from_millis(100),
+        run_duration: Duration::from_millis(40),
+        long_reset_rate: None,
+        long_reset: Duration::from_secs(300),
+        ingestion_duration: Duration::from_millis(50),
+        background_duration: Duration::from_millis(50),
+        store_default: false,
+        store_ttl: None,
+        choice_order: ChoiceOrder::RoundRobin,
+        stream_granularity: StreamGranularity::Chunk,
+        text_generator: TextGenerator::Lorem,
+        markov_corpus: None,
+        corpus: None,
+        content_language: ContentLanguage::Latin,
+        binary_corruption_rate: None,
+        binary_corruption: BinaryCorruption::Truncate,
+        transfer_encoding: TransferEncoding::Auto,
+        overstated_content_length_rate: None,
+        content_mode: ContentMode::Generated,
+        echo_transform: EchoTransform::None,
+        response_template: None,
+        retry_after_jitter: None,
+        realtime_disconnect_rate: None,
+        moderation_block_rate: None,
+        refusal_rate: None,
+        flagged_safety_identifiers: vec![],
+        abuse_error_rate: None,
+        continuous_usage_every: None,
+        tool_round_trips: false,
+        check: false,
+        command: None,
+        odd_ratelimit_headers_rate: None,
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{completions, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_completions() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/v1/completions", post(completions::completions))
+            .with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"prompt":"Say this is a test","model":"gpt-3.5-turbo-instruct"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["object"], "text_completion");
+        assert!(!body["choices"][0]["text"].as_str().unwrap().is_empty());
+    }
+}
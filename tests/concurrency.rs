@@ -0,0 +1,80 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::{Body, Bytes},
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use roy_cli::{concurrency, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_max_streams_per_key() {
+        let state = ServerState::new(Args {
+            max_streams_per_key: Some(2),
+            ..common::args()
+        });
+        // Streams that stay open until the client lets them go
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(|| async {
+                    Body::from_stream(
+                        futures_util::stream::pending::<Result<Bytes, std::io::Error>>(),
+                    )
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state,
+                concurrency::limit_streams,
+            ));
+        let send = |key: &'static str, stream: bool| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Authorization", format!("Bearer {}", key))
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            serde_json::json!({"stream": stream}).to_string(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let first = send("sk-a", true).await;
+        let second = send("sk-a", true).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+
+        let third = send("sk-a", true).await;
+        assert_eq!(third.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(third.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "concurrent_streams_exceeded");
+
+        // Other keys and regular requests aren't affected
+        assert_eq!(send("sk-b", true).await.status(), StatusCode::OK);
+        assert_eq!(send("sk-a", false).await.status(), StatusCode::OK);
+
+        // Dropping a stream releases its slot
+        drop(first);
+        assert_eq!(send("sk-a", true).await.status(), StatusCode::OK);
+        drop(second);
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{middleware, routing::get, Router};
+    use roy_cli::{connection, server_state::ServerState};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_max_requests_per_connection() {
+        let mut args = common::args();
+        args.max_requests_per_connection = Some(2);
+        let state = ServerState::new(args);
+        let app =
+            Router::new()
+                .route("/", get(|| async { "ok" }))
+                .layer(middleware::from_fn_with_state(
+                    state,
+                    connection::limit_requests,
+                ));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                connection::AcceptDelay::new(
+                    connection::CountResponses::new(
+                        app.into_make_service_with_connect_info::<SocketAddr>(),
+                    ),
+                    Duration::ZERO,
+                ),
+            )
+            .await
+            .unwrap()
+        });
+
+        // Every connection gets its own count, starting from zero
+        let client = reqwest::Client::new();
+        let mut closed = vec![];
+        for _ in 0..5 {
+            let response = client
+                .get(format!("http://{}/", addr))
+                .send()
+                .await
+                .unwrap();
+            closed.push(
+                response
+                    .headers()
+                    .get("connection")
+                    .is_some_and(|value| value == "close"),
+            );
+            response.text().await.unwrap();
+        }
+        assert_eq!(closed, [false, true, false, true, false]);
+
+        // Another connection starts from zero while the first one is still open
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.headers().get("connection").is_none());
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_track_conversations() {
+        async fn reply(state: &ServerState, body: serde_json::Value) -> String {
+            let app = Router::new()
+                .route(
+                    "/v1/chat/completions",
+                    post(chat_completions::chat_completions),
+                )
+                .with_state(state.clone());
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(body.to_string()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            body["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+        let messages = serde_json::json!([{"role": "user", "content": "Hello"}]);
+
+        let state = ServerState::new(Args {
+            track_conversations: true,
+            ..common::args()
+        });
+        for turn in 1..=2 {
+            let content = reply(
+                &state,
+                serde_json::json!({"model": "gpt-4o", "messages": messages, "conversation_id": "abc"}),
+            )
+            .await;
+            let preamble = format!("This is reply #{} in conversation abc. ", turn);
+            assert!(content.starts_with(&preamble), "{}", content);
+        }
+        // The user is the conversation when there is no id, counted on its own
+        let content = reply(
+            &state,
+            serde_json::json!({"model": "gpt-4o", "messages": messages, "user": "bob"}),
+        )
+        .await;
+        assert!(content.starts_with("This is reply #1 in conversation bob. "));
+        let content = reply(
+            &state,
+            serde_json::json!({"model": "gpt-4o", "messages": messages}),
+        )
+        .await;
+        assert!(!content.starts_with("This is reply"));
+
+        // Nothing is numbered without the flag
+        let state = ServerState::new(common::args());
+        let content = reply(
+            &state,
+            serde_json::json!({"model": "gpt-4o", "messages": messages, "conversation_id": "abc"}),
+        )
+        .await;
+        assert!(!content.starts_with("This is reply"));
+    }
+}
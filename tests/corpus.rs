@@ -0,0 +1,53 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use roy_cli::corpus::Corpus;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("guides")).unwrap();
+        std::fs::write(dir.path().join("a.md"), "# Title\n\nFirst paragraph.\n").unwrap();
+        std::fs::write(
+            dir.path().join("guides").join("b.rs"),
+            "fn main() {\n    println!(\"hi\");\n}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(".hidden"), "Never served.").unwrap();
+        std::fs::write(dir.path().join("image.png"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let corpus = Corpus::load(dir.path()).unwrap();
+        let text = corpus.generate(&mut StdRng::seed_from_u64(1), 1000);
+        assert!(text.contains("# Title\n\nFirst paragraph."));
+        assert!(text.contains("fn main() {\n    println!(\"hi\");\n}"));
+        assert!(!text.contains("Never served"));
+
+        assert!(Corpus::load(&dir.path().join("missing.txt")).is_err());
+        let empty = tempfile::tempdir().unwrap();
+        assert!(Corpus::load(empty.path()).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let corpus =
+            Corpus::from_texts(["One two three.\n\nFour five six.\n\nSeven eight."]).unwrap();
+
+        // Cut at a word boundary
+        let text = corpus.generate(&mut StdRng::seed_from_u64(2), 10);
+        assert!(!text.is_empty() && text.len() <= 10);
+        assert!(!text.ends_with(char::is_whitespace));
+        assert!(text
+            .split_whitespace()
+            .all(|word| "One two three. Four five six. Seven eight.".contains(word)));
+
+        // The same generator state gives the same text
+        let first = corpus.generate(&mut StdRng::seed_from_u64(7), 30);
+        let again = corpus.generate(&mut StdRng::seed_from_u64(7), 30);
+        assert_eq!(first, again);
+
+        assert!(Corpus::from_texts(["\n\n  \n"]).is_none());
+    }
+}
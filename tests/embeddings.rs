@@ -0,0 +1,86 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{embeddings, server_state::ServerState};
+    use serde_json::Value;
+    use tower::ServiceExt; // for `oneshot`
+
+    async fn embed(app: &Router, body: &str) -> (StatusCode, Value) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/embeddings")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_embeddings() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/v1/embeddings", post(embeddings::embeddings))
+            .with_state(state);
+
+        let (status, body) = embed(
+            &app,
+            r#"{"input":["Hello","World"],"model":"text-embedding-3-small","dimensions":8}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body["data"][0]["embedding"].as_array().unwrap().len(), 8);
+        assert!(body["usage"]["prompt_tokens"].as_u64().unwrap() > 0);
+
+        // The same input always gets the same vector
+        let (_, again) = embed(&app, r#"{"input":"Hello","dimensions":8}"#).await;
+        assert_eq!(again["data"][0]["embedding"], body["data"][0]["embedding"]);
+
+        let (_, encoded) = embed(
+            &app,
+            r#"{"input":"Hello","dimensions":8,"encoding_format":"base64"}"#,
+        )
+        .await;
+        assert!(encoded["data"][0]["embedding"].is_string());
+
+        let (status, _) = embed(&app, r#"{"input":42}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        for dimensions in ["0", "1537", "18446744073709551615"] {
+            let (status, body) = embed(
+                &app,
+                &format!(r#"{{"input":"Hello","dimensions":{}}}"#, dimensions),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["error"]["param"], "dimensions");
+        }
+        let (status, _) = embed(
+            &app,
+            r#"{"input":"Hello","model":"text-embedding-3-large","dimensions":3072}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
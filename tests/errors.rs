@@ -0,0 +1,23 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use roy_cli::errors::ApiError;
+
+    #[test]
+    fn test_noise_keeps_code_and_type() {
+        let original = ApiError::rate_limit_exceeded("Too many requests");
+        for _ in 0..20 {
+            let noisy = original.clone().with_noise();
+            assert_eq!(noisy.status, original.status);
+
+            let body = noisy.body();
+            assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+            assert_eq!(body["error"]["type"], "rate_limit_error");
+            let changed =
+                noisy.message != original.message || body["error"]["internal_trace_id"].is_string();
+            assert!(changed);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{files, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_files_lifecycle() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/v1/files", post(files::upload).get(files::list))
+            .route(
+                "/v1/files/:file_id",
+                get(files::retrieve).delete(files::delete),
+            )
+            .route("/v1/files/:file_id/content", get(files::content))
+            .with_state(state);
+
+        let multipart = concat!(
+            "--boundary\r\n",
+            "Content-Disposition: form-data; name=\"purpose\"\r\n\r\n",
+            "batch\r\n",
+            "--boundary\r\n",
+            "Content-Disposition: form-data; name=\"file\"; filename=\"input.jsonl\"\r\n",
+            "Content-Type: application/jsonl\r\n\r\n",
+            "{\"custom_id\":\"1\"}\r\n",
+            "--boundary--\r\n",
+        );
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/files")
+                    .header("Content-Type", "multipart/form-data; boundary=boundary")
+                    .body(Body::from(multipart))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let file = json_body(response).await;
+        assert_eq!(file["filename"], "input.jsonl");
+        assert_eq!(file["purpose"], "batch");
+        assert_eq!(file["bytes"], 17);
+        let id = file["id"].as_str().unwrap().to_string();
+
+        let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(get("/v1/files?purpose=batch".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["data"][0]["id"], id.as_str());
+
+        let response = app
+            .clone()
+            .oneshot(get(format!("/v1/files/{}/content", id)))
+            .await
+            .unwrap();
+        let content = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&content[..], b"{\"custom_id\":\"1\"}");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/v1/files/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(json_body(response).await["deleted"], true);
+
+        let response = app.oneshot(get(format!("/v1/files/{}", id))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
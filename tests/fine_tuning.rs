@@ -0,0 +1,95 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{files::StoredFile, fine_tuning, server_state::ServerState};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fine_tuning_job_lifecycle() {
+        let state = ServerState::new(common::args());
+        let training_file = StoredFile::new(
+            "train.jsonl".to_string(),
+            "fine-tune".to_string(),
+            br#"{"messages":[{"role":"user","content":"Hi"},{"role":"assistant","content":"Hello"}]}"#
+                .to_vec(),
+        );
+        let training_file_id = training_file.id.clone();
+        state.files().lock().unwrap().push(training_file);
+
+        let app = Router::new()
+            .route(
+                "/v1/fine_tuning/jobs",
+                post(fine_tuning::create).get(fine_tuning::list),
+            )
+            .route("/v1/fine_tuning/jobs/:job_id", get(fine_tuning::retrieve))
+            .route(
+                "/v1/fine_tuning/jobs/:job_id/events",
+                get(fine_tuning::events),
+            )
+            .with_state(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/fine_tuning/jobs")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(format!(
+                        r#"{{"model":"gpt-4o-mini","training_file":"{}","suffix":"roy"}}"#,
+                        training_file_id
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let job = json_body(response).await;
+        assert_eq!(job["status"], "validating_files");
+        let id = job["id"].as_str().unwrap().to_string();
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app
+            .clone()
+            .oneshot(get(format!("/v1/fine_tuning/jobs/{}", id)))
+            .await
+            .unwrap();
+        let job = json_body(response).await;
+        assert_eq!(job["status"], "succeeded");
+        assert!(job["fine_tuned_model"]
+            .as_str()
+            .unwrap()
+            .starts_with("ft:gpt-4o-mini:roy:roy:"));
+
+        let response = app
+            .oneshot(get(format!("/v1/fine_tuning/jobs/{}/events?limit=100", id)))
+            .await
+            .unwrap();
+        let events = json_body(response).await;
+        let events = events["data"].as_array().unwrap();
+        assert_eq!(events[0]["message"], "The job has successfully completed");
+        let metrics = events.iter().filter(|e| e["type"] == "metrics").count();
+        assert_eq!(metrics, 10);
+    }
+}
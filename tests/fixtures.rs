@@ -0,0 +1,95 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use roy_cli::{chat_completions, fixtures::Fixtures, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    fn fixtures() -> Fixtures {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("1-weather.yaml"),
+            "- regex: \"(?i)weather in \\\\w+\"\n  tool_call:\n    name: get_weather\n    arguments: {city: Paris}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("2-hello.json"),
+            r#"{"contains": "Say hello", "content": "Hello!", "finish_reason": "content_filter"}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+        Fixtures::load(dir.path()).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        let fixtures = fixtures();
+        let weather = fixtures.find("What's the WEATHER in Rome?").unwrap();
+        assert_eq!(weather.tool_call.as_ref().unwrap().name, "get_weather");
+        assert_eq!(
+            weather.tool_call.as_ref().unwrap().arguments(),
+            r#"{"city":"Paris"}"#
+        );
+        let hello = fixtures.find("Say hello to Bob").unwrap();
+        assert_eq!(hello.content.as_deref(), Some("Hello!"));
+        assert!(fixtures.find("Something else").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bad.json"), r#"{"regex": "("}"#).unwrap();
+        assert!(Fixtures::load(dir.path()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_fixtures() {
+        let state = ServerState::new(common::args()).with_fixtures(fixtures());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(state);
+        let complete = |content: &str| {
+            let app = app.clone();
+            let body = serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": content}],
+            });
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/v1/chat/completions")
+                            .header("Content-Type", "application/json")
+                            .body(Body::from(body.to_string()))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        let body = complete("Say hello").await;
+        assert_eq!(body["choices"][0]["message"]["content"], "Hello!");
+        assert_eq!(body["choices"][0]["finish_reason"], "content_filter");
+
+        let body = complete("What's the weather in Paris?").await;
+        let call = &body["choices"][0]["message"]["tool_calls"][0];
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(call["function"]["arguments"], r#"{"city":"Paris"}"#);
+        assert_eq!(body["choices"][0]["finish_reason"], "tool_calls");
+
+        let body = complete("Anything else").await;
+        assert_ne!(body["choices"][0]["message"]["content"], "Hello!");
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+    }
+}
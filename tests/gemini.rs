@@ -0,0 +1,125 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{gemini, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    const BODY: &str = r#"{"contents":[{"role":"user","parts":[{"text":"Hello"}]}]}"#;
+
+    fn app(args: Args) -> Router {
+        Router::new()
+            .route("/v1beta/models/:model", post(gemini::generate_content))
+            .with_state(ServerState::new(args))
+    }
+
+    fn request(uri: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(BODY))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_generate_content() {
+        let response = app(common::args())
+            .oneshot(request("/v1beta/models/gemini-2.0-flash:generateContent"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["modelVersion"], "gemini-2.0-flash");
+        assert_eq!(body["candidates"][0]["content"]["role"], "model");
+        assert_eq!(body["candidates"][0]["finishReason"], "STOP");
+        let text = body["candidates"][0]["content"]["parts"][0]["text"]
+            .as_str()
+            .unwrap();
+        assert_eq!(text.split_whitespace().count(), 10);
+        assert!(body["usageMetadata"]["totalTokenCount"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_generate_content() {
+        let response = app(common::args())
+            .oneshot(request(
+                "/v1beta/models/gemini-2.0-flash:streamGenerateContent?alt=sse",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let chunks = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 10);
+        let last = chunks.last().unwrap();
+        assert_eq!(last["candidates"][0]["finishReason"], "STOP");
+        assert!(last["usageMetadata"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_generate_content_error() {
+        let args = Args {
+            error_code: Some(503),
+            error_rate: Some(100),
+            ..common::args()
+        };
+        let response = app(args)
+            .oneshot(request("/v1beta/models/gemini-2.0-flash:generateContent"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], 503);
+        assert_eq!(body["error"]["status"], "UNAVAILABLE");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let app = app(Args {
+            rpm: 1,
+            ..common::args()
+        });
+        let uri = "/v1beta/models/gemini-2.0-flash:generateContent";
+        let response = app.clone().oneshot(request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["status"], "RESOURCE_EXHAUSTED");
+        assert_eq!(
+            body["error"]["message"],
+            "Resource has been exhausted (e.g. check quota)."
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, framing, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_gzip_streams() {
+        let state = ServerState::new(Args {
+            gzip_streams: true,
+            ..common::args()
+        });
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), framing::gzip))
+            .with_state(state);
+        let request = |stream: bool, accept_encoding: Option<&str>| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json");
+            if let Some(accept_encoding) = accept_encoding {
+                request = request.header("Accept-Encoding", accept_encoding);
+            }
+            request
+                .body(Body::from(
+                    serde_json::json!({
+                        "model": "gpt-4o",
+                        "messages": [{"role": "user", "content": "Hello"}],
+                        "stream": stream,
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(true, Some("br, gzip;q=0.8")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        assert_eq!(response.headers()["vary"], "accept-encoding");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&body[..]), &mut decoded)
+            .unwrap();
+        assert!(decoded.starts_with("data: "));
+        assert!(decoded.contains("data: [DONE]"));
+
+        // Left alone for clients not accepting gzip, and for regular responses
+        for (stream, accept_encoding) in [(true, None), (false, Some("gzip"))] {
+            let response = app
+                .clone()
+                .oneshot(request(stream, accept_encoding))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get("content-encoding").is_none());
+        }
+    }
+}
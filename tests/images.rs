@@ -0,0 +1,154 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use roy_cli::{
+        framing, images, server_state::ServerState, Args, BinaryCorruption, TransferEncoding,
+    };
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_image_generations() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/v1/images/generations", post(images::generations))
+            .with_state(state);
+
+        let generate = |body: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/images/generations")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(generate(
+                r#"{"prompt":"A cat","n":2,"size":"16x8","response_format":"b64_json"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+
+        let png = STANDARD
+            .decode(body["data"][0]["b64_json"].as_str().unwrap())
+            .unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[16..24], &[0, 0, 0, 16, 0, 0, 0, 8]);
+
+        let response = app
+            .oneshot(generate(r#"{"prompt":"A cat","size":"big"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_binary_corruption() {
+        for corruption in [BinaryCorruption::Truncate, BinaryCorruption::Garble] {
+            let state = ServerState::new(Args {
+                binary_corruption_rate: Some(100),
+                binary_corruption: corruption,
+                ..common::args()
+            });
+            let app = Router::new()
+                .route("/__images/:name", get(images::placeholder))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    framing::corrupt_binary,
+                ))
+                .with_state(state);
+            let request = || {
+                Request::builder()
+                    .uri("/__images/64x64.png")
+                    .body(Body::empty())
+                    .unwrap()
+            };
+
+            let original = images::placeholder_png(64, 64);
+
+            let response = app.oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()["content-length"],
+                original.len().to_string().as_str()
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            match corruption {
+                BinaryCorruption::Truncate => assert_eq!(body.len(), original.len() / 2),
+                BinaryCorruption::Garble => {
+                    assert_eq!(body.len(), original.len());
+                    assert_ne!(body, original);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer_encoding() {
+        let original = images::placeholder_png(64, 64);
+        let cases = [
+            (TransferEncoding::ContentLength, None, Some(original.len())),
+            (TransferEncoding::Chunked, None, None),
+            (TransferEncoding::Auto, Some(100), None),
+        ];
+        for (transfer_encoding, overstated_content_length_rate, content_length) in cases {
+            let state = ServerState::new(Args {
+                transfer_encoding,
+                overstated_content_length_rate,
+                ..common::args()
+            });
+            let app = Router::new()
+                .route("/__images/:name", get(images::placeholder))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    framing::transfer,
+                ))
+                .with_state(state);
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/__images/64x64.png")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let announced = response
+                .headers()
+                .get("content-length")
+                .map(|value| value.to_str().unwrap().parse::<usize>().unwrap());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, original);
+            match overstated_content_length_rate {
+                Some(_) => assert!(announced.unwrap() > original.len()),
+                None => assert_eq!(announced, content_length),
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use rand::{rngs::StdRng, SeedableRng};
+    use roy_cli::{
+        chat_completions, language, responses, server_state::ServerState, Args, ContentLanguage,
+    };
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(language::detect("Hello there"), ContentLanguage::Latin);
+        assert_eq!(language::detect("مرحبا بالعالم"), ContentLanguage::Arabic);
+        assert_eq!(
+            language::detect("Translate: 今日はいい天気"),
+            ContentLanguage::Japanese
+        );
+        assert_eq!(
+            language::detect("Привет, как дела?"),
+            ContentLanguage::Cyrillic
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(language::generate(ContentLanguage::Latin, &mut rng, 100).is_none());
+
+        let text = language::generate(ContentLanguage::Japanese, &mut rng, 100).unwrap();
+        assert!(text.chars().count() <= 100 && !text.contains(' '));
+        assert_eq!(language::detect(&text), ContentLanguage::Japanese);
+
+        let text = language::generate(ContentLanguage::Cyrillic, &mut rng, 100).unwrap();
+        assert!(text.chars().next().unwrap().is_uppercase());
+        assert_eq!(language::detect(&text), ContentLanguage::Cyrillic);
+    }
+
+    #[tokio::test]
+    async fn test_content_language_auto() {
+        let args = Args {
+            content_language: ContentLanguage::Auto,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        for (prompt, expected) in [
+            ("Hello", ContentLanguage::Latin),
+            ("مرحبا", ContentLanguage::Arabic),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            serde_json::json!({
+                                "model": "gpt-4o",
+                                "messages": [{"role": "user", "content": prompt}],
+                            })
+                            .to_string(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let content = body["choices"][0]["message"]["content"].as_str().unwrap();
+            assert_eq!(language::detect(content), expected);
+        }
+    }
+
+    #[test]
+    fn test_stream_words() {
+        assert_eq!(
+            language::stream_words("Lorem ipsum\ndolor"),
+            vec!["Lorem ", "ipsum\n", "dolor"]
+        );
+        assert_eq!(
+            language::stream_words("今日はいい天気です。"),
+            vec!["今日はい", "い天気で", "す。"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_latin_streaming() {
+        fn events(body: &[u8]) -> Vec<serde_json::Value> {
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .lines()
+                .filter_map(|line| line.strip_prefix("data: "))
+                .filter_map(|data| serde_json::from_str(data).ok())
+                .collect()
+        }
+
+        for content_language in [
+            ContentLanguage::Arabic,
+            ContentLanguage::Japanese,
+            ContentLanguage::Cyrillic,
+        ] {
+            let args = Args {
+                response_length: Some("200".to_string()),
+                content_language,
+                ..common::args()
+            };
+            let app = Router::new()
+                .route(
+                    "/v1/chat/completions",
+                    post(chat_completions::chat_completions),
+                )
+                .route("/v1/responses", post(responses::responses))
+                .with_state(ServerState::new(args));
+
+            // The deltas of Responses streams add up to the whole text
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/responses")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            r#"{"model":"gpt-4.1","input":"Hello","stream":true}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let events = events(&body);
+            let deltas: String = events
+                .iter()
+                .filter(|event| event["type"] == "response.output_text.delta")
+                .map(|event| event["delta"].as_str().unwrap())
+                .collect();
+            let done = events
+                .iter()
+                .find(|event| event["type"] == "response.output_text.done")
+                .unwrap();
+            assert!(!deltas.contains('\u{fffd}'), "{:?}", content_language);
+            assert_eq!(deltas, done["text"], "{:?}", content_language);
+
+            // Chat completions stream scripts without spaces in several deltas too
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            r#"{"model":"gpt-4o","messages":[{"role":"user","content":"Hello"}],"stream":true}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let deltas = events(&body)
+                .iter()
+                .filter_map(|chunk| {
+                    chunk["choices"][0]["delta"]["content"]
+                        .as_str()
+                        .map(String::from)
+                })
+                .collect::<Vec<_>>();
+            assert!(deltas.len() > 10, "{:?}", content_language);
+            assert_eq!(
+                language::detect(&deltas.concat()),
+                content_language,
+                "{:?}",
+                content_language
+            );
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use roy_cli::latency::LatencyDistribution;
+    use roy_cli::server_state::{ServerState, StreamDecay};
+    use std::time::Duration;
+
+    #[test]
+    fn test_quantiles() {
+        let latency = LatencyDistribution::parse_quantiles(
+            "# exported from production\np50 100\n0.99 800\np100: 1000\n",
+        )
+        .unwrap();
+
+        for _ in 0..100 {
+            let sample = latency.sample_ms();
+            assert!(sample <= 1000);
+        }
+
+        assert!(LatencyDistribution::parse_quantiles("p150 100").is_err());
+        assert!(LatencyDistribution::parse_quantiles("").is_err());
+    }
+
+    #[test]
+    fn test_stream_decay() {
+        let mut args = common::args();
+        args.stream_decay = Some("2:3".to_string());
+        let state = ServerState::new(args);
+
+        let base = Duration::from_millis(100);
+        let delays = (0..8)
+            .map(|index| state.chunk_delay(base, index))
+            .collect::<Vec<_>>();
+        assert_eq!(delays[0], base);
+        assert_eq!(delays[1], base);
+        assert_eq!(delays[2], Duration::from_millis(300));
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(delays[7] > delays[5]);
+        // Capped however long the stream goes on
+        assert_eq!(state.chunk_delay(base, 10_000), Duration::from_secs(60));
+
+        assert_eq!(
+            StreamDecay::parse("10"),
+            Some(StreamDecay {
+                every: 10,
+                factor: 2.0
+            })
+        );
+        for spec in ["10:-2", "10:0", "10:NaN", "10:inf", "0:2", "ten", "10:"] {
+            assert_eq!(StreamDecay::parse(spec), None, "{}", spec);
+        }
+    }
+}
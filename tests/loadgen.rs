@@ -0,0 +1,56 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::State, routing::post, Json, Router};
+    use roy_cli::{chat_completions::ChatCompletionRequest, loadgen};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_loadgen() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let streamed = Arc::new(AtomicU32::new(0));
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(
+                    |State(streamed): State<Arc<AtomicU32>>,
+                     Json(request): Json<ChatCompletionRequest>| async move {
+                        if request.stream == Some(true) {
+                            streamed.fetch_add(1, Ordering::SeqCst);
+                        }
+                        assert!(request.messages.is_some());
+                        "{}"
+                    },
+                ),
+            )
+            .with_state(streamed.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let load = loadgen::Load {
+            target: format!("http://{}", addr),
+            rps: 50,
+            streaming: 100,
+            duration: Some(Duration::from_millis(200)),
+            model: "gpt-4o-mini".to_string(),
+            api_key: None,
+        };
+        loadgen::loadgen(load).await.unwrap();
+        assert!(streamed.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_parse_percentage() {
+        assert_eq!(loadgen::parse_percentage("30%"), Ok(30));
+        assert_eq!(loadgen::parse_percentage("30"), Ok(30));
+        assert!(loadgen::parse_percentage("130%").is_err());
+        assert!(loadgen::parse_percentage("many").is_err());
+    }
+}
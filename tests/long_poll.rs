@@ -0,0 +1,59 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, server_state::ServerState, Args};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_long_poll_tps() {
+        let args = Args {
+            response_length: Some("500".to_string()),
+            long_poll_tps: Some(200),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let started = std::time::Instant::now();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        // The response is held for the time it takes to generate the completion
+        let completion_tokens = body["usage"]["completion_tokens"].as_u64().unwrap();
+        assert!(completion_tokens > 0);
+        assert!(elapsed >= Duration::from_secs_f64(completion_tokens as f64 / 200.0));
+    }
+}
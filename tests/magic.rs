@@ -0,0 +1,106 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, magic::Directives, responses, server_state::ServerState};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    fn post_json(uri: &str, body: Value) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let directives = Directives::parse("Hello [ROY:503] and [ROY:DELAY=3s] [ROY:TOOL_CALL]");
+        assert_eq!(directives.error_code, Some(503));
+        assert_eq!(directives.delay, Some(Duration::from_secs(3)));
+        assert!(directives.tool_call);
+        assert!(!directives.truncated_tool_arguments);
+
+        let directives = Directives::parse("[ROY:TOOL_CALL_TRUNCATED]");
+        assert!(directives.tool_call);
+        assert!(directives.truncated_tool_arguments);
+
+        assert_eq!(Directives::parse("Hello"), Directives::default());
+
+        // Only error statuses are simulated
+        for token in ["[ROY:200]", "[ROY:999]", "[ROY:42]"] {
+            assert_eq!(Directives::parse(token).error_code, None, "{}", token);
+        }
+        assert_eq!(
+            Directives::parse("[ROY:400] [ROY:599]").error_code,
+            Some(599)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_directives_from_last_user_message() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+
+        // Tokens replayed from the history, or from a prompt template, are ignored
+        let history = json!([
+            {"role": "user", "content": "Fail [ROY:503]"},
+            {"role": "assistant", "content": "Sure [ROY:500]"},
+            {"role": "user", "content": "Hello"},
+        ]);
+        for request in [
+            post_json(
+                "/v1/chat/completions",
+                json!({"model": "gpt-4o", "messages": history}),
+            ),
+            post_json(
+                "/v1/responses",
+                json!({"model": "gpt-4o", "input": history}),
+            ),
+        ] {
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let messages = json!([
+            {"role": "system", "content": "Be brief"},
+            {"role": "user", "content": [{"type": "text", "text": "Fail [ROY:503]"}]},
+        ]);
+        let response = app
+            .clone()
+            .oneshot(post_json(
+                "/v1/chat/completions",
+                json!({"model": "gpt-4o", "messages": messages}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = app
+            .oneshot(post_json(
+                "/v1/responses",
+                json!({"model": "gpt-4o", "input": "Fail [ROY:503]"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
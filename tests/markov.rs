@@ -0,0 +1,39 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use roy_cli::markov::MarkovChain;
+
+    #[test]
+    fn test_generate() {
+        let chain = MarkovChain::bundled();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let text = chain.generate(&mut rng, 300);
+        assert!(!text.is_empty() && text.len() <= 300);
+        assert!(!text.ends_with(' '));
+
+        // Whole sentences when there's room for them
+        let text = chain.generate(&mut rng, 1);
+        assert!(text.len() <= 1);
+
+        // The same generator state gives the same text
+        let first = chain.generate(&mut StdRng::seed_from_u64(7), 200);
+        let again = chain.generate(&mut StdRng::seed_from_u64(7), 200);
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn test_train() {
+        assert!(MarkovChain::train("Too short").is_none());
+
+        let chain = MarkovChain::train("The cat sat. The dog ran. The cat ran.").unwrap();
+        let text = chain.generate(&mut StdRng::seed_from_u64(3), 1000);
+        assert!(text.starts_with("The "));
+        assert!(text
+            .split_whitespace()
+            .all(|word| ["The", "cat", "dog", "sat.", "ran."].contains(&word)));
+    }
+}
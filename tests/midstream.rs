@@ -0,0 +1,74 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_midstream_tpm_exhaustion() {
+        let request = || {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true}"#,
+                ))
+                .unwrap()
+        };
+        let args = Args {
+            response_length: Some("2000".to_string()),
+            tpm: 100,
+            ..common::args()
+        };
+
+        // The stream starts, then ends with an error once the budget runs out
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(Args {
+                midstream_tpm_exhaustion: true,
+                ..args.clone()
+            }));
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+            .collect::<Vec<_>>();
+        assert!(events.len() > 1);
+        assert!(events[0]["choices"][0]["delta"].is_object());
+        let error = events.last().unwrap();
+        assert_eq!(error["error"]["code"], "rate_limit_exceeded");
+        assert!(!body.contains("[DONE]"));
+        assert!(!body.contains("finish_reason\":\"stop"));
+
+        // Otherwise the request is rejected upfront
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Json, Router,
+    };
+    use roy_cli::{mirror, server_state::ServerState, Args};
+    use serde_json::Value;
+    use std::time::Duration;
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_mirror_to() {
+        // A collector receiving the mirrored exchanges
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Value>();
+        let collector = Router::new().route(
+            "/collect",
+            post(move |Json(record): Json<Value>| async move {
+                sender.send(record).unwrap();
+                StatusCode::NO_CONTENT
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, collector).await.unwrap() });
+
+        let args = Args {
+            mirror_to: Some(format!("http://{}/collect", addr)),
+            ..common::args()
+        };
+        let state = ServerState::new(args);
+        let app = Router::new()
+            .route(
+                "/v1/echo",
+                post(|body: String| async move { (StatusCode::CREATED, body) }),
+            )
+            .route_layer(middleware::from_fn_with_state(state, mirror::mirror));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/echo")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"model":"gpt-4o"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        // The client gets the response untouched
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], br#"{"model":"gpt-4o"}"#);
+
+        let record = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record["method"], "POST");
+        assert_eq!(record["path"], "/v1/echo");
+        assert_eq!(record["request"]["model"], "gpt-4o");
+        assert_eq!(record["response"]["status"], 201);
+        assert!(record["response"]["latency_ms"].is_u64());
+    }
+}
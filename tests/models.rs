@@ -0,0 +1,43 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use roy_cli::{models, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_models() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route("/v1/models", get(models::list_models))
+            .route("/v1/models/:model", get(models::retrieve_model))
+            .with_state(state);
+
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/v1/models")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["object"], "list");
+        assert_eq!(body["data"][0]["id"], "gpt-4o");
+
+        let response = app.clone().oneshot(get("/v1/models/gpt-4o")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(get("/v1/models/unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use roy_cli::orchestrate::Topology;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn load(name: &str, content: &str) -> anyhow::Result<Topology> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        Topology::load(&path)
+    }
+
+    #[test]
+    fn test_command_args() {
+        let topology = load(
+            "topology.yaml",
+            r#"
+listeners:
+  - name: primary
+    port: 8001
+  - name: degraded
+    port: 8003
+    profile: {slowdown: "500:2000", error_code: 503}
+    args: ["--stream-granularity", "token"]
+"#,
+        )
+        .unwrap();
+
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(
+            topology.listeners[0].command_args(address),
+            ["serve", "--address", "127.0.0.1", "--port", "8001"]
+        );
+        assert_eq!(
+            topology.listeners[1].command_args(address),
+            [
+                "serve",
+                "--address",
+                "127.0.0.1",
+                "--port",
+                "8003",
+                "--error-code",
+                "503",
+                "--slowdown",
+                "500:2000",
+                "--stream-granularity",
+                "token"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_topologies() {
+        assert!(load("topology.json", r#"{"listeners": []}"#).is_err());
+        assert!(load(
+            "topology.json",
+            r#"{"listeners": [{"name": "a", "port": 8001}, {"name": "a", "port": 8002}]}"#
+        )
+        .is_err());
+        assert!(load(
+            "topology.json",
+            r#"{"listeners": [{"name": "a", "port": 8001}, {"name": "b", "port": 8001}]}"#
+        )
+        .is_err());
+        assert!(load(
+            "topology.json",
+            r#"{"listeners": [{"name": "a", "port": 8001, "rpm": 10}]}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use roy_cli::playback::{RecordedBody, Transcript};
+    use serde_json::json;
+
+    #[test]
+    fn test_sse_transcript() {
+        let transcript = Transcript::from_sse(
+            "test.sse",
+            ": roy-match path=/v1/chat/completions model=gpt-4o\n\n\
+             data: {\"id\":\"1\"}\n\n\
+             : roy-offset-ms 120\r\ndata: [DONE]\r\n\r\n",
+        );
+
+        let request = json!({"model": "gpt-4o", "stream": true});
+        assert!(transcript
+            .matcher
+            .matches("POST", "/v1/chat/completions", &request));
+        assert!(!transcript
+            .matcher
+            .matches("POST", "/v1/responses", &request));
+        assert!(!transcript.matcher.matches(
+            "POST",
+            "/v1/chat/completions",
+            &json!({"model": "gpt-4o"})
+        ));
+
+        let RecordedBody::Events(events) = transcript.body else {
+            panic!("expected events");
+        };
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].raw, "data: {\"id\":\"1\"}\n\n");
+        assert_eq!(events[0].offset_ms, None);
+        assert_eq!(events[1].raw, "data: [DONE]\n\n");
+        assert_eq!(events[1].offset_ms, Some(120));
+    }
+
+    #[test]
+    fn test_prompt_matcher() {
+        let transcript = Transcript::from_sse(
+            "test.sse",
+            ": roy-match path=/v1/responses prompt=pmpt_123 prompt_version=2\n\ndata: {}\n\n",
+        );
+
+        let request = |version: &str| json!({"stream": true, "prompt": {"id": "pmpt_123", "version": version}});
+        assert!(transcript
+            .matcher
+            .matches("POST", "/v1/responses", &request("2")));
+        assert!(!transcript
+            .matcher
+            .matches("POST", "/v1/responses", &request("3")));
+        assert!(!transcript.matcher.matches(
+            "POST",
+            "/v1/responses",
+            &json!({"stream": true, "input": "Hello"})
+        ));
+    }
+}
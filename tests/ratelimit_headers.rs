@@ -0,0 +1,77 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, server_state::ServerState, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_missing_ratelimit_headers() {
+        let headers = |args: Args| async move {
+            let app = Router::new()
+                .route(
+                    "/v1/chat/completions",
+                    post(chat_completions::chat_completions),
+                )
+                .with_state(ServerState::new(args));
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/v1/chat/completions")
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers().clone()
+        };
+
+        // All of them by default
+        let all = headers(Args {
+            missing_ratelimit_headers_rate: Some(100),
+            ..common::args()
+        })
+        .await;
+        assert!(all
+            .keys()
+            .all(|name| !name.as_str().starts_with("x-ratelimit-")));
+
+        // Only the listed ones, with or without the prefix
+        let some = headers(Args {
+            missing_ratelimit_headers_rate: Some(100),
+            missing_ratelimit_headers: vec![
+                "reset-requests".to_string(),
+                "x-ratelimit-remaining-tokens".to_string(),
+            ],
+            ..common::args()
+        })
+        .await;
+        assert!(!some.contains_key("x-ratelimit-reset-requests"));
+        assert!(!some.contains_key("x-ratelimit-remaining-tokens"));
+        assert!(some.contains_key("x-ratelimit-limit-requests"));
+        assert!(some.contains_key("x-ratelimit-reset-tokens"));
+
+        let none = headers(Args {
+            missing_ratelimit_headers_rate: Some(0),
+            ..common::args()
+        })
+        .await;
+        assert!(none.contains_key("x-ratelimit-reset-requests"));
+        assert!(none.contains_key("x-ratelimit-remaining-tokens"));
+    }
+}
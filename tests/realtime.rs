@@ -0,0 +1,97 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{routing::get, Router};
+    use futures_util::{SinkExt, StreamExt};
+    use roy_cli::{realtime, server_state::ServerState};
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    /// Serves the realtime endpoint on a random port and returns its URL.
+    async fn serve(state: ServerState) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/v1/realtime", get(realtime::realtime))
+            .with_state(state);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("ws://{}/v1/realtime?model=gpt-realtime", addr)
+    }
+
+    #[tokio::test]
+    async fn test_realtime_response() {
+        let url = serve(ServerState::new(common::args())).await;
+        let (mut socket, _) = connect_async(url).await.unwrap();
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected a text message");
+        };
+        let event: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(event["type"], "session.created");
+        assert_eq!(event["session"]["model"], "gpt-realtime");
+
+        for event in [
+            json!({
+                "type": "conversation.item.create",
+                "item": {
+                    "type": "message",
+                    "role": "user",
+                    "content": [{"type": "input_text", "text": "Hello"}],
+                },
+            }),
+            json!({"type": "response.create"}),
+        ] {
+            socket.send(Message::Text(event.to_string())).await.unwrap();
+        }
+
+        let mut types = vec![];
+        let mut deltas = String::new();
+        let done = loop {
+            let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            let _type = event["type"].as_str().unwrap().to_string();
+            if _type == "response.output_text.delta" {
+                deltas.push_str(event["delta"].as_str().unwrap());
+            }
+            types.push(_type.clone());
+            if _type == "response.done" {
+                break event;
+            }
+        };
+
+        assert_eq!(types[0], "conversation.item.created");
+        assert_eq!(types[1], "response.created");
+        assert_eq!(deltas.split_whitespace().count(), 10);
+        assert_eq!(done["response"]["status"], "completed");
+        assert!(done["response"]["usage"]["output_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_realtime_invalid_event() {
+        let url = serve(ServerState::new(common::args())).await;
+        let (mut socket, _) = connect_async(url).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+
+        socket
+            .send(Message::Text(
+                json!({"type": "audio.play", "event_id": "evt_1"}).to_string(),
+            ))
+            .await
+            .unwrap();
+
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected a text message");
+        };
+        let event: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(event["type"], "error");
+        assert_eq!(event["error"]["param"], "type");
+        assert_eq!(event["error"]["event_id"], "evt_1");
+    }
+}
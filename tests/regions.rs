@@ -0,0 +1,71 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, config::Config, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_regions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("roy.json");
+        std::fs::write(
+            &path,
+            r#"{"profiles": {"degraded": {"error_code": 503, "error_rate": 100}}, "regions": {"eu": "degraded"}}"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+
+        let state = ServerState::new(common::args());
+        let region_state = state.with_profile(&config.profiles[&config.regions["eu"]]);
+        let api = |state: ServerState| {
+            Router::new()
+                .route(
+                    "/v1/chat/completions",
+                    post(chat_completions::chat_completions),
+                )
+                .with_state(state)
+        };
+        let app = api(state).nest("/eu", api(region_state));
+
+        let status = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(
+                            r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            }
+        };
+        // Only the region gets the behaviour of its profile
+        assert_eq!(
+            status("/eu/v1/chat/completions").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status("/v1/chat/completions").await, StatusCode::OK);
+
+        // Regions must use a profile of the configuration
+        std::fs::write(&path, r#"{"regions": {"eu": "missing"}}"#).unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert!(err.to_string().contains("unknown profile 'missing'"));
+    }
+}
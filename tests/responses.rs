@@ -1,31 +1,25 @@
 // Copyright 2025 Massimiliano Pippi
 // SPDX-License-Identifier: MIT
 
+mod common;
+
 #[cfg(test)]
 mod tests {
+    use crate::common;
     use axum::{
         body::Body,
         http::{Request, StatusCode},
-        routing::post,
+        routing::{get, post},
         Router,
     };
-    use clap_verbosity_flag::Verbosity;
-    use roy_cli::{responses, server_state::ServerState, Args};
+    use roy_cli::{files, responses, server_state::ServerState, Args};
     use tower::ServiceExt; // for `oneshot`
 
     #[tokio::test]
     async fn test_responses() {
         let args = Args {
-            verbosity: Verbosity::new(0, 0),
-            port: 8000,
-            address: "127.0.0.1".parse().unwrap(),
-            response_length: Some("10".to_string()),
-            error_code: None,
-            error_rate: None,
-            rpm: 60,
-            tpm: 150000,
             slowdown: Some("0:100".to_string()),
-            timeout: None,
+            ..common::args()
         };
         let state = ServerState::new(args);
         let app = Router::new()
@@ -46,4 +40,1004 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_stored_responses() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route("/v1/responses/:response_id", get(responses::retrieve))
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/responses")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"model":"gpt-4.1","input":"Hello","store":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["store"], true);
+
+        let id = created["id"].as_str().unwrap();
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/responses/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let retrieved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(retrieved, created);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/responses/resp_unknown")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["param"], "response_id");
+    }
+
+    #[tokio::test]
+    async fn test_background_responses() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route("/v1/responses/:response_id", get(responses::retrieve))
+            .route("/v1/responses/:response_id/cancel", post(responses::cancel))
+            .with_state(ServerState::new(common::args()));
+        let send = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+        let create = || {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"model":"gpt-4.1","input":"Hello","background":true}"#,
+                ))
+                .unwrap()
+        };
+        let retrieve = |id: &str| {
+            Request::builder()
+                .uri(format!("/v1/responses/{}", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let cancel = |id: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/v1/responses/{}/cancel", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Completed after --background-duration
+        let (status, queued) = send(create()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(queued["status"], "queued");
+        let id = queued["id"].as_str().unwrap().to_string();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        let (_, completed) = send(retrieve(&id)).await;
+        assert_eq!(completed["status"], "completed");
+        assert!(completed["output"][0]["content"][0]["text"].is_string());
+        let (status, _) = send(cancel(&id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Cancelled before completing
+        let (_, queued) = send(create()).await;
+        let id = queued["id"].as_str().unwrap().to_string();
+        let (status, cancelled) = send(cancel(&id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cancelled["status"], "cancelled");
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        let (_, retrieved) = send(retrieve(&id)).await;
+        assert_eq!(retrieved["status"], "cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_delete_stored_response() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route(
+                "/v1/responses/:response_id",
+                get(responses::retrieve).delete(responses::delete),
+            )
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/responses")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"model":"gpt-4.1","input":"Hello","store":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = created["id"].as_str().unwrap();
+
+        let delete = || {
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/v1/responses/{}", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(delete()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], id);
+        assert_eq!(body["object"], "response");
+        assert_eq!(body["deleted"], true);
+
+        // Gone for good, both for retrieval and for a second deletion
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/responses/{}", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = app.oneshot(delete()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_input_items() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route(
+                "/v1/responses/:response_id/input_items",
+                get(responses::input_items),
+            )
+            .with_state(ServerState::new(common::args()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/responses")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        r#"{"model":"gpt-4.1","input":"Hello","store":true}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = created["id"].as_str().unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/v1/responses/{}/input_items?limit=10", id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let list: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(list["object"], "list");
+        assert_eq!(list["has_more"], false);
+        let item = &list["data"][0];
+        assert_eq!(item["type"], "message");
+        assert_eq!(item["role"], "user");
+        assert_eq!(item["content"][0]["text"], "Hello");
+        assert_eq!(list["first_id"], item["id"]);
+
+        // Nothing comes after the last item
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/v1/responses/{}/input_items?after={}",
+                        id,
+                        item["id"].as_str().unwrap()
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let list: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(list["data"].as_array().unwrap().len(), 0);
+        assert!(list["first_id"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_previous_response_id() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let create = |body: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "model": "gpt-4.1",
+                "input": "Hello",
+                "store": true,
+            })))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let first: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "model": "gpt-4.1",
+                "input": "Hello",
+                "previous_response_id": first["id"],
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let second: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(second["previous_response_id"], first["id"]);
+        // The same prompt again, on top of the first exchange
+        assert_eq!(
+            second["usage"]["input_tokens"].as_u64().unwrap(),
+            first["usage"]["total_tokens"].as_u64().unwrap()
+                + first["usage"]["input_tokens"].as_u64().unwrap()
+        );
+
+        let response = app
+            .oneshot(create(serde_json::json!({
+                "model": "gpt-4.1",
+                "input": "Hello",
+                "previous_response_id": "resp_unknown",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "previous_response_not_found");
+        assert_eq!(body["error"]["param"], "previous_response_id");
+    }
+
+    #[tokio::test]
+    async fn test_tool_round_trips() {
+        let args = Args {
+            tool_round_trips: true,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(args));
+        let create = |body: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let tools = serde_json::json!([{
+            "type": "function",
+            "name": "get_weather",
+            "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+        }]);
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "model": "gpt-4.1",
+                "input": "Weather in Rome?",
+                "tools": tools,
+                "store": true,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let first: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let call = &first["output"][0];
+        assert_eq!(call["type"], "function_call");
+        assert_eq!(call["name"], "get_weather");
+        let arguments: serde_json::Value =
+            serde_json::from_str(call["arguments"].as_str().unwrap()).unwrap();
+        assert!(arguments["city"].is_string());
+
+        let follow_up = |call_id: &serde_json::Value| {
+            create(serde_json::json!({
+                "model": "gpt-4.1",
+                "previous_response_id": first["id"],
+                "tools": tools,
+                "input": [{"type": "function_call_output", "call_id": call_id, "output": "Sunny"}],
+            }))
+        };
+
+        let response = app
+            .clone()
+            .oneshot(follow_up(&serde_json::json!("call_wrong")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["error"]["message"],
+            "No tool call found for function call output with call_id call_wrong."
+        );
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "model": "gpt-4.1",
+                "previous_response_id": first["id"],
+                "input": "Well?",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.oneshot(follow_up(&call["call_id"])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["output"][0]["type"], "message");
+    }
+
+    #[tokio::test]
+    async fn test_function_call_streaming() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "gpt-4.1",
+                    "input": "Weather in Rome?",
+                    "stream": true,
+                    "tools": [{
+                        "type": "function",
+                        "name": "get_weather",
+                        "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+                    }],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let events: Vec<serde_json::Value> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect();
+
+        let deltas: String = events
+            .iter()
+            .filter(|event| event["type"] == "response.function_call_arguments.delta")
+            .map(|event| event["delta"].as_str().unwrap())
+            .collect();
+        let done = events
+            .iter()
+            .find(|event| event["type"] == "response.function_call_arguments.done")
+            .unwrap();
+        assert_eq!(done["arguments"], deltas);
+
+        let completed = events
+            .iter()
+            .find(|event| event["type"] == "response.completed")
+            .unwrap();
+        let call = &completed["response"]["output"][1];
+        assert_eq!(call["type"], "function_call");
+        assert_eq!(call["name"], "get_weather");
+        assert_eq!(call["arguments"], deltas);
+    }
+
+    #[tokio::test]
+    async fn test_mcp_tools() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "gpt-4.1",
+                    "input": "Roll 2d4+1",
+                    "tools": [{
+                        "type": "mcp",
+                        "server_label": "dmcp",
+                        "server_url": "https://dmcp-server.deno.dev/sse",
+                        "allowed_tools": ["roll"],
+                        "require_approval": "never",
+                    }],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let output = body["output"].as_array().unwrap();
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0]["type"], "mcp_list_tools");
+        assert_eq!(output[0]["server_label"], "dmcp");
+        assert_eq!(output[0]["tools"][0]["name"], "roll");
+        assert_eq!(output[1]["type"], "mcp_call");
+        assert_eq!(output[1]["name"], "roll");
+        assert!(output[1]["output"].is_string());
+        assert_eq!(output[2]["type"], "message");
+    }
+
+    #[tokio::test]
+    async fn test_code_interpreter() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route("/v1/files/:file_id/content", get(files::content))
+            .with_state(ServerState::new(common::args()));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "gpt-4.1",
+                    "input": "Plot some random numbers",
+                    "tools": [{"type": "code_interpreter", "container": {"type": "auto"}}],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let call = &body["output"][0];
+        assert_eq!(call["type"], "code_interpreter_call");
+        assert!(call["code"].as_str().unwrap().contains("output.csv"));
+        assert_eq!(call["outputs"][0]["type"], "logs");
+        let annotation = &body["output"][1]["content"][0]["annotations"][0];
+        assert_eq!(annotation["type"], "container_file_citation");
+        assert_eq!(annotation["container_id"], call["container_id"]);
+
+        let request = Request::builder()
+            .uri(format!(
+                "/v1/files/{}/content",
+                annotation["file_id"].as_str().unwrap()
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(b"x,y\n"));
+    }
+
+    #[tokio::test]
+    async fn test_search_calls() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "gpt-4.1",
+                    "input": "What is the latest news about Rust?",
+                    "tools": [
+                        {"type": "web_search"},
+                        {"type": "file_search", "vector_store_ids": ["vs_unknown"]},
+                    ],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let output = body["output"].as_array().unwrap();
+        assert_eq!(output[0]["type"], "web_search_call");
+        assert_eq!(
+            output[0]["action"]["query"],
+            "What is the latest news about Rust?"
+        );
+        assert_eq!(output[1]["type"], "file_search_call");
+        assert!(!output[1]["results"].as_array().unwrap().is_empty());
+        assert_eq!(output[2]["type"], "message");
+        let annotations = output[2]["content"][0]["annotations"].as_array().unwrap();
+        assert!(annotations.iter().any(|a| a["type"] == "url_citation"));
+        assert!(annotations.iter().any(|a| a["type"] == "file_citation"));
+    }
+
+    #[tokio::test]
+    async fn test_computer_use() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let create = |body: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let tools = serde_json::json!([{
+            "type": "computer_use_preview",
+            "display_width": 1024,
+            "display_height": 768,
+            "environment": "browser",
+        }]);
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "model": "computer-use-preview",
+                "input": "Open the bank website",
+                "tools": tools,
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let call = &body["output"][0];
+        assert_eq!(call["type"], "computer_call");
+        assert!(call["action"]["type"].is_string());
+
+        let follow_up = |output: serde_json::Value| {
+            create(serde_json::json!({
+                "model": "computer-use-preview",
+                "tools": tools,
+                "input": [{"type": "computer_call_output", "call_id": call["call_id"], "output": output}],
+            }))
+        };
+        let response = app
+            .clone()
+            .oneshot(follow_up(serde_json::json!({"type": "text"})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(follow_up(serde_json::json!({
+                "type": "computer_screenshot",
+                "image_url": "data:image/png;base64,iVBORw0KGgo=",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["output"][0]["type"], "message");
+    }
+
+    #[tokio::test]
+    async fn test_structured_outputs() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let format = serde_json::json!({
+            "type": "json_schema",
+            "name": "event",
+            "strict": true,
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "participants": {"type": "array", "items": {"type": "string"}},
+                },
+                "required": ["name", "participants"],
+                "additionalProperties": false,
+            },
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "gpt-4.1",
+                    "input": "Alice and Bob are going to a science fair on Friday.",
+                    "text": {"format": format},
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["text"]["format"], format);
+        let event: serde_json::Value =
+            serde_json::from_str(body["output"][0]["content"][0]["text"].as_str().unwrap())
+                .unwrap();
+        assert!(event["name"].is_string());
+        assert!(event["participants"].as_array().unwrap()[0].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_allowed_tools() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let create = |tool_choice: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "model": "gpt-4.1",
+                        "input": "Weather in Rome?",
+                        "tools": [
+                            {"type": "function", "name": "get_weather", "parameters": {}},
+                            {"type": "function", "name": "get_time", "parameters": {}},
+                        ],
+                        "tool_choice": tool_choice,
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(create(serde_json::json!({
+                "type": "allowed_tools",
+                "mode": "auto",
+                "tools": [{"type": "function", "name": "get_time"}],
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["output"][0]["name"], "get_time");
+
+        for tool_choice in [
+            serde_json::json!({"type": "function", "name": "get_stock_price"}),
+            serde_json::json!({"type": "allowed_tools", "mode": "sometimes", "tools": []}),
+            serde_json::json!("always"),
+        ] {
+            let response = app.clone().oneshot(create(tool_choice)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"]["param"], "tool_choice");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refusal_streaming() {
+        let args = Args {
+            refusal_rate: Some(100),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(args));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                r#"{"model":"gpt-4.1","input":"Hello","stream":true}"#,
+            ))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let events: Vec<serde_json::Value> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect();
+
+        let deltas: String = events
+            .iter()
+            .filter(|event| event["type"] == "response.refusal.delta")
+            .map(|event| event["delta"].as_str().unwrap())
+            .collect();
+        assert_eq!(deltas, "I'm sorry, but I can't help with that.");
+        assert!(!events
+            .iter()
+            .any(|event| event["type"] == "response.output_text.delta"));
+        let completed = events
+            .iter()
+            .find(|event| event["type"] == "response.completed")
+            .unwrap();
+        let part = &completed["response"]["output"][1]["content"][0];
+        assert_eq!(part["type"], "refusal");
+        assert_eq!(part["refusal"], deltas);
+    }
+
+    #[tokio::test]
+    async fn test_max_output_tokens() {
+        let args = Args {
+            response_length: Some("1000".to_string()),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(args));
+        let request = |max_output_tokens: u32| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "model": "gpt-4.1",
+                        "input": "Hello",
+                        "max_output_tokens": max_output_tokens,
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(10)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.oneshot(request(20)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "incomplete");
+        assert_eq!(body["incomplete_details"]["reason"], "max_output_tokens");
+        assert_eq!(body["output"][0]["status"], "incomplete");
+        assert!(body["usage"]["output_tokens"].as_u64().unwrap() <= 20);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_template() {
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(common::args()));
+        let request = |prompt: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({"model": "gpt-4.1", "prompt": prompt}).to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(serde_json::json!({"version": "2"})))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let prompt = serde_json::json!({
+            "id": "pmpt_123",
+            "version": "2",
+            "variables": {"city": "Rome"},
+        });
+        let response = app.oneshot(request(prompt.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["prompt"], prompt);
+        assert!(body["usage"]["input_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_store_default_and_ttl() {
+        let args = Args {
+            store_default: true,
+            store_ttl: Some(std::time::Duration::from_millis(50)),
+            ..common::args()
+        };
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .route("/v1/responses/:response_id", get(responses::retrieve))
+            .with_state(ServerState::new(args));
+        let retrieve = |id: &str| {
+            Request::builder()
+                .uri(format!("/v1/responses/{}", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/responses")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{"model":"gpt-4.1","input":"Hello"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["store"], true);
+        let id = created["id"].as_str().unwrap();
+
+        let response = app.clone().oneshot(retrieve(id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        let response = app.oneshot(retrieve(id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_safety_identifier() {
+        let args = Args {
+            flagged_safety_identifiers: vec!["user-123".to_string()],
+            ..common::args()
+        };
+        let app = Router::new()
+            .route("/v1/responses", post(responses::responses))
+            .with_state(ServerState::new(args));
+        let request = |safety_identifier: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/responses")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({
+                        "model": "gpt-4.1",
+                        "input": "Hello",
+                        "safety_identifier": safety_identifier,
+                        "prompt_cache_key": "greetings",
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("user-456")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["safety_identifier"], "user-456");
+        assert_eq!(body["prompt_cache_key"], "greetings");
+
+        let response = app.oneshot(request("user-123")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "safety_identifier_blocked");
+    }
 }
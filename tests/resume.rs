@@ -0,0 +1,70 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, resume, server_state::ServerState};
+    use tower::ServiceExt; // for `oneshot`
+
+    fn event_ids(body: &str) -> Vec<u64> {
+        body.lines()
+            .filter_map(|line| line.strip_prefix("id:"))
+            .map(|id| id.trim().parse().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_resume_stream() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                resume::resume,
+            ))
+            .with_state(state);
+
+        let request = |last_event_id: Option<u64>| {
+            let mut builder = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json");
+            if let Some(id) = last_event_id {
+                builder = builder.header("Last-Event-ID", id.to_string());
+            }
+            builder
+                .body(Body::from(
+                    r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o","stream":true}"#,
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let ids = event_ids(&String::from_utf8_lossy(&body));
+        assert!(ids.len() > 2);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let response = app.oneshot(request(Some(ids[1]))).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(event_ids(&String::from_utf8_lossy(&body)), ids[2..]);
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use roy_cli::config::{Profile, ScheduleEntry};
+    use roy_cli::server_state::ServerState;
+    use std::collections::BTreeMap;
+
+    fn entry(from: &str, to: &str, profile: &str) -> ScheduleEntry {
+        ScheduleEntry {
+            from: from.to_string(),
+            to: to.to_string(),
+            profile: profile.to_string(),
+        }
+    }
+
+    fn minute(time: &str) -> u32 {
+        let (hours, minutes) = time.split_once(':').unwrap();
+        hours.parse::<u32>().unwrap() * 60 + minutes.parse::<u32>().unwrap()
+    }
+
+    #[test]
+    fn test_is_active_at() {
+        let afternoon = entry("14:00", "15:00", "degraded");
+        assert!(!afternoon.is_active_at(minute("13:59")));
+        assert!(afternoon.is_active_at(minute("14:00")));
+        assert!(afternoon.is_active_at(minute("14:59")));
+        assert!(!afternoon.is_active_at(minute("15:00")));
+
+        // Spanning midnight
+        let night = entry("22:00", "06:00", "degraded");
+        assert!(night.is_active_at(minute("22:00")));
+        assert!(night.is_active_at(minute("23:59")));
+        assert!(night.is_active_at(minute("00:00")));
+        assert!(night.is_active_at(minute("05:59")));
+        assert!(!night.is_active_at(minute("06:00")));
+        assert!(!night.is_active_at(minute("12:00")));
+
+        assert!(!entry("25:00", "06:00", "degraded").is_active_at(minute("12:00")));
+    }
+
+    #[test]
+    fn test_first_entry_wins() {
+        let profiles = BTreeMap::from([
+            (
+                "failing".to_string(),
+                Profile {
+                    error_code: Some(503),
+                    ..Default::default()
+                },
+            ),
+            (
+                "slow".to_string(),
+                Profile {
+                    slowdown: Some("500".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let schedule = [
+            entry("14:00", "16:00", "failing"),
+            entry("15:00", "17:00", "slow"),
+        ];
+        let state = ServerState::new(common::args()).with_schedule(&schedule, &profiles);
+
+        assert_eq!(state.schedule_entry_at(minute("15:30")), Some(0));
+        assert_eq!(state.schedule_entry_at(minute("16:30")), Some(1));
+        assert_eq!(state.schedule_entry_at(minute("18:00")), None);
+
+        // A request resolves the behaviour once and keeps it
+        let failing = state.scheduled(Some(0));
+        assert_eq!(failing.args().error_code, Some(503));
+        assert_eq!(failing.schedule_len(), 0);
+        let slow = state.scheduled(Some(1));
+        assert_eq!(slow.args().slowdown.as_deref(), Some("500"));
+        assert_eq!(slow.args().error_code, None);
+        assert_eq!(state.scheduled(None).args().error_code, None);
+    }
+}
@@ -0,0 +1,38 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use roy_cli::{config::SizeRule, server_state::ServerState};
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_size_rules() {
+        let rules: Vec<SizeRule> = serde_json::from_str(
+            r#"[
+                {"min_prompt_tokens": 100, "extra_latency": "200ms"},
+                {"min_prompt_tokens": 1000, "error_code": 500, "error_rate": 100}
+            ]"#,
+        )
+        .unwrap();
+        let state = ServerState::new(common::args()).with_size_rules(rules);
+
+        // Small prompts are left alone
+        let started = Instant::now();
+        assert_eq!(state.apply_size_rules(10).await, None);
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        // Large prompts wait for the extra latency
+        let started = Instant::now();
+        assert_eq!(state.apply_size_rules(500).await, None);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // The rule with the highest threshold reached wins
+        let started = Instant::now();
+        assert_eq!(state.apply_size_rules(5000).await, Some(500));
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+#[cfg(test)]
+mod tests {
+    use roy_cli::sse::{SseEvent, SseParser};
+
+    #[test]
+    fn test_parse_events() {
+        let mut parser = SseParser::default();
+        assert!(parser.push("event: delta\r\ndata: {\"a\":").is_empty());
+
+        let events = parser.push("1}\r\n\r\n: comment\n\ndata: [DONE]\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("delta".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                    id: None,
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".to_string(),
+                    id: None,
+                },
+            ]
+        );
+        assert!(parser.finish().is_none());
+    }
+
+    #[test]
+    fn test_split_data_lines() {
+        let event = SseEvent {
+            event: None,
+            data: r#"{"text":"a, b: c","values":[1,2,3]}"#.to_string(),
+            id: Some("7".to_string()),
+        };
+
+        let frame = event.to_frame(Some(4));
+        assert!(frame.matches("data: ").count() > 1);
+        let parsed = SseEvent::parse(&frame).unwrap();
+        assert_eq!(parsed.id, event.id);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&parsed.data).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&event.data).unwrap()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{chat_completions, journal, server_state::ServerState, stats};
+    use tower::ServiceExt; // for `oneshot`
+
+    fn chat(content: &str) -> Request<Body> {
+        let body = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": content}],
+        });
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                journal::record,
+            ))
+            .route("/__stats/report", get(stats::report))
+            .with_state(state);
+
+        for content in ["Hello", "Hello", "Hello [ROY:503]"] {
+            app.clone().oneshot(chat(content)).await.unwrap();
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/__stats/report")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["requests"], 3);
+        let endpoint = &report["endpoints"]["POST /v1/chat/completions"];
+        assert_eq!(endpoint["requests"], 3);
+        assert_eq!(endpoint["statuses"]["200"], 2);
+        assert_eq!(endpoint["statuses"]["503"], 1);
+        assert!(endpoint["latency_ms"]["p99"].is_u64());
+        assert_eq!(report["errors"]["503"], 1);
+        // The failed request counts against the RPM too
+        assert_eq!(report["rpm"]["peak"], 3);
+        assert_eq!(report["rpm"]["limit"], common::args().rpm);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_exemplars() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                journal::record,
+            ))
+            .route("/__stats/metrics", get(stats::metrics))
+            .with_state(state);
+
+        let response = app.clone().oneshot(chat("Hello")).await.unwrap();
+        let request_id = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/__stats/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.ends_with("# EOF\n"));
+        assert!(body.contains(
+            r#"roy_request_duration_seconds_count{method="POST",endpoint="/v1/chat/completions"} 1"#
+        ));
+        let inf = body
+            .lines()
+            .find(|line| line.contains(r#"le="+Inf""#))
+            .unwrap();
+        assert!(inf.contains(" 1"));
+        // The single request is the exemplar of exactly one bucket
+        let exemplar = format!(r#"# {{request_id="{}"}}"#, request_id);
+        assert_eq!(body.matches(&exemplar).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeseries() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route("/__stats/timeseries", get(stats::timeseries))
+            .with_state(state);
+
+        app.clone().oneshot(chat("Hello")).await.unwrap();
+
+        let timeseries = |query: &str| {
+            let request = Request::builder()
+                .uri(format!("/__stats/timeseries{}", query))
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        let body = timeseries("?minutes=2").await;
+        let points = body["points"].as_array().unwrap();
+        assert_eq!(points.len(), 120);
+        let requests = points
+            .iter()
+            .map(|point| point["requests"].as_u64().unwrap())
+            .sum::<u64>();
+        assert_eq!(requests, 1);
+
+        assert_eq!(
+            timeseries("").await["points"].as_array().unwrap().len(),
+            300
+        );
+        // Huge windows are capped to the hour the timeseries keeps
+        let body = timeseries("?minutes=18446744073709551615").await;
+        assert_eq!(body["points"].as_array().unwrap().len(), 3600);
+    }
+
+    #[tokio::test]
+    async fn test_journal_is_capped() {
+        let state = ServerState::new(common::args());
+        let total = journal::MAX_RECORDED_REQUESTS + 5;
+        for index in 0..total {
+            state.record_request(journal::RequestRecord {
+                id: format!("req_{}", index),
+                timestamp: std::time::SystemTime::now(),
+                method: "POST".to_string(),
+                path: "/v1/embeddings".to_string(),
+                endpoint: "/v1/embeddings".to_string(),
+                model: None,
+                status: 200,
+                latency_ms: 20,
+                client_headers: Default::default(),
+            });
+        }
+
+        let requests = state.recorded_requests();
+        assert_eq!(requests.len(), journal::MAX_RECORDED_REQUESTS);
+        assert_eq!(requests[0].id, "req_5");
+
+        // The metrics still count every request
+        let app = Router::new()
+            .route("/__stats/metrics", get(stats::metrics))
+            .with_state(state);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/__stats/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(&format!(
+            r#"roy_request_duration_seconds_count{{method="POST",endpoint="/v1/embeddings"}} {}"#,
+            total
+        )));
+        let bucket = format!(
+            r#"roy_request_duration_seconds_bucket{{method="POST",endpoint="/v1/embeddings",le="0.025"}} {} # {{request_id="req_{}"}}"#,
+            total,
+            total - 1
+        );
+        assert!(body.contains(&bucket));
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, scenario::Scenario, server_state::ServerState, stubs};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_stub_is_consumed() {
+        let state = ServerState::new(common::args());
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), stubs::serve))
+            .route("/__admin/stub", post(stubs::prime))
+            .with_state(state);
+
+        let post = |uri: &str, body: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let chat = || {
+            post(
+                "/v1/chat/completions",
+                r#"{"messages":[{"role":"user","content":"What's the weather?"}],"model":"gpt-4o"}"#,
+            )
+        };
+
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/__admin/stub",
+                r#"{"request":{"model":"gpt-4o","body_contains":"weather"},"response":{"status":503,"headers":{"retry-after":"1"},"body":"overloaded"}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app.clone().oneshot(chat()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "1");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"overloaded");
+
+        let response = app.oneshot(chat()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_stub_scenario_states() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"stubs":[
+                {"request":{"path":"/v1/chat/completions"},"response":{"status":200,"body":"recovered"},
+                 "scenario":"retry","required_state":"AfterFirstFailure","new_state":"Done"},
+                {"request":{"path":"/v1/chat/completions"},"response":{"status":500},
+                 "scenario":"retry","required_state":"Started","new_state":"AfterFirstFailure"}
+            ]}"#,
+        )
+        .unwrap();
+        let state = ServerState::new(common::args()).with_scenario(scenario);
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), stubs::serve))
+            .with_state(state.clone());
+
+        let chat = || {
+            Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"messages":[{"role":"user","content":"Hello"}],"model":"gpt-4o"}"#,
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(chat()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let response = app.clone().oneshot(chat()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"recovered");
+        assert_eq!(state.scenario_states()["retry"], "Done");
+    }
+}
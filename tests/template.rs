@@ -0,0 +1,94 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use roy_cli::{chat_completions, server_state::ServerState, template, Args};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[test]
+    fn test_check() {
+        assert!(template::check("{{model}} said {{ content }}").is_ok());
+        assert!(template::check("No variables").is_ok());
+        assert!(template::check("{% for word in content.split() %}{{ word }}{% endfor %}").is_ok());
+        assert!(template::check("{{unknown}}").is_err());
+        assert!(template::check("{% if model %}{{ other }}{% endif %}").is_err());
+        assert!(template::check("{{model").is_err());
+        assert!(template::check("{% if model %}").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let context = minijinja::context! {
+            model => "gpt-4o",
+            prompt => r#"Say "hi""#,
+            prompt_tokens => 5,
+        };
+        assert_eq!(
+            template::render(
+                "{{ model|upper }} {% if prompt_tokens > 1 %}long{% else %}short{% endif %}",
+                context.clone()
+            ),
+            "GPT-4O long"
+        );
+        // Values can be escaped, e.g. to answer with JSON
+        assert_eq!(
+            template::render(r#"{"prompt": {{ prompt|tojson }}}"#, context.clone()),
+            r#"{"prompt": "Say \"hi\""}"#
+        );
+        // Rendering errors leave the template as it is
+        assert_eq!(template::render("{{ other }}", context), "{{ other }}");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completions_template() {
+        let args = Args {
+            response_template: Some(
+                "{{model}} answers '{{prompt}}' to {{request_id}} ({{prompt_tokens}} tokens)"
+                    .to_string(),
+            ),
+            ..common::args()
+        };
+        args.validate().unwrap();
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "model": "gpt-4o",
+                            "messages": [{"role": "user", "content": "Hi"}],
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["choices"][0]["message"]["content"],
+            format!(
+                "gpt-4o answers 'Hi' to {} ({} tokens)",
+                body["id"].as_str().unwrap(),
+                body["usage"]["prompt_tokens"]
+            )
+        );
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use roy_cli::{chat_completions, server_state::ServerState, Args, UsageModel};
+    use tower::ServiceExt; // for `oneshot`
+
+    #[tokio::test]
+    async fn test_simple_usage_model() {
+        let args = Args {
+            usage_model: UsageModel::Simple,
+            ..common::args()
+        };
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(args));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/chat/completions")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "model": "gpt-4o",
+                            "messages": [
+                                {"role": "system", "content": "Be brief"},
+                                {"role": "user", "content": [{"type": "text", "text": "How are  you?"}]},
+                            ],
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        // One token per word of the contents, whatever the JSON around them
+        let usage = &body["usage"];
+        assert_eq!(usage["prompt_tokens"], 5);
+        let content = body["choices"][0]["message"]["content"].as_str().unwrap();
+        assert_eq!(
+            usage["completion_tokens"],
+            content.split_whitespace().count()
+        );
+        assert_eq!(
+            usage["total_tokens"],
+            5 + content.split_whitespace().count()
+        );
+    }
+}
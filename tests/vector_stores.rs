@@ -0,0 +1,94 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use roy_cli::{files::StoredFile, server_state::ServerState, vector_stores};
+    use std::time::Duration;
+    use tower::ServiceExt; // for `oneshot`
+
+    async fn call(app: &Router, method: &str, uri: &str, body: String) -> serde_json::Value {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ingestion_and_search() {
+        let state = ServerState::new(common::args());
+        let file = StoredFile::new(
+            "docs.txt".to_string(),
+            "assistants".to_string(),
+            b"Some documentation".to_vec(),
+        );
+        let file_id = file.id.clone();
+        state.files().lock().unwrap().push(file);
+
+        let app = Router::new()
+            .route("/v1/vector_stores", post(vector_stores::create))
+            .route(
+                "/v1/vector_stores/:vector_store_id",
+                get(vector_stores::retrieve),
+            )
+            .route(
+                "/v1/vector_stores/:vector_store_id/search",
+                post(vector_stores::search),
+            )
+            .with_state(state);
+
+        let store = call(
+            &app,
+            "POST",
+            "/v1/vector_stores",
+            format!(r#"{{"name":"docs","file_ids":["{}"]}}"#, file_id),
+        )
+        .await;
+        assert_eq!(store["status"], "in_progress");
+        assert_eq!(store["file_counts"]["in_progress"], 1);
+        let uri = format!("/v1/vector_stores/{}", store["id"].as_str().unwrap());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let store = call(&app, "GET", &uri, String::new()).await;
+        assert_eq!(store["status"], "completed");
+        assert_eq!(store["file_counts"]["completed"], 1);
+
+        let search = || {
+            call(
+                &app,
+                "POST",
+                &format!("{}/search", uri),
+                r#"{"query":"how to","max_num_results":3}"#.to_string(),
+            )
+        };
+        let results = search().await;
+        let data = results["data"].as_array().unwrap();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[0]["file_id"], file_id.as_str());
+        assert!(data[0]["score"].as_f64() > data[1]["score"].as_f64());
+        assert_eq!(search().await, results);
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use axum::{routing::post, Router};
+    use roy_cli::{chat_completions, server_state::ServerState, verify};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_verify_roy_itself() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(chat_completions::chat_completions),
+            )
+            .with_state(ServerState::new(common::args()));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Roy behind no gateway at all handles every behaviour
+        verify::verify(&format!("http://{}", addr), None)
+            .await
+            .unwrap();
+    }
+}
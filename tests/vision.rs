@@ -0,0 +1,63 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use roy_cli::{server_state::ServerState, vision, Args, UsageModel};
+    use serde_json::json;
+
+    /// A data URL with just enough of a PNG to read its size.
+    fn png(width: u32, height: u32) -> String {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        format!("data:image/png;base64,{}", STANDARD.encode(bytes))
+    }
+
+    #[test]
+    fn test_image_tokens() {
+        assert_eq!(vision::image_tokens(&png(4096, 8192), "low"), 85);
+        // Scaled to 1024x2048, then to 768x1536: 6 tiles
+        assert_eq!(vision::image_tokens(&png(2048, 4096), "high"), 1105);
+        assert_eq!(vision::image_tokens(&png(512, 512), "auto"), 255);
+        // Remote images are assumed to be 1024x1024
+        assert_eq!(
+            vision::image_tokens("https://example.com/cat.png", "auto"),
+            765
+        );
+    }
+
+    #[test]
+    fn test_message_tokens() {
+        let state = ServerState::new(Args {
+            usage_model: UsageModel::Simple,
+            ..common::args()
+        });
+        let messages = [json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "What's this?"},
+                {"type": "image_url", "image_url": {"url": png(512, 512), "detail": "low"}},
+            ],
+        })];
+        assert_eq!(state.count_message_tokens(&messages).unwrap(), 2 + 85);
+
+        // The image data isn't counted as text
+        let state = ServerState::new(common::args());
+        let text_only = [json!({"role": "user", "content": [{"type": "text", "text": "Hi"}]})];
+        let with_image = [json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "Hi"},
+                {"type": "image_url", "image_url": {"url": png(100_000, 100_000)}},
+            ],
+        })];
+        let text_tokens = state.count_message_tokens(&text_only).unwrap();
+        let tokens = state.count_message_tokens(&with_image).unwrap();
+        assert!(tokens > text_tokens + 765 && tokens < text_tokens + 800);
+    }
+}
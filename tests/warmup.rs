@@ -0,0 +1,87 @@
+// Copyright 2025 Massimiliano Pippi
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use roy_cli::{
+        server_state::{ServerState, Warmup},
+        Args,
+    };
+    use std::time::Duration;
+
+    fn args(warmup: &str) -> Args {
+        Args {
+            slowdown: Some("100".to_string()),
+            warmup: Some(warmup.to_string()),
+            ..common::args()
+        }
+    }
+
+    #[test]
+    fn test_warmup() {
+        assert_eq!(
+            Warmup::parse("1m:2.5x-latency,halved-rpm"),
+            Some(Warmup {
+                duration: Duration::from_secs(60),
+                latency_factor: 2.5,
+                rate_limit_factor: 0.5,
+            })
+        );
+        assert_eq!(Warmup::parse("30s").unwrap().latency_factor, 3.0);
+        assert_eq!(Warmup::parse("30s:slower"), None);
+        assert_eq!(Warmup::parse("soon:tripled-latency"), None);
+        for spec in [
+            "30s:infx-latency",
+            "30s:NaNx-latency",
+            "30s:-2x-latency",
+            "30s:0x-latency",
+        ] {
+            assert_eq!(Warmup::parse(spec), None, "{}", spec);
+        }
+
+        let state = ServerState::new(args("1h:doubled-latency,halved-rpm"));
+        assert!(state.active_warmup().is_some());
+        assert_eq!(state.get_slodown_ms(), 200);
+        assert_eq!(
+            state.get_rate_limit_headers()["x-ratelimit-limit-requests"],
+            "30"
+        );
+
+        // Back to normal once the window is over
+        let state = ServerState::new(args("0s:doubled-latency,halved-rpm"));
+        assert!(state.active_warmup().is_none());
+        assert_eq!(state.get_slodown_ms(), 100);
+        assert_eq!(
+            state.get_rate_limit_headers()["x-ratelimit-limit-requests"],
+            "60"
+        );
+    }
+
+    #[test]
+    fn test_warmup_without_latency() {
+        // The warm-up slows the server down even when no latency was asked for, and its streams
+        let state = ServerState::new(Args {
+            warmup: Some("1h:tripled-latency".to_string()),
+            ..common::args()
+        });
+        assert_eq!(state.get_slodown_ms(), 300);
+        assert_eq!(
+            state.chunk_delay(Duration::from_millis(10), 0),
+            Duration::from_millis(30)
+        );
+
+        // Limiting the requests alone keeps the usual latency
+        let state = ServerState::new(Args {
+            warmup: Some("1h:halved-rpm".to_string()),
+            ..common::args()
+        });
+        assert_eq!(state.get_slodown_ms(), 0);
+        assert_eq!(
+            state.chunk_delay(Duration::from_millis(10), 0),
+            Duration::from_millis(10)
+        );
+    }
+}